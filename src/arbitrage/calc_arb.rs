@@ -4,10 +4,13 @@
 
 use crate::arbitrage::config::{ArbitrageConfig, ArbitrageError, PairId, PositionSizer};
 use crate::arbitrage::types::{ArbOpportunity, SwapPath, TokenInArb};
+use crate::data::oracle::{OraclePriceSink, OracleUpdate};
 use crate::markets::pools::Pool;
 use anyhow::Result;
 use dashmap::DashMap;
-use log::{info, warn};
+use log::{debug, info, warn};
+use solana_sdk::pubkey::Pubkey;
+use std::str::FromStr;
 use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
@@ -17,7 +20,7 @@ use tokio::time::timeout;
 pub struct SimpleArbitrageCalculator {
     config: Arc<ArbitrageConfig>,
     position_sizer: Arc<PositionSizer>,
-    token_indices: Arc<DashMap<String, u32>>,
+    token_indices: Arc<DashMap<Pubkey, u32>>,
     consecutive_failures: Arc<AtomicU32>,
     next_opportunity_id: Arc<AtomicU64>,
     sol_price_usd: Arc<AtomicU64>, // Stored as cents for atomic updates
@@ -47,7 +50,12 @@ impl SimpleArbitrageCalculator {
         
         // Build token index for fast lookups
         for (idx, token) in tokens.iter().enumerate() {
-            token_indices.insert(token.token.clone(), idx as u32);
+            match Pubkey::from_str(&token.token) {
+                Ok(mint) => {
+                    token_indices.insert(mint, idx as u32);
+                }
+                Err(_) => warn!("Ignoring arbitrage token with invalid mint {}", token.token),
+            }
         }
         
         Self {
@@ -132,8 +140,8 @@ impl SimpleArbitrageCalculator {
         for pool in pools {
             // Get token indices - fix the pattern matching
             if let (Some(token_a_ref), Some(token_b_ref)) = (
-                self.token_indices.get(&pool.token_a),
-                self.token_indices.get(&pool.token_b),
+                self.token_indices.get(&pool.token_a.mint),
+                self.token_indices.get(&pool.token_b.mint),
             ) {
                 let token_a_idx = *token_a_ref.value();
                 let token_b_idx = *token_b_ref.value();
@@ -192,11 +200,12 @@ impl SimpleArbitrageCalculator {
         pool_sell: &Pool,
         pair_id: PairId,
     ) -> Result<Option<SimpleArbitrageOpportunity>> {
-        // Simplified price calculation (assumes 1:1 base rate)
-        let price_buy = self.calculate_effective_price(pool_buy);
-        let price_sell = self.calculate_effective_price(pool_sell);
+        // Both prices are of the same token, whichever order the pools list the pair in
+        let base_mint = pool_buy.token_a.mint;
+        let price_buy = self.calculate_effective_price(pool_buy, &base_mint);
+        let price_sell = self.calculate_effective_price(pool_sell, &base_mint);
         
-        if price_sell <= price_buy {
+        if price_buy <= 0.0 || price_sell <= price_buy {
             return Ok(None); // No arbitrage opportunity
         }
         
@@ -234,8 +243,8 @@ impl SimpleArbitrageCalculator {
         
         Ok(Some(SimpleArbitrageOpportunity {
             id: self.next_opportunity_id.fetch_add(1, Ordering::Relaxed),
-            token_a: pool_buy.token_a.clone(),
-            token_b: pool_buy.token_b.clone(),
+            token_a: pool_buy.token_a.mint.to_string(),
+            token_b: pool_buy.token_b.mint.to_string(),
             pair_id,
             pool_a: pool_buy.clone(),
             pool_b: pool_sell.clone(),
@@ -257,58 +266,43 @@ impl SimpleArbitrageCalculator {
             paths: vec![], // Will be filled by execution layer
         };
         
+        let (Some(dex_a), Some(dex_b)) = (simple_opp.pool_a.dex.clone(), simple_opp.pool_b.dex.clone()) else {
+            return Err(ArbitrageError::InvalidPoolData("Pool without a venue".to_string()).into());
+        };
         let expected_profit_lamports = (simple_opp.expected_profit_usd / self.get_sol_price() * 1_000_000_000.0) as u64;
         
         Ok(ArbOpportunity {
             path: swap_path,
             expected_profit_lamports,
-            timestamp_unix_nanos: simple_opp.timestamp.elapsed().as_nanos(),
+            timestamp_unix_nanos: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_nanos()
+                .saturating_sub(simple_opp.timestamp.elapsed().as_nanos()),
+            slot: (simple_opp.pool_a.last_update_slot > 0 && simple_opp.pool_b.last_update_slot > 0)
+                .then(|| simple_opp.pool_a.last_update_slot.max(simple_opp.pool_b.last_update_slot)),
+            generation: 0,
             execution_plan: vec![], // Will be filled by execution layer
             metadata: crate::arbitrage::types::OpportunityMetadata {
                 estimated_gas_cost: self.config.gas_cost_lamports,
                 net_profit_lamports: expected_profit_lamports as i64 - self.config.gas_cost_lamports as i64,
+                expected_profit_usd_cents: (simple_opp.expected_profit_usd * 100.0).round() as i64,
+                valuation_confidence_bps: 0,
                 profit_percentage_bps: simple_opp.price_diff_bps,
                 risk_score: self.calculate_risk_score(&simple_opp),
-                source: crate::arbitrage::types::OpportunitySource::PriceDiscrepancy {
-                    dex_a: crate::markets::types::DexLabel::Raydium, // Simplified
-                    dex_b: crate::markets::types::DexLabel::Orca,    // Simplified
-                },
+                source: crate::arbitrage::types::OpportunitySource::PriceDiscrepancy { dex_a, dex_b },
                 max_latency_ms: 100, // Simple arbitrage should be fast
             },
         })
     }
     
-    fn calculate_effective_price(&self, pool: &Pool) -> f64 {
-        // REAL AMM price calculation using actual pool data
-        // Enhanced with real-time RPC calls for accurate pricing
-        
-        // Use real pool liquidity data - no more placeholders!
-        let _sqrt_liquidity = pool.liquidity.sqrt();
-        
-        // Calculate REAL reserves from on-chain pool state
-        // We'll use the actual liquidity data from DEX APIs instead of estimates
-        let (reserve_a, reserve_b) = self.get_real_reserves(pool);
-        
-        info!("🔍 Real reserves for pool {}: A={:.2}, B={:.2}", 
-              &pool.id[..15], reserve_a, reserve_b);
-        
-        // Calculate spot price using constant product formula
-        let spot_price = if reserve_a > 0.0 {
-            reserve_b / reserve_a
-        } else {
+    /// Price of `base_mint` in the pool's other token after the pool's fee and an
+    /// estimated slippage, or zero when the pool's state isn't known
+    fn calculate_effective_price(&self, pool: &Pool, base_mint: &Pubkey) -> f64 {
+        let Some(spot_price) = pool.price_of(base_mint) else {
             return 0.0;
         };
-        
-        // Apply REAL trading fees based on pool type
-        // Raydium: 0.25%, Orca: 0.3%, Jupiter: variable
-        let fee_bps = if pool.id.contains("raydium") {
-            25 // 0.25%
-        } else if pool.id.contains("orca") {
-            30 // 0.30%
-        } else {
-            25 // Default 0.25%
-        };
-        let fee_multiplier = 1.0 - (fee_bps as f64 / 10_000.0);
+        let fee_multiplier = pool.fee_multiplier();
         
         // Account for realistic slippage based on liquidity depth
         let estimated_trade_size = 1000.0; // $1000 trade size
@@ -317,9 +311,8 @@ impl SimpleArbitrageCalculator {
         
         let effective_price = spot_price * fee_multiplier * slippage_multiplier;
         
-        // Log pricing calculation for debugging
-        info!("💰 Pool {} price calc: spot={:.6}, fee_mult={:.4}, slippage_mult={:.4}, final={:.6}",
-              &pool.id[..20], spot_price, fee_multiplier, slippage_multiplier, effective_price);
+        debug!("Pool {} price calc: spot={:.6}, fee_mult={:.4}, slippage_mult={:.4}, final={:.6}",
+              pool.id, spot_price, fee_multiplier, slippage_multiplier, effective_price);
         
         effective_price.max(0.0)
     }
@@ -332,7 +325,7 @@ impl SimpleArbitrageCalculator {
             }.into());
         }
         
-        if pool.id.is_empty() || pool.token_a.is_empty() || pool.token_b.is_empty() {
+        if pool.id.is_empty() || pool.dex.is_none() || pool.address.is_none() {
             return Err(ArbitrageError::InvalidPoolData("Missing pool identifiers".to_string()).into());
         }
         
@@ -385,78 +378,6 @@ impl SimpleArbitrageCalculator {
         self.sol_price_usd.load(Ordering::Relaxed) as f64 / 100.0
     }
     
-    /// Get REAL reserves from on-chain pool state
-    fn get_real_reserves(&self, pool: &Pool) -> (f64, f64) {
-        // Parse actual reserves from liquidity data
-        // For Raydium, Orca, Jupiter pools we have real TVL data
-        if pool.id.starts_with("raydium_") {
-            // Raydium pools have real reserve data from API
-            self.calculate_raydium_reserves(pool)
-        } else if pool.id.starts_with("orca_") {
-            // Orca pools have real reserve data from API  
-            self.calculate_orca_reserves(pool)
-        } else if pool.id.starts_with("jupiter_") {
-            // Jupiter aggregates - estimate from quote data
-            self.calculate_jupiter_reserves(pool)
-        } else {
-            // Fallback - use TVL-based calculation
-            let sqrt_liquidity = pool.liquidity.sqrt();
-            (sqrt_liquidity, sqrt_liquidity)
-        }
-    }
-    
-    fn calculate_raydium_reserves(&self, pool: &Pool) -> (f64, f64) {
-        // For Raydium, we can use the mintAmountA and mintAmountB from the API
-        // The API response in pools.rs already logs these values
-        // For now, derive from TVL with SOL price awareness
-        let sol_price = self.get_sol_price();
-        
-        if pool.token_a.contains("So11111") {
-            // SOL is token A
-            let sol_value = pool.liquidity / 2.0; // 50/50 split in AMM
-            let sol_amount = sol_value / sol_price;
-            let other_amount = pool.liquidity / 2.0; // USD value for other token
-            (sol_amount, other_amount)
-        } else if pool.token_b.contains("So11111") {
-            // SOL is token B  
-            let sol_value = pool.liquidity / 2.0;
-            let sol_amount = sol_value / sol_price;
-            let other_amount = pool.liquidity / 2.0;
-            (other_amount, sol_amount)
-        } else {
-            // Non-SOL pair - equal value split
-            let value_per_side = pool.liquidity / 2.0;
-            (value_per_side, value_per_side)
-        }
-    }
-    
-    fn calculate_orca_reserves(&self, pool: &Pool) -> (f64, f64) {
-        // Similar to Raydium but with Orca-specific logic
-        let sol_price = self.get_sol_price();
-        
-        if pool.token_a.contains("So11111") || pool.token_b.contains("So11111") {
-            let sol_value = pool.liquidity / 2.0;
-            let sol_amount = sol_value / sol_price;
-            let other_amount = pool.liquidity / 2.0;
-            
-            if pool.token_a.contains("So11111") {
-                (sol_amount, other_amount)
-            } else {
-                (other_amount, sol_amount)
-            }
-        } else {
-            let value_per_side = pool.liquidity / 2.0;
-            (value_per_side, value_per_side)
-        }
-    }
-    
-    fn calculate_jupiter_reserves(&self, pool: &Pool) -> (f64, f64) {
-        // Jupiter is an aggregator, so we estimate based on available liquidity
-        // Jupiter pools represent routing capability rather than single pool reserves
-        let estimated_depth = pool.liquidity / 4.0; // Conservative estimate
-        (estimated_depth, estimated_depth)
-    }
-    
     /// Check circuit breaker status
     pub fn is_circuit_open(&self) -> bool {
         self.consecutive_failures.load(Ordering::Relaxed) >= self.config.breaker_threshold
@@ -478,6 +399,14 @@ impl SimpleArbitrageCalculator {
     }
 }
 
+impl OraclePriceSink for SimpleArbitrageCalculator {
+    fn on_oracle_price(&self, update: &OracleUpdate) {
+        if update.is_sol() {
+            self.update_sol_price(update.price.price);
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct ArbitrageStats {
     pub consecutive_failures: u32,
@@ -496,9 +425,7 @@ pub trait ArbitragePool {
 
 impl ArbitragePool for Pool {
     fn calculate_effective_rate(&self) -> f64 {
-        // Simplified rate calculation - should be enhanced with DEX-specific logic
-        let fee_multiplier = 1.0 - (30.0 / 10_000.0); // Assume 0.3% fee
-        1.0 * fee_multiplier
+        self.fee_multiplier()
     }
     
     fn has_sufficient_liquidity(&self, amount_usd: f64) -> bool {
@@ -528,6 +455,8 @@ impl ArbitragePool for Pool {
 mod tests {
     use super::*;
     use crate::arbitrage::config::{ArbitrageConfig, PositionSizer};
+    use crate::markets::pools::{PoolState, PoolToken};
+    use crate::markets::types::DexLabel;
     
     fn create_test_calculator() -> SimpleArbitrageCalculator {
         let config = ArbitrageConfig::default();
@@ -549,12 +478,15 @@ mod tests {
     }
     
     fn create_test_pool(id: &str, token_a: &str, token_b: &str, liquidity: f64) -> Pool {
-        Pool {
-            id: id.to_string(),
-            token_a: token_a.to_string(),
-            token_b: token_b.to_string(),
+        let mint = |token: &str| PoolToken::new(Pubkey::from_str(token).unwrap_or_default(), None);
+        Pool::new(
+            id.to_string(),
+            Some(DexLabel::Raydium),
+            Some(Pubkey::new_unique()),
+            mint(token_a),
+            mint(token_b),
             liquidity,
-        }
+        )
     }
     
     #[test]
@@ -574,6 +506,32 @@ mod tests {
         assert!(calculator.validate_pool(&invalid_pool).is_err());
     }
     
+    #[test]
+    fn test_effective_price_follows_pool_orientation() {
+        let calculator = create_test_calculator();
+        let sol = Pubkey::from_str("So11111111111111111111111111111111111111112").unwrap();
+        let usdc = Pubkey::from_str("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v").unwrap();
+
+        let mut sol_usdc = create_test_pool("raydium_sol_usdc", &sol.to_string(), &usdc.to_string(), 1_000_000.0);
+        sol_usdc.token_a.decimals = Some(9);
+        sol_usdc.token_b.decimals = Some(6);
+        assert_eq!(calculator.calculate_effective_price(&sol_usdc, &sol), 0.0);
+
+        // 1_000 SOL against 150_000 USDC
+        sol_usdc.state = PoolState::Reserves { reserve_a: 1_000_000_000_000, reserve_b: 150_000_000_000 };
+        // The same pair listed the other way round, at 152
+        let mut usdc_sol = create_test_pool("orca_usdc_sol", &usdc.to_string(), &sol.to_string(), 1_000_000.0);
+        usdc_sol.token_a.decimals = Some(6);
+        usdc_sol.token_b.decimals = Some(9);
+        usdc_sol.state = PoolState::Reserves { reserve_a: 152_000_000_000, reserve_b: 1_000_000_000_000 };
+
+        let cheap = calculator.calculate_effective_price(&sol_usdc, &sol);
+        let rich = calculator.calculate_effective_price(&usdc_sol, &sol);
+        // Spot price less the 0.25% fee and 0.1% slippage
+        assert!((cheap - 150.0 * 0.9975 * 0.999).abs() < 1e-9);
+        assert!((rich - 152.0 * 0.9975 * 0.999).abs() < 1e-9);
+    }
+    
     #[test]
     fn test_sol_price_update() {
         let calculator = create_test_calculator();
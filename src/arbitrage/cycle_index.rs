@@ -0,0 +1,346 @@
+//! Pool to cycle index for event-driven re-evaluation
+//!
+//! A full scan searches the whole token graph again. The index instead lists, when a
+//! pool is added, every cycle of up to `max_hops` pools that runs through it and starts
+//! at one of the start tokens, profitable or not. When a pool's state changes only the
+//! cycles listed under it are re-priced, so the work per update follows how connected
+//! the pool is rather than how many pools there are.
+
+use crate::arbitrage::config::ArbitrageConfig;
+use crate::arbitrage::cycles::{Cycle, GraphEdge, MIN_LOG_PROFIT};
+use crate::markets::pools::{Pool, PoolEvent};
+use solana_sdk::pubkey::Pubkey;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+/// Longest cycle indexed when none is configured. Every cycle through a pool is listed,
+/// so the count grows with the hub tokens' pool counts to this power.
+pub const DEFAULT_INDEXED_HOPS: usize = 3;
+
+/// A cycle by the pools it crosses, independent of their current prices
+#[derive(Debug, Clone)]
+struct IndexedCycle {
+    start: Pubkey,
+    /// Pool address and the token sold into it, per leg
+    legs: Vec<(Pubkey, Pubkey)>,
+}
+
+/// Cycles through the start tokens, listed under every pool they use
+pub struct CycleIndex {
+    start_mints: Vec<Pubkey>,
+    max_hops: usize,
+    min_liquidity_usd: f64,
+    pools: HashMap<Pubkey, Arc<Pool>>,
+    /// Stable ids for route ids; a pool's two directions are edges `2 * id` and `2 * id + 1`
+    pool_ids: HashMap<Pubkey, u32>,
+    next_pool_id: u32,
+    /// Indexed pools trading each token
+    by_token: HashMap<Pubkey, Vec<Pubkey>>,
+    cycles: HashMap<u64, IndexedCycle>,
+    by_pool: HashMap<Pubkey, Vec<u64>>,
+    /// Edge ids rotated to start at the smallest, so a loop is listed once
+    canonical: HashMap<Vec<u32>, u64>,
+    next_cycle_id: u64,
+}
+
+impl CycleIndex {
+    pub fn new(config: &ArbitrageConfig, start_mints: Vec<Pubkey>) -> Self {
+        Self {
+            start_mints,
+            max_hops: DEFAULT_INDEXED_HOPS,
+            min_liquidity_usd: config.min_liquidity_usd,
+            pools: HashMap::new(),
+            pool_ids: HashMap::new(),
+            next_pool_id: 0,
+            by_token: HashMap::new(),
+            cycles: HashMap::new(),
+            by_pool: HashMap::new(),
+            canonical: HashMap::new(),
+            next_cycle_id: 0,
+        }
+    }
+
+    /// Index cycles of 2 to `max_hops` pools; takes effect for pools added afterwards
+    pub fn with_max_hops(mut self, max_hops: usize) -> Self {
+        self.max_hops = max_hops.max(2);
+        self
+    }
+
+    /// Index `pools`, as a registry load
+    pub fn with_pools(mut self, pools: impl IntoIterator<Item = Pool>) -> Self {
+        for pool in pools {
+            self.insert_pool(pool);
+        }
+        self
+    }
+
+    /// Number of indexed cycles
+    pub fn len(&self) -> usize {
+        self.cycles.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cycles.is_empty()
+    }
+
+    /// The indexed pool's id; its cycles' routes through it are `2 * id` and `2 * id + 1`
+    pub fn pool_id(&self, pool_address: &Pubkey) -> Option<u32> {
+        self.pool_ids.get(pool_address).copied()
+    }
+
+    pub fn cycles_through(&self, pool_address: &Pubkey) -> usize {
+        self.by_pool.get(pool_address).map_or(0, Vec::len)
+    }
+
+    /// Follow a registry event, returning the cycles through the pool that are now
+    /// profitable before price impact, most profitable first
+    pub fn apply_event(&mut self, event: &PoolEvent) -> Vec<Cycle> {
+        match event {
+            PoolEvent::PoolAdded(pool) | PoolEvent::PoolChanged { current: pool, .. } => {
+                let Some(address) = pool.address else {
+                    return Vec::new();
+                };
+                self.insert_pool(pool.clone());
+                self.reprice(&address)
+            }
+            PoolEvent::PoolRemoved(pool) => {
+                if let Some(address) = pool.address {
+                    self.remove_pool(&address);
+                }
+                Vec::new()
+            }
+        }
+    }
+
+    /// Add `pool`, or replace the state of the indexed pool at its address. Returns the
+    /// number of cycles newly indexed. Pools without a venue, an address or enough
+    /// known liquidity aren't indexed, and an indexed pool that no longer qualifies is
+    /// removed.
+    pub fn insert_pool(&mut self, pool: Pool) -> usize {
+        let Some(address) = pool.address else {
+            return 0;
+        };
+        if pool.dex.is_none() || pool.liquidity_usd().is_some_and(|tvl| tvl < self.min_liquidity_usd) {
+            self.remove_pool(&address);
+            return 0;
+        }
+        if let Some(indexed) = self.pools.get_mut(&address) {
+            *indexed = Arc::new(pool);
+            return 0;
+        }
+
+        self.pool_ids.insert(address, self.next_pool_id);
+        self.next_pool_id += 1;
+        for mint in [pool.token_a.mint, pool.token_b.mint] {
+            self.by_token.entry(mint).or_default().push(address);
+        }
+        self.pools.insert(address, Arc::new(pool));
+        self.index_cycles_through(&address)
+    }
+
+    /// Drop the pool at `address` and every cycle using it. Returns the number of
+    /// cycles dropped.
+    pub fn remove_pool(&mut self, address: &Pubkey) -> usize {
+        let Some(pool) = self.pools.remove(address) else {
+            return 0;
+        };
+        for mint in [pool.token_a.mint, pool.token_b.mint] {
+            if let Some(pools) = self.by_token.get_mut(&mint) {
+                pools.retain(|pool_address| pool_address != address);
+            }
+        }
+
+        let cycle_ids = self.by_pool.remove(address).unwrap_or_default();
+        for cycle_id in &cycle_ids {
+            let Some(cycle) = self.cycles.remove(cycle_id) else {
+                continue;
+            };
+            for (pool_address, _) in &cycle.legs {
+                if let Some(listed) = self.by_pool.get_mut(pool_address) {
+                    listed.retain(|id| id != cycle_id);
+                }
+            }
+        }
+        self.canonical.retain(|_, cycle_id| !cycle_ids.contains(cycle_id));
+        self.pool_ids.remove(address);
+        cycle_ids.len()
+    }
+
+    /// Price the cycles through the pool at `address` on the current pool states,
+    /// keeping those profitable before price impact, most profitable first
+    pub fn reprice(&self, address: &Pubkey) -> Vec<Cycle> {
+        let mut profitable: Vec<Cycle> = self
+            .by_pool
+            .get(address)
+            .into_iter()
+            .flatten()
+            .filter_map(|cycle_id| self.cycles.get(cycle_id))
+            .filter_map(|cycle| self.price(cycle))
+            .filter(|cycle| cycle.rate().ln() > MIN_LOG_PROFIT)
+            .collect();
+        profitable.sort_by(|a, b| b.rate().total_cmp(&a.rate()));
+        profitable
+    }
+
+    /// The cycle on current pool states; `None` while any pool can't be priced
+    fn price(&self, cycle: &IndexedCycle) -> Option<Cycle> {
+        let mut edge_ids = Vec::with_capacity(cycle.legs.len());
+        let mut legs = Vec::with_capacity(cycle.legs.len());
+        for (pool_address, token_in) in &cycle.legs {
+            let pool = self.pools.get(pool_address)?;
+            edge_ids.push(self.edge_id(pool, token_in)?);
+            legs.push(GraphEdge::new(pool.clone(), *token_in)?);
+        }
+        Some(Cycle {
+            start: cycle.start,
+            edge_ids,
+            legs,
+        })
+    }
+
+    fn edge_id(&self, pool: &Pool, token_in: &Pubkey) -> Option<u32> {
+        let pool_id = self.pool_ids.get(&pool.address?)?;
+        Some(pool_id * 2 + u32::from(*token_in != pool.token_a.mint))
+    }
+
+    /// List the cycles through the pool at `address`, which were unreachable before it
+    /// was indexed
+    fn index_cycles_through(&mut self, address: &Pubkey) -> usize {
+        let mut found = Vec::new();
+        for start in &self.start_mints {
+            let mut visited = HashSet::from([*start]);
+            self.walk(*start, *start, address, &mut Vec::new(), &mut visited, &mut found);
+        }
+
+        let mut added = 0;
+        for cycle in found {
+            let Some(mut key) = cycle
+                .legs
+                .iter()
+                .map(|(pool_address, token_in)| self.edge_id(self.pools.get(pool_address)?, token_in))
+                .collect::<Option<Vec<u32>>>()
+            else {
+                continue;
+            };
+            let pivot = key.iter().enumerate().min_by_key(|(_, id)| **id).map_or(0, |(position, _)| position);
+            key.rotate_left(pivot);
+            if self.canonical.contains_key(&key) {
+                continue;
+            }
+
+            let cycle_id = self.next_cycle_id;
+            self.next_cycle_id += 1;
+            for (pool_address, _) in &cycle.legs {
+                self.by_pool.entry(*pool_address).or_default().push(cycle_id);
+            }
+            self.canonical.insert(key, cycle_id);
+            self.cycles.insert(cycle_id, cycle);
+            added += 1;
+        }
+        added
+    }
+
+    /// Depth-first search for loops back to `start` that use the `required` pool,
+    /// never reusing a pool or revisiting a token
+    fn walk(
+        &self,
+        start: Pubkey,
+        token: Pubkey,
+        required: &Pubkey,
+        path: &mut Vec<(Pubkey, Pubkey)>,
+        visited: &mut HashSet<Pubkey>,
+        found: &mut Vec<IndexedCycle>,
+    ) {
+        for pool_address in self.by_token.get(&token).into_iter().flatten() {
+            if path.iter().any(|(used, _)| used == pool_address) {
+                continue;
+            }
+            let pool = &self.pools[pool_address];
+            let next = if pool.token_a.mint == token { pool.token_b.mint } else { pool.token_a.mint };
+            path.push((*pool_address, token));
+
+            if next == start {
+                if path.len() >= 2 && path.iter().any(|(used, _)| used == required) {
+                    found.push(IndexedCycle { start, legs: path.clone() });
+                }
+            } else if path.len() < self.max_hops && visited.insert(next) {
+                self.walk(start, next, required, path, visited, found);
+                visited.remove(&next);
+            }
+            path.pop();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::markets::pools::PoolState;
+
+    #[test]
+    fn test_reprices_only_cycles_through_the_changed_pool() {
+        let (x, y, z, w) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        // A fairly priced X -> Y -> Z triangle and a Z/W pool no cycle through X uses
+        let xy = Pool::with_reserves(x, y, 1_000_000, 2_000_000);
+        let yz = Pool::with_reserves(y, z, 1_000_000, 3_000_000);
+        let zx = Pool::with_reserves(z, x, 6_000_000, 1_000_000);
+        let zw = Pool::with_reserves(z, w, 1_000_000, 1_000_000);
+        let mut index = CycleIndex::new(&ArbitrageConfig::default(), vec![x])
+            .with_pools([xy.clone(), yz.clone(), zx.clone(), zw.clone()]);
+
+        // The triangle in both directions, each listed under all three of its pools
+        assert_eq!(index.len(), 2);
+        assert_eq!(index.cycles_through(&xy.address.unwrap()), 2);
+        assert_eq!(index.cycles_through(&zw.address.unwrap()), 0);
+        assert!(index.reprice(&xy.address.unwrap()).is_empty());
+
+        // Z gets 10% cheaper against X
+        let mut cheaper = zx.clone();
+        cheaper.state = PoolState::Reserves { reserve_a: 6_000_000, reserve_b: 1_100_000 };
+        let cycles = index.apply_event(&PoolEvent::PoolChanged { previous: zx.clone(), current: cheaper });
+        assert_eq!(cycles.len(), 1);
+        assert_eq!(cycles[0].hops(), 3);
+        assert_eq!(cycles[0].start, x);
+        assert!(cycles[0].rate() > 1.09);
+        let path = cycles[0].to_swap_path();
+        assert_eq!(path.paths[0].token_in, x.to_string());
+        assert_eq!(path.paths[2].token_out, x.to_string());
+
+        let unrelated = index.apply_event(&PoolEvent::PoolChanged { previous: zw.clone(), current: zw.clone() });
+        assert!(unrelated.is_empty());
+    }
+
+    #[test]
+    fn test_added_and_removed_pools_update_the_index() {
+        let (x, y) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let first = Pool::with_reserves(x, y, 1_000_000, 2_000_000);
+        let mut index = CycleIndex::new(&ArbitrageConfig::default(), vec![x, y]).with_pools([first.clone()]);
+        assert!(index.is_empty());
+
+        // A second X/Y pool 5% apart closes a 2-hop loop each way, listed once whichever
+        // start token enters it, and one way round is profitable
+        let second = Pool::with_reserves(x, y, 1_000_000, 2_100_000);
+        let cycles = index.apply_event(&PoolEvent::PoolAdded(second.clone()));
+        assert_eq!(index.len(), 2);
+        assert_eq!(cycles.len(), 1);
+        assert_eq!(cycles[0].hops(), 2);
+
+        // Shallow pools aren't indexed
+        let mut shallow = Pool::with_reserves(x, y, 1_000_000, 2_200_000);
+        shallow.liquidity = 10.0;
+        assert_eq!(index.insert_pool(shallow), 0);
+        assert_eq!(index.len(), 2);
+
+        // Pools found on chain have no known TVL and are indexed all the same
+        let mut discovered = Pool::with_reserves(x, y, 1_000_000, 2_200_000);
+        discovered.liquidity = 0.0;
+        assert_eq!(index.insert_pool(discovered.clone()), 4);
+        index.remove_pool(&discovered.address.unwrap());
+        assert_eq!(index.len(), 2);
+
+        index.apply_event(&PoolEvent::PoolRemoved(second.clone()));
+        assert!(index.is_empty());
+        assert_eq!(index.cycles_through(&first.address.unwrap()), 0);
+        assert!(index.reprice(&second.address.unwrap()).is_empty());
+    }
+}
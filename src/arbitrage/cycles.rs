@@ -0,0 +1,440 @@
+//! Multi-hop cycle detection on a log-rate token graph
+//!
+//! Tokens are nodes and every priced pool contributes an edge in each direction,
+//! weighted by `-ln(rate)` where the rate is the pool's mid price net of its fee. A
+//! cycle is profitable before price impact exactly when its weights sum below zero, so
+//! the search looks for negative cycles of 2 to `MAX_CYCLE_HOPS` edges through the
+//! requested start tokens.
+//!
+//! The search is a depth-first walk that never reuses a pool or revisits a token, and
+//! abandons a branch once even the most negative edge in the graph on every remaining
+//! hop couldn't bring it below zero. It stops at `ArbitrageConfig::max_cycle_detection_us`
+//! and reports the cycles found so far.
+
+use crate::arbitrage::config::ArbitrageConfig;
+use crate::arbitrage::types::{Market, Route, SwapPath};
+use crate::markets::pools::Pool;
+use crate::markets::types::DexLabel;
+use solana_sdk::pubkey::Pubkey;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Longest cycle searched, in pools
+pub const MAX_CYCLE_HOPS: usize = 4;
+
+/// Log-weight a cycle must fall below, about one basis point of profit
+pub(crate) const MIN_LOG_PROFIT: f64 = 1e-4;
+
+/// Expansions between deadline checks
+const DEADLINE_CHECK_INTERVAL: u32 = 256;
+
+/// One direction through a pool
+#[derive(Debug, Clone)]
+pub struct GraphEdge {
+    pub pool: Arc<Pool>,
+    pub dex: DexLabel,
+    pub pool_address: Pubkey,
+    pub token_in: Pubkey,
+    pub token_out: Pubkey,
+    /// Whole `token_out` received per whole `token_in`, net of the pool's fee
+    pub rate: f64,
+}
+
+impl GraphEdge {
+    /// Edge selling `token_in` into `pool`, if the pool has a venue, an address and a price
+    pub fn new(pool: Arc<Pool>, token_in: Pubkey) -> Option<Self> {
+        let token_out = if token_in == pool.token_a.mint {
+            pool.token_b.mint
+        } else if token_in == pool.token_b.mint {
+            pool.token_a.mint
+        } else {
+            return None;
+        };
+        let rate = pool.price_of(&token_in)? * pool.fee_multiplier();
+        Some(Self {
+            dex: pool.dex.clone()?,
+            pool_address: pool.address?,
+            token_in,
+            token_out,
+            rate,
+            pool,
+        })
+    }
+
+    pub fn weight(&self) -> f64 {
+        -self.rate.ln()
+    }
+
+    /// Whether the edge swaps the pool's token A for token B
+    pub fn a_to_b(&self) -> bool {
+        self.token_in == self.pool.token_a.mint
+    }
+
+    /// How far `amount_out` falls short of the mid rate for `amount_in`, in basis points
+    pub fn price_impact_bps(&self, amount_in: u64, amount_out: u64) -> u16 {
+        let (decimals_in, decimals_out) = if self.a_to_b() {
+            (self.pool.token_a.decimals, self.pool.token_b.decimals)
+        } else {
+            (self.pool.token_b.decimals, self.pool.token_a.decimals)
+        };
+        let (Some(decimals_in), Some(decimals_out)) = (decimals_in, decimals_out) else {
+            return 0;
+        };
+        let mid_out = amount_in as f64 * self.rate * 10f64.powi(decimals_out as i32 - decimals_in as i32);
+        if mid_out <= 0.0 {
+            return 0;
+        }
+        ((1.0 - amount_out as f64 / mid_out).clamp(0.0, 1.0) * 10_000.0).round() as u16
+    }
+}
+
+/// Directed token graph over the executable, priced pools
+pub struct TokenGraph {
+    edges: Vec<GraphEdge>,
+    /// Edge ids leaving each token
+    outgoing: HashMap<Pubkey, Vec<u32>>,
+    /// Most negative edge weight, the best any single hop can contribute
+    min_weight: f64,
+}
+
+impl TokenGraph {
+    /// Pools need a venue, an address, a price and at least `min_liquidity_usd` of TVL
+    /// when their TVL is known
+    pub fn build(pools: &[Pool], min_liquidity_usd: f64) -> Self {
+        let mut edges = Vec::new();
+        for pool in pools.iter().filter(|pool| pool.liquidity_usd().is_none_or(|tvl| tvl >= min_liquidity_usd)) {
+            let pool = Arc::new(pool.clone());
+            let forward = GraphEdge::new(pool.clone(), pool.token_a.mint);
+            let backward = GraphEdge::new(pool.clone(), pool.token_b.mint);
+            if let (Some(forward), Some(backward)) = (forward, backward) {
+                edges.extend([forward, backward]);
+            }
+        }
+
+        let mut outgoing: HashMap<Pubkey, Vec<u32>> = HashMap::new();
+        for (id, edge) in edges.iter().enumerate() {
+            outgoing.entry(edge.token_in).or_default().push(id as u32);
+        }
+        let min_weight = edges.iter().map(GraphEdge::weight).fold(f64::INFINITY, f64::min);
+        Self { edges, outgoing, min_weight }
+    }
+
+    pub fn edge(&self, id: u32) -> &GraphEdge {
+        &self.edges[id as usize]
+    }
+
+    fn outgoing(&self, token: &Pubkey) -> &[u32] {
+        self.outgoing.get(token).map_or(&[], Vec::as_slice)
+    }
+}
+
+/// A profitable sequence of swaps back to the start token
+#[derive(Debug, Clone)]
+pub struct Cycle {
+    pub start: Pubkey,
+    /// Graph edge ids, which double as route ids
+    pub edge_ids: Vec<u32>,
+    pub legs: Vec<GraphEdge>,
+}
+
+impl Cycle {
+    pub fn hops(&self) -> usize {
+        self.legs.len()
+    }
+
+    /// Start tokens returned per start token traded, before price impact
+    pub fn rate(&self) -> f64 {
+        self.legs.iter().map(|leg| leg.rate).product()
+    }
+
+    /// Latest slot any of its pools' states was observed at. `None` while one of them
+    /// hasn't been read on-chain (slot 0), as its state is of unknown age.
+    pub fn slot(&self) -> Option<u64> {
+        if self.legs.iter().any(|leg| leg.pool.last_update_slot == 0) {
+            return None;
+        }
+        self.legs.iter().map(|leg| leg.pool.last_update_slot).max()
+    }
+
+    /// Decimals of the start token, as the first pool records them
+    pub fn start_decimals(&self) -> Option<u8> {
+        let pool = &self.legs.first()?.pool;
+        if pool.token_a.mint == self.start {
+            pool.token_a.decimals
+        } else {
+            pool.token_b.decimals
+        }
+    }
+
+    pub fn to_swap_path(&self) -> SwapPath {
+        let paths: Vec<Route> = self
+            .edge_ids
+            .iter()
+            .zip(&self.legs)
+            .map(|(id, leg)| Route {
+                id: *id,
+                dex: leg.dex.clone(),
+                pool_address: leg.pool_address.to_string(),
+                token_in: leg.token_in.to_string(),
+                token_out: leg.token_out.to_string(),
+                token_0to1: leg.a_to_b(),
+            })
+            .collect();
+        SwapPath {
+            id_paths: self.edge_ids.clone(),
+            hops: paths.len(),
+            paths,
+        }
+    }
+
+    pub fn markets(&self) -> Vec<Market> {
+        self.legs
+            .iter()
+            .map(|leg| Market {
+                id: leg.pool.id.clone(),
+                dex_label: leg.dex.clone(),
+            })
+            .collect()
+    }
+
+    /// Edge ids rotated to start at the smallest, identifying the cycle whichever
+    /// token it's entered from
+    fn canonical_edges(&self) -> Vec<u32> {
+        let pivot = self
+            .edge_ids
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, id)| **id)
+            .map_or(0, |(position, _)| position);
+        let mut edges = self.edge_ids.clone();
+        edges.rotate_left(pivot);
+        edges
+    }
+}
+
+/// Outcome of one search
+#[derive(Debug, Clone, Default)]
+pub struct CycleScan {
+    /// Most profitable first
+    pub cycles: Vec<Cycle>,
+    /// The time budget ran out before the search finished
+    pub timed_out: bool,
+    pub elapsed_us: u64,
+}
+
+/// Bounded search for profitable cycles
+#[derive(Debug, Clone)]
+pub struct CycleDetector {
+    max_hops: usize,
+    min_liquidity_usd: f64,
+    budget: Duration,
+}
+
+impl CycleDetector {
+    pub fn new(config: &ArbitrageConfig) -> Self {
+        Self {
+            max_hops: MAX_CYCLE_HOPS,
+            min_liquidity_usd: config.min_liquidity_usd,
+            budget: Duration::from_micros(config.max_cycle_detection_us),
+        }
+    }
+
+    /// Search cycles of 2 to `max_hops` pools, capped at `MAX_CYCLE_HOPS`
+    pub fn with_max_hops(mut self, max_hops: usize) -> Self {
+        self.max_hops = max_hops.clamp(2, MAX_CYCLE_HOPS);
+        self
+    }
+
+    /// Profitable cycles through `pools` that start and end at one of `start_mints`
+    pub fn detect(&self, pools: &[Pool], start_mints: &[Pubkey]) -> CycleScan {
+        let started = Instant::now();
+        let graph = TokenGraph::build(pools, self.min_liquidity_usd);
+        let mut scan = self.detect_in(&graph, start_mints, started);
+        scan.elapsed_us = started.elapsed().as_micros() as u64;
+        scan
+    }
+
+    /// Search an already built graph; the budget counts from `started`
+    pub fn detect_in(&self, graph: &TokenGraph, start_mints: &[Pubkey], started: Instant) -> CycleScan {
+        let mut search = Search {
+            graph,
+            max_hops: self.max_hops,
+            deadline: started + self.budget,
+            expansions: 0,
+            timed_out: false,
+            path: Vec::with_capacity(self.max_hops),
+            visited: HashSet::with_capacity(self.max_hops),
+            used_pools: HashSet::with_capacity(self.max_hops),
+            found: Vec::new(),
+        };
+        for start in start_mints {
+            if search.timed_out {
+                break;
+            }
+            search.visited.insert(*start);
+            search.walk(*start, *start, 0.0);
+            search.visited.clear();
+        }
+
+        let mut seen = HashSet::new();
+        let mut cycles: Vec<Cycle> = search
+            .found
+            .into_iter()
+            .filter(|cycle| seen.insert(cycle.canonical_edges()))
+            .collect();
+        cycles.sort_by(|a, b| b.rate().total_cmp(&a.rate()));
+        CycleScan {
+            cycles,
+            timed_out: search.timed_out,
+            elapsed_us: started.elapsed().as_micros() as u64,
+        }
+    }
+}
+
+struct Search<'a> {
+    graph: &'a TokenGraph,
+    max_hops: usize,
+    deadline: Instant,
+    expansions: u32,
+    timed_out: bool,
+    path: Vec<u32>,
+    visited: HashSet<Pubkey>,
+    used_pools: HashSet<Pubkey>,
+    found: Vec<Cycle>,
+}
+
+impl Search<'_> {
+    fn out_of_time(&mut self) -> bool {
+        self.expansions += 1;
+        if self.expansions.is_multiple_of(DEADLINE_CHECK_INTERVAL) && Instant::now() >= self.deadline {
+            self.timed_out = true;
+        }
+        self.timed_out
+    }
+
+    fn walk(&mut self, start: Pubkey, token: Pubkey, weight: f64) {
+        for &id in self.graph.outgoing(&token) {
+            if self.out_of_time() {
+                return;
+            }
+            let edge = self.graph.edge(id);
+            if self.used_pools.contains(&edge.pool_address) {
+                continue;
+            }
+            let weight = weight + edge.weight();
+            let hops = self.path.len() + 1;
+
+            if edge.token_out == start {
+                if hops >= 2 && weight < -MIN_LOG_PROFIT {
+                    self.record(start, id);
+                }
+                continue;
+            }
+            // Even the best edge on every remaining hop, closing one included, can't
+            // make this branch profitable
+            let remaining = self.max_hops - hops;
+            if remaining == 0
+                || self.visited.contains(&edge.token_out)
+                || weight + remaining as f64 * self.graph.min_weight >= -MIN_LOG_PROFIT
+            {
+                continue;
+            }
+
+            self.path.push(id);
+            self.visited.insert(edge.token_out);
+            self.used_pools.insert(edge.pool_address);
+            self.walk(start, edge.token_out, weight);
+            self.used_pools.remove(&edge.pool_address);
+            self.visited.remove(&edge.token_out);
+            self.path.pop();
+        }
+    }
+
+    fn record(&mut self, start: Pubkey, closing_edge: u32) {
+        let edge_ids: Vec<u32> = self.path.iter().copied().chain([closing_edge]).collect();
+        let legs = edge_ids.iter().map(|id| self.graph.edge(*id).clone()).collect();
+        self.found.push(Cycle { start, edge_ids, legs });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn detector() -> CycleDetector {
+        CycleDetector::new(&ArbitrageConfig {
+            max_cycle_detection_us: 1_000_000,
+            ..ArbitrageConfig::default()
+        })
+    }
+
+    #[test]
+    fn test_finds_profitable_triangle() {
+        let (x, y, z) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let pools = vec![
+            // 1 X = 2 Y, 1 Y = 3 Z, 1 Z = 0.18 X: the loop returns 1.08 before fees
+            Pool::with_reserves(x, y, 1_000_000, 2_000_000),
+            Pool::with_reserves(y, z, 1_000_000, 3_000_000),
+            Pool::with_reserves(z, x, 1_000_000, 180_000),
+        ];
+        let scan = detector().detect(&pools, &[x]);
+        assert!(!scan.timed_out);
+        assert_eq!(scan.cycles.len(), 1);
+
+        let cycle = &scan.cycles[0];
+        assert_eq!(cycle.hops(), 3);
+        let fees = pools[0].fee_multiplier().powi(3);
+        assert!((cycle.rate() - 1.08 * fees).abs() < 1e-9);
+
+        let path = cycle.to_swap_path();
+        assert_eq!((path.hops, path.id_paths.len()), (3, 3));
+        assert_eq!(path.paths[0].token_in, x.to_string());
+        assert_eq!(path.paths[2].token_out, x.to_string());
+        for (route, leg) in path.paths.iter().zip(&cycle.legs) {
+            assert_eq!(route.pool_address, leg.pool_address.to_string());
+            assert_eq!(route.token_0to1, leg.token_in == leg.pool.token_a.mint);
+        }
+        assert_eq!(path.paths[0].token_out, path.paths[1].token_in);
+
+        // Pools never read on-chain leave the cycle's age unknown
+        assert_eq!(cycle.slot(), None);
+        let observed: Vec<Pool> = pools
+            .iter()
+            .zip([90, 95, 92])
+            .map(|(pool, last_update_slot)| Pool { last_update_slot, ..pool.clone() })
+            .collect();
+        assert_eq!(detector().detect(&observed, &[x]).cycles[0].slot(), Some(95));
+    }
+
+    #[test]
+    fn test_skips_unprofitable_and_duplicate_cycles() {
+        let (x, y, z) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let fair = vec![
+            Pool::with_reserves(x, y, 1_000_000, 2_000_000),
+            Pool::with_reserves(y, z, 1_000_000, 3_000_000),
+            Pool::with_reserves(z, x, 6_000_000, 1_000_000),
+            Pool::with_reserves(x, y, 1_000_000, 2_000_000),
+        ];
+        assert!(detector().detect(&fair, &[x, y, z]).cycles.is_empty());
+
+        // Two X/Y pools a few percent apart make one 2-hop cycle, found once even
+        // when searched from both of its tokens
+        let mismatched = vec![Pool::with_reserves(x, y, 1_000_000, 2_000_000), Pool::with_reserves(x, y, 1_000_000, 2_100_000)];
+        let scan = detector().detect(&mismatched, &[x, y]);
+        assert_eq!(scan.cycles.len(), 1);
+        assert_eq!(scan.cycles[0].hops(), 2);
+        assert_ne!(scan.cycles[0].legs[0].pool_address, scan.cycles[0].legs[1].pool_address);
+
+        // Four-hop cycles are out of reach when the search is capped at three
+        let w = Pubkey::new_unique();
+        let square = vec![
+            Pool::with_reserves(x, y, 1_000_000, 1_000_000),
+            Pool::with_reserves(y, z, 1_000_000, 1_000_000),
+            Pool::with_reserves(z, w, 1_000_000, 1_000_000),
+            Pool::with_reserves(w, x, 1_000_000, 1_100_000),
+        ];
+        assert_eq!(detector().detect(&square, &[x]).cycles.len(), 1);
+        assert!(detector().with_max_hops(3).detect(&square, &[x]).cycles.is_empty());
+    }
+}
@@ -0,0 +1,174 @@
+//! Opportunity deduplication in front of the executor queue
+//!
+//! The market event processor, the strategy scans and pool-update re-pricing can all
+//! find the same trade within a slot or two, and the executor would try every copy.
+//! `OpportunityDeduplicator` wraps the executor's sender and lets one opportunity per
+//! pool set and direction through per TTL, unless a copy priced on fresher pool states,
+//! or on the same states more profitably, supersedes it. Each copy sent carries a new
+//! generation, and the executor's `OpportunityQueue` replaces the queued copy with it.
+
+use crate::arbitrage::types::ArbOpportunity;
+use crate::telemetry::Metrics;
+use dashmap::mapref::entry::Entry;
+use dashmap::DashMap;
+use solana_sdk::pubkey::Pubkey;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc::{self, error::TrySendError};
+
+/// How long a forwarded opportunity suppresses its duplicates when none is configured
+pub const DEFAULT_DEDUP_TTL_MS: u64 = 1_000;
+
+/// Pool and input token of every leg, rotated to start at the smallest, so a cycle
+/// entered from any of its tokens has one key and its reverse another
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct OpportunityKey(Vec<(Pubkey, Pubkey)>);
+
+impl OpportunityKey {
+    /// Key of the execution plan, or of the path while there's no plan. `None` when
+    /// neither names a pool.
+    pub fn of(opportunity: &ArbOpportunity) -> Option<Self> {
+        let mut legs: Vec<(Pubkey, Pubkey)> = if opportunity.execution_plan.is_empty() {
+            opportunity
+                .path
+                .paths
+                .iter()
+                .map(|route| Some((Pubkey::from_str(&route.pool_address).ok()?, Pubkey::from_str(&route.token_in).ok()?)))
+                .collect::<Option<_>>()?
+        } else {
+            opportunity.execution_plan.iter().map(|leg| (leg.pool_address, leg.token_in)).collect()
+        };
+        let pivot = legs.iter().enumerate().min_by_key(|(_, leg)| **leg)?.0;
+        legs.rotate_left(pivot);
+        Some(Self(legs))
+    }
+}
+
+/// What `OpportunityDeduplicator::try_send` did with an opportunity
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dispatch {
+    Sent,
+    /// A copy priced on the same or fresher pool states, and at least as profitable on
+    /// the same ones, was sent within the TTL
+    Suppressed,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Forwarded {
+    at: Instant,
+    slot: Option<u64>,
+    profit_lamports: u64,
+}
+
+impl Forwarded {
+    /// Whether a copy priced at `slot` for `profit_lamports` replaces this one
+    fn superseded_by(&self, slot: Option<u64>, profit_lamports: u64) -> bool {
+        slot > self.slot || (slot == self.slot && profit_lamports > self.profit_lamports)
+    }
+}
+
+/// The executor's sender, passing one opportunity per `OpportunityKey` per TTL.
+/// Clones share what has been forwarded and the generation counter.
+#[derive(Clone)]
+pub struct OpportunityDeduplicator {
+    sender: mpsc::Sender<ArbOpportunity>,
+    ttl: Duration,
+    forwarded: Arc<DashMap<OpportunityKey, Forwarded>>,
+    /// Generations only increase, so a copy sent after the TTL still replaces one queued
+    last_generation: Arc<AtomicU64>,
+    metrics: Arc<Metrics>,
+}
+
+impl OpportunityDeduplicator {
+    pub fn new(sender: mpsc::Sender<ArbOpportunity>, ttl: Duration, metrics: Arc<Metrics>) -> Self {
+        Self {
+            sender,
+            ttl,
+            forwarded: Arc::new(DashMap::new()),
+            last_generation: Arc::new(AtomicU64::new(0)),
+            metrics,
+        }
+    }
+
+    /// Send `opportunity`, stamped with the next generation, unless the copy that went
+    /// out within the TTL isn't superseded by it.
+    /// Suppressed copies are counted in `Metrics::duplicates_suppressed`; opportunities
+    /// without a key always go through, unstamped. An opportunity the channel refuses is
+    /// dropped.
+    pub fn try_send(&self, mut opportunity: ArbOpportunity) -> Result<Dispatch, TrySendError<()>> {
+        let now = Instant::now();
+        self.forwarded.retain(|_, forwarded| now.duration_since(forwarded.at) < self.ttl);
+
+        let Some(key) = OpportunityKey::of(&opportunity) else {
+            return self.send(opportunity);
+        };
+        let forwarded = Forwarded {
+            at: now,
+            slot: opportunity.slot,
+            profit_lamports: opportunity.expected_profit_lamports,
+        };
+        // The entry stays locked through the send, so concurrent copies see its outcome
+        // and generations reach the channel in order
+        let entry = self.forwarded.entry(key);
+        if let Entry::Occupied(previous) = &entry {
+            if !previous.get().superseded_by(forwarded.slot, forwarded.profit_lamports) {
+                self.metrics.inc_duplicates_suppressed();
+                return Ok(Dispatch::Suppressed);
+            }
+        }
+        opportunity.generation = self.last_generation.fetch_add(1, Ordering::Relaxed) + 1;
+        self.send(opportunity)?;
+        entry.insert(forwarded);
+        Ok(Dispatch::Sent)
+    }
+
+    fn send(&self, opportunity: ArbOpportunity) -> Result<Dispatch, TrySendError<()>> {
+        match self.sender.try_send(opportunity) {
+            Ok(()) => Ok(Dispatch::Sent),
+            Err(TrySendError::Full(_)) => Err(TrySendError::Full(())),
+            Err(TrySendError::Closed(_)) => Err(TrySendError::Closed(())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_suppresses_copies_over_the_same_pools() {
+        let metrics = Metrics::new();
+        let (sender, mut receiver) = mpsc::channel(16);
+        let dedup = OpportunityDeduplicator::new(sender, Duration::from_secs(60), metrics.clone());
+        let [p1, p2, p3] = [Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique()];
+        let [x, y, z] = [Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique()];
+
+        assert_eq!(dedup.try_send(ArbOpportunity::through_pools(&[p1, p2, p3], &[x, y, z], 100, 10)), Ok(Dispatch::Sent));
+        // The same triangle entered at Y, and a less profitable copy, on the same states
+        assert_eq!(dedup.try_send(ArbOpportunity::through_pools(&[p2, p3, p1], &[y, z, x], 100, 10)), Ok(Dispatch::Suppressed));
+        assert_eq!(dedup.try_send(ArbOpportunity::through_pools(&[p1, p2, p3], &[x, y, z], 90, 10)), Ok(Dispatch::Suppressed));
+        // A more profitable copy priced on older states is stale
+        assert_eq!(dedup.try_send(ArbOpportunity::through_pools(&[p1, p2, p3], &[x, y, z], 200, 9)), Ok(Dispatch::Suppressed));
+        assert_eq!(metrics.duplicates_suppressed.load(Ordering::Relaxed), 3);
+
+        // More profitable on the same states, or priced on fresher ones however
+        // profitable, supersedes; the reverse direction is another trade
+        assert_eq!(dedup.try_send(ArbOpportunity::through_pools(&[p1, p2, p3], &[x, y, z], 200, 10)), Ok(Dispatch::Sent));
+        assert_eq!(dedup.try_send(ArbOpportunity::through_pools(&[p1, p2, p3], &[x, y, z], 50, 11)), Ok(Dispatch::Sent));
+        assert_eq!(dedup.try_send(ArbOpportunity::through_pools(&[p3, p2, p1], &[x, z, y], 100, 10)), Ok(Dispatch::Sent));
+        let sent: Vec<(u64, u64)> = std::iter::from_fn(|| receiver.try_recv().ok())
+            .map(|opportunity| (opportunity.expected_profit_lamports, opportunity.generation))
+            .collect();
+        assert_eq!(sent, vec![(100, 1), (200, 2), (50, 3), (100, 4)]);
+
+        // Nothing is suppressed once the TTL has passed
+        let (sender, mut receiver) = mpsc::channel(16);
+        let dedup = OpportunityDeduplicator::new(sender, Duration::ZERO, metrics.clone());
+        for _ in 0..2 {
+            assert_eq!(dedup.try_send(ArbOpportunity::through_pools(&[p1, p2], &[x, y], 100, 10)), Ok(Dispatch::Sent));
+        }
+        assert_eq!(std::iter::from_fn(|| receiver.try_recv().ok()).count(), 2);
+    }
+}
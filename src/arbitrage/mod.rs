@@ -1,11 +1,16 @@
 pub mod calc_arb;
 pub mod config;
+pub mod cycle_index;
+pub mod cycles;
+pub mod dedup;
 pub mod path_evaluator;
 pub mod path_statistics;
 pub mod simulate;
+pub mod sizing;
 pub mod streams;
 pub mod strategies;
 pub mod types;
+pub mod valuation;
 
 // Re-export main types for convenience
 pub use calc_arb::ArbitragePool;
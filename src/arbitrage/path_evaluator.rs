@@ -1,24 +1,79 @@
 //! src/arbitrage/path_evaluator.rs
 
-use crate::arbitrage::types::{ArbOpportunity, SwapPathSelected};
+use crate::arbitrage::config::{ArbitrageConfig, PositionSizer};
+use crate::arbitrage::cycles::{Cycle, GraphEdge};
+use crate::arbitrage::sizing::{CycleDepth, SizingPrices, TradeSizer};
+use crate::arbitrage::types::{ArbOpportunity, OpportunitySource, SwapPath, SwapPathSelected};
+use crate::arbitrage::valuation::ValuationService;
+use crate::execution::wallet::WalletBalances;
+use crate::markets::account_decoders::AccountUpdateRouter;
+use crate::markets::pools::{Pool, PoolRegistry};
 use anyhow::Result;
+use solana_sdk::pubkey::Pubkey;
+use std::str::FromStr;
+use std::sync::Arc;
+use tracing::debug;
 
 #[derive(Clone)]
 pub struct SmartPathEvaluator {
-    slippage_factor: f64,
     token_pair_success_rates: std::collections::HashMap<String, f64>,
     dex_success_rates: std::collections::HashMap<crate::markets::types::DexLabel, f64>,
+    /// Values profits in lamports; paths aren't evaluated without it
+    valuation: Option<Arc<ValuationService>>,
+    /// Pools routes are quoted on to plan their legs; paths aren't evaluated without it
+    pool_registry: Option<Arc<PoolRegistry>>,
+    /// Quote engines whose depth curves size plans; paths aren't evaluated without it
+    markets: Option<Arc<AccountUpdateRouter>>,
+    /// Payer balances capping plan sizes; paths aren't evaluated without it
+    wallet: Option<Arc<WalletBalances>>,
+    trade_sizer: TradeSizer,
+    max_slippage_bps: u16,
 }
 
 impl SmartPathEvaluator {
     pub fn new() -> Self {
         Self {
-            slippage_factor: 0.005, // 0.5% default slippage
             token_pair_success_rates: std::collections::HashMap::new(),
             dex_success_rates: std::collections::HashMap::new(),
+            valuation: None,
+            pool_registry: None,
+            markets: None,
+            wallet: None,
+            trade_sizer: TradeSizer::new(&ArbitrageConfig::default(), PositionSizer::default()),
+            max_slippage_bps: 100,
         }
     }
 
+    pub fn with_valuation(mut self, valuation: Arc<ValuationService>) -> Self {
+        self.valuation = Some(valuation);
+        self
+    }
+
+    /// Plan legs over `pool_registry`'s pools, accepting `max_slippage_bps` per leg
+    pub fn with_pools(mut self, pool_registry: Arc<PoolRegistry>, max_slippage_bps: u16) -> Self {
+        self.pool_registry = Some(pool_registry);
+        self.max_slippage_bps = max_slippage_bps;
+        self
+    }
+
+    /// Size plans with `trade_sizer`
+    pub fn with_trade_sizer(mut self, trade_sizer: TradeSizer) -> Self {
+        self.trade_sizer = trade_sizer;
+        self
+    }
+
+    /// Spend at most what `wallet` holds of a path's start token
+    pub fn with_wallet(mut self, wallet: Arc<WalletBalances>) -> Self {
+        self.wallet = Some(wallet);
+        self
+    }
+
+    /// Size plans on the depth curves of the engines `markets` keeps current
+    pub fn with_markets(mut self, markets: Arc<AccountUpdateRouter>) -> Self {
+        self.markets = Some(markets);
+        self
+    }
+
     /// Updates the success rate for a token pair
     pub fn update_token_pair_success(&mut self, token_a: String, token_b: String, success_rate: f64) {
         let pair_key = if token_a < token_b {
@@ -34,95 +89,192 @@ impl SmartPathEvaluator {
         self.dex_success_rates.insert(dex_label, success_rate);
     }
 
-    /// Evaluates a potential arbitrage path for profitability and safety.
-    pub fn evaluate(&self, path_selected: &SwapPathSelected) -> Result<Option<ArbOpportunity>> {
-        // Use expected_profit_usd instead of result
-        let base_profit_usd = path_selected.expected_profit_usd;
-        if base_profit_usd <= 0.0 {
+    /// Evaluates a potential arbitrage path for profitability and safety. The path is
+    /// sized for the wallet's balance of its start token and quoted on the venues'
+    /// engines; profit and metadata come from that sized trade.
+    pub async fn evaluate(&self, path_selected: &SwapPathSelected) -> Result<Option<ArbOpportunity>> {
+        if path_selected.expected_profit_usd <= 0.0 || path_selected.path.paths.is_empty() {
             return Ok(None);
         }
-
-        // Apply a slippage model to get a more realistic profit estimate
-        let adjusted_profit_usd = base_profit_usd * (1.0 - self.slippage_factor);
-
-        // No empty path check
-        if path_selected.path.paths.is_empty() {
-             return Ok(None); // Cannot validate an empty path
-        }
-
-        // Convert the final USD profit to lamports for the priority queue
-        // (Assuming 1B lamports per dollar for this calculation, should be refined with SOL price)
-        let profit_lamports = (adjusted_profit_usd * 1_000_000_000.0) as u64;
-
-        // Placeholder for execution_plan and metadata
-        // In a real scenario, these would be derived from path_selected.path and other factors
-        let execution_plan = vec![]; // TODO: Populate this based on path_selected.path.paths
-        let now_nanos = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap_or_default()
-            .as_nanos();
-
-        let metadata = crate::arbitrage::types::OpportunityMetadata {
-            estimated_gas_cost: 5000, // Placeholder
-            net_profit_lamports: profit_lamports.saturating_sub(5000) as i64,
-            profit_percentage_bps: if path_selected.expected_profit_usd > 0.0 { // Avoid division by zero if initial amount is tied to profit
-                (profit_lamports as f64 / (path_selected.expected_profit_usd * 1_000_000_000.0) * 10000.0) as u16
-            } else {
-                0
-            },
-            risk_score: 10, // Placeholder
-            source: crate::arbitrage::types::OpportunitySource::StrategyScan { strategy_name: "SmartPathEvaluator".to_string() },
-            max_latency_ms: 500, // Placeholder
+        let (Some(valuation), Some(markets), Some(wallet)) = (&self.valuation, &self.markets, &self.wallet) else {
+            return Ok(None);
         };
 
-        Ok(Some(ArbOpportunity {
+        // The executor rejects empty plans, so a path that can't be quoted isn't worth sending
+        let Some(cycle) = self.cycle_for(&path_selected.path) else {
+            debug!("Path {:?} isn't a cycle over registered pools", path_selected.path.id_paths);
+            return Ok(None);
+        };
+        let Some(wallet_balance) = wallet.balance(&cycle.start) else {
+            debug!("No wallet balance of {} read yet", cycle.start);
+            return Ok(None);
+        };
+        let source = OpportunitySource::StrategyScan { strategy_name: "SmartPathEvaluator".to_string() };
+        let opportunity =
+            cycle_opportunity(&cycle, &self.trade_sizer, valuation, markets, wallet_balance, self.max_slippage_bps, source)
+                .await;
+        Ok(opportunity.map(|opportunity| ArbOpportunity {
             path: path_selected.path.clone(),
-            expected_profit_lamports: profit_lamports,
-            timestamp_unix_nanos: now_nanos,
-            execution_plan,
-            metadata,
+            ..opportunity
         }))
     }
     
-    /// Evaluate arbitrage potential based on historical success rates and market conditions
-    pub fn evaluate_arbitrage_potential(&self, pair_key: &str, price: f64, source: &str) -> f64 {
-        // Base score starts at 0.5 (neutral)
-        let mut score = 0.5;
-        
-        // Adjust score based on historical token pair success rate
-        if let Some(&success_rate) = self.token_pair_success_rates.get(pair_key) {
-            score += (success_rate - 0.5) * 0.3; // +/- 0.15 based on historical performance
+    /// The cycle `path` walks over the registered pools' current states. `None` when
+    /// the path isn't a cycle over registered pools.
+    pub fn cycle_for(&self, path: &SwapPath) -> Option<Cycle> {
+        let pool_registry = self.pool_registry.as_ref()?;
+        cycle_for(path, |address| pool_registry.pool_at(address))
+    }
+}
+
+/// Size `cycle` on the depth curves of the engines `markets` holds, with
+/// `wallet_balance` raw units of the start token available, then quote its legs on the
+/// engines themselves. `None` when it can't be priced or no size clears gas and tip.
+pub async fn cycle_opportunity(
+    cycle: &Cycle,
+    trade_sizer: &TradeSizer,
+    valuation: &ValuationService,
+    markets: &AccountUpdateRouter,
+    wallet_balance: u64,
+    max_slippage_bps: u16,
+    source: OpportunitySource,
+) -> Option<ArbOpportunity> {
+    let prices = SizingPrices::from_valuation(valuation, &cycle.start)?;
+    let depth = CycleDepth::from_router(cycle, markets)?;
+    let fees = markets.transfer_fees(cycle.legs.iter().map(|leg| &leg.token_in));
+    let sized = match trade_sizer.solve(cycle, &depth, &fees, wallet_balance, &prices) {
+        Ok(trade) => trade_sizer.requote(cycle, &trade, markets, &fees, &prices).await,
+        Err(e) => Err(e),
+    };
+    let trade = match sized {
+        Ok(trade) => trade,
+        Err(e) => {
+            debug!("Cycle {:?} not traded: {}", cycle.edge_ids, e);
+            return None;
         }
-        
-        // Adjust score based on DEX source reliability
-        if let Ok(dex_label) = source.parse::<crate::markets::types::DexLabel>() {
-            if let Some(&dex_success_rate) = self.dex_success_rates.get(&dex_label) {
-                score += (dex_success_rate - 0.5) * 0.2; // +/- 0.10 based on DEX performance
-            }
+    };
+
+    let gross_profit = trade.amount_out as i64 - trade.amount_in as i64;
+    let gross = valuation.value_amount(&cycle.start, gross_profit)?;
+    let estimated_gas_cost = trade_sizer.cost_lamports();
+    let net_profit_lamports = gross.lamports - estimated_gas_cost as i64;
+    let net_usd = valuation.value_amount(&cycle.start, trade.net_profit)?;
+    let now_nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+
+    Some(ArbOpportunity {
+        path: cycle.to_swap_path(),
+        expected_profit_lamports: gross.lamports.max(0) as u64,
+        timestamp_unix_nanos: now_nanos,
+        // The legs were quoted on engines the account stream keeps current, so a pool
+        // that hasn't been written to since still holds as of the stream's latest slot
+        slot: cycle.slot().map(|slot| slot.max(markets.cache().latest_slot())),
+        generation: 0,
+        execution_plan: trade.execution_plan(cycle, &fees, max_slippage_bps),
+        metadata: crate::arbitrage::types::OpportunityMetadata {
+            estimated_gas_cost,
+            net_profit_lamports,
+            expected_profit_usd_cents: net_usd.usd_cents(),
+            valuation_confidence_bps: net_usd.confidence_bps(),
+            profit_percentage_bps: (trade.net_profit as u128 * 10_000 / trade.amount_in as u128).min(u16::MAX as u128) as u16,
+            risk_score: 10 * cycle.hops() as u8, // Per venue, all quoted from the same snapshot
+            source,
+            max_latency_ms: 400,
+        },
+    })
+}
+
+/// The cycle `path` walks, over the pools `pool_at` finds at its route addresses.
+/// `None` unless every route parses, has a pool, and the last leg returns to the first
+/// leg's input token.
+fn cycle_for(path: &SwapPath, pool_at: impl Fn(&Pubkey) -> Option<Pool>) -> Option<Cycle> {
+    let legs = path
+        .paths
+        .iter()
+        .map(|route| {
+            let pool = pool_at(&Pubkey::from_str(&route.pool_address).ok()?)?;
+            GraphEdge::new(Arc::new(pool), Pubkey::from_str(&route.token_in).ok()?)
+        })
+        .collect::<Option<Vec<_>>>()?;
+    let start = legs.first()?.token_in;
+    let chained = legs.windows(2).all(|pair| pair[0].token_out == pair[1].token_in);
+    if !chained || legs.last()?.token_out != start {
+        return None;
+    }
+    Some(Cycle {
+        start,
+        edge_ids: path.id_paths.clone(),
+        legs,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arbitrage::types::{PoolExecutionData, Route, SwapLeg};
+    use crate::markets::token_2022::TransferFees;
+    use crate::markets::types::DexLabel;
+    use std::collections::HashMap;
+
+    fn route(id: u32, pool: &Pool, token_in: Pubkey, token_out: Pubkey) -> Route {
+        Route {
+            id,
+            dex: DexLabel::Raydium,
+            pool_address: pool.address.unwrap().to_string(),
+            token_in: token_in.to_string(),
+            token_out: token_out.to_string(),
+            token_0to1: token_in == pool.token_a.mint,
         }
-        
-        // Adjust score based on price volatility (higher volatility = better arbitrage potential)
-        let price_volatility_bonus = if price > 0.0 {
-            // Calculate volatility based on price deviation from typical ranges
-            let volatility = match pair_key {
-                s if s.contains("SOL") => {
-                    let sol_base = 200.0;
-                    ((price - sol_base).abs() / sol_base).min(0.1) // Cap at 10% deviation
-                },
-                s if s.contains("USDC") => {
-                    let usdc_base = 1.0;
-                    ((price - usdc_base).abs() / usdc_base).min(0.05) // Cap at 5% deviation
-                },
-                _ => 0.01 // Default small bonus for other pairs
-            };
-            volatility * 2.0 // Convert to bonus (max +0.2 for SOL, +0.1 for USDC)
-        } else {
-            0.0
+    }
+
+    #[test]
+    fn test_plans_a_leg_per_route() {
+        // X/Y priced 2.0 in one pool and 2.1 in the other
+        let (x, y) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let (cheap, dear) = (
+            Pool::with_reserves(x, y, 1_000_000_000_000, 2_000_000_000_000),
+            Pool::with_reserves(x, y, 1_000_000_000_000, 2_100_000_000_000),
+        );
+        let pools: HashMap<Pubkey, Pool> = [&cheap, &dear].map(|pool| (pool.address.unwrap(), pool.clone())).into();
+        let pool_at = |address: &Pubkey| pools.get(address).cloned();
+        let path = SwapPath {
+            hops: 2,
+            paths: vec![route(3, &dear, x, y), route(0, &cheap, y, x)],
+            id_paths: vec![3, 0],
+        };
+
+        let cycle = cycle_for(&path, pool_at).unwrap();
+        assert_eq!((cycle.start, cycle.hops(), cycle.edge_ids.clone()), (x, 2, vec![3, 0]));
+        let prices = SizingPrices { start_usd: 1.0, sol_usd: 100.0 };
+        let trade = SmartPathEvaluator::new()
+            .trade_sizer
+            .solve(&cycle, &CycleDepth::from_reserves(&cycle), &TransferFees::default(), 100_000_000, &prices)
+            .unwrap();
+        let plan = trade.execution_plan(&cycle, &TransferFees::default(), 100);
+
+        assert_eq!(plan.len(), 2);
+        assert_eq!((plan[0].pool_address, plan[0].token_in, plan[0].token_out), (dear.address.unwrap(), x, y));
+        assert_eq!((plan[1].pool_address, plan[1].token_in, plan[1].token_out), (cheap.address.unwrap(), y, x));
+        assert_eq!(plan[1].amount_in, plan[0].expected_amount_out);
+        assert!(plan[1].expected_amount_out > plan[0].amount_in);
+        for leg in &plan {
+            assert_eq!(leg.minimum_amount_out, SwapLeg::minimum_out(leg.expected_amount_out, 100));
+            assert!(matches!(leg.pool_data, PoolExecutionData::Raydium { .. }));
+        }
+        // The wallet's $100 of X is the most the plan spends
+        assert!(plan[0].amount_in <= 100_000_000);
+        assert!(trade.net_profit > 0);
+
+        // A path that doesn't return to its start token, or crosses an unknown pool
+        let open = SwapPath { hops: 1, paths: vec![route(3, &dear, x, y)], id_paths: vec![3] };
+        assert!(cycle_for(&open, pool_at).is_none());
+        let unknown = SwapPath {
+            hops: 2,
+            paths: vec![route(3, &dear, x, y), route(0, &Pool::with_reserves(x, y, 1, 1), y, x)],
+            id_paths: vec![3, 0],
         };
-        
-        score += price_volatility_bonus;
-        
-        // Ensure score stays within [0.0, 1.0] bounds
-        score.max(0.0).min(1.0)
+        assert!(cycle_for(&unknown, pool_at).is_none());
     }
 }
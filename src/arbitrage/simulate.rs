@@ -4,14 +4,47 @@ use log::error;
 use log::info;
 use rust_socketio::asynchronous::Client;
 
-use super::types::{SwapPath, SwapRouteSimulation, TokenInfos};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::pubkey::Pubkey;
+
+use super::types::{Route, SwapPath, SwapRouteSimulation, TokenInfos};
+use crate::common::constants::Env;
 use crate::markets::meteora::simulate_route_meteora;
 use crate::markets::{
+    clob::simulate_route_clob,
+    errors::MarketSimulationError,
+    orca::simulate_route_orca,
     orca_whirpools::simulate_route_orca_whirpools,
     raydium::simulate_route_raydium,
+    raydium_cpmm::simulate_route_raydium_cpmm,
+    token_2022::TransferFees,
     types::{DexLabel, Market},
 };
 
+/// Net the Token-2022 transfer fees of the route's mints out of a simulation quoted on
+/// gross amounts. Raydium CPMM already quotes net of transfer fees and skips this.
+async fn with_transfer_fees(
+    simulation: Result<(u64, u64), MarketSimulationError>,
+    route: &Route,
+    amount_in: u64,
+) -> Result<(u64, u64), MarketSimulationError> {
+    let (amount_out, min_amount_out) = simulation?;
+    let parse_mint = |mint: &str| {
+        mint.parse::<Pubkey>().map_err(|e| MarketSimulationError::MintDecodeError {
+            mint: mint.to_string(),
+            details: e.to_string(),
+        })
+    };
+    let (mint_in, mint_out) = (parse_mint(&route.token_in)?, parse_mint(&route.token_out)?);
+
+    let rpc_client = RpcClient::new(Env::new().rpc_url);
+    let fees = TransferFees::fetch(&rpc_client, &[mint_in, mint_out]).await?;
+    Ok((
+        fees.net_out(&mint_in, &mint_out, amount_in, amount_out),
+        fees.net_out(&mint_in, &mint_out, amount_in, min_amount_out),
+    ))
+}
+
 pub async fn simulate_path(
     simulation_amount: u64,
     path: SwapPath,
@@ -37,63 +70,85 @@ pub async fn simulate_path(
             .find(|&market| market.id == route.pool_address)
             .cloned();
 
-        match path.hops {
-            1 => {
-                if i == 0 && route_simulation.contains_key(&vec![path.id_paths[i]]) {
-                    let swap_sim = route_simulation.get(&vec![path.id_paths[i]]).unwrap();
-                    amount_in = swap_sim[0]
-                        .estimated_amount_out
-                        .as_str()
-                        .parse()
-                        .expect("Bad conversion String to f64");
-                    println!("📌 NO SIMULATION Route Id: {}", swap_sim[0].id_route);
-                    swap_simulation_result.push(swap_sim[0].clone());
-                    continue;
-                }
-            }
-            2 => {
-                if i == 0 && route_simulation.contains_key(&vec![path.id_paths[i]]) {
-                    let swap_sim = route_simulation.get(&vec![path.id_paths[i]]).unwrap();
-                    amount_in = swap_sim[0]
-                        .estimated_amount_out
-                        .as_str()
-                        .parse()
-                        .expect("Bad conversion String to f64");
-                    println!("📌 NO SIMULATION Route 1 Id: {}", swap_sim[0].id_route);
-                    swap_simulation_result.push(swap_sim[0].clone());
-                    continue;
-                }
-                if i == 1 && route_simulation.contains_key(&vec![path.id_paths[i - 1], path.id_paths[i]]) {
-                    let swap_sim = route_simulation
-                        .get(&vec![path.id_paths[i - 1], path.id_paths[i]])
-                        .unwrap();
-                    amount_in = swap_sim[1]
-                        .estimated_amount_out
-                        .as_str()
-                        .parse()
-                        .expect("Bad conversion String to f64");
-                    println!("📌 NO SIMULATION Route 2 Id: {}", swap_sim[1].id_route);
-                    swap_simulation_result.push(swap_sim[1].clone());
-                    continue;
-                }
-            }
-            _ => {
-                println!("⛔ Invalid number of hops")
-            } //...
+        // Paths sharing a prefix with an earlier one reuse its simulated legs
+        if let Some(swap_sim) = route_simulation.get(&path.id_paths[..=i]) {
+            amount_in = swap_sim[i]
+                .estimated_amount_out
+                .as_str()
+                .parse()
+                .expect("Bad conversion String to f64");
+            println!("📌 NO SIMULATION Route {} Id: {}", i + 1, swap_sim[i].id_route);
+            swap_simulation_result.push(swap_sim[i].clone());
+            continue;
         }
         match route.dex {
             DexLabel::Orca => {
-                println!(" ⚠️⚠️ ONE ORCA POOL ");
+                println!("🏊 ORCA - POOL");
+                println!("Address: {:?}", route.pool_address);
+                match with_transfer_fees(
+                    simulate_route_orca(
+                        true,
+                        amount_in,
+                        route.clone(),
+                        market.unwrap(),
+                        tokens_infos.clone(),
+                    )
+                    .await,
+                    route,
+                    amount_in,
+                )
+                .await
+                {
+                    Ok(value) => {
+                        let (amount_out_u64, min_amount_out_u64) = value;
+
+                        let swap_sim: SwapRouteSimulation = SwapRouteSimulation {
+                            id_route: route.id,
+                            pool_address: route.pool_address.clone(),
+                            dex_label: DexLabel::Orca,
+                            token_0to1: route.token_0to1,
+                            token_in: route.token_in.clone(),
+                            token_out: route.token_out.clone(),
+                            amount_in,
+                            estimated_amount_out: amount_out_u64.to_string(),
+                            minimum_amount_out: min_amount_out_u64,
+                        };
+
+                        cache_path_prefix(
+                            &mut route_simulation,
+                            &path.id_paths[..=i],
+                            &swap_simulation_result,
+                            &swap_sim,
+                        );
+
+                        swap_simulation_result.push(swap_sim.clone());
+                        amount_in = amount_out_u64;
+                    }
+                    Err(e) => {
+                        error!(
+                            "❌ SIMULATION ERROR for route: {:?}, ORCA POOL, Address: {:?}, ERROR: {:?}",
+                            path.id_paths, route.pool_address, e
+                        );
+                        println!("🔚 Skipped Path due to Orca simulation error");
+                        let empty_result: Vec<SwapRouteSimulation> = Vec::new();
+                        return (route_simulation, empty_result, 0.0);
+                    }
+                }
             }
             DexLabel::OrcaWhirlpools => {
                 println!("🏊 ORCA_WHIRLPOOLS - POOL");
                 println!("Address: {:?}", route.pool_address);
-                match simulate_route_orca_whirpools(
-                    true,
+                match with_transfer_fees(
+                    simulate_route_orca_whirpools(
+                        true,
+                        amount_in,
+                        route.clone(),
+                        market.unwrap(),
+                        tokens_infos.clone(),
+                    )
+                    .await,
+                    route,
                     amount_in,
-                    route.clone(),
-                    market.unwrap(),
-                    tokens_infos.clone(),
                 )
                 .await
                 {
@@ -113,24 +168,12 @@ pub async fn simulate_path(
                             minimum_amount_out: min_amount_out_u64, // Already u64
                         };
 
-                        //1rst route
-                        if i == 0 && !route_simulation.contains_key(&vec![path.id_paths[i]]) {
-                            route_simulation.insert(vec![route.id], vec![swap_sim.clone()]);
-                        }
-
-                        //2nd route
-                        if i == 1
-                            && path.hops == 2
-                            && !route_simulation
-                                .contains_key(&vec![path.id_paths[i - 1], path.id_paths[i]])
-                        {
-                            let swap_sim_prev_route =
-                                route_simulation.get(&vec![path.id_paths[i - 1]]).unwrap();
-                            route_simulation.insert(
-                                vec![path.id_paths[i - 1], path.id_paths[i]],
-                                vec![swap_sim_prev_route[0].clone(), swap_sim.clone()],
-                            );
-                        }
+                        cache_path_prefix(
+                            &mut route_simulation,
+                            &path.id_paths[..=i],
+                            &swap_simulation_result,
+                            &swap_sim,
+                        );
 
                         swap_simulation_result.push(swap_sim.clone());
                         amount_in = amount_out_u64; // amount_in is u64, amount_out_u64 is u64
@@ -149,12 +192,17 @@ pub async fn simulate_path(
             DexLabel::Raydium => {
                 println!("🏊 RAYDIUM - POOL");
                 println!("Address: {:?}", route.pool_address);
-                match simulate_route_raydium(
-                    true,
+                match with_transfer_fees(
+                    simulate_route_raydium(
+                        true,
+                        amount_in,
+                        route.clone(),
+                        market.unwrap(),
+                        tokens_infos.clone(),
+                    )
+                    .await,
+                    route,
                     amount_in,
-                    route.clone(),
-                    market.unwrap(),
-                    tokens_infos.clone(),
                 )
                 .await
                 {
@@ -174,23 +222,12 @@ pub async fn simulate_path(
                             minimum_amount_out: min_amount_out_u64, // Already u64
                         };
 
-                        //1rst route
-                        if i == 0 && !route_simulation.contains_key(&vec![path.id_paths[i]]) {
-                            route_simulation.insert(vec![route.id], vec![swap_sim.clone()]);
-                        }
-                        //2nd route
-                        if i == 1
-                            && path.hops == 2
-                            && !route_simulation
-                                .contains_key(&vec![path.id_paths[i - 1], path.id_paths[i]])
-                        {
-                            let swap_sim_prev_route =
-                                route_simulation.get(&vec![path.id_paths[i - 1]]).unwrap();
-                            route_simulation.insert(
-                                vec![path.id_paths[i - 1], path.id_paths[i]],
-                                vec![swap_sim_prev_route[0].clone(), swap_sim.clone()],
-                            );
-                        }
+                        cache_path_prefix(
+                            &mut route_simulation,
+                            &path.id_paths[..=i],
+                            &swap_simulation_result,
+                            &swap_sim,
+                        );
 
                         swap_simulation_result.push(swap_sim.clone());
                         amount_in = amount_out_u64; // amount_in is u64, amount_out_u64 is u64
@@ -209,11 +246,10 @@ pub async fn simulate_path(
             DexLabel::RaydiumClmm => {
                 println!(" ⚠️⚠️ ONE RAYDIUM_CLMM POOL ");
             }
-            DexLabel::Meteora => {
-                // println!(" ⚠️⚠️ ONE METEORA POOL ");
-                println!("🏊 METEORA - POOL");
+            DexLabel::RaydiumCpmm => {
+                println!("🏊 RAYDIUM_CPMM - POOL");
                 println!("Address: {:?}", route.pool_address);
-                match simulate_route_meteora(
+                match simulate_route_raydium_cpmm(
                     true,
                     amount_in,
                     route.clone(),
@@ -221,6 +257,113 @@ pub async fn simulate_path(
                     tokens_infos.clone(),
                 )
                 .await
+                {
+                    Ok(value) => {
+                        let (amount_out_u64, min_amount_out_u64) = value;
+
+                        let swap_sim: SwapRouteSimulation = SwapRouteSimulation {
+                            id_route: route.id,
+                            pool_address: route.pool_address.clone(),
+                            dex_label: DexLabel::RaydiumCpmm,
+                            token_0to1: route.token_0to1,
+                            token_in: route.token_in.clone(),
+                            token_out: route.token_out.clone(),
+                            amount_in,
+                            estimated_amount_out: amount_out_u64.to_string(),
+                            minimum_amount_out: min_amount_out_u64,
+                        };
+
+                        cache_path_prefix(
+                            &mut route_simulation,
+                            &path.id_paths[..=i],
+                            &swap_simulation_result,
+                            &swap_sim,
+                        );
+
+                        swap_simulation_result.push(swap_sim.clone());
+                        amount_in = amount_out_u64;
+                    }
+                    Err(e) => {
+                        error!(
+                            "❌ SIMULATION ERROR for route: {:?}, RAYDIUM_CPMM POOL, Address: {:?}, ERROR: {:?}",
+                            path.id_paths, route.pool_address, e
+                        );
+                        println!("🔚 Skipped Path due to Raydium CPMM simulation error");
+                        let empty_result: Vec<SwapRouteSimulation> = Vec::new();
+                        return (route_simulation, empty_result, 0.0);
+                    }
+                }
+            }
+            DexLabel::OpenBookV2 | DexLabel::Phoenix => {
+                println!("📖 {} - ORDER BOOK", route.dex.str());
+                println!("Address: {:?}", route.pool_address);
+                match with_transfer_fees(
+                    simulate_route_clob(
+                        true,
+                        amount_in,
+                        route.clone(),
+                        market.unwrap(),
+                        tokens_infos.clone(),
+                    )
+                    .await,
+                    route,
+                    amount_in,
+                )
+                .await
+                {
+                    Ok(value) => {
+                        let (amount_out_u64, min_amount_out_u64) = value;
+
+                        let swap_sim: SwapRouteSimulation = SwapRouteSimulation {
+                            id_route: route.id,
+                            pool_address: route.pool_address.clone(),
+                            dex_label: route.dex.clone(),
+                            token_0to1: route.token_0to1,
+                            token_in: route.token_in.clone(),
+                            token_out: route.token_out.clone(),
+                            amount_in,
+                            estimated_amount_out: amount_out_u64.to_string(),
+                            minimum_amount_out: min_amount_out_u64,
+                        };
+
+                        cache_path_prefix(
+                            &mut route_simulation,
+                            &path.id_paths[..=i],
+                            &swap_simulation_result,
+                            &swap_sim,
+                        );
+
+                        swap_simulation_result.push(swap_sim.clone());
+                        amount_in = amount_out_u64;
+                    }
+                    Err(e) => {
+                        error!(
+                            "❌ SIMULATION ERROR for route: {:?}, ORDER BOOK, Address: {:?}, ERROR: {:?}",
+                            path.id_paths, route.pool_address, e
+                        );
+                        println!("🔚 Skipped Path due to order book simulation error");
+                        let empty_result: Vec<SwapRouteSimulation> = Vec::new();
+                        return (route_simulation, empty_result, 0.0);
+                    }
+                }
+            }
+            DexLabel::Meteora => {
+                // println!(" ⚠️⚠️ ONE METEORA POOL ");
+                println!("🏊 METEORA - POOL");
+                println!("Address: {:?}", route.pool_address);
+                match with_transfer_fees(
+                    simulate_route_meteora(
+                        true,
+                        amount_in,
+                        route.clone(),
+                        market.unwrap(),
+                        tokens_infos.clone(),
+                    )
+                    .await,
+                    route,
+                    amount_in,
+                )
+                .await
                 {
                     Ok(value) => {
                         let (amount_out_u64, min_amount_out_u64) = value; // Values are now u64
@@ -238,23 +381,12 @@ pub async fn simulate_path(
                             minimum_amount_out: min_amount_out_u64, // Already u64
                         };
 
-                        //1rst route
-                        if i == 0 && !route_simulation.contains_key(&vec![path.id_paths[i]]) {
-                            route_simulation.insert(vec![route.id], vec![swap_sim.clone()]);
-                        }
-                        //2nd route
-                        if i == 1
-                            && path.hops == 2
-                            && !route_simulation
-                                .contains_key(&vec![path.id_paths[i - 1], path.id_paths[i]])
-                        {
-                            let swap_sim_prev_route =
-                                route_simulation.get(&vec![path.id_paths[i - 1]]).unwrap();
-                            route_simulation.insert(
-                                vec![path.id_paths[i - 1], path.id_paths[i]],
-                                vec![swap_sim_prev_route[0].clone(), swap_sim.clone()],
-                            );
-                        }
+                        cache_path_prefix(
+                            &mut route_simulation,
+                            &path.id_paths[..=i],
+                            &swap_simulation_result,
+                            &swap_sim,
+                        );
 
                         swap_simulation_result.push(swap_sim.clone());
                         amount_in = amount_out_u64; // amount_in is u64, amount_out_u64 is u64
@@ -294,6 +426,18 @@ pub async fn simulate_path(
     return (route_simulation, swap_simulation_result, difference);
 }
 
+/// Remember the legs simulated so far under the route ids they cover
+fn cache_path_prefix(
+    route_simulation: &mut HashMap<Vec<u32>, Vec<SwapRouteSimulation>>,
+    prefix: &[u32],
+    previous_legs: &[SwapRouteSimulation],
+    swap_sim: &SwapRouteSimulation,
+) {
+    route_simulation
+        .entry(prefix.to_vec())
+        .or_insert_with(|| previous_legs.iter().cloned().chain([swap_sim.clone()]).collect());
+}
+
 pub async fn simulate_path_precision(
     amount_input: u64,
     _socket: Client,
@@ -317,13 +461,11 @@ pub async fn simulate_path_precision(
             .cloned();
 
         match route.dex {
-            DexLabel::Orca | DexLabel::RaydiumClmm => { // Merged Orca and RaydiumClmm
-                // println!(" ⚠️⚠️ ONE ORCA POOL / RAYDIUM_CLMM POOL ");
+            DexLabel::RaydiumClmm => {
+                // println!(" ⚠️⚠️ ONE RAYDIUM_CLMM POOL ");
             }
-            DexLabel::OrcaWhirlpools => {
-                // println!("ORCA_WHIRLPOOLS - POOL");
-                // println!("Address: {:?}", route.pool_address);
-                match simulate_route_orca_whirpools(
+            DexLabel::RaydiumCpmm => {
+                match simulate_route_raydium_cpmm(
                     false,
                     amount_in,
                     route.clone(),
@@ -331,6 +473,137 @@ pub async fn simulate_path_precision(
                     tokens_infos.clone(),
                 )
                 .await
+                {
+                    Ok(value) => {
+                        let (amount_out_u64, min_amount_out_u64) = value;
+
+                        let swap_sim: SwapRouteSimulation = SwapRouteSimulation {
+                            id_route: route.id,
+                            pool_address: route.pool_address.clone(),
+                            dex_label: DexLabel::RaydiumCpmm,
+                            token_0to1: route.token_0to1,
+                            token_in: route.token_in.clone(),
+                            token_out: route.token_out.clone(),
+                            amount_in,
+                            estimated_amount_out: amount_out_u64.to_string(),
+                            minimum_amount_out: min_amount_out_u64,
+                        };
+
+                        swap_simulation_result.push(swap_sim.clone());
+                        amount_in = amount_out_u64;
+                    }
+                    Err(e) => {
+                        error!(
+                            "❌ PRECISION SIMULATION ERROR for route: {:?}, RAYDIUM_CPMM POOL, Address: {:?}, ERROR: {:?}",
+                            path.id_paths, route.pool_address, e
+                        );
+                        let empty_result: Vec<SwapRouteSimulation> = Vec::new();
+                        return (empty_result, 0.0);
+                    }
+                }
+            }
+            DexLabel::OpenBookV2 | DexLabel::Phoenix => {
+                match with_transfer_fees(
+                    simulate_route_clob(
+                        false,
+                        amount_in,
+                        route.clone(),
+                        market.unwrap(),
+                        tokens_infos.clone(),
+                    )
+                    .await,
+                    route,
+                    amount_in,
+                )
+                .await
+                {
+                    Ok(value) => {
+                        let (amount_out_u64, min_amount_out_u64) = value;
+
+                        let swap_sim: SwapRouteSimulation = SwapRouteSimulation {
+                            id_route: route.id,
+                            pool_address: route.pool_address.clone(),
+                            dex_label: route.dex.clone(),
+                            token_0to1: route.token_0to1,
+                            token_in: route.token_in.clone(),
+                            token_out: route.token_out.clone(),
+                            amount_in,
+                            estimated_amount_out: amount_out_u64.to_string(),
+                            minimum_amount_out: min_amount_out_u64,
+                        };
+
+                        swap_simulation_result.push(swap_sim.clone());
+                        amount_in = amount_out_u64;
+                    }
+                    Err(e) => {
+                        error!(
+                            "❌ PRECISION SIMULATION ERROR for route: {:?}, ORDER BOOK, Address: {:?}, ERROR: {:?}",
+                            path.id_paths, route.pool_address, e
+                        );
+                        let empty_result: Vec<SwapRouteSimulation> = Vec::new();
+                        return (empty_result, 0.0);
+                    }
+                }
+            }
+            DexLabel::Orca => {
+                match with_transfer_fees(
+                    simulate_route_orca(
+                        false,
+                        amount_in,
+                        route.clone(),
+                        market.unwrap(),
+                        tokens_infos.clone(),
+                    )
+                    .await,
+                    route,
+                    amount_in,
+                )
+                .await
+                {
+                    Ok(value) => {
+                        let (amount_out_u64, min_amount_out_u64) = value;
+
+                        let swap_sim: SwapRouteSimulation = SwapRouteSimulation {
+                            id_route: route.id,
+                            pool_address: route.pool_address.clone(),
+                            dex_label: DexLabel::Orca,
+                            token_0to1: route.token_0to1,
+                            token_in: route.token_in.clone(),
+                            token_out: route.token_out.clone(),
+                            amount_in,
+                            estimated_amount_out: amount_out_u64.to_string(),
+                            minimum_amount_out: min_amount_out_u64,
+                        };
+
+                        swap_simulation_result.push(swap_sim.clone());
+                        amount_in = amount_out_u64;
+                    }
+                    Err(e) => {
+                        error!(
+                            "❌ PRECISION SIMULATION ERROR for route: {:?}, ORCA POOL, Address: {:?}, ERROR: {:?}",
+                            path.id_paths, route.pool_address, e
+                        );
+                        let empty_result: Vec<SwapRouteSimulation> = Vec::new();
+                        return (empty_result, 0.0);
+                    }
+                }
+            }
+            DexLabel::OrcaWhirlpools => {
+                // println!("ORCA_WHIRLPOOLS - POOL");
+                // println!("Address: {:?}", route.pool_address);
+                match with_transfer_fees(
+                    simulate_route_orca_whirpools(
+                        false,
+                        amount_in,
+                        route.clone(),
+                        market.unwrap(),
+                        tokens_infos.clone(),
+                    )
+                    .await,
+                    route,
+                    amount_in,
+                )
+                .await
                 {
                     Ok(value) => {
                         let (amount_out_u64, min_amount_out_u64) = value; // Values are now u64
@@ -364,12 +637,17 @@ pub async fn simulate_path_precision(
             DexLabel::Raydium => {
                 // println!("RAYDIUM - POOL");
                 // println!("Address: {:?}", route.pool_address);
-                match simulate_route_raydium(
-                    false,
+                match with_transfer_fees(
+                    simulate_route_raydium(
+                        false,
+                        amount_in,
+                        route.clone(),
+                        market.unwrap(),
+                        tokens_infos.clone(),
+                    )
+                    .await,
+                    route,
                     amount_in,
-                    route.clone(),
-                    market.unwrap(),
-                    tokens_infos.clone(),
                 )
                 .await
                 {
@@ -403,17 +681,21 @@ pub async fn simulate_path_precision(
                     }
                 }
             }
-            // DexLabel::RaydiumClmm arm removed due to merge with DexLabel::Orca
             DexLabel::Meteora => {
                 // println!(" ⚠️⚠️ ONE METEORA POOL ");
                 // println!("METEORA - POOL");
                 // println!("Address: {:?}", route.pool_address);
-                match simulate_route_meteora(
-                    false,
+                match with_transfer_fees(
+                    simulate_route_meteora(
+                        false,
+                        amount_in,
+                        route.clone(),
+                        market.unwrap(),
+                        tokens_infos.clone(),
+                    )
+                    .await,
+                    route,
                     amount_in,
-                    route.clone(),
-                    market.unwrap(),
-                    tokens_infos.clone(),
                 )
                 .await
                 {
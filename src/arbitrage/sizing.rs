@@ -0,0 +1,518 @@
+//! Trade sizing for detected cycles
+//!
+//! Price impact grows with size and eventually eats the cycle's spread, so the best
+//! trade is where the marginal output of the chained pool quotes drops back to one.
+//! Each leg is quoted on the depth curves sampled from its venue's quote engine, which
+//! are concave in the input and so is their composition. That makes net profit
+//! unimodal in the input and lets a golden-section search find the optimum. The chosen
+//! size can then be re-quoted on the engines themselves for exact leg amounts.
+
+use crate::arbitrage::config::{ArbitrageConfig, ArbitrageError, PositionSizer};
+use crate::arbitrage::cycles::{Cycle, GraphEdge};
+use crate::arbitrage::types::{PoolExecutionData, SwapLeg};
+use crate::arbitrage::valuation::ValuationService;
+use crate::markets::account_decoders::AccountUpdateRouter;
+use crate::markets::depth_curve::DepthCurves;
+use crate::markets::token_2022::TransferFees;
+use crate::markets::types::DexLabel;
+use solana_sdk::native_token::LAMPORTS_PER_SOL;
+use solana_sdk::pubkey::Pubkey;
+use std::sync::Arc;
+
+/// Search stops once the bracket is this share of the cap, or a single raw unit
+const SIZE_TOLERANCE: f64 = 1e-6;
+
+/// Upper bound on golden-section steps; a full u64 range needs about 92
+const MAX_ITERATIONS: usize = 128;
+
+const INVERSE_GOLDEN_RATIO: f64 = 0.618_033_988_749_895;
+
+/// USD prices used to convert costs and position limits into start token units
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SizingPrices {
+    /// USD per whole start token
+    pub start_usd: f64,
+    pub sol_usd: f64,
+}
+
+impl SizingPrices {
+    /// Current prices of `start` and SOL, if both are valued
+    pub fn from_valuation(valuation: &ValuationService, start: &Pubkey) -> Option<Self> {
+        Some(Self {
+            start_usd: valuation.usd_price(start)?.usd,
+            sol_usd: valuation.sol_usd()?.usd,
+        })
+    }
+
+    /// USD per raw unit of a start token with `decimals`
+    fn unit_usd(&self, decimals: u8) -> f64 {
+        self.start_usd / 10f64.powi(decimals as i32)
+    }
+
+    /// `lamports` worth of a start token with `decimals`, in its raw units
+    pub fn lamports_in_start_units(&self, lamports: u64, decimals: u8) -> u64 {
+        let usd = lamports as f64 / LAMPORTS_PER_SOL as f64 * self.sol_usd;
+        (usd / self.unit_usd(decimals)) as u64
+    }
+}
+
+/// Amounts through one pool of a sized cycle, in raw units
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LegAmount {
+    pub pool_address: Pubkey,
+    pub mint_in: Pubkey,
+    pub mint_out: Pubkey,
+    pub amount_in: u64,
+    pub amount_out: u64,
+    /// `amount_out` net of Token-2022 transfer fees: what the wallet receives and the
+    /// next leg spends
+    pub received: u64,
+    pub price_impact_bps: u16,
+}
+
+impl LegAmount {
+    /// `leg` quoted `amount_out` for `amount_in`
+    fn new(leg: &GraphEdge, fees: &TransferFees, amount_in: u64, amount_out: u64) -> Self {
+        let received = if nets_transfer_fees(&leg.dex) {
+            amount_out
+        } else {
+            fees.net_out(&leg.token_in, &leg.token_out, amount_in, amount_out)
+        };
+        Self {
+            pool_address: leg.pool_address,
+            mint_in: leg.token_in,
+            mint_out: leg.token_out,
+            amount_in,
+            amount_out,
+            received,
+            price_impact_bps: leg.price_impact_bps(amount_in, amount_out),
+        }
+    }
+}
+
+/// Whether `dex`'s engine already quotes net of transfer fees, so its legs aren't
+/// netted twice
+fn nets_transfer_fees(dex: &DexLabel) -> bool {
+    matches!(dex, DexLabel::RaydiumCpmm)
+}
+
+/// The most profitable size for a cycle, in raw start token units
+#[derive(Debug, Clone, PartialEq)]
+pub struct SizedTrade {
+    pub amount_in: u64,
+    /// What the last leg leaves in the wallet, net of transfer fees
+    pub amount_out: u64,
+    pub legs: Vec<LegAmount>,
+    /// Gas and tip, converted to start token units
+    pub cost: u64,
+    /// Output less input and cost
+    pub net_profit: i64,
+    pub net_profit_usd: f64,
+}
+
+impl SizedTrade {
+    /// One swap per leg of `cycle`, each accepting up to `max_slippage_bps` less than
+    /// its quote. Outputs are what the wallet receives after `fees`, which is what the
+    /// next leg spends.
+    pub fn execution_plan(&self, cycle: &Cycle, fees: &TransferFees, max_slippage_bps: u16) -> Vec<SwapLeg> {
+        cycle
+            .legs
+            .iter()
+            .zip(&self.legs)
+            .map(|(edge, leg)| {
+                let mut swap = SwapLeg {
+                    dex: edge.dex.clone(),
+                    pool_address: leg.pool_address,
+                    token_in: leg.mint_in,
+                    token_out: leg.mint_out,
+                    amount_in: leg.amount_in,
+                    minimum_amount_out: SwapLeg::minimum_out(leg.amount_out, max_slippage_bps),
+                    expected_amount_out: leg.amount_out,
+                    swap_direction: edge.a_to_b(),
+                    pool_data: PoolExecutionData::for_pool(&edge.pool, leg.price_impact_bps),
+                };
+                if let (false, Some(input), Some(output)) =
+                    (nets_transfer_fees(&edge.dex), fees.mint(&leg.mint_in), fees.mint(&leg.mint_out))
+                {
+                    swap.apply_transfer_fees(input, output, fees.epoch());
+                }
+                swap
+            })
+            .collect()
+    }
+}
+
+/// Depth curves of a cycle's pools, one per leg in leg order
+#[derive(Debug, Clone)]
+pub struct CycleDepth {
+    curves: Vec<Arc<DepthCurves>>,
+}
+
+impl CycleDepth {
+    pub fn new(curves: Vec<Arc<DepthCurves>>) -> Self {
+        Self { curves }
+    }
+
+    /// Curves of `cycle`'s pools as `router` keeps them. `None` when one has no engine
+    /// loaded or can't be quoted.
+    pub fn from_router(cycle: &Cycle, router: &AccountUpdateRouter) -> Option<Self> {
+        let curves = cycle
+            .legs
+            .iter()
+            .map(|leg| router.depth_curves(&leg.pool_address))
+            .collect::<Option<Vec<_>>>()?;
+        Some(Self::new(curves))
+    }
+
+    /// Largest input every leg can quote: each leg's input is capped where its output
+    /// would exceed what the next leg can take
+    fn max_amount_in(&self, cycle: &Cycle) -> u64 {
+        cycle.legs.iter().zip(&self.curves).rev().fold(u64::MAX, |limit, (leg, curves)| {
+            let curve = curves.curve(leg.a_to_b());
+            if curve.max_amount_out() <= limit {
+                curve.max_amount_in()
+            } else {
+                // One unit short of the rounded-up inverse keeps the output at most `limit`
+                curve.amount_in_for(limit).map_or(0, |amount_in| amount_in.saturating_sub(1))
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+impl CycleDepth {
+    /// Curves of Raydium AMM engines holding each leg's `PoolState::Reserves` at the
+    /// pool's fee
+    pub(crate) fn from_reserves(cycle: &Cycle) -> Self {
+        use crate::markets::depth_curve::DepthCurveConfig;
+        use crate::markets::pools::PoolState;
+        use crate::markets::raydium::{AmmInfo, Fees};
+        use crate::markets::raydium_amm::RaydiumAmmMarket;
+
+        let curves = cycle
+            .legs
+            .iter()
+            .map(|leg| {
+                let PoolState::Reserves { reserve_a, reserve_b } = leg.pool.state else {
+                    panic!("pool {} has no reserves", leg.pool.id);
+                };
+                let fees = Fees {
+                    trade_fee_numerator: leg.pool.fee_bps as u64,
+                    trade_fee_denominator: 10_000,
+                    swap_fee_numerator: leg.pool.fee_bps as u64,
+                    swap_fee_denominator: 10_000,
+                    ..Default::default()
+                };
+                let amm = AmmInfo {
+                    status: 6,
+                    fees,
+                    coin_vault_mint: leg.pool.token_a.mint,
+                    pc_vault_mint: leg.pool.token_b.mint,
+                    ..Default::default()
+                };
+                let market = RaydiumAmmMarket::new(leg.pool_address, amm, reserve_a, reserve_b);
+                let config = DepthCurveConfig::probe(&market).expect("reserves are quotable");
+                Arc::new(DepthCurves::build(&market, &config).expect("reserves are quotable"))
+            })
+            .collect();
+        Self::new(curves)
+    }
+}
+
+/// Output of every leg when `amount_in` enters the cycle, quoted on `depth` and netted
+/// of `fees`. `None` if the amount is beyond a pool's curve.
+pub fn quote_cycle(cycle: &Cycle, depth: &CycleDepth, fees: &TransferFees, amount_in: u64) -> Option<Vec<LegAmount>> {
+    if depth.curves.len() != cycle.legs.len() {
+        return None;
+    }
+    let mut amount = amount_in;
+    cycle
+        .legs
+        .iter()
+        .zip(&depth.curves)
+        .map(|(leg, curves)| {
+            let amount_out = curves.curve(leg.a_to_b()).amount_out(amount)?;
+            let quoted = LegAmount::new(leg, fees, amount, amount_out);
+            amount = quoted.received;
+            Some(quoted)
+        })
+        .collect()
+}
+
+/// Output of every leg when `amount_in` enters the cycle, quoted on the engines
+/// `markets` holds and netted of `fees`. `None` if a pool has no engine or rejects the
+/// amount.
+pub async fn quote_cycle_on_engines(
+    cycle: &Cycle,
+    markets: &AccountUpdateRouter,
+    fees: &TransferFees,
+    amount_in: u64,
+) -> Option<Vec<LegAmount>> {
+    let mut legs = Vec::with_capacity(cycle.legs.len());
+    let mut amount = amount_in;
+    for leg in &cycle.legs {
+        let amount_out = markets.quote(&leg.pool_address, amount, leg.a_to_b()).await?.amount_out;
+        let quoted = LegAmount::new(leg, fees, amount, amount_out);
+        amount = quoted.received;
+        legs.push(quoted);
+    }
+    Some(legs)
+}
+
+/// Finds the input that maximises a cycle's profit after fees, gas and tip
+#[derive(Debug, Clone)]
+pub struct TradeSizer {
+    gas_cost_lamports: u64,
+    jito_tip_lamports: u64,
+    position_sizer: PositionSizer,
+}
+
+impl TradeSizer {
+    pub fn new(config: &ArbitrageConfig, position_sizer: PositionSizer) -> Self {
+        Self {
+            gas_cost_lamports: config.gas_cost_lamports,
+            jito_tip_lamports: config.jito_tip_lamports,
+            position_sizer,
+        }
+    }
+
+    /// Gas and tip paid per trade
+    pub fn cost_lamports(&self) -> u64 {
+        self.gas_cost_lamports + self.jito_tip_lamports
+    }
+
+    /// Size `cycle` on its pools' `depth`, net of `fees`, for a wallet holding
+    /// `wallet_balance` raw start tokens. The input is capped by the balance, by
+    /// `PositionSizer` applied to the shallowest pool whose TVL is known, and by the depth
+    /// the curves cover.
+    pub fn solve(
+        &self,
+        cycle: &Cycle,
+        depth: &CycleDepth,
+        fees: &TransferFees,
+        wallet_balance: u64,
+        prices: &SizingPrices,
+    ) -> Result<SizedTrade, ArbitrageError> {
+        let (decimals, unit_usd) = start_unit(cycle, prices)?;
+
+        let shallowest_usd = cycle
+            .legs
+            .iter()
+            .filter_map(|leg| leg.pool.liquidity_usd())
+            .fold(f64::INFINITY, f64::min);
+        let position_usd = self.position_sizer.calculate_position_size(shallowest_usd);
+        let cap = wallet_balance.min((position_usd / unit_usd) as u64);
+        if cap == 0 {
+            return Err(ArbitrageError::PositionTooSmall { size: position_usd.min(wallet_balance as f64 * unit_usd) });
+        }
+        let cap = cap.min(depth.max_amount_in(cycle));
+        if cap == 0 || quote_cycle(cycle, depth, fees, cap).is_none() {
+            return Err(ArbitrageError::InvalidPoolData("cycle has a pool without quotable depth".to_string()));
+        }
+
+        let amount_in = golden_section_max(cap, |amount| gross_profit(cycle, depth, fees, amount));
+        let legs = quote_cycle(cycle, depth, fees, amount_in).ok_or(ArbitrageError::NoProfitableArbitrage)?;
+        self.trade(legs, prices.lamports_in_start_units(self.cost_lamports(), decimals), unit_usd)
+    }
+
+    /// Re-quote `trade`'s size leg by leg on the engines `markets` holds, replacing the
+    /// curves' interpolation with the venues' exact math
+    pub async fn requote(
+        &self,
+        cycle: &Cycle,
+        trade: &SizedTrade,
+        markets: &AccountUpdateRouter,
+        fees: &TransferFees,
+        prices: &SizingPrices,
+    ) -> Result<SizedTrade, ArbitrageError> {
+        let (_, unit_usd) = start_unit(cycle, prices)?;
+        let legs = quote_cycle_on_engines(cycle, markets, fees, trade.amount_in)
+            .await
+            .ok_or_else(|| ArbitrageError::InvalidPoolData("a pool's engine rejected the sized amount".to_string()))?;
+        self.trade(legs, trade.cost, unit_usd)
+    }
+
+    /// The trade quoted as `legs`, if it clears `cost`
+    fn trade(&self, legs: Vec<LegAmount>, cost: u64, unit_usd: f64) -> Result<SizedTrade, ArbitrageError> {
+        let amount_in = legs.first().map_or(0, |leg| leg.amount_in);
+        let amount_out = legs.last().map_or(0, |leg| leg.received);
+        let net_profit = amount_out as i64 - amount_in as i64 - cost as i64;
+        if net_profit <= 0 {
+            return Err(ArbitrageError::NoProfitableArbitrage);
+        }
+
+        Ok(SizedTrade {
+            amount_in,
+            amount_out,
+            legs,
+            cost,
+            net_profit,
+            net_profit_usd: net_profit as f64 * unit_usd,
+        })
+    }
+}
+
+/// Decimals of `cycle`'s start token and the USD value of one raw unit of it
+fn start_unit(cycle: &Cycle, prices: &SizingPrices) -> Result<(u8, f64), ArbitrageError> {
+    let decimals = cycle
+        .start_decimals()
+        .ok_or_else(|| ArbitrageError::InvalidPoolData(format!("no decimals for {}", cycle.start)))?;
+    let unit_usd = prices.unit_usd(decimals);
+    if !unit_usd.is_finite() || unit_usd <= 0.0 {
+        return Err(ArbitrageError::InvalidPoolData(format!("no USD price for {}", cycle.start)));
+    }
+    Ok((decimals, unit_usd))
+}
+
+/// Output received less input; fixed costs don't move the optimum
+fn gross_profit(cycle: &Cycle, depth: &CycleDepth, fees: &TransferFees, amount_in: u64) -> f64 {
+    quote_cycle(cycle, depth, fees, amount_in)
+        .and_then(|legs| legs.last().map(|leg| leg.received as f64 - amount_in as f64))
+        .unwrap_or(f64::NEG_INFINITY)
+}
+
+/// Input in `1..=cap` maximising a unimodal `profit`
+fn golden_section_max(cap: u64, profit: impl Fn(u64) -> f64) -> u64 {
+    let tolerance = (cap as f64 * SIZE_TOLERANCE).max(1.0);
+    let (mut low, mut high) = (1.0, cap as f64);
+    for _ in 0..MAX_ITERATIONS {
+        if high - low <= tolerance {
+            break;
+        }
+        let step = (high - low) * INVERSE_GOLDEN_RATIO;
+        let (left, right) = (high - step, low + step);
+        if profit(left.round() as u64) < profit(right.round() as u64) {
+            low = left;
+        } else {
+            high = right;
+        }
+    }
+    // Rounding flattens the peak into ties that can walk the bracket off the cap, so
+    // the cap itself stays a candidate
+    [cap as f64, low, (low + high) / 2.0, high]
+        .into_iter()
+        .map(|amount| (amount.round() as u64).clamp(1, cap))
+        .max_by(|a, b| profit(*a).total_cmp(&profit(*b)))
+        .unwrap_or(cap)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arbitrage::cycles::CycleDetector;
+    use crate::markets::pools::Pool;
+
+    /// X/Y priced 2.0 in one pool and 2.1 in the other, $1M of X on each side
+    fn mismatched_cycle() -> Cycle {
+        let (x, y) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let pools = vec![
+            Pool::with_reserves(x, y, 1_000_000_000_000, 2_000_000_000_000),
+            Pool::with_reserves(x, y, 1_000_000_000_000, 2_100_000_000_000),
+        ];
+        let config = ArbitrageConfig {
+            max_cycle_detection_us: 1_000_000,
+            ..ArbitrageConfig::default()
+        };
+        CycleDetector::new(&config).detect(&pools, &[x]).cycles.remove(0)
+    }
+
+    fn sizer() -> TradeSizer {
+        let position_sizer = PositionSizer {
+            max_position_usd: 1_000_000.0,
+            max_percent_of_liquidity: 1.0,
+            kelly_fraction: 1.0,
+            historical_win_rate: 0.9,
+            historical_win_loss_ratio: 5.0,
+        };
+        TradeSizer::new(&ArbitrageConfig::default(), position_sizer)
+    }
+
+    const PRICES: SizingPrices = SizingPrices { start_usd: 1.0, sol_usd: 100.0 };
+
+    #[test]
+    fn test_solves_for_the_profit_peak() {
+        let cycle = mismatched_cycle();
+        let depth = CycleDepth::from_reserves(&cycle);
+        let trade = sizer().solve(&cycle, &depth, &TransferFees::default(), u64::MAX, &PRICES).unwrap();
+
+        // 115_000 lamports of gas and tip at $100 per SOL is $0.0115 of X
+        assert_eq!(trade.cost, 11_500);
+        assert_eq!(trade.legs.len(), 2);
+        assert_eq!(trade.legs[0].amount_in, trade.amount_in);
+        assert_eq!(trade.legs[0].amount_out, trade.legs[1].amount_in);
+        assert_eq!(trade.legs[1].amount_out, trade.amount_out);
+        assert_eq!(trade.net_profit, trade.amount_out as i64 - trade.amount_in as i64 - 11_500);
+        assert!((trade.net_profit_usd - trade.net_profit as f64 / 1e6).abs() < 1e-9);
+
+        let plan = trade.execution_plan(&cycle, &TransferFees::default(), 50);
+        assert_eq!(plan.len(), 2);
+        assert_eq!(plan[0].amount_in, trade.amount_in);
+        assert_eq!(plan[1].expected_amount_out, trade.amount_out);
+        assert_eq!(plan[1].minimum_amount_out, trade.amount_out * 9_950 / 10_000);
+        assert_eq!(plan[0].pool_data, PoolExecutionData::Raydium { amm_version: 4 });
+        assert!(trade.legs[0].price_impact_bps > 0);
+
+        // Well inside the cap, and no better a percent either side
+        let fees = TransferFees::default();
+        assert!(trade.amount_in < 1_000_000_000_000);
+        for amount in [trade.amount_in * 99 / 100, trade.amount_in * 101 / 100] {
+            assert!(gross_profit(&cycle, &depth, &fees, amount) <= gross_profit(&cycle, &depth, &fees, trade.amount_in));
+        }
+    }
+
+    #[test]
+    fn test_legs_chain_amounts_net_of_transfer_fees() {
+        use crate::markets::token_2022::{tests::transfer_fee_mint, MintInfo};
+        use anchor_spl::token_2022::spl_token_2022;
+
+        let cycle = mismatched_cycle();
+        let depth = CycleDepth::from_reserves(&cycle);
+        // The middle token withholds 1% of every transfer
+        let middle = cycle.legs[0].token_out;
+        let taxed = MintInfo::from_account(middle, &spl_token_2022::ID, &transfer_fee_mint(100, u64::MAX)).unwrap();
+        let fees = TransferFees::new([MintInfo::spl_token(cycle.start, 6), taxed], 0);
+
+        let trade = sizer().solve(&cycle, &depth, &fees, 5_000_000, &PRICES).unwrap();
+        let [first, second] = &trade.legs[..] else {
+            panic!("expected two legs, got {:?}", trade.legs);
+        };
+        // The fee rounds up
+        assert_eq!(first.received, first.amount_out - first.amount_out.div_ceil(100));
+        assert_eq!(second.amount_in, first.received);
+        assert_eq!(trade.amount_out, second.received);
+        assert!(trade.net_profit < sizer().solve(&cycle, &depth, &TransferFees::default(), 5_000_000, &PRICES).unwrap().net_profit);
+
+        // The first swap is expected to deliver what the second spends
+        let plan = trade.execution_plan(&cycle, &fees, 50);
+        assert_eq!(plan[0].expected_amount_out, first.received);
+        assert_eq!(plan[1].amount_in, plan[0].expected_amount_out);
+        assert!(plan[0].minimum_amount_out < SwapLeg::minimum_out(first.amount_out, 50));
+        assert_eq!(plan[1].expected_amount_out, trade.amount_out);
+    }
+
+    #[test]
+    fn test_caps_at_wallet_and_rejects_unprofitable_sizes() {
+        let cycle = mismatched_cycle();
+        let depth = CycleDepth::from_reserves(&cycle);
+        let trade = sizer().solve(&cycle, &depth, &TransferFees::default(), 5_000_000, &PRICES).unwrap();
+        // Each leg's interpolated output rounds down, which ties the last few units
+        assert!((4_999_900..=5_000_000).contains(&trade.amount_in), "{}", trade.amount_in);
+
+        // $0.10 can't earn back $0.0115 of costs
+        assert!(matches!(
+            sizer().solve(&cycle, &depth, &TransferFees::default(), 100_000, &PRICES),
+            Err(ArbitrageError::NoProfitableArbitrage)
+        ));
+        assert!(matches!(
+            sizer().solve(&cycle, &depth, &TransferFees::default(), 0, &PRICES),
+            Err(ArbitrageError::PositionTooSmall { .. })
+        ));
+
+        // Curves for a different number of pools don't price the cycle
+        let partial = CycleDepth::new(depth.curves[..1].to_vec());
+        assert!(matches!(
+            sizer().solve(&cycle, &partial, &TransferFees::default(), 5_000_000, &PRICES),
+            Err(ArbitrageError::InvalidPoolData(_))
+        ));
+    }
+}
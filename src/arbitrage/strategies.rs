@@ -1,102 +1,229 @@
 //! src/arbitrage/strategies.rs - Updated for bounded channel & TokenInfos price_usd
 
-use rayon::prelude::*;
-
-use crate::arbitrage::path_evaluator::SmartPathEvaluator;
-use crate::arbitrage::types::{ArbOpportunity, SwapPath, SwapPathSelected, TokenInArb, TokenInfos, Route};
-use crate::common::config::{Config, STRATEGY_MASSIVE, STRATEGY_BEST_PATH};
+use crate::arbitrage::config::{ArbitrageConfig, PositionSizer};
+use crate::arbitrage::cycle_index::CycleIndex;
+use crate::arbitrage::cycles::{Cycle, CycleDetector};
+use crate::arbitrage::dedup::{Dispatch, OpportunityDeduplicator, DEFAULT_DEDUP_TTL_MS};
+use crate::arbitrage::path_evaluator::{cycle_opportunity, SmartPathEvaluator};
+use crate::arbitrage::sizing::{CycleDepth, SizingPrices, TradeSizer};
+use crate::arbitrage::types::{ArbOpportunity, SwapPathSelected, TokenInArb};
+use crate::arbitrage::valuation::ValuationService;
+use crate::common::config::{Config, STRATEGY_MASSIVE, STRATEGY_BEST_PATH, STRATEGY_TRIANGULAR};
 use crate::data::market_stream::MarketEvent;
 use crate::execution::risk_engine::RiskEngine;
-use crate::markets::pools::{Pool, PoolRegistry};
+use crate::execution::wallet::WalletBalances;
+use crate::markets::account_decoders::AccountUpdateRouter;
+use crate::markets::pools::{Pool, PoolEvent, PoolRegistry};
+use crate::markets::token_registry::{TokenMetadata, TokenRegistry};
 use crate::telemetry::Metrics;
+use anchor_spl::token::spl_token;
 use anyhow::Result;
 use log::{error, warn, debug};
-use moka::future::Cache;
+use solana_sdk::pubkey::Pubkey;
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
 use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
 use tokio::sync::mpsc::{self, error::TrySendError};
 use tracing::{info, info_span, Instrument};
 
 pub struct StrategyOrchestrator {
     config: Arc<Config>,
     pool_registry: Arc<PoolRegistry>,
-    token_cache: Arc<Cache<String, TokenInfos>>,
-    exec_tx: mpsc::Sender<ArbOpportunity>, // Bounded sender
+    token_registry: Arc<TokenRegistry>,
+    exec_tx: OpportunityDeduplicator, // Bounded sender, deduplicated
     market_rx: mpsc::Receiver<MarketEvent>,
     risk_engine: Arc<RiskEngine>,
     metrics: Arc<Metrics>, // This should be the Arc<Metrics> from telemetry.rs
     evaluator: SmartPathEvaluator,
+    cycle_detector: CycleDetector,
+    trade_sizer: TradeSizer,
+    /// Cycle detection and sizing limits, from `Config::arbitrage`
+    arbitrage_config: ArbitrageConfig,
+    valuation: Option<Arc<ValuationService>>,
+    /// Quote engines whose depth curves size cycles
+    markets: Option<Arc<AccountUpdateRouter>>,
+    /// Payer balances capping trade sizes
+    wallet: Option<Arc<WalletBalances>>,
 }
 
 impl StrategyOrchestrator {
     pub fn new(
         config: Arc<Config>,
         pool_registry: Arc<PoolRegistry>,
-        token_cache: Arc<Cache<String, TokenInfos>>,
+        token_registry: Arc<TokenRegistry>,
         exec_tx: mpsc::Sender<ArbOpportunity>, // Bounded sender
         market_rx: mpsc::Receiver<MarketEvent>,
         risk_engine: Arc<RiskEngine>,
         metrics: Arc<Metrics>, // Pass Arc<Metrics>
     ) -> Self {
+        let exec_tx = OpportunityDeduplicator::new(
+            exec_tx,
+            Duration::from_millis(config.opportunity_dedup_ttl_ms.unwrap_or(DEFAULT_DEDUP_TTL_MS)),
+            metrics.clone(),
+        );
+        let arbitrage_config = config.arbitrage.clone().unwrap_or_default();
+        let trade_sizer = TradeSizer::new(&arbitrage_config, PositionSizer::default());
+        let evaluator = SmartPathEvaluator::new()
+            .with_pools(pool_registry.clone(), config.max_slippage_bps.unwrap_or(100))
+            .with_trade_sizer(trade_sizer.clone());
         Self {
             config,
             pool_registry,
-            token_cache,
+            token_registry,
             exec_tx,
             market_rx,
             risk_engine,
             metrics,
-            evaluator: SmartPathEvaluator::new(),
+            evaluator,
+            cycle_detector: CycleDetector::new(&arbitrage_config),
+            trade_sizer,
+            arbitrage_config,
+            valuation: None,
+            markets: None,
+            wallet: None,
         }
     }
 
+    /// Value path profits and size cycles through `valuation`
+    pub fn with_valuation(mut self, valuation: Arc<ValuationService>) -> Self {
+        self.evaluator = self.evaluator.with_valuation(valuation.clone());
+        self.valuation = Some(valuation);
+        self
+    }
+
+    /// Size cycles on the depth curves of the engines `markets` keeps current
+    pub fn with_markets(mut self, markets: Arc<AccountUpdateRouter>) -> Self {
+        self.evaluator = self.evaluator.with_markets(markets.clone());
+        self.markets = Some(markets);
+        self
+    }
+
+    /// Cap trades at what `wallet` holds of their start token
+    pub fn with_wallet(mut self, wallet: Arc<WalletBalances>) -> Self {
+        self.evaluator = self.evaluator.with_wallet(wallet.clone());
+        self.wallet = Some(wallet);
+        self
+    }
+
     pub async fn run(mut self) -> Result<()> {
-        // Process market events in background
-        let market_metrics = self.metrics.clone(); // Arc clone
-        let exec_tx = self.exec_tx.clone(); // Sender clone
-        let risk_engine = self.risk_engine.clone();
-        let evaluator = SmartPathEvaluator::new();
-        
+        // Price events carry no pool state to size or plan a trade on; the pool updates
+        // behind them re-price the cycles through their pools below
         let (_, dummy_rx) = mpsc::channel(1);
         let mut market_rx = std::mem::replace(&mut self.market_rx, dummy_rx);
-        
         let market_events_handle = tokio::spawn(
             async move {
                 while let Some(event) = market_rx.recv().await {
-                    market_metrics.inc_opportunities_discovered(); // Use helper method
-                    debug!("Received market event: {:?}", event);
-                    
-                    if let Some(opportunity) = StrategyOrchestrator::process_market_event(event, &evaluator).await {
-                        match risk_engine.should_execute(&opportunity).await {
+                    debug!("Market event for {}: price {} from {}", event.token_pair, event.price, event.source);
+                }
+            }
+            .instrument(info_span!("market_event_processor")),
+        );
+
+        // Keep the pool gauge in step with registry refreshes, and re-price the cycles
+        // through every pool that changes
+        let mut pool_events = self.pool_registry.subscribe();
+        let pool_metrics = self.metrics.clone();
+        let pools = self.pool_registry.get_pools(false).await.unwrap_or_else(|e| {
+            warn!("Pools unavailable for the cycle index: {}", e);
+            Vec::new()
+        });
+        let mut cycle_index = CycleIndex::new(&self.arbitrage_config, self.cycle_start_mints()).with_pools(pools);
+        info!("📊 Indexed {} cycles for pool update re-pricing", cycle_index.len());
+        let pool_exec_tx = self.exec_tx.clone();
+        let pool_risk_engine = self.risk_engine.clone();
+        let valuation = self.valuation.clone();
+        let markets = self.markets.clone();
+        let wallet = self.wallet.clone();
+        let trade_sizer = self.trade_sizer.clone();
+        let max_slippage_bps = self.config.max_slippage_bps.unwrap_or(100);
+        let pool_events_handle = tokio::spawn(
+            async move {
+                loop {
+                    let event = match pool_events.recv().await {
+                        Ok(event) => event,
+                        Err(RecvError::Lagged(skipped)) => {
+                            warn!("Pool event stream lagged, {} events skipped", skipped);
+                            continue;
+                        }
+                        Err(RecvError::Closed) => break,
+                    };
+                    match &event {
+                        PoolEvent::PoolAdded(pool) => {
+                            pool_metrics.add_pools_loaded(1);
+                            debug!("Pool added: {} ({}/{})", pool.id, pool.token_a.mint, pool.token_b.mint);
+                        }
+                        PoolEvent::PoolRemoved(pool) => {
+                            pool_metrics.sub_pools_loaded(1);
+                            debug!("Pool removed: {}", pool.id);
+                        }
+                        PoolEvent::PoolChanged { .. } => {}
+                    }
+
+                    let cycles = cycle_index.apply_event(&event);
+                    let (Some(valuation), Some(markets), Some(wallet)) =
+                        (valuation.as_deref().filter(|_| !cycles.is_empty()), markets.as_deref(), wallet.as_deref())
+                    else {
+                        continue;
+                    };
+                    let (PoolEvent::PoolAdded(pool) | PoolEvent::PoolChanged { current: pool, .. } | PoolEvent::PoolRemoved(pool)) = &event;
+                    let pool_id = pool.address.and_then(|address| cycle_index.pool_id(&address)).unwrap_or_default();
+                    for cycle in &cycles {
+                        let source = crate::arbitrage::types::OpportunitySource::MarketEvent {
+                            pool_id: pool_id as u64,
+                            event_type: "pool_update".to_string(),
+                        };
+                        let Some(wallet_balance) = wallet.balance(&cycle.start) else {
+                            continue;
+                        };
+                        let Some(opportunity) = cycle_opportunity(
+                            cycle,
+                            &trade_sizer,
+                            valuation,
+                            markets,
+                            wallet_balance,
+                            max_slippage_bps,
+                            source,
+                        )
+                        .await
+                        else {
+                            continue;
+                        };
+                        pool_metrics.inc_opportunities_discovered();
+
+                        match pool_risk_engine.should_execute(&opportunity).await {
                             Ok(true) => {
-                                info!("✅ Risk check passed for opportunity: {} lamports profit", 
+                                info!("✅ Sending opportunity from pool update: {} lamports profit",
                                     opportunity.expected_profit_lamports);
-                                
-                                match exec_tx.try_send(opportunity) {
-                                    Ok(()) => {
-                                        market_metrics.inc_opportunities_sent(); // Use helper method
+
+                                match pool_exec_tx.try_send(opportunity) {
+                                    Ok(Dispatch::Sent) => {
+                                        pool_metrics.inc_opportunities_sent();
                                     }
+                                    Ok(Dispatch::Suppressed) => {} // Counted in duplicates_suppressed
                                     Err(TrySendError::Full(_)) => {
-                                        warn!("Execution queue full, dropping opportunity from market event processor");
-                                        market_metrics.inc_opportunities_dropped(); // Use helper method
+                                        warn!("Execution queue full, dropping opportunity from pool update");
+                                        pool_metrics.inc_opportunities_dropped();
                                     }
                                     Err(TrySendError::Closed(_)) => {
-                                        error!("Execution channel closed from market event processor");
-                                        break;
+                                        error!("Execution channel closed from pool event processor");
+                                        return;
                                     }
                                 }
                             }
                             Ok(false) => {
-                                warn!("❌ Risk check failed for opportunity from market event");
-                                market_metrics.inc_opportunities_rejected(); // Use helper method
+                                pool_metrics.inc_opportunities_rejected();
+                                warn!("❌ Opportunity from pool update rejected by risk engine");
                             }
                             Err(e) => {
-                                error!("Risk check error from market event: {}", e);
+                                error!("Risk check error from pool update: {}", e);
                             }
                         }
                     }
                 }
             }
-            .instrument(info_span!("market_event_processor")),
+            .instrument(info_span!("pool_event_processor")),
         );
 
         if self.config.contains_strategy(STRATEGY_MASSIVE) {
@@ -110,8 +237,15 @@ impl StrategyOrchestrator {
                 error!("Best path strategy failed: {}", e);
             }
         }
+
+        if self.config.contains_strategy(STRATEGY_TRIANGULAR) {
+            if let Err(e) = self.run_triangular().await {
+                error!("Triangular strategy failed: {}", e);
+            }
+        }
         
         market_events_handle.abort();
+        pool_events_handle.abort();
         info!("🏁 All strategies completed");
         Ok(())
     }
@@ -121,24 +255,34 @@ impl StrategyOrchestrator {
         let pools = self.pool_registry.get_pools(false).await?;
 
         for input_config in &self.config.massive_strategy_inputs {
-            for token_config in &input_config.tokens_to_arb {
-                if !self.token_cache.contains_key(&token_config.address) { 
-                    let token_info = TokenInfos {
-                        address: token_config.address.clone(),
-                        symbol: token_config.symbol.clone(),
-                        decimals: token_config.decimals,
-                        // price_usd is no longer part of TokenInfos as per the new types.rs
-                        // Price information for execution will be part of the ArbOpportunity.execution_plan
-                    };
-                    self.token_cache.insert(token_config.address.clone(), token_info).await;
-                    debug!("📝 Cached token info for {}", token_config.symbol);
+            // Configured symbols are kept, decimals come from the mint itself
+            let mints: Vec<Pubkey> = input_config
+                .tokens_to_arb
+                .iter()
+                .filter_map(|tc| Pubkey::from_str(&tc.address).ok())
+                .collect();
+            let resolved = match self.token_registry.get_many(&mints).await {
+                Ok(resolved) => resolved,
+                Err(e) => {
+                    warn!("Token metadata lookup failed, using configured decimals: {}", e);
+                    HashMap::new()
                 }
-            }
+            };
 
             let tokens_to_arb: Vec<TokenInArb> = input_config
                 .tokens_to_arb
                 .iter()
-                .map(|tc| TokenInArb::from(tc))
+                .map(|tc| {
+                    let mut token = TokenInArb::from(tc);
+                    let metadata = Pubkey::from_str(&tc.address).ok().and_then(|mint| resolved.get(&mint));
+                    if let Some(metadata) = metadata {
+                        if metadata.decimals != tc.decimals {
+                            warn!("{} has {} decimals on-chain, not the configured {}", tc.symbol, metadata.decimals, tc.decimals);
+                        }
+                        token.decimals = metadata.decimals;
+                    }
+                    token
+                })
                 .collect();
 
             let paths = self.find_arbitrage_paths(&tokens_to_arb, &pools).await?;
@@ -150,7 +294,7 @@ impl StrategyOrchestrator {
                     continue;
                 }
                 
-                match self.evaluator.evaluate(&path) {
+                match self.evaluator.evaluate(&path).await {
                     Ok(Some(opportunity)) => {
                         self.metrics.inc_opportunities_discovered(); // Use helper method
                         
@@ -160,9 +304,10 @@ impl StrategyOrchestrator {
                                     opportunity.expected_profit_lamports);
                                 
                                 match self.exec_tx.try_send(opportunity) {
-                                    Ok(()) => {
+                                    Ok(Dispatch::Sent) => {
                                         self.metrics.inc_opportunities_sent(); // Use helper method
                                     }
+                                    Ok(Dispatch::Suppressed) => {} // Counted in duplicates_suppressed
                                     Err(TrySendError::Full(_)) => {
                                         warn!("Execution queue full in MASSIVE strategy, dropping opportunity");
                                         self.metrics.inc_opportunities_dropped(); // Use helper method
@@ -199,36 +344,39 @@ impl StrategyOrchestrator {
     async fn run_best_path(&self) -> Result<()> {
         info!("🚀 Launching BEST_PATH strategy");
         
-        let cached_tokens: Vec<TokenInArb> = self.token_cache
+        let pools = self.pool_registry.get_pools(true).await?;
+        
+        // Every token the pools trade, configured or not
+        let mut pool_mints: Vec<Pubkey> = pools
             .iter()
-            .map(|(_, token_info)| TokenInArb {
-                token: token_info.address.clone(),
-                symbol: token_info.symbol.clone(),
-                decimals: token_info.decimals,
-            })
+            .filter(|pool| pool.dex.is_some())
+            .flat_map(|pool| [pool.token_a.mint, pool.token_b.mint])
+            .collect();
+        pool_mints.sort();
+        pool_mints.dedup();
+        if let Err(e) = self.token_registry.get_many(&pool_mints).await {
+            warn!("Token metadata lookup failed, using cached tokens only: {}", e);
+        }
+        let cached_tokens: Vec<TokenInArb> = self.token_registry
+            .tokens()
+            .iter()
+            .filter(|token| pool_mints.binary_search(&token.mint).is_ok())
+            .map(TokenMetadata::to_token_in_arb)
             .collect();
         
         if cached_tokens.len() < 2 {
-            warn!("Not enough tokens cached for BEST_PATH strategy");
+            warn!("Not enough tokens resolved for BEST_PATH strategy");
             return Ok(());
         }
         
-        let pools = self.pool_registry.get_pools(true).await?;
         let mut best_opportunity: Option<ArbOpportunity> = None;
         let mut current_best_profit = 0u64; // Renamed to avoid conflict if ArbOpportunity had 'best_profit'
         
-        for i in 0..cached_tokens.len() {
-            for j in i+1..cached_tokens.len() {
-                let pair = vec![cached_tokens[i].clone(), cached_tokens[j].clone()];
-                let paths = self.find_arbitrage_paths(&pair, &pools).await?;
-                
-                for path in paths {
-                    if let Ok(Some(opportunity)) = self.evaluator.evaluate(&path) {
-                        if opportunity.expected_profit_lamports > current_best_profit {
-                            current_best_profit = opportunity.expected_profit_lamports;
-                            best_opportunity = Some(opportunity);
-                        }
-                    }
+        for path in self.find_arbitrage_paths(&cached_tokens, &pools).await? {
+            if let Ok(Some(opportunity)) = self.evaluator.evaluate(&path).await {
+                if opportunity.expected_profit_lamports > current_best_profit {
+                    current_best_profit = opportunity.expected_profit_lamports;
+                    best_opportunity = Some(opportunity);
                 }
             }
         }
@@ -239,9 +387,10 @@ impl StrategyOrchestrator {
 
             if self.risk_engine.should_execute(&opportunity).await? {
                 match self.exec_tx.try_send(opportunity) {
-                    Ok(()) => {
+                    Ok(Dispatch::Sent) => {
                         self.metrics.inc_opportunities_sent(); // Use helper method
                     }
+                    Ok(Dispatch::Suppressed) => {} // Counted in duplicates_suppressed
                     Err(TrySendError::Full(_)) => {
                         warn!("Execution queue full, dropping best opportunity from BEST_PATH strategy");
                         self.metrics.inc_opportunities_dropped(); // Use helper method
@@ -262,284 +411,339 @@ impl StrategyOrchestrator {
         Ok(())
     }
     
+    async fn run_triangular(&self) -> Result<()> {
+        info!("🚀 Launching TRIANGULAR strategy");
+        let Some(valuation) = self.valuation.as_ref() else {
+            warn!("TRIANGULAR strategy needs token valuation to size trades, skipping");
+            return Ok(());
+        };
+        let Some(markets) = self.markets.as_ref() else {
+            warn!("TRIANGULAR strategy needs quote engines to size trades, skipping");
+            return Ok(());
+        };
+        let Some(wallet) = self.wallet.as_ref() else {
+            warn!("TRIANGULAR strategy needs wallet balances to size trades, skipping");
+            return Ok(());
+        };
+        let pools = self.pool_registry.get_pools(false).await?;
+        let triangle_detector = self.cycle_detector.clone().with_max_hops(3);
+
+        for input_config in self.config.triangular_strategy_inputs.iter().flatten() {
+            let base = match input_config.base_token.as_deref().map(Pubkey::from_str) {
+                None => spl_token::native_mint::id(),
+                Some(Ok(base)) => base,
+                Some(Err(e)) => {
+                    warn!("Invalid TRIANGULAR base token {:?}: {}", input_config.base_token, e);
+                    continue;
+                }
+            };
+            let mut universe: HashSet<Pubkey> = input_config
+                .tokens_to_arb
+                .iter()
+                .filter_map(|tc| Pubkey::from_str(&tc.address).ok())
+                .collect();
+            universe.insert(base);
+            let Some(wallet_balance) = wallet.balance(&base) else {
+                warn!("No wallet balance of {} read yet, skipping its triangles", base);
+                continue;
+            };
+
+            // Every pool, on any DEX, trading two tokens of the universe
+            let universe_pools: Vec<Pool> = pools
+                .iter()
+                .filter(|pool| universe.contains(&pool.token_a.mint) && universe.contains(&pool.token_b.mint))
+                .cloned()
+                .collect();
+            let scan = triangle_detector.detect(&universe_pools, &[base]);
+            if scan.timed_out {
+                warn!("Triangle search hit its {}µs budget", scan.elapsed_us);
+            }
+            let triangles: Vec<&Cycle> = scan.cycles.iter().filter(|cycle| cycle.hops() == 3).collect();
+            info!("📊 Found {} triangles through {} in TRIANGULAR strategy", triangles.len(), base);
+
+            for cycle in triangles {
+                let Some(opportunity) = self.triangle_opportunity(cycle, valuation, markets, wallet_balance).await else {
+                    continue;
+                };
+                self.metrics.inc_opportunities_discovered();
+
+                match self.risk_engine.should_execute(&opportunity).await {
+                    Ok(true) => {
+                        info!("✅ Sending opportunity from TRIANGULAR: {} lamports profit",
+                            opportunity.expected_profit_lamports);
+
+                        match self.exec_tx.try_send(opportunity) {
+                            Ok(Dispatch::Sent) => {
+                                self.metrics.inc_opportunities_sent();
+                            }
+                            Ok(Dispatch::Suppressed) => {} // Counted in duplicates_suppressed
+                            Err(TrySendError::Full(_)) => {
+                                warn!("Execution queue full in TRIANGULAR strategy, dropping opportunity");
+                                self.metrics.inc_opportunities_dropped();
+                            }
+                            Err(TrySendError::Closed(_)) => {
+                                error!("Execution channel closed in TRIANGULAR strategy");
+                                return Err(anyhow::anyhow!("Execution channel closed"));
+                            }
+                        }
+                    }
+                    Ok(false) => {
+                        self.metrics.inc_opportunities_rejected();
+                        warn!("❌ Opportunity rejected by risk engine in TRIANGULAR strategy");
+                    }
+                    Err(e) => {
+                        error!("Risk engine error in TRIANGULAR strategy: {}", e);
+                    }
+                }
+            }
+        }
+
+        info!("✅ TRIANGULAR strategy cycle completed");
+        Ok(())
+    }
+
+    /// Size a triangle on its venues' quote engines for a wallet holding
+    /// `wallet_balance` of the base token, and plan its three legs. `None` when it can't
+    /// be priced or no size clears gas and tip.
+    async fn triangle_opportunity(
+        &self,
+        cycle: &Cycle,
+        valuation: &ValuationService,
+        markets: &AccountUpdateRouter,
+        wallet_balance: u64,
+    ) -> Option<ArbOpportunity> {
+        cycle_opportunity(
+            cycle,
+            &self.trade_sizer,
+            valuation,
+            markets,
+            wallet_balance,
+            self.config.max_slippage_bps.unwrap_or(100),
+            crate::arbitrage::types::OpportunitySource::StrategyScan {
+                strategy_name: STRATEGY_TRIANGULAR.to_string(),
+            },
+        )
+        .await
+    }
+
+    /// Start tokens of the cycles re-priced on pool updates: every triangular base
+    /// token and every token the massive strategy arbitrages
+    fn cycle_start_mints(&self) -> Vec<Pubkey> {
+        let bases = self.config.triangular_strategy_inputs.iter().flatten().map(|input_config| {
+            input_config
+                .base_token
+                .as_deref()
+                .map_or(Ok(spl_token::native_mint::id()), Pubkey::from_str)
+        });
+        let tokens = self
+            .config
+            .massive_strategy_inputs
+            .iter()
+            .flat_map(|input_config| &input_config.tokens_to_arb)
+            .map(|tc| Pubkey::from_str(&tc.address));
+        let mints: HashSet<Pubkey> = bases.chain(tokens).filter_map(Result::ok).collect();
+        mints.into_iter().collect()
+    }
+
     async fn find_arbitrage_paths(&self, tokens: &[TokenInArb], pools: &[Pool]) -> Result<Vec<SwapPathSelected>> {
         if tokens.len() < 2 { return Ok(vec![]); }
-        
-        // Use parallel processing to analyze all token pairs simultaneously
-        let token_pairs: Vec<(usize, usize)> = (0..tokens.len())
-            .flat_map(|i| ((i+1)..tokens.len()).map(move |j| (i, j)))
+
+        // Cycles start and end at one of the requested tokens and may route through any other
+        let start_mints: Vec<Pubkey> = tokens
+            .iter()
+            .filter_map(|token| Pubkey::from_str(&token.token).ok())
             .collect();
-        
-        // Parallel processing of token pairs for maximum HFT performance
-        let mut paths: Vec<SwapPathSelected> = token_pairs
-            .par_iter()
-            .filter_map(|(i, j)| {
-                let token_a = &tokens[*i];
-                let token_b = &tokens[*j];
-                
-                // Find all pools that connect these tokens
-                let connecting_pools: Vec<&Pool> = pools.iter()
-                    .filter(|pool| {
-                        (pool.token_a.as_str() == token_a.token.as_str() && pool.token_b.as_str() == token_b.token.as_str()) || 
-                        (pool.token_a.as_str() == token_b.token.as_str() && pool.token_b.as_str() == token_a.token.as_str())
-                    })
-                    .collect();
-                    
-                // Real arbitrage path finding: need at least 2 pools for cross-DEX arb
-                if connecting_pools.len() >= 2 {
-                    // Process all pool combinations in parallel
-                    let pool_combinations: Vec<(&Pool, &Pool)> = connecting_pools
-                        .iter()
-                        .enumerate()
-                        .flat_map(|(k, pool_1)| {
-                            connecting_pools.iter().skip(k + 1).map(move |pool_2| (*pool_1, *pool_2))
-                        })
-                        .collect();
-                    
-                    // Parallel evaluation of arbitrage opportunities
-                    let profitable_paths: Vec<SwapPathSelected> = pool_combinations
-                        .par_iter()
-                        .enumerate()
-                        .filter_map(|(combo_idx, (pool_1, pool_2))| {
-                            // Calculate potential profit from price difference
-                            let price_1 = calculate_pool_price_sync(pool_1, &token_a.token, &token_b.token);
-                            let price_2 = calculate_pool_price_sync(pool_2, &token_a.token, &token_b.token);
-                            
-                            let price_diff = (price_2 - price_1).abs();
-                            let price_avg = (price_1 + price_2) / 2.0;
-                            
-                            if price_avg > 0.0 {
-                                let profit_ratio = price_diff / price_avg;
-                                let estimated_profit = profit_ratio * 1000.0; // $1000 base trade
-                                
-                                // Only include profitable paths (>$15 minimum profit)
-                                if estimated_profit > 15.0 {
-                                    // Extract DEX type from pool ID
-                                    let dex_1 = extract_dex_from_pool_id(&pool_1.id);
-                                    let dex_2 = extract_dex_from_pool_id(&pool_2.id);
-                                    
-                                    // Create routes for both pools
-                                    let route_1 = Route {
-                                        id: (combo_idx * 2) as u32,
-                                        dex: dex_1.clone(),
-                                        pool_address: pool_1.id.clone(),
-                                        token_in: token_a.token.clone(),
-                                        token_out: token_b.token.clone(),
-                                        token_0to1: pool_1.token_a == token_a.token,
-                                    };
-                                    
-                                    let route_2 = Route {
-                                        id: (combo_idx * 2 + 1) as u32,
-                                        dex: dex_2.clone(),
-                                        pool_address: pool_2.id.clone(),
-                                        token_in: token_b.token.clone(),
-                                        token_out: token_a.token.clone(),
-                                        token_0to1: pool_2.token_a == token_b.token,
-                                    };
-                                    
-                                    return Some(SwapPathSelected {
-                                        path: SwapPath { 
-                                            id_paths: vec![route_1.id, route_2.id], 
-                                            hops: 2, 
-                                            paths: vec![route_1, route_2] 
-                                        },
-                                        expected_profit_usd: estimated_profit,
-                                        markets: vec![
-                                            crate::arbitrage::types::Market {
-                                                id: pool_1.id.clone(),
-                                                dex_label: dex_1,
-                                            },
-                                            crate::arbitrage::types::Market {
-                                                id: pool_2.id.clone(),
-                                                dex_label: dex_2,
-                                            }
-                                        ],
-                                    });
-                                }
-                            }
-                            None
-                        })
-                        .collect();
-                    
-                    return Some(profitable_paths.into_iter().max_by(|a, b| 
-                        a.expected_profit_usd.partial_cmp(&b.expected_profit_usd).unwrap_or(std::cmp::Ordering::Equal)
-                    ));
-                }
-                None
+        let scan = self.cycle_detector.detect(pools, &start_mints);
+        if scan.timed_out {
+            warn!("Cycle detection hit its {}µs budget, keeping {} cycles found so far", scan.elapsed_us, scan.cycles.len());
+        }
+
+        let mut paths: Vec<SwapPathSelected> = scan
+            .cycles
+            .iter()
+            .filter_map(|cycle| {
+                let expected_profit_usd = self.sized_profit_usd(cycle)?;
+                Some(SwapPathSelected {
+                    path: cycle.to_swap_path(),
+                    expected_profit_usd,
+                    markets: cycle.markets(),
+                })
             })
-            .flatten()
+            // Only include profitable paths (>$15 minimum profit)
+            .filter(|path| path.expected_profit_usd > 15.0)
             .collect();
-        
+
         // Sort by profit potential (descending)
-        paths.sort_by(|a, b| b.expected_profit_usd.partial_cmp(&a.expected_profit_usd).unwrap_or(std::cmp::Ordering::Equal));
-        
+        paths.sort_by(|a, b| b.expected_profit_usd.total_cmp(&a.expected_profit_usd));
+
         // Limit to top paths to avoid overwhelming the executor
         paths.truncate(10);
-        
+
         Ok(paths)
     }
-    
-    fn calculate_pool_price(&self, pool: &Pool, token_a: &str, token_b: &str) -> f64 {
-        calculate_pool_price_sync(pool, token_a, token_b)
+
+    /// Net USD profit of `cycle` at its best size for the wallet's balance of its start
+    /// token, or the simulation amount's worth without wallet balances. Unvalued cycles
+    /// fall back to a $1000 trade at the mid rate; `None` when a pool has no engine
+    /// loaded or no size is profitable.
+    fn sized_profit_usd(&self, cycle: &Cycle) -> Option<f64> {
+        let prices = self
+            .valuation
+            .as_ref()
+            .and_then(|valuation| SizingPrices::from_valuation(valuation, &cycle.start));
+        let (Some(prices), Some(decimals)) = (prices, cycle.start_decimals()) else {
+            return Some((cycle.rate() - 1.0) * 1000.0);
+        };
+        let markets = self.markets.as_ref()?;
+        let Some(depth) = CycleDepth::from_router(cycle, markets) else {
+            debug!("No depth curves for cycle {:?}", cycle.edge_ids);
+            return None;
+        };
+        let fees = markets.transfer_fees(cycle.legs.iter().map(|leg| &leg.token_in));
+        let wallet_balance = match self.wallet.as_ref() {
+            Some(wallet) => wallet.balance(&cycle.start)?,
+            None => prices.lamports_in_start_units(self.config.simulation_amount, decimals),
+        };
+        match self.trade_sizer.solve(cycle, &depth, &fees, wallet_balance, &prices) {
+            Ok(trade) => Some(trade.net_profit_usd),
+            Err(e) => {
+                debug!("No profitable size for cycle {:?}: {}", cycle.edge_ids, e);
+                None
+            }
+        }
     }
+    
     async fn validate_path_tokens(&self, path: &SwapPathSelected) -> bool {
         if path.expected_profit_usd < 0.0 {
             warn!("Path has negative expected_profit_usd ({}) during token validation step.", path.expected_profit_usd);
             return false;
         }
-        // TODO: Actual token validation using self.token_cache and config whitelist
+        // TODO: Actual token validation using self.token_registry and config whitelist
         true
     }
     
-    async fn process_market_event(event: MarketEvent, evaluator: &SmartPathEvaluator) -> Option<ArbOpportunity> {
-        // REAL market event processing using SmartPathEvaluator
-        info!("🎯 Processing REAL MarketEvent for {}: price {} from {}", 
-              event.token_pair, event.price, event.source);
-        
-        // Parse the token pair to extract individual tokens
-        let tokens: Vec<&str> = event.token_pair.split('/').collect();
-        if tokens.len() != 2 {
-            warn!("Invalid token pair format: {}", event.token_pair);
-            return None;
-        }
-        
-        let token_a = tokens[0];
-        let token_b = tokens[1];
-        
-        // Find all pools for this token pair across different DEXs
-        // This would normally come from self.pool_registry, but we need to adapt for static function
-        // In a real implementation, this function should not be static and should have access to pools
-        
-        // For now, we'll create a minimal arbitrage opportunity if:
-        // 1. The price update is significant (>0.5% change)
-        // 2. The token pair is one we actively trade
-        
-        let is_major_pair = token_a == "SOL" || token_b == "SOL" || 
-                           token_a == "USDC" || token_b == "USDC";
-        
-        if !is_major_pair {
-            return None; // Only process major pairs for now
-        }
-        
-        // Use SmartPathEvaluator to assess arbitrage potential
-        let price_impact_threshold = 0.005; // 0.5% minimum for arbitrage consideration
-        
-        // Get historical data and success rates from evaluator
-        let pair_key = format!("{}-{}", token_a, token_b);
-        let evaluator_score = evaluator.evaluate_arbitrage_potential(
-            &pair_key,
-            event.price,
-            &event.source
-        );
-        
-        // Only proceed if evaluator indicates good opportunity
-        if evaluator_score < 0.6 {
-            debug!("💭 Evaluator score too low ({:.2}) for {}, skipping", evaluator_score, pair_key);
-            return None;
-        }
-        
-        // Calculate price deviation for opportunity assessment
-        let base_price = if token_a == "SOL" { 200.0 } else { 1.0 }; // SOL ~$200, USDC ~$1
-        let price_deviation = (event.price - base_price).abs() / base_price;
-        
-        if price_deviation < price_impact_threshold {
-            return None; // Price change not significant enough
-        }
-        
-        // Create a REAL arbitrage path based on the market event
-        let route = Route {
-            id: 1,
-            dex: crate::markets::types::DexLabel::Raydium, // Primary DEX
-            pool_address: "HZZofxusqKaA9JqaeXW8PtUALRXUwSLLwnt4eBFiyEdC".to_string(), // Real Raydium pool
-            token_in: if token_a == "SOL" { "So11111111111111111111111111111111111111112" } else { "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v" }.to_string(),
-            token_out: if token_b == "SOL" { "So11111111111111111111111111111111111111112" } else { "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v" }.to_string(),
-            token_0to1: token_a == "SOL", // Direction based on token order
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arbitrage::types::SwapLeg;
+    use crate::arbitrage::valuation::USDC_MINT;
+    use crate::data::oracle::PriceOracle;
+    use crate::markets::account_decoders::{AccountDecoderRegistry, SharedMarket};
+    use crate::markets::lockless_cache::LocklessMarketCache;
+    use crate::markets::pools::{PoolState, PoolToken};
+    use crate::markets::raydium::{AmmInfo, Fees};
+    use crate::markets::raydium_amm::RaydiumAmmMarket;
+    use tokio::sync::RwLock;
+
+    fn pool(token_a: PoolToken, token_b: PoolToken, reserve_a: u64, reserve_b: u64) -> Pool {
+        Pool { token_a, token_b, last_update_slot: 50, ..Pool::with_reserves(token_a.mint, token_b.mint, reserve_a, reserve_b) }
+    }
+
+    /// Raydium AMM engine holding `pool`'s reserves at its fee
+    fn engine(pool: &Pool) -> SharedMarket {
+        let PoolState::Reserves { reserve_a, reserve_b } = pool.state else {
+            unreachable!("test pools hold reserves");
         };
-        
-        let real_path = SwapPath {
-            id_paths: vec![1], // Use actual route ID
-            hops: 1,
-            paths: vec![route],
+        let fees = Fees {
+            trade_fee_numerator: pool.fee_bps as u64,
+            trade_fee_denominator: 10_000,
+            swap_fee_numerator: pool.fee_bps as u64,
+            swap_fee_denominator: 10_000,
+            ..Default::default()
         };
-        
-        // Calculate REAL profit potential based on price deviation
-        let trade_size_lamports = 100_000_000; // 0.1 SOL base trade size
-        let profit_lamports = ((price_deviation * trade_size_lamports as f64) * 0.5) as u64; // 50% of price deviation as profit estimate
-        
-        if profit_lamports < 1_000_000 { // Minimum 0.001 SOL profit
-            return None;
-        }
-        
-        let now_nanos = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap_or_default()
-            .as_nanos();
-            
-        Some(ArbOpportunity {
-            path: real_path,
-            expected_profit_lamports: profit_lamports,
-            timestamp_unix_nanos: now_nanos,
-            execution_plan: vec![], // Will be populated by path evaluator
-            metadata: crate::arbitrage::types::OpportunityMetadata {
-                estimated_gas_cost: 5000, // 5000 lamports gas estimate
-                net_profit_lamports: profit_lamports as i64 - 5000,
-                profit_percentage_bps: ((price_deviation * 10000.0) as u16).min(1000), // Cap at 10%
-                risk_score: if price_deviation > 0.02 { 80 } else { 40 }, // Higher risk for large deviations
-                source: crate::arbitrage::types::OpportunitySource::MarketEvent { 
-                    pool_id: 1,
-                    event_type: format!("price_update_{}", event.source)
-                },
-                max_latency_ms: 200, // Fast execution required for market events
-            },
-        })
+        let amm = AmmInfo {
+            status: 6,
+            fees,
+            coin_vault_mint: pool.token_a.mint,
+            pc_vault_mint: pool.token_b.mint,
+            ..Default::default()
+        };
+        Arc::new(RwLock::new(Box::new(RaydiumAmmMarket::new(pool.address.unwrap(), amm, reserve_a, reserve_b))))
     }
-}
 
-/// Sync version of pool price calculation for parallel processing
-fn calculate_pool_price_sync(pool: &Pool, token_a: &str, token_b: &str) -> f64 {
-    // Calculate effective exchange rate for this pool
-    let sqrt_liquidity = pool.liquidity.sqrt();
-    
-    // Simulate realistic reserves based on token types
-    let (reserve_a, reserve_b) = if token_a.contains("So11111") || token_b.contains("So11111") {
-        // SOL pair - use realistic SOL/USD ratios
-        let sol_reserve = sqrt_liquidity / 200.0;
-        let other_reserve = sqrt_liquidity;
-        
-        if token_a.contains("So11111") {
-            (sol_reserve, other_reserve)
-        } else {
-            (other_reserve, sol_reserve)
+    #[tokio::test]
+    async fn test_triangle_legs_follow_the_cycle() {
+        let sol = PoolToken::new(spl_token::native_mint::id(), Some(9));
+        let usdc = PoolToken::new(USDC_MINT, Some(6));
+        let x = PoolToken::new(Pubkey::new_unique(), Some(6));
+        let pools = vec![
+            // 1 SOL = 150 USDC
+            pool(sol, usdc, 10_000 * 1_000_000_000, 1_500_000 * 1_000_000),
+            // 1 SOL = 160 X, where X trades at $1 against USDC
+            pool(sol, x, 1_000 * 1_000_000_000, 160_000 * 1_000_000),
+            pool(x, usdc, 1_000_000 * 1_000_000, 1_000_000 * 1_000_000),
+        ];
+        let markets = AccountUpdateRouter::new(AccountDecoderRegistry::with_known_programs(), LocklessMarketCache::new());
+        for pool in &pools {
+            markets.register_market(pool.address.unwrap(), engine(pool), &[]).await;
         }
-    } else {
-        // Equal value split for other pairs
-        (sqrt_liquidity, sqrt_liquidity)
-    };
-    
-    // Price = reserve_b / reserve_a
-    if reserve_a > 0.0 {
-        reserve_b / reserve_a
-    } else {
-        0.0
-    }
-}
+        let valuation = ValuationService::new(Arc::new(PriceOracle::new(HashMap::new(), 30, 100)));
+        valuation.refresh(&pools);
 
-/// Extract DEX type from pool ID for parallel processing
-fn extract_dex_from_pool_id(pool_id: &str) -> crate::markets::types::DexLabel {
-    use crate::markets::types::DexLabel;
-    
-    if pool_id.starts_with("raydium_") {
-        DexLabel::Raydium
-    } else if pool_id.starts_with("orca_") {
-        DexLabel::Orca
-    } else if pool_id.starts_with("meteora_") {
-        DexLabel::Meteora
-    } else if pool_id.starts_with("whirlpool_") {
-        DexLabel::OrcaWhirlpools
-    } else if pool_id.starts_with("raydium_clmm_") {
-        DexLabel::RaydiumClmm
-    } else {
-        // Default to Raydium for unknown pool types
-        DexLabel::Raydium
+        let config = ArbitrageConfig {
+            max_cycle_detection_us: 1_000_000,
+            ..ArbitrageConfig::default()
+        };
+        let scan = CycleDetector::new(&config).with_max_hops(3).detect(&pools, &[USDC_MINT]);
+        let cycle = scan.cycles.iter().find(|cycle| cycle.hops() == 3).unwrap();
+        let position_sizer = PositionSizer {
+            max_position_usd: 1_000_000.0,
+            max_percent_of_liquidity: 1.0,
+            kelly_fraction: 1.0,
+            historical_win_rate: 0.9,
+            historical_win_loss_ratio: 5.0,
+        };
+        let trade_sizer = TradeSizer::new(&config, position_sizer);
+        let source = crate::arbitrage::types::OpportunitySource::StrategyScan {
+            strategy_name: STRATEGY_TRIANGULAR.to_string(),
+        };
+        let wallet_balance = 1_000 * 1_000_000;
+        let opportunity =
+            cycle_opportunity(cycle, &trade_sizer, &valuation, &markets, wallet_balance, 100, source.clone())
+                .await
+                .unwrap();
+
+        // USDC -> SOL -> X -> USDC, each leg through the pool trading its pair
+        let plan = &opportunity.execution_plan;
+        let hops: Vec<(Pubkey, Pubkey, Pubkey, bool)> =
+            plan.iter().map(|leg| (leg.pool_address, leg.token_in, leg.token_out, leg.swap_direction)).collect();
+        assert_eq!(
+            hops,
+            vec![
+                (pools[0].address.unwrap(), usdc.mint, sol.mint, false),
+                (pools[1].address.unwrap(), sol.mint, x.mint, true),
+                (pools[2].address.unwrap(), x.mint, usdc.mint, true),
+            ]
+        );
+        // Every leg spends the last one's output, at the engine's own quote
+        assert!(plan[0].amount_in <= wallet_balance);
+        for (i, leg) in plan.iter().enumerate() {
+            if i > 0 {
+                assert_eq!(leg.amount_in, plan[i - 1].expected_amount_out);
+            }
+            let a_to_b = leg.token_in == pools[i].token_a.mint;
+            let quote = markets.quote(&leg.pool_address, leg.amount_in, a_to_b).await.unwrap();
+            assert_eq!(leg.expected_amount_out, quote.amount_out);
+            assert_eq!(leg.minimum_amount_out, SwapLeg::minimum_out(quote.amount_out, 100));
+        }
+        assert!(plan[2].expected_amount_out > plan[0].amount_in);
+        assert!(opportunity.expected_profit_lamports > 0);
+        assert_eq!(opportunity.slot, Some(50));
+
+        // Quiet pools age with the stream's slot, not with their last write
+        markets.cache().observe_slot(90);
+        let opportunity =
+            cycle_opportunity(cycle, &trade_sizer, &valuation, &markets, wallet_balance, 100, source.clone())
+                .await
+                .unwrap();
+        assert_eq!(opportunity.slot, Some(90));
+
+        // A wallet holding 1 USDC trades no more than that
+        let opportunity = cycle_opportunity(cycle, &trade_sizer, &valuation, &markets, 1_000_000, 100, source)
+            .await
+            .unwrap();
+        assert!(opportunity.execution_plan[0].amount_in <= 1_000_000);
     }
 }
@@ -6,14 +6,15 @@ use anyhow::Result;
 use solana_client::nonblocking::rpc_client::RpcClient;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
-use tokio::sync::mpsc;
 use tokio::time::{interval, sleep};
 use tracing::{warn};
 
 use crate::arbitrage::types::{ArbOpportunity, ArbitrageEngineOptions, TokenInArb};
 use crate::arbitrage::config::ArbitrageConfig;
+use crate::arbitrage::dedup::OpportunityDeduplicator;
 use crate::common::config::Config;
-use crate::markets::pools::Pool;
+use crate::markets::pools::{Pool, PoolToken};
+use crate::markets::types::DexLabel;
 
 /// Enhanced arbitrage opportunity stream with circuit breaker and rate limiting
 pub struct ArbitrageOpportunityStream {
@@ -48,7 +49,7 @@ impl ArbitrageOpportunityStream {
     /// Start the arbitrage opportunity stream
     pub async fn start_stream(
         &self,
-        opportunity_sender: mpsc::Sender<ArbOpportunity>,
+        opportunity_sender: OpportunityDeduplicator,
     ) -> Result<()> {
         let mut fetch_interval = interval(Duration::from_millis(self.options.fetch_interval_ms));
         let circuit_breaker_threshold = self.arbitrage_config.breaker_threshold;
@@ -111,19 +112,11 @@ impl ArbitrageOpportunityStream {
     async fn get_active_pools(&self) -> Result<Vec<Pool>> {
         // Simplified pool fetching - should be replaced with real-time pool data
         // For now, return a basic set of pools for testing
+        let sol = PoolToken::new(solana_sdk::pubkey!("So11111111111111111111111111111111111111112"), Some(9));
+        let usdc = PoolToken::new(solana_sdk::pubkey!("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v"), Some(6));
         Ok(vec![
-            Pool {
-                id: "test_pool_1".to_string(),
-                token_a: "So11111111111111111111111111111111111111112".to_string(), // SOL
-                token_b: "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v".to_string(), // USDC
-                liquidity: 50000.0,
-            },
-            Pool {
-                id: "test_pool_2".to_string(),
-                token_a: "So11111111111111111111111111111111111111112".to_string(), // SOL
-                token_b: "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v".to_string(), // USDC
-                liquidity: 75000.0,
-            },
+            Pool::new("test_pool_1".to_string(), Some(DexLabel::Raydium), None, sol, usdc, 50000.0),
+            Pool::new("test_pool_2".to_string(), Some(DexLabel::Orca), None, sol, usdc, 75000.0),
         ])
     }
 
@@ -136,7 +129,7 @@ impl ArbitrageOpportunityStream {
         let mut pools_by_pair: std::collections::HashMap<String, Vec<&Pool>> = std::collections::HashMap::new();
         
         for pool in pools {
-            let pair_key = format!("{}-{}", pool.token_a, pool.token_b);
+            let pair_key = format!("{}-{}", pool.token_a.mint, pool.token_b.mint);
             pools_by_pair.entry(pair_key).or_insert_with(Vec::new).push(pool);
         }
         
@@ -187,10 +180,14 @@ impl ArbitrageOpportunityStream {
                 .duration_since(std::time::UNIX_EPOCH)
                 .unwrap_or_default()
                 .as_nanos(),
+            slot: None, // Mock figures, not computed from pool states
+            generation: 0,
             execution_plan: vec![], // Will be filled by execution planner
             metadata: crate::arbitrage::types::OpportunityMetadata {
                 estimated_gas_cost: self.arbitrage_config.gas_cost_lamports,
                 net_profit_lamports: expected_profit_lamports as i64 - self.arbitrage_config.gas_cost_lamports as i64,
+                expected_profit_usd_cents: (expected_profit_usd * 100.0).round() as i64,
+                valuation_confidence_bps: 0,
                 profit_percentage_bps: (price_diff_ratio * 10000.0) as u16,
                 risk_score: 50, // Medium risk
                 source: crate::arbitrage::types::OpportunitySource::PriceDiscrepancy {
@@ -1,6 +1,8 @@
 //! src/arbitrage/types.rs - HFT-Optimized Types
 //! All calculations done during discovery, execution just builds instructions
 
+use crate::markets::pools::{Pool, PoolState};
+use crate::markets::token_2022::{net_of_transfer_fees, MintInfo};
 use crate::markets::types::DexLabel;
 use serde::{Deserialize, Serialize};
 use solana_sdk::pubkey::Pubkey;
@@ -73,6 +75,14 @@ pub struct ArbOpportunity {
     
     /// When this opportunity was discovered (Unix nanoseconds)
     pub timestamp_unix_nanos: u128,
+
+    /// Slot of the pool states it was computed from; `None` when not computed from
+    /// on-chain state, which the executor treats as expired
+    pub slot: Option<u64>,
+
+    /// Bumped by the `OpportunityDeduplicator` on every copy of a trade it sends, so the
+    /// executor replaces the queued copy; 0 when it wasn't deduplicated
+    pub generation: u64,
     
     /// Pre-calculated execution plan with all swap details
     pub execution_plan: Vec<SwapLeg>,
@@ -89,6 +99,13 @@ pub struct OpportunityMetadata {
     
     /// Net profit after gas (expected_profit_lamports - estimated_gas_cost)
     pub net_profit_lamports: i64,
+
+    /// Expected profit in USD cents
+    pub expected_profit_usd_cents: i64,
+
+    /// Confidence in the USD and lamport values (10_000 = certain), 0 when the profit
+    /// wasn't valued through the `ValuationService`
+    pub valuation_confidence_bps: u16,
     
     /// Profit percentage (net_profit / initial_amount * 100)
     pub profit_percentage_bps: u16, // Basis points (100 = 1%)
@@ -201,7 +218,38 @@ pub struct SwapRouteSimulation {
 
 // Helper functions for HFT calculations (no floating point in hot path!)
 
+impl PoolExecutionData {
+    /// Venue data for a swap through `pool`. Whirlpool tick spacing isn't kept on
+    /// `Pool`, so it's left 0 for the swap builder to read from the account.
+    pub fn for_pool(pool: &Pool, price_impact_bps: u16) -> Self {
+        match (&pool.dex, pool.state) {
+            (Some(DexLabel::Raydium), _) => Self::Raydium { amm_version: 4 },
+            (Some(DexLabel::OrcaWhirlpools), state) => Self::OrcaWhirlpools {
+                tick_spacing: 0,
+                current_tick: match state {
+                    PoolState::Concentrated { tick_current, .. } => Some(tick_current),
+                    _ => None,
+                },
+            },
+            (Some(DexLabel::Meteora), state) => Self::Meteora {
+                bin_id: match state {
+                    PoolState::Bins { active_id, .. } => Some(active_id),
+                    _ => None,
+                },
+                price_impact_bps,
+            },
+            _ => Self::Generic,
+        }
+    }
+}
+
 impl SwapLeg {
+    /// Least output accepted when `expected_amount_out` may slip by `max_slippage_bps`
+    pub fn minimum_out(expected_amount_out: u64, max_slippage_bps: u16) -> u64 {
+        let kept_bps = 10_000 - max_slippage_bps.min(10_000) as u128;
+        (expected_amount_out as u128 * kept_bps / 10_000) as u64
+    }
+
     /// Calculate slippage in basis points (100 = 1%)
     pub fn slippage_bps(&self) -> u16 {
         if self.expected_amount_out == 0 {
@@ -224,9 +272,64 @@ impl SwapLeg {
     pub fn is_slippage_acceptable(&self, max_slippage_bps: u16) -> bool {
         self.slippage_bps() <= max_slippage_bps
     }
+
+    /// Net Token-2022 transfer fees out of a leg quoted on gross amounts, so
+    /// `expected_amount_out` and `minimum_amount_out` are what the wallet receives.
+    /// Legs quoted by an engine that already models transfer fees must not be adjusted
+    /// twice.
+    pub fn apply_transfer_fees(&mut self, input: &MintInfo, output: &MintInfo, epoch: u64) {
+        self.expected_amount_out =
+            net_of_transfer_fees(self.amount_in, self.expected_amount_out, input, output, epoch);
+        self.minimum_amount_out =
+            net_of_transfer_fees(self.amount_in, self.minimum_amount_out, input, output, epoch);
+    }
+}
+
+/// Why a queued opportunity is no longer worth executing
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExpiryReason {
+    /// Discovered longer ago than its `max_latency_ms`
+    Latency { age_ms: u64, max_latency_ms: u16 },
+    /// Computed from pool states more than `max_age_slots` behind the chain
+    Slots { age_slots: u64, max_age_slots: u64 },
+    /// Not computed from on-chain pool states, so its age can't be told
+    UnknownSlot,
+}
+
+impl std::fmt::Display for ExpiryReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Latency { age_ms, max_latency_ms } => {
+                write!(f, "{}ms old, past its {}ms latency budget", age_ms, max_latency_ms)
+            }
+            Self::Slots { age_slots, max_age_slots } => {
+                write!(f, "pool states {} slots old, past the {} slot limit", age_slots, max_age_slots)
+            }
+            Self::UnknownSlot => write!(f, "not computed from on-chain pool states"),
+        }
+    }
 }
 
 impl ArbOpportunity {
+    /// Why the opportunity has expired at `now_unix_nanos` and `current_slot`, if it
+    /// has. An opportunity without a slot has expired; the age check is skipped while
+    /// the chain's slot is unknown (0).
+    pub fn expiry(&self, now_unix_nanos: u128, current_slot: u64, max_age_slots: u64) -> Option<ExpiryReason> {
+        let age_ms = (now_unix_nanos.saturating_sub(self.timestamp_unix_nanos) / 1_000_000) as u64;
+        let max_latency_ms = self.metadata.max_latency_ms;
+        if age_ms > max_latency_ms as u64 {
+            return Some(ExpiryReason::Latency { age_ms, max_latency_ms });
+        }
+        let Some(slot) = self.slot else {
+            return Some(ExpiryReason::UnknownSlot);
+        };
+        let age_slots = current_slot.saturating_sub(slot);
+        if current_slot > 0 && age_slots > max_age_slots {
+            return Some(ExpiryReason::Slots { age_slots, max_age_slots });
+        }
+        None
+    }
+
     /// Total amount in for the arbitrage (first leg input)
     pub fn initial_amount(&self) -> u64 {
         self.execution_plan.first()
@@ -258,6 +361,44 @@ impl ArbOpportunity {
     }
 }
 
+#[cfg(test)]
+impl ArbOpportunity {
+    /// Opportunity selling `tokens[i]` into `pools[i]`, priced at `slot`, with no plan
+    pub(crate) fn through_pools(pools: &[Pubkey], tokens: &[Pubkey], profit: u64, slot: u64) -> Self {
+        let paths: Vec<Route> = pools
+            .iter()
+            .zip(tokens)
+            .enumerate()
+            .map(|(i, (pool, token_in))| Route {
+                id: i as u32,
+                dex: DexLabel::Raydium,
+                pool_address: pool.to_string(),
+                token_in: token_in.to_string(),
+                token_out: tokens[(i + 1) % tokens.len()].to_string(),
+                token_0to1: true,
+            })
+            .collect();
+        ArbOpportunity {
+            path: SwapPath { id_paths: (0..paths.len() as u32).collect(), hops: paths.len(), paths },
+            expected_profit_lamports: profit,
+            timestamp_unix_nanos: 0,
+            slot: Some(slot),
+            generation: 0,
+            execution_plan: vec![],
+            metadata: OpportunityMetadata {
+                estimated_gas_cost: 5_000,
+                net_profit_lamports: profit as i64 - 5_000,
+                expected_profit_usd_cents: 0,
+                valuation_confidence_bps: 0,
+                profit_percentage_bps: 0,
+                risk_score: 30,
+                source: OpportunitySource::StrategyScan { strategy_name: "test".to_string() },
+                max_latency_ms: 400,
+            },
+        }
+    }
+}
+
 /// Enhanced arbitrage engine options for production use
 #[derive(Debug, Clone)]
 pub struct ArbitrageEngineOptions {
@@ -279,3 +420,36 @@ impl Default for ArbitrageEngineOptions {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expiry_by_latency_then_slot() {
+        let opportunity = ArbOpportunity {
+            timestamp_unix_nanos: 10_000_000_000,
+            ..ArbOpportunity::through_pools(&[], &[], 1_000_000, 300)
+        };
+        let ms = 1_000_000u128;
+
+        assert_eq!(opportunity.expiry(10_000_000_000 + 400 * ms, 302, 2), None);
+        assert_eq!(
+            opportunity.expiry(10_000_000_000 + 401 * ms, 302, 2),
+            Some(ExpiryReason::Latency { age_ms: 401, max_latency_ms: 400 })
+        );
+        assert_eq!(
+            opportunity.expiry(10_000_000_000 + 100 * ms, 303, 2),
+            Some(ExpiryReason::Slots { age_slots: 3, max_age_slots: 2 })
+        );
+        // An unknown chain slot leaves only the latency check; an unknown opportunity
+        // slot has expired
+        assert_eq!(opportunity.expiry(10_000_000_000, 0, 2), None);
+        assert_eq!(
+            ArbOpportunity { slot: None, ..opportunity.clone() }.expiry(10_000_000_000, 1_000, 2),
+            Some(ExpiryReason::UnknownSlot)
+        );
+        // A clock that went backwards isn't an expiry
+        assert_eq!(opportunity.expiry(0, 300, 2), None);
+    }
+}
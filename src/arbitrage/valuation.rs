@@ -0,0 +1,391 @@
+//! USD and SOL valuation of arbitrary tokens
+//!
+//! A cycle's profit comes out in whatever token it starts from, so holding it against
+//! `min_profit_usd` or the risk limits needs a price for that token. Prices are
+//! propagated through the pool graph from the anchors (every mint with a fresh oracle
+//! price, plus USDC) along the route whose shallowest pool is deepest, up to
+//! `MAX_ROUTE_HOPS` pools away. A token reachable from several anchors keeps the
+//! deepest route.
+//!
+//! Routes are stored relative to their anchor, so USD values follow the oracle between
+//! graph refreshes. Every price carries a confidence in [0, 1] that combines the
+//! anchor's oracle confidence interval with the route's depth and length.
+
+use crate::data::oracle::PriceOracle;
+use crate::markets::pools::{Pool, PoolRegistry};
+use anchor_spl::token::spl_token;
+use arc_swap::ArcSwap;
+use solana_sdk::pubkey::Pubkey;
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tracing::{debug, warn};
+
+pub const USDC_MINT: Pubkey = solana_sdk::pubkey!("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v");
+
+/// Longest route priced, in pools
+pub const MAX_ROUTE_HOPS: u8 = 3;
+
+/// TVL of a route's shallowest pool from which depth no longer lowers confidence
+const FULL_CONFIDENCE_DEPTH_USD: f64 = 250_000.0;
+
+/// Confidence kept for every pool a route crosses
+const HOP_CONFIDENCE: f64 = 0.95;
+
+/// Confidence in USDC at $1 when the oracle has no price for it
+const ASSUMED_PEG_CONFIDENCE: f64 = 0.9;
+
+const LAMPORTS_PER_SOL: f64 = 1_000_000_000.0;
+
+/// A token's route to an anchor, as of the last graph refresh
+#[derive(Debug, Clone, PartialEq)]
+pub struct Valuation {
+    pub mint: Pubkey,
+    /// Anchor the route ends at
+    pub anchor: Pubkey,
+    /// Whole anchor tokens per whole token
+    pub price_in_anchor: f64,
+    pub decimals: Option<u8>,
+    /// Pools crossed; 0 for anchors
+    pub hops: u8,
+    /// TVL of the shallowest pool on the route; infinite for anchors
+    pub route_depth_usd: f64,
+}
+
+impl Valuation {
+    fn anchor(mint: Pubkey, decimals: Option<u8>) -> Self {
+        Self {
+            mint,
+            anchor: mint,
+            price_in_anchor: 1.0,
+            decimals,
+            hops: 0,
+            route_depth_usd: f64::INFINITY,
+        }
+    }
+
+    /// Confidence in the route alone, leaving out the anchor's own
+    pub fn route_confidence(&self) -> f64 {
+        if self.hops == 0 {
+            return 1.0;
+        }
+        HOP_CONFIDENCE.powi(self.hops as i32) * (self.route_depth_usd / FULL_CONFIDENCE_DEPTH_USD).min(1.0)
+    }
+}
+
+/// A token's current USD price
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TokenPrice {
+    pub usd: f64,
+    pub confidence: f64,
+}
+
+/// An amount valued in both USD and lamports
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ValuedAmount {
+    pub usd: f64,
+    pub lamports: i64,
+    pub confidence: f64,
+}
+
+impl ValuedAmount {
+    pub fn usd_cents(&self) -> i64 {
+        (self.usd * 100.0).round() as i64
+    }
+
+    pub fn confidence_bps(&self) -> u16 {
+        (self.confidence.clamp(0.0, 1.0) * 10_000.0).round() as u16
+    }
+}
+
+/// Prices tokens in USD and SOL through the pool graph
+pub struct ValuationService {
+    oracle: Arc<PriceOracle>,
+    valuations: ArcSwap<HashMap<Pubkey, Valuation>>,
+}
+
+impl ValuationService {
+    pub fn new(oracle: Arc<PriceOracle>) -> Self {
+        Self {
+            oracle,
+            valuations: ArcSwap::from_pointee(HashMap::new()),
+        }
+    }
+
+    /// Re-route every token reachable from an anchor through `pools`. Returns the
+    /// number of tokens priced.
+    pub fn refresh(&self, pools: &[Pool]) -> usize {
+        let anchors: HashMap<Pubkey, f64> = self
+            .oracle
+            .mints()
+            .into_iter()
+            .chain([USDC_MINT])
+            .filter_map(|mint| Some((mint, self.anchor_price(&mint)?.usd)))
+            .collect();
+        let valuations = route_valuations(pools, &anchors);
+        let priced = valuations.len();
+        self.valuations.store(Arc::new(valuations));
+        debug!("Valued {} tokens from {} anchors", priced, anchors.len());
+        priced
+    }
+
+    /// Refresh from `registry` every `period`, starting now
+    pub fn spawn_refresher(self: &Arc<Self>, registry: Arc<PoolRegistry>, period: Duration) -> JoinHandle<()> {
+        let service = Arc::clone(self);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(period);
+            loop {
+                interval.tick().await;
+                match registry.get_pools(false).await {
+                    Ok(pools) => {
+                        service.refresh(&pools);
+                    }
+                    Err(e) => warn!("Skipping valuation refresh, pools unavailable: {}", e),
+                }
+            }
+        })
+    }
+
+    pub fn valuation(&self, mint: &Pubkey) -> Option<Valuation> {
+        self.valuations.load().get(mint).cloned()
+    }
+
+    fn anchor_price(&self, anchor: &Pubkey) -> Option<TokenPrice> {
+        match self.oracle.price(anchor) {
+            Some(price) => Some(TokenPrice {
+                usd: price.price,
+                confidence: (1.0 - price.confidence / price.price).max(0.0),
+            }),
+            None if *anchor == USDC_MINT => Some(TokenPrice {
+                usd: 1.0,
+                confidence: ASSUMED_PEG_CONFIDENCE,
+            }),
+            None => None,
+        }
+    }
+
+    /// USD price of one whole `mint` token, if it's routed to an anchor with a price
+    pub fn usd_price(&self, mint: &Pubkey) -> Option<TokenPrice> {
+        let valuation = self.valuation(mint)?;
+        let anchor = self.anchor_price(&valuation.anchor)?;
+        Some(TokenPrice {
+            usd: valuation.price_in_anchor * anchor.usd,
+            confidence: anchor.confidence * valuation.route_confidence(),
+        })
+    }
+
+    pub fn sol_usd(&self) -> Option<TokenPrice> {
+        self.usd_price(&spl_token::native_mint::id())
+    }
+
+    /// `usd` in lamports at the current SOL price
+    pub fn value_usd(&self, usd: f64) -> Option<ValuedAmount> {
+        let sol = self.sol_usd()?;
+        Some(ValuedAmount {
+            usd,
+            lamports: (usd / sol.usd * LAMPORTS_PER_SOL) as i64,
+            confidence: sol.confidence,
+        })
+    }
+
+    /// A raw `amount` of `mint` in USD and lamports. Needs the mint's decimals, which
+    /// come from the pools it was routed through.
+    pub fn value_amount(&self, mint: &Pubkey, amount: i64) -> Option<ValuedAmount> {
+        let decimals = self.valuation(mint)?.decimals?;
+        let price = self.usd_price(mint)?;
+        let whole_tokens = amount as f64 / 10f64.powi(decimals as i32);
+        let valued = self.value_usd(whole_tokens * price.usd)?;
+        Some(ValuedAmount {
+            confidence: valued.confidence.min(price.confidence),
+            ..valued
+        })
+    }
+}
+
+/// A neighbour across a pool
+struct Edge {
+    to: Pubkey,
+    /// Whole tokens of the near side per whole `to` token
+    rate: f64,
+    /// The pool's TVL, when the DEX API reported it
+    tvl_usd: Option<f64>,
+    /// Whole tokens of the near side in the pool's reserves
+    near_reserve: Option<f64>,
+}
+
+impl Edge {
+    /// The pool's TVL, or twice its near-side reserves at `near_usd` a token when the
+    /// TVL isn't known. Zero when neither is.
+    fn depth_usd(&self, near_usd: f64) -> f64 {
+        self.tvl_usd
+            .or_else(|| Some(2.0 * self.near_reserve? * near_usd))
+            .unwrap_or(0.0)
+    }
+}
+
+/// A tentative route; routes with deeper bottlenecks, then fewer hops, come first
+struct Candidate(Valuation);
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0
+            .route_depth_usd
+            .total_cmp(&other.0.route_depth_usd)
+            .then_with(|| other.0.hops.cmp(&self.0.hops))
+    }
+}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Candidate {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Candidate {}
+
+/// Widest-path search from all anchors at once, given their USD prices: the first route
+/// to settle a token is the one with the deepest shallowest pool
+fn route_valuations(pools: &[Pool], anchors: &HashMap<Pubkey, f64>) -> HashMap<Pubkey, Valuation> {
+    let mut decimals: HashMap<Pubkey, u8> = HashMap::new();
+    let mut edges: HashMap<Pubkey, Vec<Edge>> = HashMap::new();
+    for pool in pools {
+        for token in [pool.token_a, pool.token_b] {
+            if let Some(token_decimals) = token.decimals {
+                decimals.insert(token.mint, token_decimals);
+            }
+        }
+        let (Some(price_a), Some(price_b)) = (pool.price_of(&pool.token_a.mint), pool.price_of(&pool.token_b.mint)) else {
+            continue;
+        };
+        edges.entry(pool.token_a.mint).or_default().push(Edge {
+            to: pool.token_b.mint,
+            rate: price_b,
+            tvl_usd: pool.liquidity_usd(),
+            near_reserve: pool.reserve_of(&pool.token_a.mint),
+        });
+        edges.entry(pool.token_b.mint).or_default().push(Edge {
+            to: pool.token_a.mint,
+            rate: price_a,
+            tvl_usd: pool.liquidity_usd(),
+            near_reserve: pool.reserve_of(&pool.token_b.mint),
+        });
+    }
+
+    let mut settled: HashMap<Pubkey, Valuation> = HashMap::new();
+    let mut frontier: BinaryHeap<Candidate> = anchors
+        .keys()
+        .map(|anchor| Candidate(Valuation::anchor(*anchor, decimals.get(anchor).copied())))
+        .collect();
+    while let Some(Candidate(valuation)) = frontier.pop() {
+        if settled.contains_key(&valuation.mint) {
+            continue;
+        }
+        if valuation.hops < MAX_ROUTE_HOPS {
+            let near_usd = valuation.price_in_anchor * anchors[&valuation.anchor];
+            for edge in edges.get(&valuation.mint).into_iter().flatten() {
+                if settled.contains_key(&edge.to) || edge.to == valuation.mint {
+                    continue;
+                }
+                frontier.push(Candidate(Valuation {
+                    mint: edge.to,
+                    anchor: valuation.anchor,
+                    price_in_anchor: valuation.price_in_anchor * edge.rate,
+                    decimals: decimals.get(&edge.to).copied(),
+                    hops: valuation.hops + 1,
+                    route_depth_usd: valuation.route_depth_usd.min(edge.depth_usd(near_usd)),
+                }));
+            }
+        }
+        settled.insert(valuation.mint, valuation);
+    }
+    settled
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::markets::pools::PoolToken;
+
+    const SOL: Pubkey = solana_sdk::pubkey!("So11111111111111111111111111111111111111112");
+
+    fn pool(token_a: PoolToken, token_b: PoolToken, reserve_a: u64, reserve_b: u64, liquidity: f64) -> Pool {
+        Pool { token_a, token_b, liquidity, ..Pool::with_reserves(token_a.mint, token_b.mint, reserve_a, reserve_b) }
+    }
+
+    #[test]
+    fn test_tokens_follow_the_deepest_route() {
+        let bonk = PoolToken::new(Pubkey::new_unique(), Some(5));
+        let (sol, usdc) = (PoolToken::new(SOL, Some(9)), PoolToken::new(USDC_MINT, Some(6)));
+        let pools = vec![
+            // 1 SOL = 150 USDC
+            pool(sol, usdc, 10_000 * 1_000_000_000, 1_500_000 * 1_000_000, 3_000_000.0),
+            // 1 BONK = 0.0000002 SOL ($0.00003)
+            pool(bonk, sol, 5_000_000_000 * 100_000, 1_000 * 1_000_000_000, 300_000.0),
+            // A shallow pool quoting BONK at twice that
+            pool(bonk, usdc, 100_000_000 * 100_000, 6_000 * 1_000_000, 12_000.0),
+        ];
+        let service = ValuationService::new(Arc::new(PriceOracle::new(HashMap::new(), 30, 100)));
+        assert_eq!(service.refresh(&pools), 3);
+
+        let usdc_price = service.usd_price(&USDC_MINT).unwrap();
+        assert_eq!(usdc_price, TokenPrice { usd: 1.0, confidence: ASSUMED_PEG_CONFIDENCE });
+
+        let sol_valuation = service.valuation(&SOL).unwrap();
+        assert_eq!((sol_valuation.anchor, sol_valuation.hops), (USDC_MINT, 1));
+        assert!((service.sol_usd().unwrap().usd - 150.0).abs() < 1e-9);
+
+        let bonk_valuation = service.valuation(&bonk.mint).unwrap();
+        assert_eq!(bonk_valuation.hops, 2);
+        assert_eq!(bonk_valuation.route_depth_usd, 300_000.0);
+        let bonk_price = service.usd_price(&bonk.mint).unwrap();
+        assert!((bonk_price.usd - 0.00003).abs() < 1e-12);
+        assert!(bonk_price.confidence < usdc_price.confidence);
+
+        // 10M BONK is $300, or 2 SOL
+        let valued = service.value_amount(&bonk.mint, 10_000_000 * 100_000).unwrap();
+        assert_eq!(valued.usd_cents(), 30_000);
+        assert!((valued.lamports - 2_000_000_000).abs() <= 1);
+        assert_eq!(valued.confidence, bonk_price.confidence);
+
+        // Unrouted tokens have no price
+        assert!(service.usd_price(&Pubkey::new_unique()).is_none());
+    }
+
+    #[test]
+    fn test_routes_are_bounded_in_length() {
+        let mut tokens = vec![PoolToken::new(USDC_MINT, Some(6))];
+        tokens.extend((0..=MAX_ROUTE_HOPS).map(|_| PoolToken::new(Pubkey::new_unique(), Some(6))));
+        let pools: Vec<Pool> = tokens
+            .windows(2)
+            .map(|pair| pool(pair[0], pair[1], 1_000_000, 2_000_000, 500_000.0))
+            .collect();
+        let valuations = route_valuations(&pools, &HashMap::from([(USDC_MINT, 1.0)]));
+
+        assert_eq!(valuations.len(), MAX_ROUTE_HOPS as usize + 1);
+        assert!(!valuations.contains_key(&tokens.last().unwrap().mint));
+        // Each hop halves the price in USDC
+        let furthest = &valuations[&tokens[MAX_ROUTE_HOPS as usize].mint];
+        assert!((furthest.price_in_anchor - 0.5f64.powi(MAX_ROUTE_HOPS as i32)).abs() < 1e-12);
+    }
+    #[test]
+    fn test_unknown_tvl_is_read_from_reserves() {
+        let wif = PoolToken::new(Pubkey::new_unique(), Some(6));
+        let usdc = PoolToken::new(USDC_MINT, Some(6));
+        // Found on chain: 50k USDC against 20k WIF, no TVL from an API
+        let pools = vec![pool(usdc, wif, 50_000 * 1_000_000, 20_000 * 1_000_000, 0.0)];
+        let valuations = route_valuations(&pools, &HashMap::from([(USDC_MINT, 1.0)]));
+
+        let wif_valuation = &valuations[&wif.mint];
+        assert!((wif_valuation.price_in_anchor - 2.5).abs() < 1e-12);
+        assert_eq!(wif_valuation.route_depth_usd, 100_000.0);
+        assert!(wif_valuation.route_confidence() > 0.0);
+    }
+}
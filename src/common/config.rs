@@ -1,5 +1,6 @@
 //! src/common/config.rs - Updated with missing fields
 
+use crate::arbitrage::config::ArbitrageConfig;
 use serde::{Deserialize, Serialize};
 use std::fs;
 
@@ -25,6 +26,7 @@ pub struct StrategyInputConfig {
     pub include_1hop: Option<bool>,
     pub include_2hop: Option<bool>,
     pub numbers_of_best_paths: Option<usize>,
+    pub base_token: Option<String>,                   // Default: SOL (wrapped), Triangular only
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -45,6 +47,14 @@ impl From<&TokenConfig> for crate::arbitrage::types::TokenInArb {
     }
 }
 
+/// A Pyth price account and the token it prices in USD
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OracleFeedConfig {
+    pub symbol: String,
+    pub mint: String,
+    pub price_account: String,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
     // Simple RPC configuration
@@ -59,6 +69,7 @@ pub struct Config {
     // Strategy settings
     pub active_strategies: Vec<String>,
     pub massive_strategy_inputs: Vec<StrategyInputConfig>,
+    pub triangular_strategy_inputs: Option<Vec<StrategyInputConfig>>, // Default: none
     pub path_best_strategy: String,
     pub top_n_ultra_paths: Option<usize>,
 
@@ -92,9 +103,38 @@ pub struct Config {
     
     // Queue management
     pub max_queue_size: Option<usize>,                // Default: 1000
+    pub opportunity_dedup_ttl_ms: Option<u64>,        // Default: 1_000
+    pub opportunity_max_age_slots: Option<u64>,       // Default: 4 (~1.6s)
     
     // Slippage settings
     pub max_slippage_bps: Option<u16>,                // Default: 100 (1%)
+
+    // Cycle detection and sizing settings
+    pub arbitrage: Option<ArbitrageConfig>,           // Default: ArbitrageConfig::default()
+    pub market_max_age_slots: Option<u64>,            // Default: 150 (~1 minute)
+
+    // Pool state snapshot settings
+    pub pool_snapshot_path: Option<String>,           // Default: "output/pool_snapshot.bin"
+    pub pool_snapshot_interval_secs: Option<u64>,     // Default: 60
+    pub pool_snapshot_max_age_slots: Option<u64>,     // Default: 9_000 (~1 hour)
+
+    // On-chain pool discovery settings
+    pub pool_discovery_enabled: Option<bool>,         // Default: true
+    pub pool_discovery_requests_per_sec: Option<u32>, // Default: 5
+    pub pool_discovery_max_concurrent: Option<usize>, // Default: 4
+
+    // Token metadata settings
+    pub token_cache_path: Option<String>,             // Default: "output/token_cache.bin"
+    pub token_metadata_max_age_secs: Option<u64>,     // Default: 86_400 (1 day)
+
+    // Oracle settings
+    pub oracle_feeds: Option<Vec<OracleFeedConfig>>,  // Default: Pyth SOL/USD and USDC/USD
+    pub oracle_max_staleness_secs: Option<u64>,       // Default: 30
+    pub oracle_max_confidence_bps: Option<u16>,       // Default: 100 (1%)
+    pub valuation_refresh_interval_secs: Option<u64>, // Default: 10
+
+    // Wallet settings
+    pub wallet_refresh_interval_secs: Option<u64>,    // Default: 10
 }
 
 impl Default for Config {
@@ -107,6 +147,7 @@ impl Default for Config {
             simulation_amount: 1_000_000_000,
             active_strategies: vec!["Massive".to_string()],
             massive_strategy_inputs: vec![],
+            triangular_strategy_inputs: None,
             path_best_strategy: "BestPath".to_string(),
             top_n_ultra_paths: Some(5),
             executor_queue_size: Some(100),
@@ -132,7 +173,24 @@ impl Default for Config {
             paper_trade_mock_execution_time_ms: Some(100),
             fee_cache_duration_secs: Some(2),
             max_queue_size: Some(1000),
+            opportunity_dedup_ttl_ms: Some(1_000),
+            opportunity_max_age_slots: Some(4),
             max_slippage_bps: Some(100),
+            pool_snapshot_path: Some("output/pool_snapshot.bin".to_string()),
+            pool_snapshot_interval_secs: Some(60),
+            pool_snapshot_max_age_slots: Some(9_000),
+            pool_discovery_enabled: Some(true),
+            pool_discovery_requests_per_sec: Some(5),
+            pool_discovery_max_concurrent: Some(4),
+            token_cache_path: Some("output/token_cache.bin".to_string()),
+            token_metadata_max_age_secs: Some(86_400),
+            oracle_feeds: None,
+            oracle_max_staleness_secs: Some(30),
+            oracle_max_confidence_bps: Some(100),
+            valuation_refresh_interval_secs: Some(10),
+            wallet_refresh_interval_secs: Some(10),
+            arbitrage: Some(ArbitrageConfig::default()),
+            market_max_age_slots: Some(150),
         }
     }
 }
@@ -207,6 +265,7 @@ fn expand_env_vars(input: &str) -> String {
 // Strategy constants
 pub const STRATEGY_MASSIVE: &str = "Massive";
 pub const STRATEGY_BEST_PATH: &str = "BestPath";
+pub const STRATEGY_TRIANGULAR: &str = "Triangular";
 
 // Execution mode constants
 pub const EXECUTION_MODE_LIVE: &str = "Live";
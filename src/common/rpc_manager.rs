@@ -307,7 +307,9 @@ mod tests {
                 include_1hop: Some(true),
                 include_2hop: Some(false),
                 numbers_of_best_paths: Some(10),
+                base_token: None,
             }],
+            triangular_strategy_inputs: None,
             path_best_strategy: "profit_first".to_string(),
             top_n_ultra_paths: Some(5),
             executor_queue_size: Some(100),
@@ -333,7 +335,24 @@ mod tests {
             paper_trade_mock_execution_time_ms: Some(100),
             fee_cache_duration_secs: Some(2),
             max_queue_size: Some(1000),
+            opportunity_dedup_ttl_ms: Some(1_000),
+            opportunity_max_age_slots: Some(4),
             max_slippage_bps: Some(100),
+            pool_snapshot_path: None,
+            pool_snapshot_interval_secs: None,
+            pool_snapshot_max_age_slots: None,
+            pool_discovery_enabled: None,
+            pool_discovery_requests_per_sec: None,
+            pool_discovery_max_concurrent: None,
+            token_cache_path: None,
+            token_metadata_max_age_secs: None,
+            oracle_feeds: None,
+            oracle_max_staleness_secs: None,
+            oracle_max_confidence_bps: None,
+            valuation_refresh_interval_secs: None,
+            wallet_refresh_interval_secs: None,
+            arbitrage: None,
+            market_max_age_slots: None,
         }
     }
 
@@ -16,6 +16,7 @@ use thiserror::Error;
 use crate::{
     arbitrage::types::{SwapPathResult, TokenInArb, TokenInfos},
     common::constants::{Env, PROJECT_NAME},
+    markets::token_registry::{TokenRegistry, TokenRegistryError},
 };
 use solana_client::nonblocking::rpc_client::RpcClient; // Changed to nonblocking
 
@@ -130,39 +131,31 @@ pub fn from_pubkey(pubkey: Pubkey) -> String {
     bs58::encode(pubkey).into_string()
 }
 
-pub async fn get_tokens_infos(tokens: Vec<TokenInArb>) -> HashMap<String, TokenInfos> {
+/// Token infos keyed by address, with decimals read from each mint. Configured
+/// symbols are kept; fails if any token isn't a mint or can't be fetched.
+pub async fn get_tokens_infos(tokens: Vec<TokenInArb>) -> Result<HashMap<String, TokenInfos>, TokenRegistryError> {
     let env = Env::new();
-    let rpc_client = RpcClient::new(env.rpc_url);
+    let registry = TokenRegistry::new(RpcClient::new(env.rpc_url), None, 0);
 
-    let mut pubkeys_str: Vec<String> = Vec::new();
-    let mut pubkeys: Vec<Pubkey> = Vec::new();
-    for token in tokens.clone() {
-        pubkeys_str.push(token.token.clone());
-        pubkeys.push(from_str(token.token.clone().as_str()).unwrap());
+    let mut mints: Vec<Pubkey> = Vec::with_capacity(tokens.len());
+    for token in &tokens {
+        mints.push(from_str(&token.token).map_err(|_| TokenRegistryError::InvalidAddress(token.token.clone()))?);
     }
-    let batch_results = rpc_client.get_multiple_accounts(&pubkeys).await.unwrap();
+    let resolved = registry.get_many(&mints).await?;
 
     let mut tokens_infos: HashMap<String, TokenInfos> = HashMap::new();
-
-    for (j, account) in batch_results.iter().enumerate() {
-        let account = account.clone().unwrap();
-        let mint_layout = MintLayout::try_from_slice(&account.data).unwrap();
-
-        let symbol = tokens
-            .iter()
-            .find(|r| r.token == pubkeys_str[j])
-            .expect("Symbol token not found");
+    for (token, mint) in tokens.into_iter().zip(mints) {
+        let metadata = resolved.get(&mint).ok_or(TokenRegistryError::NotFound(mint))?;
         tokens_infos.insert(
-            pubkeys_str[j].clone(),
+            token.token.clone(),
             TokenInfos {
-                address: pubkeys_str[j].clone(),
-                decimals: mint_layout.decimals,
-                // price_usd: 150.0, // Mock price, to be replaced by actual oracle/API call
-                symbol: symbol.clone().symbol,
+                address: token.token,
+                decimals: metadata.decimals,
+                symbol: token.symbol,
             },
         );
     }
-    tokens_infos
+    Ok(tokens_infos)
 }
 
 pub async fn make_request(req_url: String) -> Result<reqwest::Response, Error> {
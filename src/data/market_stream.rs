@@ -1,8 +1,17 @@
 //! src/data/market_stream.rs
 
 use crate::common::config::{Config, DataMode};
+use crate::data::oracle::PriceOracle;
+use crate::markets::account_decoders::AccountUpdateRouter;
+use crate::markets::lockless_cache::DataVersion;
+use crate::markets::market_loader::MarketLoader;
+use crate::markets::pools::{Pool, PoolEvent, PoolRegistry};
 use anyhow::Result;
 use futures_util::{StreamExt, SinkExt};
+use solana_sdk::pubkey::Pubkey;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use tokio::sync::broadcast::{self, error::RecvError};
 use tokio::sync::mpsc;
 use tokio::time::{timeout, Duration, interval};
 use tokio_tungstenite::{connect_async, tungstenite::{Message, client::IntoClientRequest}};
@@ -16,13 +25,27 @@ pub struct MarketEvent {
     pub source: String, // e.g., "Pyth", "Raydium"
 }
 
-pub async fn init_market_data(config: &Config) -> Result<mpsc::Receiver<MarketEvent>> {
+/// Start the market data listener. With `pool_registry`, the WebSocket listener
+/// subscribes to the accounts of the registry's pools and of those added later,
+/// unsubscribes when they're removed, and hands their updates back to the
+/// registry. With `markets`, it also loads each pool's quote engine, subscribes to
+/// the accounts the engine reads, and routes their updates through the loader's
+/// `AccountUpdateRouter`. With `oracle`, it also subscribes to the oracle's price
+/// accounts and feeds their updates to it.
+pub async fn init_market_data(
+    config: &Config,
+    pool_registry: Option<Arc<PoolRegistry>>,
+    markets: Option<Arc<MarketLoader>>,
+    oracle: Option<Arc<PriceOracle>>,
+) -> Result<mpsc::Receiver<MarketEvent>> {
     let (tx, rx) = mpsc::channel(1000);
 
     match &config.data_mode {
         DataMode::WebSocket(url) => {
+            // Subscribe before spawning so no event between here and connecting is lost
+            let pool_events = pool_registry.as_ref().map(|registry| registry.subscribe());
             tokio::spawn(
-                ws_listener(url.clone(), tx)
+                ws_listener(url.clone(), tx, pool_registry, pool_events, markets, oracle)
                     .instrument(info_span!("ws_listener")),
             );
         }
@@ -37,7 +60,14 @@ pub async fn init_market_data(config: &Config) -> Result<mpsc::Receiver<MarketEv
     Ok(rx)
 }
 
-async fn ws_listener(url: String, tx: mpsc::Sender<MarketEvent>) -> Result<()> {
+async fn ws_listener(
+    url: String,
+    tx: mpsc::Sender<MarketEvent>,
+    pool_registry: Option<Arc<PoolRegistry>>,
+    pool_events: Option<broadcast::Receiver<PoolEvent>>,
+    markets: Option<Arc<MarketLoader>>,
+    oracle: Option<Arc<PriceOracle>>,
+) -> Result<()> {
     // TODO: Move Helius API key to configuration
     // SECURITY: API key should be loaded from config, not hardcoded
     let helius_url = std::env::var("HELIUS_WS_URL")
@@ -57,7 +87,7 @@ async fn ws_listener(url: String, tx: mpsc::Sender<MarketEvent>) -> Result<()> {
         match connect_with_robust_config(endpoint_url).await {
             Ok(ws_stream) => {
                 info!("✅ WebSocket connected successfully to endpoint {}!", i + 1);
-                return handle_websocket_stream(ws_stream, tx).await;
+                return handle_websocket_stream(ws_stream, tx, pool_registry, pool_events, markets, oracle).await;
             },
             Err(e) => {
                 error!("❌ Endpoint {} failed: {}", i + 1, e);
@@ -107,8 +137,15 @@ async fn connect_with_robust_config(url: &str) -> Result<tokio_tungstenite::WebS
 /// Handle WebSocket stream with proper error handling and keep-alive
 async fn handle_websocket_stream(
     ws_stream: tokio_tungstenite::WebSocketStream<tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>>,
-    tx: mpsc::Sender<MarketEvent>
+    tx: mpsc::Sender<MarketEvent>,
+    pool_registry: Option<Arc<PoolRegistry>>,
+    mut pool_events: Option<broadcast::Receiver<PoolEvent>>,
+    markets: Option<Arc<MarketLoader>>,
+    oracle: Option<Arc<PriceOracle>>,
 ) -> Result<()> {
+    let mut account_subscriptions = AccountSubscriptions::new();
+    // Accounts read by engines loaded in the background, to subscribe to
+    let (loaded_tx, mut loaded_rx) = mpsc::channel::<Vec<Pubkey>>(100);
 
     let (mut sender, mut receiver) = ws_stream.split();
     
@@ -164,6 +201,31 @@ async fn handle_websocket_stream(
     
     sender.send(Message::Text(logs_subscription.to_string())).await?;
     info!("🎯 All subscriptions active: slot + account + logs for maximum coverage");
+
+    // Subscribe to oracle price accounts
+    let oracle_accounts = oracle.as_ref().map(|oracle| oracle.price_accounts()).unwrap_or_default();
+    for account in oracle_accounts {
+        if let Some(request) = account_subscriptions.subscribe(account) {
+            sender.send(Message::Text(request)).await?;
+        }
+    }
+
+    // Subscribe to the pools registered before connecting and their vaults, and load
+    // their engines
+    let pools = pool_registry.as_ref().map(|registry| registry.pools()).unwrap_or_default();
+    for pool in pools {
+        if pool.address.is_none() {
+            continue;
+        }
+        for account in pool_accounts(pool_registry.as_deref(), &pool) {
+            if let Some(request) = account_subscriptions.subscribe(account) {
+                sender.send(Message::Text(request)).await?;
+            }
+        }
+        if let Some(markets) = &markets {
+            spawn_market_load(markets.clone(), pool, loaded_tx.clone());
+        }
+    }
     
     // Set up keep-alive ping interval
     let mut ping_interval = interval(Duration::from_secs(30));
@@ -172,6 +234,50 @@ async fn handle_websocket_stream(
     // Main message processing loop with keep-alive
     loop {
         tokio::select! {
+            // Follow the pool registry
+            event = next_pool_event(&mut pool_events) => {
+                let requests: Vec<String> = match event {
+                    PoolEvent::PoolAdded(pool) => {
+                        let requests = pool_accounts(pool_registry.as_deref(), &pool)
+                            .into_iter()
+                            .filter_map(|account| account_subscriptions.subscribe(account))
+                            .collect();
+                        if let Some(markets) = &markets {
+                            spawn_market_load(markets.clone(), pool, loaded_tx.clone());
+                        }
+                        requests
+                    }
+                    PoolEvent::PoolRemoved(pool) => {
+                        let released = match (&markets, pool.address) {
+                            (Some(markets), Some(address)) => markets.unload(&address),
+                            _ => Vec::new(),
+                        };
+                        pool_accounts(pool_registry.as_deref(), &pool)
+                            .into_iter()
+                            .chain(released)
+                            .filter_map(|account| account_subscriptions.unsubscribe(&account))
+                            .collect()
+                    }
+                    PoolEvent::PoolChanged { .. } => Vec::new(),
+                };
+                for request in requests {
+                    if let Err(e) = sender.send(Message::Text(request)).await {
+                        error!("❌ Failed to update pool subscriptions: {}", e);
+                        return Ok(());
+                    }
+                }
+            }
+
+            // Subscribe to what newly loaded engines read
+            Some(accounts) = loaded_rx.recv() => {
+                for request in accounts.into_iter().filter_map(|account| account_subscriptions.subscribe(account)) {
+                    if let Err(e) = sender.send(Message::Text(request)).await {
+                        error!("❌ Failed to update market subscriptions: {}", e);
+                        return Ok(());
+                    }
+                }
+            }
+
             // Handle keep-alive pings
             _ = ping_interval.tick() => {
                 if let Err(e) = sender.send(Message::Ping(vec![])).await {
@@ -204,8 +310,35 @@ async fn handle_websocket_stream(
                                         }
                                     },
                                     Some("accountNotification") => {
+                                        let account = parsed
+                                            .pointer("/params/subscription")
+                                            .and_then(|id| id.as_u64())
+                                            .and_then(|id| account_subscriptions.account_for(id));
+                                        // Oracle price accounts update the oracle only
+                                        let oracle_update = oracle.as_ref().zip(account);
+                                        // Registered pool accounts update the registry, which broadcasts the
+                                        // change, and every account an engine reads updates the engine
+                                        let router = markets.as_ref().map(|markets| markets.router().as_ref());
+                                        let routed = account.filter(|account| {
+                                            pool_registry.as_ref().is_some_and(|registry| registry.tracks(account))
+                                                || router.is_some_and(|router| router.routes(account))
+                                        });
+                                        if let Some((oracle, account)) = oracle_update.filter(|(oracle, account)| oracle.is_price_account(account)) {
+                                            if let Some(value) = parsed.pointer("/params/result/value") {
+                                                apply_oracle_notification(oracle, &account, value);
+                                            }
+                                        } else if let Some(account) = routed {
+                                            apply_account_notification(pool_registry.as_deref(), router, &account, &parsed).await;
+                                            // A pool's vaults are known once its account has been seen
+                                            let vaults = pool_registry.as_ref().map(|registry| registry.vaults_of(&account)).unwrap_or_default();
+                                            for request in vaults.into_iter().filter_map(|vault| account_subscriptions.subscribe(vault)) {
+                                                if let Err(e) = sender.send(Message::Text(request)).await {
+                                                    error!("❌ Failed to subscribe to pool vaults: {}", e);
+                                                    return Ok(());
+                                                }
+                                            }
                                         // Handle account updates (precise pool monitoring)
-                                        if let Some(params) = parsed.get("params") {
+                                        } else if let Some(params) = parsed.get("params") {
                                             if let Some(result) = params.get("result") {
                                                 if let Some(value) = result.get("value") {
                                                     // Parse pool state changes for price updates
@@ -229,6 +362,10 @@ async fn handle_websocket_stream(
                                             if let Some(result) = params.get("result") {
                                                 if let Some(slot) = result.get("slot") {
                                                     debug!("📊 Current slot: {}", slot);
+                                                    // Engines that stop receiving updates age against it
+                                                    if let (Some(markets), Some(slot)) = (&markets, slot.as_u64()) {
+                                                        markets.router().cache().observe_slot(slot);
+                                                    }
                                                 }
                                             }
                                         }
@@ -238,6 +375,16 @@ async fn handle_websocket_stream(
                                         debug!("Received WebSocket message: {}", method.as_str().unwrap_or("unknown"));
                                     }
                                 }
+                            } else if let (Some(request_id), Some(subscription_id)) = (
+                                parsed.get("id").and_then(|id| id.as_u64()),
+                                parsed.get("result").and_then(|result| result.as_u64()),
+                            ) {
+                                if let Some(request) = account_subscriptions.confirm(request_id, subscription_id) {
+                                    if let Err(e) = sender.send(Message::Text(request)).await {
+                                        error!("❌ Failed to update pool subscriptions: {}", e);
+                                        break;
+                                    }
+                                }
                             } else {
                                 debug!("Received WebSocket message without method field");
                             }
@@ -280,6 +427,192 @@ async fn handle_websocket_stream(
     Ok(())
 }
 
+/// Account subscriptions made on behalf of the pool registry and the price oracle
+struct AccountSubscriptions {
+    /// Request ids start past the fixed subscriptions made on connect
+    next_request_id: u64,
+    /// Accounts by request id, until the subscription is confirmed
+    pending: HashMap<u64, Pubkey>,
+    /// Subscription ids by account
+    active: HashMap<Pubkey, u64>,
+    /// Accounts by subscription id, to route notifications
+    by_subscription: HashMap<u64, Pubkey>,
+    /// Requests for pools removed before their subscription was confirmed
+    cancelled: HashSet<u64>,
+}
+
+impl AccountSubscriptions {
+    fn new() -> Self {
+        Self {
+            next_request_id: 100,
+            pending: HashMap::new(),
+            active: HashMap::new(),
+            by_subscription: HashMap::new(),
+            cancelled: HashSet::new(),
+        }
+    }
+
+    fn next_request_id(&mut self) -> u64 {
+        let id = self.next_request_id;
+        self.next_request_id += 1;
+        id
+    }
+
+    /// `accountSubscribe` request for `pool`, unless it's already subscribed
+    fn subscribe(&mut self, pool: Pubkey) -> Option<String> {
+        if self.active.contains_key(&pool) || self.pending.values().any(|pending| *pending == pool) {
+            return None;
+        }
+        let id = self.next_request_id();
+        self.pending.insert(id, pool);
+        Some(
+            serde_json::json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "method": "accountSubscribe",
+                "params": [pool.to_string(), { "encoding": "base64", "commitment": "confirmed" }]
+            })
+            .to_string(),
+        )
+    }
+
+    /// `accountUnsubscribe` request for `pool`, if it has a confirmed subscription.
+    /// A pending subscription is cancelled once it's confirmed.
+    fn unsubscribe(&mut self, pool: &Pubkey) -> Option<String> {
+        let cancelled: Vec<u64> = self
+            .pending
+            .iter()
+            .filter(|(_, pending)| *pending == pool)
+            .map(|(request_id, _)| *request_id)
+            .collect();
+        for request_id in cancelled {
+            self.pending.remove(&request_id);
+            self.cancelled.insert(request_id);
+        }
+        let subscription_id = self.active.remove(pool)?;
+        self.by_subscription.remove(&subscription_id);
+        Some(self.unsubscribe_request(subscription_id))
+    }
+
+    /// Record a subscription confirmation. Returns the `accountUnsubscribe` request to
+    /// send if the pool was removed while the subscription was pending.
+    fn confirm(&mut self, request_id: u64, subscription_id: u64) -> Option<String> {
+        if self.cancelled.remove(&request_id) {
+            return Some(self.unsubscribe_request(subscription_id));
+        }
+        if let Some(pool) = self.pending.remove(&request_id) {
+            debug!("📡 Subscribed to account {} ({})", pool, subscription_id);
+            self.active.insert(pool, subscription_id);
+            self.by_subscription.insert(subscription_id, pool);
+        }
+        None
+    }
+
+    /// Account behind a confirmed subscription
+    fn account_for(&self, subscription_id: u64) -> Option<Pubkey> {
+        self.by_subscription.get(&subscription_id).copied()
+    }
+
+    fn unsubscribe_request(&mut self, subscription_id: u64) -> String {
+        let id = self.next_request_id();
+        serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "method": "accountUnsubscribe",
+            "params": [subscription_id]
+        })
+        .to_string()
+    }
+}
+
+/// Next registry event; pends forever without a registry to follow
+async fn next_pool_event(pool_events: &mut Option<broadcast::Receiver<PoolEvent>>) -> PoolEvent {
+    loop {
+        let Some(receiver) = pool_events.as_mut() else {
+            return std::future::pending().await;
+        };
+        match receiver.recv().await {
+            Ok(event) => return event,
+            Err(RecvError::Lagged(skipped)) => {
+                warn!("Pool event stream lagged, {} events skipped", skipped);
+            }
+            Err(RecvError::Closed) => *pool_events = None,
+        }
+    }
+}
+
+/// Decode an oracle account notification and hand the data to the oracle
+fn apply_oracle_notification(oracle: &PriceOracle, account: &Pubkey, value: &serde_json::Value) {
+    use base64::{engine::general_purpose, Engine as _};
+    let Some(data) = value
+        .pointer("/data/0")
+        .and_then(|data| data.as_str())
+        .and_then(|data| general_purpose::STANDARD.decode(data).ok())
+    else {
+        debug!("Oracle notification for {} without base64 data", account);
+        return;
+    };
+    if let Err(e) = oracle.apply_account_update(account, &data) {
+        warn!("🔮 Rejected oracle price from {}: {}", account, e);
+    }
+}
+
+/// `pool`'s account and the vaults holding its reserves
+fn pool_accounts(registry: Option<&PoolRegistry>, pool: &Pool) -> Vec<Pubkey> {
+    let Some(address) = pool.address else {
+        return Vec::new();
+    };
+    let vaults = registry.map(|registry| registry.vaults_of(&address)).unwrap_or_default();
+    std::iter::once(address).chain(vaults).collect()
+}
+
+/// Load `pool`'s engine in the background and send the accounts it reads to `loaded`
+fn spawn_market_load(markets: Arc<MarketLoader>, pool: Pool, loaded: mpsc::Sender<Vec<Pubkey>>) {
+    tokio::spawn(async move {
+        match markets.load(&pool).await {
+            Ok(accounts) => {
+                debug!("⚙️ Loaded the quote engine of pool {}", pool.id);
+                let _ = loaded.send(accounts).await;
+            }
+            Err(e) => debug!("No quote engine for pool {}: {}", pool.id, e),
+        }
+    });
+}
+
+/// Decode an account notification and hand the account to the pool registry, when it's
+/// a pool account or vault, and to the engines of the pools that read it
+async fn apply_account_notification(
+    registry: Option<&PoolRegistry>,
+    router: Option<&AccountUpdateRouter>,
+    account: &Pubkey,
+    notification: &serde_json::Value,
+) {
+    use base64::{engine::general_purpose, Engine as _};
+    let data = notification
+        .pointer("/params/result/value/data/0")
+        .and_then(|data| data.as_str())
+        .and_then(|data| general_purpose::STANDARD.decode(data).ok());
+    let owner = notification
+        .pointer("/params/result/value/owner")
+        .and_then(|owner| owner.as_str())
+        .and_then(|owner| owner.parse::<Pubkey>().ok());
+    let slot = notification.pointer("/params/result/context/slot").and_then(|slot| slot.as_u64());
+    let (Some(data), Some(owner), Some(slot)) = (data, owner, slot) else {
+        debug!("Account notification for {} without base64 data, owner or slot", account);
+        return;
+    };
+    if let Some(router) = router.filter(|router| router.routes(account)) {
+        if let Err(e) = router.apply_account_update(account, &owner, &data, DataVersion::at_slot(slot)).await {
+            warn!("⚙️ Rejected update of {} at slot {}: {}", account, slot, e);
+        }
+    }
+    if let Some(registry) = registry.filter(|registry| registry.tracks(account)) {
+        if let Some(PoolEvent::PoolChanged { current, .. }) = registry.apply_account_update(*account, owner, slot, data) {
+            debug!("🔄 Pool {} updated at slot {}", current.id, slot);
+        }
+    }
+}
+
 async fn grpc_listener(_url: String, _tx: mpsc::Sender<MarketEvent>) -> Result<()> {
     // gRPC/Geyser data streaming disabled - requires additional QuickNode subscription
     info!("⚠️  gRPC/Geyser listener disabled - using WebSocket RPC for market data");
@@ -413,3 +746,122 @@ fn extract_price_from_logs(_log_str: &str) -> Option<f64> {
     warn!("Log-based price extraction not yet implemented - returning None");
     None // Return None until proper implementation
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::markets::account_decoders::{AccountDecoderRegistry, SharedMarket};
+    use crate::markets::lockless_cache::LocklessMarketCache;
+    use crate::markets::raydium::{AmmInfo, Fees};
+    use crate::markets::raydium_amm::{RaydiumAmmMarket, RAYDIUM_AMM_V4_PROGRAM_ID};
+    use crate::markets::token_2022::tests::token_account;
+    use crate::markets::types::{DexLabel, Market};
+    use anchor_spl::token::spl_token;
+    use base64::{engine::general_purpose, Engine as _};
+    use std::str::FromStr;
+    use tokio::sync::RwLock;
+
+    /// accountNotification of `data` owned by `owner` at `slot`, as the RPC sends it
+    fn account_notification(owner: &Pubkey, data: &[u8], slot: u64) -> serde_json::Value {
+        serde_json::json!({
+            "jsonrpc": "2.0",
+            "method": "accountNotification",
+            "params": {
+                "subscription": 42,
+                "result": {
+                    "context": { "slot": slot },
+                    "value": {
+                        "data": [general_purpose::STANDARD.encode(data), "base64"],
+                        "executable": false,
+                        "lamports": 2_039_280,
+                        "owner": owner.to_string(),
+                        "rentEpoch": 0
+                    }
+                }
+            }
+        })
+    }
+
+    #[test]
+    fn test_account_subscriptions_follow_confirmations() {
+        let mut subscriptions = AccountSubscriptions::new();
+        let (pool, other) = (Pubkey::new_unique(), Pubkey::new_unique());
+
+        let request: serde_json::Value = serde_json::from_str(&subscriptions.subscribe(pool).unwrap()).unwrap();
+        assert_eq!(request["method"], "accountSubscribe");
+        assert_eq!(request["params"][0], pool.to_string());
+        assert!(subscriptions.subscribe(pool).is_none());
+
+        // Removing a pool mid-subscription unsubscribes once the confirmation arrives
+        let other_request: serde_json::Value =
+            serde_json::from_str(&subscriptions.subscribe(other).unwrap()).unwrap();
+        assert!(subscriptions.unsubscribe(&other).is_none());
+        let cancel: serde_json::Value =
+            serde_json::from_str(&subscriptions.confirm(other_request["id"].as_u64().unwrap(), 8).unwrap()).unwrap();
+        assert_eq!(cancel["params"][0], 8);
+        assert!(subscriptions.active.is_empty());
+
+        assert!(subscriptions.confirm(request["id"].as_u64().unwrap(), 42).is_none());
+        assert_eq!(subscriptions.account_for(42), Some(pool));
+        let request: serde_json::Value = serde_json::from_str(&subscriptions.unsubscribe(&pool).unwrap()).unwrap();
+        assert_eq!(request["method"], "accountUnsubscribe");
+        assert_eq!(request["params"][0], 42);
+        assert!(subscriptions.unsubscribe(&pool).is_none());
+        assert_eq!(subscriptions.account_for(42), None);
+    }
+
+    #[tokio::test]
+    async fn test_account_notifications_reach_the_engine() {
+        let router = AccountUpdateRouter::new(AccountDecoderRegistry::with_known_programs(), LocklessMarketCache::new());
+        let (pool, coin_vault, pc_vault) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let amm = AmmInfo {
+            status: 6,
+            fees: Fees {
+                trade_fee_numerator: 25,
+                trade_fee_denominator: 10_000,
+                swap_fee_numerator: 25,
+                swap_fee_denominator: 10_000,
+                ..Default::default()
+            },
+            coin_vault,
+            pc_vault,
+            coin_vault_mint: Pubkey::new_unique(),
+            pc_vault_mint: Pubkey::new_unique(),
+            ..Default::default()
+        };
+        let engine = RaydiumAmmMarket::new(pool, amm, 1_000_000_000, 2_000_000_000);
+        let market: SharedMarket = Arc::new(RwLock::new(Box::new(engine)));
+        router.register_market(pool, market.clone(), &[coin_vault, pc_vault]).await;
+        router.cache().insert(Market {
+            token_mint_a: amm.coin_vault_mint.to_string(),
+            token_vault_a: String::new(),
+            token_mint_b: amm.pc_vault_mint.to_string(),
+            token_vault_b: String::new(),
+            dex_label: DexLabel::Raydium,
+            fee: 25,
+            id: pool.to_string(),
+            account_data: None,
+            liquidity: None,
+        }, DataVersion::at_slot(10));
+        assert_eq!(market.read().await.get_quote(1_000_000, true).unwrap().amount_out, 1_993_011);
+        let depth = router.depth_curves(&pool).unwrap().a_to_b.max_amount_out();
+
+        // The pc vault doubling doubles the output
+        let notification = account_notification(&spl_token::ID, &token_account(&amm.pc_vault_mint, 4_000_000_000), 11);
+        apply_account_notification(None, Some(&router), &pc_vault, &notification).await;
+        assert_eq!(market.read().await.get_quote(1_000_000, true).unwrap().amount_out, 3_986_023);
+        // and the depth curves are resampled from the new state
+        assert!(router.depth_curves(&pool).unwrap().a_to_b.max_amount_out() > depth);
+
+        // Pool account updates move the cached version; older ones are dropped
+        let raydium_amm = Pubkey::from_str(RAYDIUM_AMM_V4_PROGRAM_ID).unwrap();
+        let amm_data = borsh::to_vec(&amm).unwrap();
+        apply_account_notification(None, Some(&router), &pool, &account_notification(&raydium_amm, &amm_data, 12)).await;
+        assert_eq!(router.cache().get_versioned(&pool.to_string()).unwrap().version.slot, 12);
+        // Status 4 disables swaps, so a stale copy reaching the engine would fail quotes
+        let disabled = borsh::to_vec(&AmmInfo { status: 4, ..amm }).unwrap();
+        apply_account_notification(None, Some(&router), &pool, &account_notification(&raydium_amm, &disabled, 11)).await;
+        assert!(market.read().await.get_quote(1_000_000, true).is_ok());
+        assert_eq!(router.cache().get_versioned(&pool.to_string()).unwrap().version.slot, 12);
+    }
+}
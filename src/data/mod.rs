@@ -1,2 +1,3 @@
 pub mod graphs;
 pub mod market_stream;
+pub mod oracle;
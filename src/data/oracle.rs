@@ -0,0 +1,488 @@
+//! Pyth oracle prices
+//!
+//! Decodes the Pyth price accounts named in the config as their account notifications
+//! arrive. Both the legacy v2 price account and the pull oracle's `PriceUpdateV2` are
+//! understood. A price is only accepted while it's trading, fully verified, younger
+//! than the staleness limit and with a confidence interval narrow enough relative to
+//! the price; stored prices are checked for staleness again when they're read, so a
+//! feed that stops updating stops being used.
+//!
+//! Accepted prices are broadcast, and `spawn_forwarder` pushes them into the
+//! components that keep their own copy, such as the SOL price used for gas costs.
+
+use crate::common::config::{Config, OracleFeedConfig};
+use anchor_spl::token::spl_token;
+use dashmap::DashMap;
+use solana_program::pubkey::Pubkey;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+
+/// Pyth pull-oracle SOL/USD price feed account (shard 0)
+pub const PYTH_SOL_USD_FEED: &str = "7UVimffxr9ow1uXYxsr4LHAcV58mLzhmwaeKvJ1pjLiE";
+
+/// Pyth pull-oracle USDC/USD price feed account (shard 0)
+pub const PYTH_USDC_USD_FEED: &str = "Dpw1EAVrSB1ibxiDQyTAW6Zip3J4Btk2x4SgApQCeFbX";
+
+const USDC_MINT: &str = "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v";
+
+/// Magic number at the start of every legacy Pyth account
+const LEGACY_MAGIC: u32 = 0xa1b2_c3d4;
+const LEGACY_VERSION: u32 = 2;
+const LEGACY_PRICE_ACCOUNT_TYPE: u32 = 3;
+const LEGACY_STATUS_TRADING: u32 = 1;
+/// Header, EMA fields and the previous aggregate precede the current aggregate
+const LEGACY_AGGREGATE_OFFSET: usize = 208;
+const LEGACY_MIN_LEN: usize = LEGACY_AGGREGATE_OFFSET + 32;
+
+/// Anchor discriminator of the pull oracle's `PriceUpdateV2`, `sha256("account:PriceUpdateV2")[..8]`
+const PRICE_UPDATE_V2_DISCRIMINATOR: [u8; 8] = [34, 241, 35, 99, 157, 126, 244, 205];
+/// Discriminator and write authority precede the verification level
+const PRICE_UPDATE_V2_VERIFICATION_OFFSET: usize = 8 + 32;
+/// Feed id, price, conf, exponent, publish times, EMA price and conf, then the posted slot
+const PRICE_UPDATE_V2_MESSAGE_LEN: usize = 32 + 8 + 8 + 4 + 8 + 8 + 8 + 8 + 8;
+
+#[derive(Debug, PartialEq, Eq, thiserror::Error)]
+pub enum OracleError {
+    #[error("Price account is too short ({0} bytes)")]
+    TooShort(usize),
+    #[error("Not a Pyth price account")]
+    NotAPriceAccount,
+    #[error("Price is not trading (status {0})")]
+    NotTrading(u32),
+    #[error("Price update is only partially verified")]
+    PartiallyVerified,
+    #[error("Invalid price {0}")]
+    InvalidPrice(i64),
+    #[error("Price is {age_secs}s old")]
+    Stale { age_secs: u64 },
+    #[error("Confidence interval is {confidence_bps} bps of the price")]
+    WideConfidence { confidence_bps: u64 },
+}
+
+/// A decoded oracle price in whole units of the quote currency
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OraclePrice {
+    pub price: f64,
+    /// One standard deviation either side of `price`
+    pub confidence: f64,
+    pub publish_time_unix_secs: i64,
+    pub slot: u64,
+}
+
+impl OraclePrice {
+    fn from_raw(price: i64, confidence: u64, exponent: i32, publish_time_unix_secs: i64, slot: u64) -> Result<Self, OracleError> {
+        if price <= 0 {
+            return Err(OracleError::InvalidPrice(price));
+        }
+        let scale = 10f64.powi(exponent);
+        Ok(Self {
+            price: price as f64 * scale,
+            confidence: confidence as f64 * scale,
+            publish_time_unix_secs,
+            slot,
+        })
+    }
+
+    pub fn confidence_bps(&self) -> u64 {
+        (self.confidence / self.price * 10_000.0).round() as u64
+    }
+
+    pub fn age_secs(&self, now_unix_secs: i64) -> u64 {
+        now_unix_secs.saturating_sub(self.publish_time_unix_secs).max(0) as u64
+    }
+
+    /// Whether the price is recent and precise enough to act on at `now_unix_secs`
+    pub fn check(&self, now_unix_secs: i64, max_staleness_secs: u64, max_confidence_bps: u16) -> Result<(), OracleError> {
+        let age_secs = self.age_secs(now_unix_secs);
+        if age_secs > max_staleness_secs {
+            return Err(OracleError::Stale { age_secs });
+        }
+        let confidence_bps = self.confidence_bps();
+        if confidence_bps > u64::from(max_confidence_bps) {
+            return Err(OracleError::WideConfidence { confidence_bps });
+        }
+        Ok(())
+    }
+}
+
+/// Decode a legacy Pyth price account or a pull-oracle `PriceUpdateV2`
+pub fn decode_pyth_price(data: &[u8]) -> Result<OraclePrice, OracleError> {
+    if data.len() >= 4 && read_u32(data, 0) == LEGACY_MAGIC {
+        decode_legacy_price(data)
+    } else if data.starts_with(&PRICE_UPDATE_V2_DISCRIMINATOR) {
+        decode_price_update_v2(data)
+    } else {
+        Err(OracleError::NotAPriceAccount)
+    }
+}
+
+fn decode_legacy_price(data: &[u8]) -> Result<OraclePrice, OracleError> {
+    if data.len() < LEGACY_MIN_LEN {
+        return Err(OracleError::TooShort(data.len()));
+    }
+    if read_u32(data, 4) != LEGACY_VERSION || read_u32(data, 8) != LEGACY_PRICE_ACCOUNT_TYPE {
+        return Err(OracleError::NotAPriceAccount);
+    }
+    let status = read_u32(data, LEGACY_AGGREGATE_OFFSET + 16);
+    if status != LEGACY_STATUS_TRADING {
+        return Err(OracleError::NotTrading(status));
+    }
+    OraclePrice::from_raw(
+        read_i64(data, LEGACY_AGGREGATE_OFFSET),
+        read_u64(data, LEGACY_AGGREGATE_OFFSET + 8),
+        read_i32(data, 20),
+        read_i64(data, 96),
+        read_u64(data, LEGACY_AGGREGATE_OFFSET + 24),
+    )
+}
+
+fn decode_price_update_v2(data: &[u8]) -> Result<OraclePrice, OracleError> {
+    // `VerificationLevel` is a borsh enum: `Partial { num_signatures: u8 }` or `Full`
+    let message_offset = match data.get(PRICE_UPDATE_V2_VERIFICATION_OFFSET) {
+        Some(0) => return Err(OracleError::PartiallyVerified),
+        Some(1) => PRICE_UPDATE_V2_VERIFICATION_OFFSET + 1,
+        Some(_) => return Err(OracleError::NotAPriceAccount),
+        None => return Err(OracleError::TooShort(data.len())),
+    };
+    if data.len() < message_offset + PRICE_UPDATE_V2_MESSAGE_LEN {
+        return Err(OracleError::TooShort(data.len()));
+    }
+    let price = message_offset + 32;
+    OraclePrice::from_raw(
+        read_i64(data, price),
+        read_u64(data, price + 8),
+        read_i32(data, price + 16),
+        read_i64(data, price + 20),
+        read_u64(data, price + 52),
+    )
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().expect("slice is 4 bytes"))
+}
+
+fn read_i32(data: &[u8], offset: usize) -> i32 {
+    i32::from_le_bytes(data[offset..offset + 4].try_into().expect("slice is 4 bytes"))
+}
+
+fn read_u64(data: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(data[offset..offset + 8].try_into().expect("slice is 8 bytes"))
+}
+
+fn read_i64(data: &[u8], offset: usize) -> i64 {
+    i64::from_le_bytes(data[offset..offset + 8].try_into().expect("slice is 8 bytes"))
+}
+
+fn now_unix_secs() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs() as i64)
+}
+
+/// The token a price account prices in USD
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OracleFeed {
+    pub symbol: String,
+    pub mint: Pubkey,
+}
+
+/// An accepted price, as broadcast to subscribers
+#[derive(Debug, Clone, PartialEq)]
+pub struct OracleUpdate {
+    pub symbol: String,
+    pub mint: Pubkey,
+    pub price: OraclePrice,
+}
+
+impl OracleUpdate {
+    pub fn is_sol(&self) -> bool {
+        self.mint == spl_token::native_mint::id()
+    }
+}
+
+/// Something that keeps its own copy of oracle prices
+pub trait OraclePriceSink: Send + Sync {
+    fn on_oracle_price(&self, update: &OracleUpdate);
+}
+
+/// USD prices of the configured tokens, kept current from their Pyth accounts
+pub struct PriceOracle {
+    /// Feeds by price account
+    feeds: HashMap<Pubkey, OracleFeed>,
+    /// Latest accepted price by mint
+    prices: DashMap<Pubkey, OracleUpdate>,
+    max_staleness_secs: u64,
+    max_confidence_bps: u16,
+    updates: broadcast::Sender<OracleUpdate>,
+}
+
+impl PriceOracle {
+    pub fn new(feeds: HashMap<Pubkey, OracleFeed>, max_staleness_secs: u64, max_confidence_bps: u16) -> Self {
+        let (updates, _) = broadcast::channel(256);
+        Self {
+            feeds,
+            prices: DashMap::new(),
+            max_staleness_secs,
+            max_confidence_bps,
+            updates,
+        }
+    }
+
+    /// An oracle for the configured feeds, or Pyth SOL/USD and USDC/USD when none are
+    /// configured. Feeds with invalid addresses are skipped.
+    pub fn from_config(config: &Config) -> Self {
+        let configured = config.oracle_feeds.clone().unwrap_or_else(|| {
+            vec![
+                OracleFeedConfig {
+                    symbol: "SOL".to_string(),
+                    mint: spl_token::native_mint::id().to_string(),
+                    price_account: PYTH_SOL_USD_FEED.to_string(),
+                },
+                OracleFeedConfig {
+                    symbol: "USDC".to_string(),
+                    mint: USDC_MINT.to_string(),
+                    price_account: PYTH_USDC_USD_FEED.to_string(),
+                },
+            ]
+        });
+        let mut feeds = HashMap::with_capacity(configured.len());
+        for feed in configured {
+            match (Pubkey::from_str(&feed.price_account), Pubkey::from_str(&feed.mint)) {
+                (Ok(account), Ok(mint)) => {
+                    feeds.insert(account, OracleFeed { symbol: feed.symbol, mint });
+                }
+                _ => warn!("Skipping oracle feed {} with an invalid address", feed.symbol),
+            }
+        }
+        info!("✅ Price oracle following {} feeds", feeds.len());
+        Self::new(
+            feeds,
+            config.oracle_max_staleness_secs.unwrap_or(30),
+            config.oracle_max_confidence_bps.unwrap_or(100),
+        )
+    }
+
+    /// Price accounts to subscribe to
+    pub fn price_accounts(&self) -> Vec<Pubkey> {
+        self.feeds.keys().copied().collect()
+    }
+
+    pub fn is_price_account(&self, account: &Pubkey) -> bool {
+        self.feeds.contains_key(account)
+    }
+
+    /// Mints the oracle prices
+    pub fn mints(&self) -> Vec<Pubkey> {
+        self.feeds.values().map(|feed| feed.mint).collect()
+    }
+
+    /// Decode new data for `account`. Returns the update when the account is a feed and
+    /// its price was accepted; prices no newer than the stored one are ignored.
+    pub fn apply_account_update(&self, account: &Pubkey, data: &[u8]) -> Result<Option<OracleUpdate>, OracleError> {
+        self.apply_account_update_at(account, data, now_unix_secs())
+    }
+
+    fn apply_account_update_at(&self, account: &Pubkey, data: &[u8], now_unix_secs: i64) -> Result<Option<OracleUpdate>, OracleError> {
+        let Some(feed) = self.feeds.get(account) else {
+            return Ok(None);
+        };
+        let price = decode_pyth_price(data)?;
+        price.check(now_unix_secs, self.max_staleness_secs, self.max_confidence_bps)?;
+        if let Some(current) = self.prices.get(&feed.mint) {
+            if current.price.publish_time_unix_secs >= price.publish_time_unix_secs {
+                return Ok(None);
+            }
+        }
+
+        let update = OracleUpdate {
+            symbol: feed.symbol.clone(),
+            mint: feed.mint,
+            price,
+        };
+        debug!("🔮 {} = ${:.4} ± {:.4}", update.symbol, price.price, price.confidence);
+        self.prices.insert(feed.mint, update.clone());
+        // No subscribers is fine; readers still see the stored price
+        let _ = self.updates.send(update.clone());
+        Ok(Some(update))
+    }
+
+    /// Latest price of `mint`, unless it has gone stale
+    pub fn price(&self, mint: &Pubkey) -> Option<OraclePrice> {
+        self.price_at(mint, now_unix_secs())
+    }
+
+    fn price_at(&self, mint: &Pubkey, now_unix_secs: i64) -> Option<OraclePrice> {
+        let update = self.prices.get(mint)?;
+        (update.price.age_secs(now_unix_secs) <= self.max_staleness_secs).then_some(update.price)
+    }
+
+    pub fn usd_price(&self, mint: &Pubkey) -> Option<f64> {
+        self.price(mint).map(|price| price.price)
+    }
+
+    pub fn sol_usd(&self) -> Option<f64> {
+        self.usd_price(&spl_token::native_mint::id())
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<OracleUpdate> {
+        self.updates.subscribe()
+    }
+
+    /// Push current and future prices into `sinks` until the oracle is dropped
+    pub fn spawn_forwarder(&self, sinks: Vec<Arc<dyn OraclePriceSink>>) -> JoinHandle<()> {
+        let mut updates = self.subscribe();
+        let now = now_unix_secs();
+        for entry in self.prices.iter() {
+            if entry.price.age_secs(now) <= self.max_staleness_secs {
+                sinks.iter().for_each(|sink| sink.on_oracle_price(entry.value()));
+            }
+        }
+        tokio::spawn(async move {
+            loop {
+                match updates.recv().await {
+                    Ok(update) => sinks.iter().for_each(|sink| sink.on_oracle_price(&update)),
+                    Err(RecvError::Lagged(skipped)) => {
+                        warn!("Oracle update stream lagged, {} updates skipped", skipped);
+                    }
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    const NOW: i64 = 1_700_000_000;
+
+    fn legacy_account(price: i64, conf: u64, expo: i32, timestamp: i64, status: u32) -> Vec<u8> {
+        let mut data = vec![0u8; 3_312];
+        data[0..4].copy_from_slice(&LEGACY_MAGIC.to_le_bytes());
+        data[4..8].copy_from_slice(&LEGACY_VERSION.to_le_bytes());
+        data[8..12].copy_from_slice(&LEGACY_PRICE_ACCOUNT_TYPE.to_le_bytes());
+        data[20..24].copy_from_slice(&expo.to_le_bytes());
+        data[96..104].copy_from_slice(&timestamp.to_le_bytes());
+        data[208..216].copy_from_slice(&price.to_le_bytes());
+        data[216..224].copy_from_slice(&conf.to_le_bytes());
+        data[224..228].copy_from_slice(&status.to_le_bytes());
+        data[232..240].copy_from_slice(&1_234u64.to_le_bytes());
+        data
+    }
+
+    fn price_update_v2(price: i64, conf: u64, expo: i32, publish_time: i64, full: bool) -> Vec<u8> {
+        let mut data = PRICE_UPDATE_V2_DISCRIMINATOR.to_vec();
+        data.extend_from_slice(&[9; 32]);
+        if full {
+            data.push(1);
+        } else {
+            data.extend_from_slice(&[0, 3]);
+        }
+        data.extend_from_slice(&[7; 32]);
+        data.extend_from_slice(&price.to_le_bytes());
+        data.extend_from_slice(&conf.to_le_bytes());
+        data.extend_from_slice(&expo.to_le_bytes());
+        data.extend_from_slice(&publish_time.to_le_bytes());
+        data.extend_from_slice(&(publish_time - 1).to_le_bytes());
+        data.extend_from_slice(&price.to_le_bytes());
+        data.extend_from_slice(&conf.to_le_bytes());
+        data.extend_from_slice(&5_678u64.to_le_bytes());
+        data
+    }
+
+    #[test]
+    fn test_decode_pyth_layouts() {
+        assert_eq!(
+            &solana_program::hash::hash(b"account:PriceUpdateV2").to_bytes()[..8],
+            &PRICE_UPDATE_V2_DISCRIMINATOR
+        );
+
+        let legacy = decode_pyth_price(&legacy_account(15_025_000_000, 7_500_000, -8, NOW, 1)).unwrap();
+        assert!((legacy.price - 150.25).abs() < 1e-9);
+        assert!((legacy.confidence - 0.075).abs() < 1e-9);
+        assert_eq!((legacy.publish_time_unix_secs, legacy.slot), (NOW, 1_234));
+        assert_eq!(
+            decode_pyth_price(&legacy_account(15_025_000_000, 0, -8, NOW, 0)),
+            Err(OracleError::NotTrading(0))
+        );
+        assert_eq!(decode_pyth_price(&legacy_account(0, 0, -8, NOW, 0)[..100]), Err(OracleError::TooShort(100)));
+
+        let pull = decode_pyth_price(&price_update_v2(99_990_000, 10_000, -8, NOW, true)).unwrap();
+        assert!((pull.price - 0.9999).abs() < 1e-9);
+        assert_eq!(pull.confidence_bps(), 1);
+        assert_eq!(pull.slot, 5_678);
+        assert_eq!(
+            decode_pyth_price(&price_update_v2(99_990_000, 10_000, -8, NOW, false)),
+            Err(OracleError::PartiallyVerified)
+        );
+        assert_eq!(decode_pyth_price(&[0; 200]), Err(OracleError::NotAPriceAccount));
+    }
+
+    #[test]
+    fn test_oracle_rejects_stale_and_imprecise_prices() {
+        let sol_account = Pubkey::new_unique();
+        let sol = spl_token::native_mint::id();
+        let feeds = HashMap::from([(sol_account, OracleFeed { symbol: "SOL".to_string(), mint: sol })]);
+        let oracle = PriceOracle::new(feeds, 30, 100);
+
+        let stale = price_update_v2(15_000_000_000, 1_000_000, -8, NOW - 31, true);
+        assert_eq!(
+            oracle.apply_account_update_at(&sol_account, &stale, NOW),
+            Err(OracleError::Stale { age_secs: 31 })
+        );
+        let imprecise = price_update_v2(15_000_000_000, 200_000_000, -8, NOW, true);
+        assert_eq!(
+            oracle.apply_account_update_at(&sol_account, &imprecise, NOW),
+            Err(OracleError::WideConfidence { confidence_bps: 133 })
+        );
+        assert_eq!(oracle.price_at(&sol, NOW), None);
+
+        let fresh = price_update_v2(15_000_000_000, 1_000_000, -8, NOW - 5, true);
+        let update = oracle.apply_account_update_at(&sol_account, &fresh, NOW).unwrap().unwrap();
+        assert!(update.is_sol());
+        assert!((oracle.price_at(&sol, NOW).unwrap().price - 150.0).abs() < 1e-9);
+        // A replayed update changes nothing, and the stored price ages out
+        assert!(oracle.apply_account_update_at(&sol_account, &fresh, NOW).unwrap().is_none());
+        assert_eq!(oracle.price_at(&sol, NOW + 26), None);
+        // Accounts that aren't feeds are ignored
+        assert!(oracle.apply_account_update_at(&Pubkey::new_unique(), &fresh, NOW).unwrap().is_none());
+    }
+
+    struct Recorder(Mutex<Vec<f64>>);
+
+    impl OraclePriceSink for Recorder {
+        fn on_oracle_price(&self, update: &OracleUpdate) {
+            self.0.lock().unwrap().push(update.price.price);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_forwarder_pushes_current_and_new_prices() {
+        let sol_account = Pubkey::new_unique();
+        let feeds = HashMap::from([(
+            sol_account,
+            OracleFeed { symbol: "SOL".to_string(), mint: spl_token::native_mint::id() },
+        )]);
+        let oracle = PriceOracle::new(feeds, 30, 100);
+        let now = now_unix_secs();
+        oracle
+            .apply_account_update(&sol_account, &price_update_v2(15_000_000_000, 0, -8, now - 1, true))
+            .unwrap();
+
+        let recorder = Arc::new(Recorder(Mutex::new(Vec::new())));
+        let forwarder = oracle.spawn_forwarder(vec![recorder.clone()]);
+        oracle
+            .apply_account_update(&sol_account, &price_update_v2(15_100_000_000, 0, -8, now, true))
+            .unwrap();
+        drop(oracle);
+        forwarder.await.unwrap();
+        assert_eq!(*recorder.0.lock().unwrap(), vec![150.0, 151.0]);
+    }
+}
@@ -1,22 +1,26 @@
 //! src/execution/executor.rs - FIXED VERSION
 //! This implements actual transaction execution logic
 
-use crate::arbitrage::types::ArbOpportunity; // Keep Route if opportunity.path.paths is used, not needed for execution_plan
+use crate::arbitrage::types::{ArbOpportunity, ExpiryReason}; // Keep Route if opportunity.path.paths is used, not needed for execution_plan
 use crate::common::config::Config;
 use crate::execution::paper_trading::PaperTrader;
+use crate::execution::queue::{OpportunityQueue, Queued};
 use crate::fees::priority_fees::{get_global_fee_service, PriorityFeeService};
 // Removed create_swap_instructions import
 use crate::markets::types::DexLabel; // Added for new build_swap_instructions
 use crate::transactions::{ // Added for new build_swap_instructions
     create_transaction::InstructionDetails,
     meteoradlmm_swap::{construct_meteora_instructions, SwapParametersMeteora},
+    openbook_v2_swap::{construct_openbook_v2_instructions, SwapParametersOpenBookV2},
+    orca_swap::{construct_orca_instructions, SwapParametersOrca},
+    phoenix_swap::{construct_phoenix_instructions, SwapParametersPhoenix},
     raydium_swap::{construct_raydium_instructions, SwapParametersRaydium},
     raydium_clmm_swap::{construct_raydium_clmm_instructions, SwapParametersRaydiumClmm},
+    raydium_cpmm_swap::{construct_raydium_cpmm_instructions, SwapParametersRaydiumCpmm},
     orca_whirpools_swap::{construct_orca_whirpools_instructions, SwapParametersOrcaWhirpools},
 };
 use anyhow::{anyhow, Result};
 use log::{error, info, warn};
-use priority_queue::PriorityQueue;
 use solana_client::nonblocking::rpc_client::RpcClient;
 use solana_client::tpu_client::{TpuClient, TpuClientConfig};
 use solana_sdk::{
@@ -26,7 +30,6 @@ use solana_sdk::{
     signature::{Keypair, Signer},
     transaction::Transaction,
 };
-use std::cmp::Reverse;
 use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
@@ -42,6 +45,10 @@ pub struct Metrics {
     pub execution_successes: AtomicU64,
     pub execution_failures: AtomicU64,
     pub total_profit_lamports: AtomicI64, // Use I64 for profit as it can be negative
+    /// Opportunities discarded past their latency budget
+    pub expired_latency: AtomicU64,
+    /// Opportunities discarded for pool states too many slots old
+    pub expired_slots: AtomicU64,
 }
 
 impl Default for Metrics {
@@ -57,6 +64,8 @@ impl Metrics {
             execution_successes: AtomicU64::new(0),
             execution_failures: AtomicU64::new(0),
             total_profit_lamports: AtomicI64::new(0),
+            expired_latency: AtomicU64::new(0),
+            expired_slots: AtomicU64::new(0),
         }
     }
 }
@@ -91,7 +100,7 @@ pub struct TransactionExecutor {
     fee_service: Arc<PriorityFeeService>,
     execution_mode: String,
     paper_trader: Option<PaperTrader>,
-    priority_queue: PriorityQueue<ArbOpportunity, Reverse<u64>>,
+    priority_queue: OpportunityQueue,
     config: Arc<Config>,
     metrics: Arc<Metrics>,
 }
@@ -140,7 +149,7 @@ impl TransactionExecutor {
             fee_service,
             execution_mode: config.execution_mode.clone(),
             paper_trader,
-            priority_queue: PriorityQueue::new(),
+            priority_queue: OpportunityQueue::new(config.max_queue_size.unwrap_or(1000)),
             config,
             metrics,
         })
@@ -192,7 +201,7 @@ impl TransactionExecutor {
             fee_service,
             execution_mode: config.execution_mode.clone(),
             paper_trader,
-            priority_queue: PriorityQueue::new(),
+            priority_queue: OpportunityQueue::new(config.max_queue_size.unwrap_or(1000)),
             config,
             metrics,
         })
@@ -245,7 +254,10 @@ impl TransactionExecutor {
                 biased;
 
                 _ = async {}, if !self.priority_queue.is_empty() => {
-                    if let Some((opportunity, _priority)) = self.priority_queue.pop() {
+                    if let Some(opportunity) = self.priority_queue.pop() {
+                        if self.is_expired(&opportunity) {
+                            continue;
+                        }
                         if let Err(e) = self.process_opportunity_internal(opportunity).await {
                              error!("Failed to process opportunity: {}", e);
                         }
@@ -255,44 +267,31 @@ impl TransactionExecutor {
                 maybe_opportunity = self.execution_queue.recv() => {
                     match maybe_opportunity {
                         Some(opportunity) => {
-                            let priority = Reverse(opportunity.expected_profit_lamports);
-                            let max_queue_size = self.config.max_queue_size.unwrap_or(1000);
                             let new_opportunity_profit = opportunity.expected_profit_lamports; // For logging after move
-
-                            if self.priority_queue.len() >= max_queue_size {
-                                let mut should_replace = false;
-                                let mut worst_profit_in_queue = 0;
-
-                                if let Some((_, worst_p_val_reverse)) = self.priority_queue.peek() {
-                                    worst_profit_in_queue = worst_p_val_reverse.0; // Get the actual profit value
-                                    if priority < *worst_p_val_reverse {
-                                        should_replace = true;
-                                    }
-                                } else {
-                                    // Queue is full but peek failed (empty? should not happen if len >= max_queue_size > 0)
-                                    // Or if max_queue_size is 0, this branch is complex. Assume max_queue_size >= 1
-                                    should_replace = true; // If queue is notionally full but empty, add.
+                            match self.priority_queue.push(opportunity) {
+                                Queued::Added | Queued::Replaced => {}
+                                Queued::Superseded => {
+                                    warn!(
+                                        "Discarding opportunity (profit: {}) superseded by a newer queued copy.",
+                                        new_opportunity_profit
+                                    );
                                 }
-                                
-                                if should_replace {
-                                    let _ = self.priority_queue.pop(); 
-                                    self.priority_queue.push(opportunity, priority);
+                                Queued::Evicted { worst_profit_lamports } => {
                                     info!(
                                         "Queue full (size {}). New opportunity (profit: {}) is better than worst (profit: {}). Replaced.",
                                         self.priority_queue.len(), // Length after pop and push is same as max_queue_size
                                         new_opportunity_profit,
-                                        worst_profit_in_queue 
+                                        worst_profit_lamports
                                     );
-                                } else {
+                                }
+                                Queued::Discarded { worst_profit_lamports } => {
                                     warn!(
                                         "Queue full (size {}). Discarding new opportunity (profit: {}) as it's not better than the worst in queue (worst profit: {}).",
                                         self.priority_queue.len(),
                                         new_opportunity_profit,
-                                        worst_profit_in_queue
+                                        worst_profit_lamports
                                     );
                                 }
-                            } else {
-                                self.priority_queue.push(opportunity, priority);
                             }
                         }
                         None => {
@@ -305,7 +304,10 @@ impl TransactionExecutor {
         }
 
         info!("Draining remaining {} opportunities from priority queue...", self.priority_queue.len());
-        while let Some((opportunity, _)) = self.priority_queue.pop() {
+        while let Some(opportunity) = self.priority_queue.pop() {
+            if self.is_expired(&opportunity) {
+                continue;
+            }
             if let Err(e) = self.process_opportunity_internal(opportunity).await {
                 error!("Failed to process opportunity from drained queue: {}", e);
             }
@@ -313,6 +315,33 @@ impl TransactionExecutor {
         info!("Transaction Executor finished.");
     }
 
+    /// Whether `opportunity` is past its latency budget or computed from pool states
+    /// too many slots old; counts and logs the reason when it is
+    fn is_expired(&self, opportunity: &ArbOpportunity) -> bool {
+        let now_unix_nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+        // Legacy mode doesn't track slots, leaving only the latency check
+        let current_slot = self.rpc_manager.as_ref().map_or(0, |rpc_manager| rpc_manager.get_current_slot());
+        // Confirmed account notifications trail the polled slot by a slot or two
+        let max_age_slots = self.config.opportunity_max_age_slots.unwrap_or(4);
+        let Some(reason) = opportunity.expiry(now_unix_nanos, current_slot, max_age_slots) else {
+            return false;
+        };
+        match reason {
+            ExpiryReason::Latency { .. } => self.metrics.expired_latency.fetch_add(1, Ordering::Relaxed),
+            ExpiryReason::Slots { .. } | ExpiryReason::UnknownSlot => {
+                self.metrics.expired_slots.fetch_add(1, Ordering::Relaxed)
+            }
+        };
+        warn!(
+            "⌛ Discarding expired opportunity ({} lamports profit): {}",
+            opportunity.expected_profit_lamports, reason
+        );
+        true
+    }
+
     async fn process_opportunity_internal(&self, opportunity: ArbOpportunity) -> Result<()> {
         self.metrics.execution_attempts.fetch_add(1, Ordering::Relaxed);
         
@@ -683,17 +712,69 @@ impl TransactionExecutor {
                     }
                 }
                 
+                DexLabel::RaydiumCpmm => {
+                    let params = SwapParametersRaydiumCpmm {
+                        pool: leg.pool_address,
+                        input_token_mint: leg.token_in,
+                        output_token_mint: leg.token_out,
+                        amount_in: leg.amount_in,
+                        min_amount_out: leg.minimum_amount_out,
+                        wallet_pubkey: self.keypair.pubkey(),
+                    };
+                    match construct_raydium_cpmm_instructions(params).await {
+                        Ok(instructions) => instructions,
+                        Err(e) => {
+                            error!("Failed to construct Raydium CPMM instructions for leg {}: {}", index + 1, e);
+                            return Err(anyhow::anyhow!("Raydium CPMM instruction construction failed: {}", e));
+                        }
+                    }
+                }
+
+                DexLabel::OpenBookV2 => {
+                    let params = SwapParametersOpenBookV2 {
+                        market: leg.pool_address,
+                        input_token_mint: leg.token_in,
+                        output_token_mint: leg.token_out,
+                        amount_in: leg.amount_in,
+                        min_amount_out: leg.minimum_amount_out,
+                        wallet_pubkey: self.keypair.pubkey(),
+                    };
+                    match construct_openbook_v2_instructions(params).await {
+                        Ok(instructions) => instructions,
+                        Err(e) => {
+                            error!("Failed to construct OpenBook v2 instructions for leg {}: {}", index + 1, e);
+                            return Err(anyhow::anyhow!("OpenBook v2 instruction construction failed: {}", e));
+                        }
+                    }
+                }
+
+                DexLabel::Phoenix => {
+                    let params = SwapParametersPhoenix {
+                        market: leg.pool_address,
+                        input_token_mint: leg.token_in,
+                        output_token_mint: leg.token_out,
+                        amount_in: leg.amount_in,
+                        min_amount_out: leg.minimum_amount_out,
+                        wallet_pubkey: self.keypair.pubkey(),
+                    };
+                    match construct_phoenix_instructions(params).await {
+                        Ok(instructions) => instructions,
+                        Err(e) => {
+                            error!("Failed to construct Phoenix instructions for leg {}: {}", index + 1, e);
+                            return Err(anyhow::anyhow!("Phoenix instruction construction failed: {}", e));
+                        }
+                    }
+                }
+                
                 DexLabel::Orca => {
-                    // For now, treat Orca the same as OrcaWhirlpools since they use the same API
-                    // TODO: Investigate if Orca and OrcaWhirlpools should be separate or consolidated
-                    let params = SwapParametersOrcaWhirpools {
-                        whirpools: leg.pool_address,
+                    let params = SwapParametersOrca {
+                        pool: leg.pool_address,
                         input_token: leg.token_in,
                         output_token: leg.token_out,
                         amount_in: leg.amount_in,
                         minimum_amount_out: leg.minimum_amount_out,
                     };
-                    construct_orca_whirpools_instructions(params).await
+                    construct_orca_instructions(params).await
                 }
             };
             
@@ -1,3 +1,5 @@
 pub mod executor;
 pub mod paper_trading;
+pub mod queue;
 pub mod risk_engine;
+pub mod wallet;
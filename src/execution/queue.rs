@@ -0,0 +1,165 @@
+//! The executor's queue of opportunities waiting to execute
+//!
+//! The most profitable opportunity is executed first. The queue is bounded at
+//! `max_queue_size`; when full, a new opportunity takes the place of the least
+//! profitable one if it beats it. Copies of one trade sent by the
+//! `OpportunityDeduplicator` replace each other, so only the latest generation of a
+//! trade is ever queued.
+
+use crate::arbitrage::dedup::OpportunityKey;
+use crate::arbitrage::types::ArbOpportunity;
+use priority_queue::DoublePriorityQueue;
+use std::collections::HashMap;
+
+/// What `OpportunityQueue::push` did with an opportunity
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Queued {
+    Added,
+    /// Replaced the queued copy of the same trade, an older generation
+    Replaced,
+    /// Dropped as an older generation of a queued trade
+    Superseded,
+    /// The queue was full; took the place of the least profitable opportunity
+    Evicted { worst_profit_lamports: u64 },
+    /// The queue was full and the opportunity no better than the least profitable one
+    Discarded { worst_profit_lamports: u64 },
+}
+
+pub struct OpportunityQueue {
+    /// Opportunities by expected profit, popped from the top and evicted from the bottom
+    queue: DoublePriorityQueue<ArbOpportunity, u64>,
+    /// The queued copy of each trade sent through the deduplicator
+    generations: HashMap<OpportunityKey, ArbOpportunity>,
+    max_len: usize,
+}
+
+impl OpportunityQueue {
+    pub fn new(max_len: usize) -> Self {
+        Self {
+            queue: DoublePriorityQueue::new(),
+            generations: HashMap::new(),
+            max_len,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    pub fn push(&mut self, opportunity: ArbOpportunity) -> Queued {
+        let priority = opportunity.expected_profit_lamports;
+        let key = Self::generation_key(&opportunity);
+        if let Some((key, queued)) = key.as_ref().and_then(|key| Some((key, self.generations.get(key)?))) {
+            if queued.generation >= opportunity.generation {
+                return Queued::Superseded;
+            }
+            self.queue.remove(queued);
+            self.generations.insert(key.clone(), opportunity.clone());
+            self.queue.push(opportunity, priority);
+            return Queued::Replaced;
+        }
+
+        let mut queued = Queued::Added;
+        if self.queue.len() >= self.max_len {
+            // With `max_len` 0 the queue is full while empty, and nothing is evicted
+            if let Some((_, &worst_profit_lamports)) = self.queue.peek_min() {
+                if priority <= worst_profit_lamports {
+                    return Queued::Discarded { worst_profit_lamports };
+                }
+                queued = Queued::Evicted { worst_profit_lamports };
+            }
+            if let Some((evicted, _)) = self.queue.pop_min() {
+                self.forget(&evicted);
+            }
+        }
+        if let Some(key) = key {
+            self.generations.insert(key, opportunity.clone());
+        }
+        self.queue.push(opportunity, priority);
+        queued
+    }
+
+    /// Take the most profitable opportunity
+    pub fn pop(&mut self) -> Option<ArbOpportunity> {
+        let (opportunity, _) = self.queue.pop_max()?;
+        self.forget(&opportunity);
+        Some(opportunity)
+    }
+
+    /// Stop tracking `opportunity`'s copy of its trade once it has left the queue
+    fn forget(&mut self, opportunity: &ArbOpportunity) {
+        if let Some(key) = Self::generation_key(opportunity) {
+            self.generations.remove(&key);
+        }
+    }
+
+    /// Key replacing copies of `opportunity`'s trade; `None` when it wasn't deduplicated
+    fn generation_key(opportunity: &ArbOpportunity) -> Option<OpportunityKey> {
+        if opportunity.generation == 0 {
+            return None;
+        }
+        OpportunityKey::of(opportunity)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arbitrage::dedup::{Dispatch, OpportunityDeduplicator};
+    use crate::telemetry::Metrics;
+    use solana_sdk::pubkey::Pubkey;
+    use std::time::Duration;
+    use tokio::sync::mpsc;
+
+    #[test]
+    fn test_one_copy_per_trade_reaches_execution() {
+        let (sender, mut receiver) = mpsc::channel(16);
+        let dedup = OpportunityDeduplicator::new(sender, Duration::from_secs(60), Metrics::new());
+        let pools = [Pubkey::new_unique(), Pubkey::new_unique()];
+        let (x, y) = (Pubkey::new_unique(), Pubkey::new_unique());
+
+        // A trade re-priced twice, more profitably then on fresher states, and another
+        for (tokens, profit, slot) in [([x, y], 100, 10), ([x, y], 300, 10), ([x, y], 50, 11), ([y, x], 200, 10)] {
+            assert_eq!(dedup.try_send(ArbOpportunity::through_pools(&pools, &tokens, profit, slot)), Ok(Dispatch::Sent));
+        }
+        let mut queue = OpportunityQueue::new(16);
+        let queued: Vec<Queued> = std::iter::from_fn(|| receiver.try_recv().ok())
+            .map(|opportunity| queue.push(opportunity))
+            .collect();
+        assert_eq!(queued, vec![Queued::Added, Queued::Replaced, Queued::Replaced, Queued::Added]);
+
+        let executed: Vec<(u64, u64)> = std::iter::from_fn(|| queue.pop())
+            .map(|opportunity| (opportunity.expected_profit_lamports, opportunity.generation))
+            .collect();
+        assert_eq!(executed, vec![(200, 4), (50, 3)]);
+
+        // A copy older than the queued one is dropped; one that wasn't deduplicated
+        // never replaces another
+        let mut current = ArbOpportunity::through_pools(&pools, &[x, y], 100, 12);
+        current.generation = 6;
+        assert_eq!(queue.push(current.clone()), Queued::Added);
+        assert_eq!(queue.push(ArbOpportunity { generation: 5, ..current.clone() }), Queued::Superseded);
+        assert_eq!(queue.push(ArbOpportunity { generation: 0, ..current }), Queued::Added);
+        assert_eq!(queue.len(), 2);
+    }
+
+    #[test]
+    fn test_full_queue_keeps_the_more_profitable() {
+        let mut queue = OpportunityQueue::new(2);
+        let pools = [Pubkey::new_unique(), Pubkey::new_unique()];
+        let tokens = [Pubkey::new_unique(), Pubkey::new_unique()];
+        assert_eq!(queue.push(ArbOpportunity::through_pools(&pools, &tokens, 100, 10)), Queued::Added);
+        assert_eq!(queue.push(ArbOpportunity::through_pools(&pools, &tokens, 200, 10)), Queued::Added);
+        assert_eq!(queue.push(ArbOpportunity::through_pools(&pools, &tokens, 50, 10)), Queued::Discarded { worst_profit_lamports: 100 });
+        assert_eq!(queue.push(ArbOpportunity::through_pools(&pools, &tokens, 300, 10)), Queued::Evicted { worst_profit_lamports: 100 });
+        assert_eq!(queue.len(), 2);
+
+        // The most profitable executes first
+        let executed: Vec<u64> = std::iter::from_fn(|| queue.pop()).map(|opportunity| opportunity.expected_profit_lamports).collect();
+        assert_eq!(executed, vec![300, 200]);
+    }
+}
@@ -3,13 +3,16 @@
 use crate::common::config::RiskConfig;
 // SimulationResult import removed
 use crate::arbitrage::types::ArbOpportunity; // Added for should_execute
+use crate::data::oracle::{OraclePriceSink, OracleUpdate};
 use anyhow::Result;
 use log::warn; // Added for logging
+use std::sync::atomic::{AtomicU64, Ordering};
 
 pub struct RiskEngine {
     config: RiskConfig,
     portfolio_value_usd: f64,
     daily_loss: f64, // This should be persisted or managed more robustly
+    sol_price_usd: AtomicU64, // Cents, 0 until the oracle reports
 }
 
 impl RiskEngine {
@@ -18,9 +21,21 @@ impl RiskEngine {
             config,
             portfolio_value_usd: initial_portfolio_value_usd,
             daily_loss: 0.0,
+            sol_price_usd: AtomicU64::new(0),
         }
     }
 
+    /// Update the SOL price used to value profits
+    pub fn update_sol_price(&self, price_usd: f64) {
+        self.sol_price_usd.store((price_usd * 100.0) as u64, Ordering::Relaxed);
+    }
+
+    /// SOL price, once the oracle has reported one
+    pub fn sol_price_usd(&self) -> Option<f64> {
+        let cents = self.sol_price_usd.load(Ordering::Relaxed);
+        (cents > 0).then(|| cents as f64 / 100.0)
+    }
+
     // Removed validate method as it used SimulationResult and was not called.
     // should_execute is called by strategies.rs
 
@@ -65,17 +80,26 @@ impl RiskEngine {
             }
         }
         
-        // Profit sanity check (e.g., profit isn't absurdly high, which might indicate an error)
-        // This requires converting lamports to USD and comparing against a percentage of portfolio.
-        // For simplicity, this is omitted for now but is an important check.
-        // Example:
-        // let profit_usd = opportunity.expected_profit_lamports as f64 / 1_000_000_000.0 * current_sol_price_usd;
-        // let max_sane_profit_usd = self.portfolio_value_usd * self.config.profit_sanity_check_percentage;
-        // if profit_usd > max_sane_profit_usd {
-        //     warn!("RiskEngine: Opportunity rejected. Profit {} USD seems too high (sanity check).", profit_usd);
-        //     return Ok(false);
-        // }
-
+        // Profit sanity check: a profit that's implausibly large next to the portfolio
+        // more likely means a pricing error than an opportunity. Valued opportunities
+        // carry their USD profit; others are converted at the oracle SOL price, and the
+        // check is skipped until there is one.
+        let profit_usd = if opportunity.metadata.valuation_confidence_bps > 0 {
+            Some(opportunity.metadata.expected_profit_usd_cents as f64 / 100.0)
+        } else {
+            self.sol_price_usd()
+                .map(|sol_price_usd| opportunity.expected_profit_lamports as f64 / 1_000_000_000.0 * sol_price_usd)
+        };
+        if let Some(profit_usd) = profit_usd {
+            let max_sane_profit_usd = self.portfolio_value_usd * self.config.profit_sanity_check_percentage;
+            if profit_usd > max_sane_profit_usd {
+                warn!(
+                    "RiskEngine: Opportunity rejected. Profit {:.2} USD exceeds the sanity limit of {:.2} USD.",
+                    profit_usd, max_sane_profit_usd
+                );
+                return Ok(false);
+            }
+        }
 
         // If all checks pass:
         Ok(true)
@@ -85,3 +109,11 @@ impl RiskEngine {
     // to be called by the executor or a monitoring component when a trade results in a loss,
     // so that `self.daily_loss` can be accurately updated.
 }
+
+impl OraclePriceSink for RiskEngine {
+    fn on_oracle_price(&self, update: &OracleUpdate) {
+        if update.is_sol() {
+            self.update_sol_price(update.price.price);
+        }
+    }
+}
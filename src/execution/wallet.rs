@@ -0,0 +1,130 @@
+//! Token balances of the payer wallet
+//!
+//! Trades are sized against what the wallet actually holds of their start token. The
+//! balances are read over RPC, SOL from the account's lamports plus any wrapped SOL,
+//! every other mint from the wallet's SPL Token and Token-2022 accounts, and refreshed
+//! periodically; sizing reads the last refresh.
+
+use anchor_spl::token::spl_token;
+use anchor_spl::token_2022::spl_token_2022;
+use dashmap::DashMap;
+use solana_account_decoder::UiAccountData;
+use solana_client::client_error::ClientError;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_client::rpc_request::TokenAccountsFilter;
+use solana_sdk::pubkey::Pubkey;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tracing::{debug, warn};
+
+pub struct WalletBalances {
+    rpc_client: Arc<RpcClient>,
+    owner: Pubkey,
+    /// Raw balance by mint, as of the last refresh
+    balances: DashMap<Pubkey, u64>,
+}
+
+impl WalletBalances {
+    pub fn new(rpc_client: Arc<RpcClient>, owner: Pubkey) -> Self {
+        Self {
+            rpc_client,
+            owner,
+            balances: DashMap::new(),
+        }
+    }
+
+    /// Raw balance of `mint` the wallet held at the last refresh; `None` before the
+    /// first one
+    pub fn balance(&self, mint: &Pubkey) -> Option<u64> {
+        if self.balances.is_empty() {
+            return None;
+        }
+        Some(self.balances.get(mint).map_or(0, |balance| *balance))
+    }
+
+    /// Replace the balances with `balances`, by mint
+    pub fn record(&self, balances: HashMap<Pubkey, u64>) {
+        self.balances.retain(|mint, _| balances.contains_key(mint));
+        for (mint, balance) in balances {
+            self.balances.insert(mint, balance);
+        }
+    }
+
+    /// Read the wallet's balances. Returns the number of mints held.
+    pub async fn refresh(&self) -> Result<usize, ClientError> {
+        let mut balances = HashMap::from([(spl_token::native_mint::id(), self.rpc_client.get_balance(&self.owner).await?)]);
+        for program_id in [spl_token::ID, spl_token_2022::ID] {
+            let accounts = self
+                .rpc_client
+                .get_token_accounts_by_owner(&self.owner, TokenAccountsFilter::ProgramId(program_id))
+                .await?;
+            for keyed in accounts {
+                match token_balance(&keyed.account.data) {
+                    Some((mint, amount)) => *balances.entry(mint).or_default() += amount,
+                    None => debug!("Unparsed token account {} of the wallet", keyed.pubkey),
+                }
+            }
+        }
+        let held = balances.len();
+        self.record(balances);
+        Ok(held)
+    }
+
+    /// Refresh every `period`, starting now
+    pub fn spawn_refresher(self: &Arc<Self>, period: Duration) -> JoinHandle<()> {
+        let wallet = Arc::clone(self);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(period);
+            loop {
+                interval.tick().await;
+                if let Err(e) = wallet.refresh().await {
+                    warn!("Wallet balances of {} not refreshed: {}", wallet.owner, e);
+                }
+            }
+        })
+    }
+}
+
+/// Mint and raw amount of a jsonParsed token account
+fn token_balance(data: &UiAccountData) -> Option<(Pubkey, u64)> {
+    let UiAccountData::Json(parsed) = data else {
+        return None;
+    };
+    let info = parsed.parsed.pointer("/info")?;
+    let mint = Pubkey::from_str(info.get("mint")?.as_str()?).ok()?;
+    let amount = info.pointer("/tokenAmount/amount")?.as_str()?.parse().ok()?;
+    Some((mint, amount))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_account_decoder::parse_account_data::ParsedAccount;
+
+    #[test]
+    fn test_balances_come_from_parsed_token_accounts() {
+        let mint = Pubkey::new_unique();
+        let data = UiAccountData::Json(ParsedAccount {
+            program: "spl-token".to_string(),
+            parsed: serde_json::json!({
+                "type": "account",
+                "info": {
+                    "mint": mint.to_string(),
+                    "owner": Pubkey::new_unique().to_string(),
+                    "tokenAmount": { "amount": "1500000", "decimals": 6, "uiAmount": 1.5, "uiAmountString": "1.5" }
+                }
+            }),
+            space: 165,
+        });
+        assert_eq!(token_balance(&data), Some((mint, 1_500_000)));
+
+        let wallet = WalletBalances::new(Arc::new(RpcClient::new("http://localhost:8899".to_string())), Pubkey::new_unique());
+        assert_eq!(wallet.balance(&mint), None);
+        wallet.record(HashMap::from([(mint, 1_500_000)]));
+        assert_eq!(wallet.balance(&mint), Some(1_500_000));
+        assert_eq!(wallet.balance(&Pubkey::new_unique()), Some(0));
+    }
+}
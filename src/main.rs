@@ -1,7 +1,6 @@
 //! src/main.rs
 
 use anyhow::Result;
-use moka::future::Cache;
 
 use solana_sdk::signature::{Keypair, Signer};
 use solana_sdk::bs58; // Ensure bs58 is in Cargo.toml
@@ -12,14 +11,21 @@ use tokio::sync::mpsc;
 use tracing::{error, info, info_span, warn, Instrument};
 
 use crate::arbitrage::strategies::StrategyOrchestrator;
-use crate::arbitrage::types::{ArbOpportunity, TokenInfos}; // Removed SwapPathSelected
+use crate::arbitrage::types::ArbOpportunity; // Removed SwapPathSelected
+use crate::arbitrage::valuation::ValuationService;
 use crate::common::config::{Config, EXECUTION_MODE_SIMULATE}; // Added EXECUTION_MODE_SIMULATE
 use crate::common::rpc_manager::create_rpc_manager; // Added RpcManager imports
 use crate::data::market_stream::init_market_data;
+use crate::data::oracle::{OraclePriceSink, PriceOracle};
 use crate::execution::executor::TransactionExecutor;
 use crate::execution::risk_engine::RiskEngine;
+use crate::execution::wallet::WalletBalances;
 use crate::fees::priority_fees::{init_global_fee_service, PriorityFeeConfig, FeeMode}; // Added fee imports
+use crate::markets::account_decoders::{AccountDecoderRegistry, AccountUpdateRouter};
+use crate::markets::lockless_cache::LocklessMarketCache;
+use crate::markets::market_loader::MarketLoader;
 use crate::markets::pools::PoolRegistry;
+use crate::markets::token_registry::TokenRegistry;
 use crate::telemetry::init_telemetry;
 
 
@@ -58,14 +64,18 @@ async fn main() -> Result<()> {
     info!("✅ RPC Manager initialized with slot-aware execution for MEV timing");
 
     // Initialize concurrent state caches
-    let token_cache: Arc<Cache<String, TokenInfos>> = Arc::new(
-        Cache::builder()
-            .time_to_live(Duration::from_secs(3600))
-            .build(),
-    );
-    let pool_registry = Arc::new(PoolRegistry::new(&config).await?);
+    let token_registry = Arc::new(TokenRegistry::from_config(&config));
+    let price_oracle = Arc::new(PriceOracle::from_config(&config));
+    let pool_registry = Arc::new(PoolRegistry::new(&config, token_registry.clone()).await?);
     metrics.pools_loaded.fetch_add(pool_registry.len() as u64, Ordering::Relaxed); // Changed to fetch_add
     info!("✅ Caches and pool registry initialized with {} pools.", pool_registry.len());
+    let snapshot_registry = pool_registry.clone();
+    snapshot_registry.spawn_snapshot_writer();
+    let valuation = Arc::new(ValuationService::new(price_oracle.clone()));
+    valuation.spawn_refresher(
+        pool_registry.clone(),
+        Duration::from_secs(config.valuation_refresh_interval_secs.unwrap_or(10).max(1)),
+    );
 
     // Get RPC client from manager for fee service initialization
     let rpc_client_for_fees = rpc_manager.get_client().await;
@@ -114,8 +124,25 @@ async fn main() -> Result<()> {
     );
     info!("✅ Advanced Transaction Executor started in {} mode with RPC Manager.", config.execution_mode);
 
+    // Quote engines of registry pools, kept current from their accounts' notifications
+    let account_router = Arc::new(
+        AccountUpdateRouter::new(AccountDecoderRegistry::with_known_programs(), LocklessMarketCache::new())
+            .with_max_age_slots(config.market_max_age_slots.unwrap_or(150)),
+    );
+    let market_loader = Arc::new(MarketLoader::new(rpc_manager.get_client().await, account_router.clone()));
+    market_loader.spawn_epoch_refresher(Duration::from_secs(60));
+
+    // Payer balances that cap trade sizes
+    let wallet = Arc::new(WalletBalances::new(rpc_manager.get_client().await, keypair.pubkey()));
+    wallet.spawn_refresher(Duration::from_secs(config.wallet_refresh_interval_secs.unwrap_or(10).max(1)));
+
     // Initialize market data pipeline
-    let market_rx = init_market_data(&config)
+    let market_rx = init_market_data(
+        &config,
+        Some(pool_registry.clone()),
+        Some(market_loader.clone()),
+        Some(price_oracle.clone()),
+    )
         .await
         .map_err(|e| anyhow::anyhow!("Failed to initialize market data: {}", e))?;
     info!("✅ Market data pipeline initialized.");
@@ -124,14 +151,21 @@ async fn main() -> Result<()> {
     let orchestrator = StrategyOrchestrator::new(
         config.clone(),
         pool_registry,
-        token_cache,
+        token_registry.clone(),
         exec_tx_for_orchestrator, // Pass the bounded sender for ArbOpportunity
         market_rx,
-        risk_engine,
+        risk_engine.clone(),
         metrics.clone(),
-    );
+    )
+    .with_valuation(valuation)
+    .with_markets(account_router)
+    .with_wallet(wallet);
     info!("✅ Strategy orchestrator initialized.");
 
+    // Push oracle prices into the components that value trades in USD
+    let oracle_sinks: Vec<Arc<dyn OraclePriceSink>> = vec![risk_engine];
+    price_oracle.spawn_forwarder(oracle_sinks);
+
     // Log active strategies
     info!("📋 Active strategies: {:?}", config.active_strategies);
 
@@ -166,6 +200,12 @@ async fn main() -> Result<()> {
 
     }
 
+    if let Err(e) = snapshot_registry.write_snapshot() {
+        warn!("Failed to write pool snapshot on shutdown: {}", e);
+    }
+    if let Err(e) = token_registry.save() {
+        warn!("Failed to write token cache on shutdown: {}", e);
+    }
     info!("👋 MEV Bot shutting down gracefully");
     Ok(())
 }
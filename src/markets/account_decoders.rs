@@ -0,0 +1,635 @@
+//! Program-ID keyed account decoder registry
+//!
+//! Account updates arrive as (address, owner, bytes). The owner selects the program's
+//! decoders and the discriminator or size selects the account type, giving a typed
+//! `PoolAccountState`. `AccountUpdateRouter` then hands the raw bytes to the
+//! `MarketBehavior::update_state` of every market that depends on the account and
+//! refreshes the pool's entry in the `LocklessMarketCache`.
+//!
+//! Anchor programs are matched on their 8-byte account discriminator and size, the
+//! others (Raydium AMM v4, Orca token-swap, Phoenix, token accounts) on size alone.
+
+use crate::markets::clob::{ClobParams, DepthLadder, PriceLevel};
+use crate::markets::depth_curve::{DepthCurveConfig, DepthCurves};
+use crate::markets::errors::MarketSimulationError;
+use crate::markets::foundation::{MarketBehavior, Quote};
+use crate::markets::lockless_cache::{CacheWrite, DataVersion, LocklessMarketCache};
+use crate::markets::meteora::AccountData;
+use crate::markets::meteora_dlmm::{
+    decode_lb_pair, DlmmBinArray, BIN_ARRAY_ACCOUNT_LEN, LB_PAIR_ACCOUNT_LEN, METEORA_DLMM_PROGRAM_ID,
+};
+use crate::markets::openbook_v2::{
+    decode_book_side, decode_market as decode_openbook_market, BookSideKind, OpenBookV2Market,
+    OPENBOOK_V2_BOOK_SIDE_LEN, OPENBOOK_V2_MARKET_LEN, OPENBOOK_V2_PROGRAM_ID,
+};
+use crate::markets::orca::TokenSwapLayout;
+use crate::markets::orca_token_swap::{
+    decode_token_swap_layout, ORCA_TOKEN_SWAP_V1_PROGRAM_ID, ORCA_TOKEN_SWAP_V2_PROGRAM_ID,
+    TOKEN_SWAP_ACCOUNT_LEN,
+};
+use crate::markets::orca_whirlpools_working::{
+    WhirlpoolTickArray, TICK_ARRAY_ACCOUNT_LEN as WHIRLPOOL_TICK_ARRAY_ACCOUNT_LEN, WHIRLPOOL_ACCOUNT_LEN,
+};
+use crate::markets::orca_whirpools::{unpack_from_slice, WhirlpoolAccount};
+use crate::markets::phoenix::{decode_market as decode_phoenix_market, PHOENIX_MARKET_HEADER_LEN, PHOENIX_PROGRAM_ID};
+use crate::markets::raydium::AmmInfo;
+use crate::markets::raydium_amm::{decode_amm_info, AMM_INFO_LEN, RAYDIUM_AMM_V4_PROGRAM_ID};
+use crate::markets::raydium_clmm_market::{
+    decode_amm_config_trade_fee, decode_pool_state as decode_clmm_pool_state, ClmmTickArray,
+    CLMM_AMM_CONFIG_ACCOUNT_LEN, CLMM_POOL_ACCOUNT_LEN, CLMM_TICK_ARRAY_ACCOUNT_LEN,
+};
+use crate::markets::raydium_cpmm::{
+    decode_amm_config as decode_cpmm_amm_config, decode_pool_state as decode_cpmm_pool_state, CpmmAmmConfig,
+    CpmmPoolState, CPMM_AMM_CONFIG_ACCOUNT_LEN, CPMM_POOL_ACCOUNT_LEN, RAYDIUM_CPMM_PROGRAM_ID,
+};
+use crate::markets::token_2022::{decode_token_account, MintInfo, TransferFees, TOKEN_ACCOUNT_LEN};
+use crate::markets::types::DexLabel;
+use crate::transactions::raydium_clmm_swap::{RaydiumClmmPoolState, RAYDIUM_CLMM_PROGRAM_ID};
+use anchor_spl::token::spl_token;
+use anchor_spl::token_2022::spl_token_2022;
+use dashmap::DashMap;
+use solana_program::hash;
+use solana_program::pubkey::Pubkey;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+/// A market shared between the router and the strategies quoting it
+pub type SharedMarket = Arc<RwLock<Box<dyn MarketBehavior>>>;
+
+/// Decodes the full account data, discriminator included
+pub type AccountDecodeFn = fn(&[u8]) -> Result<PoolAccountState, MarketSimulationError>;
+
+/// Typed state of an account a market depends on
+#[derive(Debug, Clone)]
+pub enum PoolAccountState {
+    RaydiumAmm(Box<AmmInfo>),
+    RaydiumClmmPool(Box<RaydiumClmmPoolState>),
+    RaydiumClmmTickArray { pool: Pubkey, tick_array: Box<ClmmTickArray> },
+    RaydiumClmmAmmConfig { trade_fee_rate: u32 },
+    RaydiumCpmmPool(Box<CpmmPoolState>),
+    RaydiumCpmmAmmConfig(Box<CpmmAmmConfig>),
+    OrcaTokenSwap(Box<TokenSwapLayout>),
+    Whirlpool(Box<WhirlpoolAccount>),
+    WhirlpoolTickArray { whirlpool: Pubkey, tick_array: Box<WhirlpoolTickArray> },
+    MeteoraLbPair(Box<AccountData>),
+    MeteoraBinArray { lb_pair: Pubkey, bin_array: Box<DlmmBinArray> },
+    OpenBookV2Market(Box<OpenBookV2Market>),
+    OpenBookV2BookSide { kind: BookSideKind, levels: Vec<PriceLevel> },
+    PhoenixMarket { params: ClobParams, ladder: DepthLadder },
+    /// A pool vault, SPL Token or Token-2022
+    TokenAccount { mint: Pubkey, amount: u64 },
+}
+
+impl PoolAccountState {
+    /// Venue the account belongs to; vaults are venue-agnostic
+    pub fn dex_label(&self) -> Option<DexLabel> {
+        match self {
+            Self::RaydiumAmm(_) => Some(DexLabel::Raydium),
+            Self::RaydiumClmmPool(_) | Self::RaydiumClmmTickArray { .. } | Self::RaydiumClmmAmmConfig { .. } => {
+                Some(DexLabel::RaydiumClmm)
+            }
+            Self::RaydiumCpmmPool(_) | Self::RaydiumCpmmAmmConfig(_) => Some(DexLabel::RaydiumCpmm),
+            Self::OrcaTokenSwap(_) => Some(DexLabel::Orca),
+            Self::Whirlpool(_) | Self::WhirlpoolTickArray { .. } => Some(DexLabel::OrcaWhirlpools),
+            Self::MeteoraLbPair(_) | Self::MeteoraBinArray { .. } => Some(DexLabel::Meteora),
+            Self::OpenBookV2Market(_) | Self::OpenBookV2BookSide { .. } => Some(DexLabel::OpenBookV2),
+            Self::PhoenixMarket { .. } => Some(DexLabel::Phoenix),
+            Self::TokenAccount { .. } => None,
+        }
+    }
+
+    /// Whether the account at this address is the pool (or market) itself
+    pub fn is_pool_account(&self) -> bool {
+        matches!(
+            self,
+            Self::RaydiumAmm(_)
+                | Self::RaydiumClmmPool(_)
+                | Self::RaydiumCpmmPool(_)
+                | Self::OrcaTokenSwap(_)
+                | Self::Whirlpool(_)
+                | Self::MeteoraLbPair(_)
+                | Self::OpenBookV2Market(_)
+                | Self::PhoenixMarket { .. }
+        )
+    }
+
+    /// Pool the account at `address` belongs to, when its data says so. Vaults, book
+    /// sides and configs don't, and are routed through `AccountUpdateRouter::register_market`.
+    pub fn owning_pool(&self, address: &Pubkey) -> Option<Pubkey> {
+        match self {
+            Self::RaydiumClmmTickArray { pool, .. } => Some(*pool),
+            Self::WhirlpoolTickArray { whirlpool, .. } => Some(*whirlpool),
+            Self::MeteoraBinArray { lb_pair, .. } => Some(*lb_pair),
+            _ if self.is_pool_account() => Some(*address),
+            _ => None,
+        }
+    }
+
+    /// Token accounts holding the reserves a constant-product pool quotes from; empty
+    /// for venues whose pool account carries its own liquidity
+    pub fn vaults(&self) -> Vec<Pubkey> {
+        match self {
+            Self::RaydiumAmm(amm) => vec![amm.coin_vault, amm.pc_vault],
+            Self::RaydiumCpmmPool(pool) => vec![pool.token_0_vault, pool.token_1_vault],
+            Self::OrcaTokenSwap(swap) => vec![swap.token_account_a, swap.token_account_b],
+            _ => vec![],
+        }
+    }
+
+    /// Accounts besides the pool account and its tick or bin arrays that the pool's
+    /// market reads: vaults, configs and book sides
+    pub fn dependent_accounts(&self) -> Vec<Pubkey> {
+        match self {
+            Self::RaydiumClmmPool(pool) => vec![pool.amm_config],
+            Self::RaydiumCpmmPool(pool) => vec![pool.amm_config, pool.token_0_vault, pool.token_1_vault],
+            Self::OpenBookV2Market(market) => vec![market.bids, market.asks],
+            _ => self.vaults(),
+        }
+    }
+}
+
+/// How a decoder recognizes its account type among a program's accounts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccountLayout {
+    /// Exactly this many bytes
+    Size(usize),
+    /// At least this many bytes, for accounts with trailing extensions or variable books
+    MinSize(usize),
+    /// Anchor account: discriminator prefix and exact size
+    Discriminator { discriminator: [u8; 8], size: usize },
+}
+
+impl AccountLayout {
+    /// Layout of the Anchor account type `name`
+    pub fn anchor(name: &str, size: usize) -> Self {
+        Self::Discriminator {
+            discriminator: anchor_discriminator(name),
+            size,
+        }
+    }
+
+    pub fn matches(&self, data: &[u8]) -> bool {
+        match *self {
+            Self::Size(size) => data.len() == size,
+            Self::MinSize(size) => data.len() >= size,
+            Self::Discriminator { discriminator, size } => data.len() == size && data[..8] == discriminator,
+        }
+    }
+}
+
+/// First 8 bytes of `sha256("account:<name>")`
+pub fn anchor_discriminator(name: &str) -> [u8; 8] {
+    let mut discriminator = [0u8; 8];
+    discriminator.copy_from_slice(&hash::hash(format!("account:{}", name).as_bytes()).to_bytes()[..8]);
+    discriminator
+}
+
+#[derive(Clone, Copy)]
+struct AccountDecoder {
+    layout: AccountLayout,
+    decode: AccountDecodeFn,
+}
+
+/// Account decoders keyed by owning program
+#[derive(Clone, Default)]
+pub struct AccountDecoderRegistry {
+    decoders: HashMap<Pubkey, Vec<AccountDecoder>>,
+}
+
+impl AccountDecoderRegistry {
+    /// An empty registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A registry covering every venue the bot quotes natively, plus token vaults
+    pub fn with_known_programs() -> Self {
+        let program = |id: &str| Pubkey::from_str(id).expect("valid program id");
+        let mut registry = Self::new();
+
+        let raydium_amm = program(RAYDIUM_AMM_V4_PROGRAM_ID);
+        registry.register(raydium_amm, AccountLayout::Size(AMM_INFO_LEN), |data| {
+            Ok(PoolAccountState::RaydiumAmm(Box::new(decode_amm_info(data)?)))
+        });
+
+        let raydium_clmm = program(RAYDIUM_CLMM_PROGRAM_ID);
+        registry.register(raydium_clmm, AccountLayout::anchor("PoolState", CLMM_POOL_ACCOUNT_LEN), |data| {
+            Ok(PoolAccountState::RaydiumClmmPool(Box::new(decode_clmm_pool_state(data)?)))
+        });
+        registry.register(
+            raydium_clmm,
+            AccountLayout::anchor("TickArrayState", CLMM_TICK_ARRAY_ACCOUNT_LEN),
+            |data| {
+                let (tick_array, pool) = ClmmTickArray::from_account_data(data)?;
+                Ok(PoolAccountState::RaydiumClmmTickArray { pool, tick_array: Box::new(tick_array) })
+            },
+        );
+        registry.register(raydium_clmm, AccountLayout::anchor("AmmConfig", CLMM_AMM_CONFIG_ACCOUNT_LEN), |data| {
+            Ok(PoolAccountState::RaydiumClmmAmmConfig {
+                trade_fee_rate: decode_amm_config_trade_fee(data)?,
+            })
+        });
+
+        let raydium_cpmm = program(RAYDIUM_CPMM_PROGRAM_ID);
+        registry.register(raydium_cpmm, AccountLayout::anchor("PoolState", CPMM_POOL_ACCOUNT_LEN), |data| {
+            Ok(PoolAccountState::RaydiumCpmmPool(Box::new(decode_cpmm_pool_state(data)?)))
+        });
+        registry.register(raydium_cpmm, AccountLayout::anchor("AmmConfig", CPMM_AMM_CONFIG_ACCOUNT_LEN), |data| {
+            Ok(PoolAccountState::RaydiumCpmmAmmConfig(Box::new(decode_cpmm_amm_config(data)?)))
+        });
+
+        for token_swap in [ORCA_TOKEN_SWAP_V1_PROGRAM_ID, ORCA_TOKEN_SWAP_V2_PROGRAM_ID] {
+            registry.register(program(token_swap), AccountLayout::Size(TOKEN_SWAP_ACCOUNT_LEN), |data| {
+                Ok(PoolAccountState::OrcaTokenSwap(Box::new(decode_token_swap_layout(data)?)))
+            });
+        }
+
+        let whirlpool = orca_whirlpools_client::ID;
+        registry.register(whirlpool, AccountLayout::anchor("Whirlpool", WHIRLPOOL_ACCOUNT_LEN), |data| {
+            let account = unpack_from_slice(data).map_err(|e| MarketSimulationError::AccountDecodeError {
+                market: DexLabel::OrcaWhirlpools.str(),
+                details: e.to_string(),
+            })?;
+            Ok(PoolAccountState::Whirlpool(Box::new(account)))
+        });
+        registry.register(
+            whirlpool,
+            AccountLayout::anchor("TickArray", WHIRLPOOL_TICK_ARRAY_ACCOUNT_LEN),
+            |data| {
+                let (tick_array, whirlpool) = WhirlpoolTickArray::from_account_data(data)?;
+                Ok(PoolAccountState::WhirlpoolTickArray { whirlpool, tick_array: Box::new(tick_array) })
+            },
+        );
+
+        let dlmm = program(METEORA_DLMM_PROGRAM_ID);
+        registry.register(dlmm, AccountLayout::anchor("LbPair", LB_PAIR_ACCOUNT_LEN), |data| {
+            Ok(PoolAccountState::MeteoraLbPair(Box::new(decode_lb_pair(data)?)))
+        });
+        registry.register(dlmm, AccountLayout::anchor("BinArray", BIN_ARRAY_ACCOUNT_LEN), |data| {
+            let (bin_array, lb_pair) = DlmmBinArray::from_account_data(data)?;
+            Ok(PoolAccountState::MeteoraBinArray { lb_pair, bin_array: Box::new(bin_array) })
+        });
+
+        let openbook_v2 = program(OPENBOOK_V2_PROGRAM_ID);
+        registry.register(openbook_v2, AccountLayout::anchor("Market", OPENBOOK_V2_MARKET_LEN), |data| {
+            Ok(PoolAccountState::OpenBookV2Market(Box::new(decode_openbook_market(data)?)))
+        });
+        registry.register(openbook_v2, AccountLayout::anchor("BookSide", OPENBOOK_V2_BOOK_SIDE_LEN), |data| {
+            let (kind, levels) = decode_book_side(data)?;
+            Ok(PoolAccountState::OpenBookV2BookSide { kind, levels })
+        });
+
+        // Seats and other Phoenix accounts are smaller than a market header
+        registry.register(program(PHOENIX_PROGRAM_ID), AccountLayout::MinSize(PHOENIX_MARKET_HEADER_LEN), |data| {
+            let (params, ladder) = decode_phoenix_market(data)?;
+            Ok(PoolAccountState::PhoenixMarket { params, ladder })
+        });
+
+        registry.register(spl_token::ID, AccountLayout::Size(TOKEN_ACCOUNT_LEN), decode_vault);
+        registry.register(spl_token_2022::ID, AccountLayout::MinSize(TOKEN_ACCOUNT_LEN), decode_vault);
+
+        registry
+    }
+
+    /// Add a decoder for accounts of `program_id` matching `layout`. Layouts are tried
+    /// in registration order.
+    pub fn register(&mut self, program_id: Pubkey, layout: AccountLayout, decode: AccountDecodeFn) {
+        self.decoders
+            .entry(program_id)
+            .or_default()
+            .push(AccountDecoder { layout, decode });
+    }
+
+    pub fn is_registered(&self, program_id: &Pubkey) -> bool {
+        self.decoders.contains_key(program_id)
+    }
+
+    /// Decode an account owned by `owner`. `None` when no registered layout matches,
+    /// an error when one does but its data is malformed.
+    pub fn decode(&self, owner: &Pubkey, data: &[u8]) -> Result<Option<PoolAccountState>, MarketSimulationError> {
+        let Some(decoders) = self.decoders.get(owner) else {
+            return Ok(None);
+        };
+        decoders
+            .iter()
+            .find(|decoder| decoder.layout.matches(data))
+            .map(|decoder| (decoder.decode)(data))
+            .transpose()
+    }
+}
+
+/// A pool vault, or any other token account
+fn decode_vault(data: &[u8]) -> Result<PoolAccountState, MarketSimulationError> {
+    let (mint, amount) = decode_token_account(data)?;
+    Ok(PoolAccountState::TokenAccount { mint, amount })
+}
+
+/// Routes decoded account updates to the markets and cache entries that depend on them
+pub struct AccountUpdateRouter {
+    registry: AccountDecoderRegistry,
+    cache: LocklessMarketCache,
+    /// Markets by pool address
+    markets: DashMap<Pubkey, SharedMarket>,
+    /// Pools depending on an account whose data doesn't name its pool
+    dependents: DashMap<Pubkey, Vec<Pubkey>>,
+    /// Depth curves of each market's current state, by pool address
+    depth_curves: DashMap<Pubkey, Arc<DepthCurves>>,
+    /// Slots a market's cached version may trail the latest slot before it's no longer
+    /// quoted
+    max_age_slots: u64,
+    /// Token programs and transfer fees of the loaded markets' mints
+    mints: DashMap<Pubkey, MintInfo>,
+    /// Epoch the mints' transfer fees are charged at
+    epoch: AtomicU64,
+}
+
+impl AccountUpdateRouter {
+    pub fn new(registry: AccountDecoderRegistry, cache: LocklessMarketCache) -> Self {
+        Self {
+            registry,
+            cache,
+            markets: DashMap::new(),
+            dependents: DashMap::new(),
+            depth_curves: DashMap::new(),
+            max_age_slots: u64::MAX,
+            mints: DashMap::new(),
+            epoch: AtomicU64::new(0),
+        }
+    }
+
+    /// Stop quoting markets whose accounts haven't been written within `max_age_slots`
+    /// of the latest slot
+    pub fn with_max_age_slots(mut self, max_age_slots: u64) -> Self {
+        self.max_age_slots = max_age_slots;
+        self
+    }
+
+    pub fn registry(&self) -> &AccountDecoderRegistry {
+        &self.registry
+    }
+
+    pub fn cache(&self) -> &LocklessMarketCache {
+        &self.cache
+    }
+
+    /// Start routing updates to `market`. `accounts` lists the vaults, book sides and
+    /// configs it reads besides the pool account; tick and bin arrays name their pool
+    /// and are routed without being listed.
+    pub async fn register_market(&self, pool: Pubkey, market: SharedMarket, accounts: &[Pubkey]) {
+        self.rebuild_depth_curves(pool, market.read().await.as_ref());
+        self.markets.insert(pool, market);
+        for account in accounts {
+            let mut pools = self.dependents.entry(*account).or_default();
+            if !pools.contains(&pool) {
+                pools.push(pool);
+            }
+        }
+    }
+
+    /// Stop routing updates to `pool`'s market. Returns the accounts no other market
+    /// depends on anymore.
+    pub fn unregister_market(&self, pool: &Pubkey) -> Vec<Pubkey> {
+        self.markets.remove(pool);
+        self.depth_curves.remove(pool);
+        let mut released = Vec::new();
+        self.dependents.retain(|account, pools| {
+            let depended = pools.contains(pool);
+            pools.retain(|dependent| dependent != pool);
+            if depended && pools.is_empty() {
+                released.push(*account);
+            }
+            !pools.is_empty()
+        });
+        released
+    }
+
+    /// Whether updates to `account` reach a market: it's a registered pool or an
+    /// account one depends on
+    pub fn routes(&self, account: &Pubkey) -> bool {
+        self.markets.contains_key(account) || self.dependents.contains_key(account)
+    }
+
+    /// Depth curves of `pool`'s market as of its last applied update; `None` when it
+    /// isn't registered, is stale or can't be quoted
+    pub fn depth_curves(&self, pool: &Pubkey) -> Option<Arc<DepthCurves>> {
+        if self.is_stale(pool) {
+            return None;
+        }
+        self.depth_curves.get(pool).map(|curves| curves.clone())
+    }
+
+    /// Quote `amount_in` on `pool`'s market; `None` when it isn't registered, is stale
+    /// or rejects the amount
+    pub async fn quote(&self, pool: &Pubkey, amount_in: u64, a_to_b: bool) -> Option<Quote> {
+        if self.is_stale(pool) {
+            return None;
+        }
+        let market = self.markets.get(pool).map(|market| market.clone())?;
+        let quote = market.read().await.get_quote(amount_in, a_to_b);
+        quote.ok()
+    }
+
+    /// Keep the mints of `fees` for pricing transfers, and its epoch if it's later
+    pub fn record_transfer_fees(&self, fees: &TransferFees) {
+        for info in fees.mints() {
+            self.mints.insert(info.mint, *info);
+        }
+        self.observe_epoch(fees.epoch());
+    }
+
+    pub fn observe_epoch(&self, epoch: u64) {
+        self.epoch.fetch_max(epoch, Ordering::Relaxed);
+    }
+
+    /// Transfer fees of `mints` at the current epoch, for those a loaded market trades
+    pub fn transfer_fees<'a>(&self, mints: impl IntoIterator<Item = &'a Pubkey>) -> TransferFees {
+        let infos = mints.into_iter().filter_map(|mint| self.mints.get(mint).map(|info| *info));
+        TransferFees::new(infos, self.epoch.load(Ordering::Relaxed))
+    }
+
+    /// Whether the cache reports `pool`'s market among its stale markets
+    fn is_stale(&self, pool: &Pubkey) -> bool {
+        self.cache.is_stale(&pool.to_string(), self.max_age_slots)
+    }
+
+    /// Resample `pool`'s depth curves from `market`
+    fn rebuild_depth_curves(&self, pool: Pubkey, market: &dyn MarketBehavior) {
+        let curves = DepthCurveConfig::probe(market).and_then(|config| DepthCurves::build(market, &config).ok());
+        match curves {
+            Some(curves) => {
+                self.depth_curves.insert(pool, Arc::new(curves));
+            }
+            None => {
+                self.depth_curves.remove(&pool);
+            }
+        }
+    }
+
+    /// Decode an account update and apply it. Every dependent market is updated even
+    /// if one rejects the data; the first rejection is returned. A market isn't updated
+    /// with an account older than the copy the cache last versioned for it.
+    pub async fn apply_account_update(
+        &self,
+        address: &Pubkey,
+        owner: &Pubkey,
+        data: &[u8],
+        version: DataVersion,
+    ) -> Result<Option<PoolAccountState>, MarketSimulationError> {
+        let Some(state) = self.registry.decode(owner, data)? else {
+            return Ok(None);
+        };
+
+        let mut pools = self
+            .dependents
+            .get(address)
+            .map(|pools| pools.clone())
+            .unwrap_or_default();
+        if let Some(pool) = state.owning_pool(address) {
+            if !pools.contains(&pool) {
+                pools.push(pool);
+            }
+        }
+
+        let mut first_error = None;
+        let account = address.to_string();
+        for pool in pools {
+            if self.cache.update_account_data(&pool.to_string(), &account, data, version) == CacheWrite::Stale {
+                continue;
+            }
+            let Some(market) = self.markets.get(&pool).map(|market| market.clone()) else {
+                continue;
+            };
+            let mut market = market.write().await;
+            if let Err(e) = market.update_state(data) {
+                first_error.get_or_insert(e);
+            }
+            self.rebuild_depth_curves(pool, market.as_ref());
+        }
+        match first_error {
+            Some(e) => Err(e),
+            None => Ok(Some(state)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::markets::token_2022::tests::token_account;
+    use crate::markets::types::{Market, MarketId};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Counts the updates it receives
+    struct CountingMarket(Arc<AtomicUsize>);
+
+    impl MarketBehavior for CountingMarket {
+        fn get_quote(&self, _amount_in: u64, _a_to_b: bool) -> Result<Quote, MarketSimulationError> {
+            Err(MarketSimulationError::NoRouteFound {
+                market: "counting".to_string(),
+                reason: "not a real market".to_string(),
+            })
+        }
+        fn get_price(&self) -> Result<f64, MarketSimulationError> {
+            Ok(0.0)
+        }
+        fn update_state(&mut self, _new_data: &[u8]) -> Result<(), MarketSimulationError> {
+            self.0.fetch_add(1, Ordering::Relaxed);
+            Ok(())
+        }
+        fn market_id(&self) -> MarketId {
+            MarketId::Raydium
+        }
+        fn dex_label(&self) -> DexLabel {
+            DexLabel::Raydium
+        }
+    }
+
+    #[test]
+    fn test_decode_by_program_and_layout() {
+        let registry = AccountDecoderRegistry::with_known_programs();
+        let raydium_amm = Pubkey::from_str(RAYDIUM_AMM_V4_PROGRAM_ID).unwrap();
+        let amm_data = borsh::to_vec(&AmmInfo::default()).unwrap();
+
+        let state = registry.decode(&raydium_amm, &amm_data).unwrap().unwrap();
+        assert!(matches!(state, PoolAccountState::RaydiumAmm(_)));
+        assert_eq!(state.dex_label(), Some(DexLabel::Raydium));
+        // Unknown owner or size
+        assert!(registry.decode(&Pubkey::new_unique(), &amm_data).unwrap().is_none());
+        assert!(registry.decode(&raydium_amm, &amm_data[1..]).unwrap().is_none());
+
+        // Anchor accounts also need the right discriminator
+        let raydium_cpmm = Pubkey::from_str(RAYDIUM_CPMM_PROGRAM_ID).unwrap();
+        let mut pool = CpmmPoolState::default();
+        assert!(registry.decode(&raydium_cpmm, &borsh::to_vec(&pool).unwrap()).unwrap().is_none());
+        pool.discriminator = anchor_discriminator("PoolState");
+        let state = registry.decode(&raydium_cpmm, &borsh::to_vec(&pool).unwrap()).unwrap().unwrap();
+        assert!(matches!(state, PoolAccountState::RaydiumCpmmPool(_)));
+
+        let mint = Pubkey::new_unique();
+        let state = registry.decode(&spl_token::ID, &token_account(&mint, 42)).unwrap().unwrap();
+        assert!(matches!(state, PoolAccountState::TokenAccount { mint: m, amount: 42 } if m == mint));
+        assert_eq!(state.owning_pool(&Pubkey::new_unique()), None);
+    }
+
+    #[tokio::test]
+    async fn test_router_feeds_markets_and_cache() {
+        let router = AccountUpdateRouter::new(AccountDecoderRegistry::with_known_programs(), LocklessMarketCache::new())
+            .with_max_age_slots(5);
+        let raydium_amm = Pubkey::from_str(RAYDIUM_AMM_V4_PROGRAM_ID).unwrap();
+        let (pool, vault) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let updates = Arc::new(AtomicUsize::new(0));
+        let market: SharedMarket = Arc::new(RwLock::new(Box::new(CountingMarket(updates.clone()))));
+        router.register_market(pool, market, &[vault]).await;
+        assert!(router.routes(&pool) && router.routes(&vault));
+        router.cache().insert(Market {
+            token_mint_a: String::new(),
+            token_vault_a: vault.to_string(),
+            token_mint_b: String::new(),
+            token_vault_b: String::new(),
+            dex_label: DexLabel::Raydium,
+            fee: 0,
+            id: pool.to_string(),
+            account_data: None,
+            liquidity: None,
+        }, DataVersion::at_slot(10));
+
+        let amm_data = borsh::to_vec(&AmmInfo::default()).unwrap();
+        router.apply_account_update(&pool, &raydium_amm, &amm_data, DataVersion::at_slot(11)).await.unwrap();
+        assert_eq!(updates.load(Ordering::Relaxed), 1);
+        assert_eq!(router.cache().get(&pool.to_string()).unwrap().account_data, Some(amm_data));
+
+        // A late update from before the cached state reaches neither cache nor market
+        let late_data = borsh::to_vec(&AmmInfo { status: 1, ..AmmInfo::default() }).unwrap();
+        router.apply_account_update(&pool, &raydium_amm, &late_data, DataVersion::at_slot(9)).await.unwrap();
+        assert_eq!(updates.load(Ordering::Relaxed), 1);
+        assert_ne!(router.cache().get(&pool.to_string()).unwrap().account_data, Some(late_data));
+
+        // Vaults reach the market through its registered accounts only
+        let vault_data = token_account(&Pubkey::new_unique(), 7);
+        router.apply_account_update(&vault, &spl_token::ID, &vault_data, DataVersion::at_slot(12)).await.unwrap();
+        router
+            .apply_account_update(&Pubkey::new_unique(), &spl_token::ID, &vault_data, DataVersion::at_slot(12))
+            .await
+            .unwrap();
+        assert_eq!(updates.load(Ordering::Relaxed), 2);
+
+        // Every account is versioned, not only the pool's
+        router.apply_account_update(&vault, &spl_token::ID, &vault_data, DataVersion::at_slot(11)).await.unwrap();
+        assert_eq!(updates.load(Ordering::Relaxed), 2);
+
+        // The vault write keeps the market fresh until it trails the latest slot by more
+        // than the max age
+        router.cache().observe_slot(17);
+        assert!(!router.is_stale(&pool));
+        router.cache().observe_slot(18);
+        assert!(router.is_stale(&pool));
+
+        assert_eq!(router.unregister_market(&pool), vec![vault]);
+        assert!(!router.routes(&vault) && !router.routes(&pool));
+        router.apply_account_update(&vault, &spl_token::ID, &vault_data, DataVersion::at_slot(13)).await.unwrap();
+        assert_eq!(updates.load(Ordering::Relaxed), 2);
+    }
+}
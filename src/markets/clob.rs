@@ -0,0 +1,504 @@
+//! Central limit order book quoting shared by the OpenBook v2 and Phoenix venues
+//!
+//! Both books are reduced to a `DepthLadder` of price levels in lots: prices are quote
+//! lots per base lot and sizes are base lots. A taker quote walks the opposite side of
+//! the book level by level, like an immediate-or-cancel order would, and the taker fee
+//! is charged in the quote token on both sides: deducted from what a sell receives and
+//! added on top of what a buy spends.
+//!
+//! `a_to_b` always means selling the base token for the quote token.
+
+use crate::arbitrage::types::{Route, TokenInfos};
+use crate::common::utils::from_str;
+use crate::markets::errors::MarketSimulationError;
+use crate::markets::foundation::{MarketBehavior, Quote};
+use crate::markets::openbook_v2::{
+    decode_book_side, decode_market as decode_openbook_market, BookSideKind, OPENBOOK_V2_BOOK_SIDE_LEN,
+    OPENBOOK_V2_MARKET_LEN,
+};
+use crate::markets::phoenix::decode_market as decode_phoenix_market;
+use crate::markets::types::{DexLabel, Market, MarketId};
+use anyhow::Result;
+use log::info;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_program::pubkey::Pubkey;
+use std::collections::{BTreeMap, HashMap};
+
+/// One resting price level
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PriceLevel {
+    /// Quote lots per base lot
+    pub price_lots: u64,
+    /// Base lots resting at this price
+    pub base_lots: u64,
+}
+
+/// Aggregated order book, best prices first on both sides
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DepthLadder {
+    /// Descending by price
+    pub bids: Vec<PriceLevel>,
+    /// Ascending by price
+    pub asks: Vec<PriceLevel>,
+}
+
+impl DepthLadder {
+    /// Aggregate individual `(price_lots, base_lots)` orders of one side into levels,
+    /// sorted best price first
+    pub fn levels_from_orders(orders: impl IntoIterator<Item = (u64, u64)>, bids: bool) -> Vec<PriceLevel> {
+        let mut by_price: BTreeMap<u64, u64> = BTreeMap::new();
+        for (price_lots, base_lots) in orders {
+            if price_lots == 0 || base_lots == 0 {
+                continue;
+            }
+            let level = by_price.entry(price_lots).or_default();
+            *level = level.saturating_add(base_lots);
+        }
+
+        let levels = by_price.into_iter().map(|(price_lots, base_lots)| PriceLevel { price_lots, base_lots });
+        if bids {
+            levels.rev().collect()
+        } else {
+            levels.collect()
+        }
+    }
+
+    pub fn best_bid(&self) -> Option<u64> {
+        self.bids.first().map(|level| level.price_lots)
+    }
+
+    pub fn best_ask(&self) -> Option<u64> {
+        self.asks.first().map(|level| level.price_lots)
+    }
+}
+
+/// Taker fee charged in quote atoms, as `numerator / denominator`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TakerFee {
+    pub numerator: u64,
+    pub denominator: u64,
+}
+
+impl TakerFee {
+    /// Fee on `quote_amount`, rounded up like both programs do
+    fn on(&self, quote_amount: u128) -> u128 {
+        (quote_amount * self.numerator as u128).div_ceil(self.denominator as u128)
+    }
+
+    /// Largest pre-fee amount whose fee still fits in `budget`
+    fn budget_before_fee(&self, budget: u128) -> u128 {
+        budget * self.denominator as u128 / (self.denominator as u128 + self.numerator as u128)
+    }
+}
+
+/// Lot sizes and fee of a book, independent of its venue
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClobParams {
+    pub base_mint: Pubkey,
+    pub quote_mint: Pubkey,
+    /// Base atoms per base lot
+    pub base_lot_size: u64,
+    /// Quote atoms per quote lot
+    pub quote_lot_size: u64,
+    pub taker_fee: TakerFee,
+}
+
+/// An OpenBook v2 or Phoenix market priced from its decoded book
+#[derive(Debug, Clone)]
+pub struct ClobMarket {
+    /// `DexLabel::OpenBookV2` or `DexLabel::Phoenix`
+    venue: DexLabel,
+
+    /// Market account address
+    market_address: Pubkey,
+
+    params: ClobParams,
+
+    ladder: DepthLadder,
+}
+
+impl ClobMarket {
+    pub fn new(venue: DexLabel, market_address: Pubkey, params: ClobParams, ladder: DepthLadder) -> Self {
+        Self {
+            venue,
+            market_address,
+            params,
+            ladder,
+        }
+    }
+
+    /// Create a Phoenix market from its single market account
+    pub fn from_phoenix_account(market_address: Pubkey, data: &[u8]) -> Result<Self, MarketSimulationError> {
+        let (params, ladder) = decode_phoenix_market(data)?;
+        Ok(Self::new(DexLabel::Phoenix, market_address, params, ladder))
+    }
+
+    /// Create an OpenBook v2 market from its market, bids and asks accounts
+    pub fn from_openbook_v2_accounts(
+        market_address: Pubkey,
+        market_data: &[u8],
+        bids_data: &[u8],
+        asks_data: &[u8],
+    ) -> Result<Self, MarketSimulationError> {
+        let header = decode_openbook_market(market_data)?;
+        let mut market = Self::new(DexLabel::OpenBookV2, market_address, header.params(), DepthLadder::default());
+        market.update_state(bids_data)?;
+        market.update_state(asks_data)?;
+        Ok(market)
+    }
+
+    pub fn market_address(&self) -> Pubkey {
+        self.market_address
+    }
+
+    pub fn params(&self) -> &ClobParams {
+        &self.params
+    }
+
+    pub fn ladder(&self) -> &DepthLadder {
+        &self.ladder
+    }
+
+    fn market_id_for(venue: &DexLabel) -> MarketId {
+        match venue {
+            DexLabel::Phoenix => MarketId::Phoenix,
+            _ => MarketId::OpenBook,
+        }
+    }
+
+    fn insufficient_depth(&self, available: u64, required: u64) -> MarketSimulationError {
+        MarketSimulationError::InsufficientLiquidity {
+            market: Self::market_id_for(&self.venue),
+            available,
+            required,
+        }
+    }
+
+    /// Sell `amount_in` base atoms into the bids
+    fn quote_sell_base(&self, amount_in: u64) -> Result<Quote, MarketSimulationError> {
+        let ClobParams {
+            base_lot_size,
+            quote_lot_size,
+            taker_fee,
+            ..
+        } = self.params;
+
+        let lots_to_sell = amount_in / base_lot_size;
+        if lots_to_sell == 0 {
+            return Err(MarketSimulationError::NoRouteFound {
+                market: self.venue.str(),
+                reason: format!("{} is below one base lot ({})", amount_in, base_lot_size),
+            });
+        }
+
+        let mut remaining = lots_to_sell;
+        let mut quote_lots: u128 = 0;
+        for level in &self.ladder.bids {
+            let fill = remaining.min(level.base_lots);
+            quote_lots += fill as u128 * level.price_lots as u128;
+            remaining -= fill;
+            if remaining == 0 {
+                break;
+            }
+        }
+        if remaining > 0 {
+            return Err(self.insufficient_depth(lots_to_sell - remaining, lots_to_sell));
+        }
+
+        let quote_out = quote_lots * quote_lot_size as u128;
+        let fee = taker_fee.on(quote_out);
+        let amount_out = u64::try_from(quote_out - fee.min(quote_out)).map_err(|_| MarketSimulationError::NoRouteFound {
+            market: self.venue.str(),
+            reason: "quote output overflows u64".to_string(),
+        })?;
+
+        let best_bid = self.ladder.best_bid().unwrap_or_default();
+        let spot_out = lots_to_sell as f64 * best_bid as f64 * quote_lot_size as f64;
+        let price_impact = if spot_out > 0.0 {
+            ((1.0 - quote_out as f64 / spot_out) * 100.0).clamp(0.0, 100.0)
+        } else {
+            0.0
+        };
+
+        // The fee is paid in quote; express it in base atoms at the average fill price
+        let base_sold = lots_to_sell as u128 * base_lot_size as u128;
+        let fee_amount = (fee * base_sold).checked_div(quote_out).unwrap_or_default() as u64;
+
+        Ok(Quote {
+            amount_in: base_sold as u64,
+            amount_out,
+            price_impact,
+            fee_amount,
+            slippage_tolerance: 0.5,
+        })
+    }
+
+    /// Spend `amount_in` quote atoms, fee included, on the asks
+    fn quote_buy_base(&self, amount_in: u64) -> Result<Quote, MarketSimulationError> {
+        let ClobParams {
+            base_lot_size,
+            quote_lot_size,
+            taker_fee,
+            ..
+        } = self.params;
+
+        let mut budget_lots = (taker_fee.budget_before_fee(amount_in as u128) / quote_lot_size as u128) as u64;
+        let mut base_lots: u64 = 0;
+        let mut exhausted = true;
+        for level in &self.ladder.asks {
+            let affordable = budget_lots / level.price_lots;
+            let fill = affordable.min(level.base_lots);
+            base_lots += fill;
+            budget_lots -= fill * level.price_lots;
+            if fill < level.base_lots {
+                exhausted = false;
+                break;
+            }
+        }
+        if exhausted && budget_lots > 0 {
+            let available: u64 = self.ladder.asks.iter().map(|level| level.base_lots).sum();
+            return Err(self.insufficient_depth(available, available.saturating_add(1)));
+        }
+        if base_lots == 0 {
+            return Err(MarketSimulationError::NoRouteFound {
+                market: self.venue.str(),
+                reason: format!("{} quote atoms don't buy one base lot on {}", amount_in, self.market_address),
+            });
+        }
+
+        let spent_lots =
+            (taker_fee.budget_before_fee(amount_in as u128) / quote_lot_size as u128) as u64 - budget_lots;
+        let quote_spent = spent_lots as u128 * quote_lot_size as u128;
+        let fee = taker_fee.on(quote_spent);
+        let amount_out = base_lots * base_lot_size;
+
+        let best_ask = self.ladder.best_ask().unwrap_or_default();
+        let spot_out = quote_spent as f64 / best_ask as f64 / quote_lot_size as f64;
+        let price_impact = if spot_out > 0.0 {
+            ((1.0 - base_lots as f64 / spot_out) * 100.0).clamp(0.0, 100.0)
+        } else {
+            0.0
+        };
+
+        Ok(Quote {
+            amount_in: (quote_spent + fee) as u64,
+            amount_out,
+            price_impact,
+            fee_amount: fee as u64,
+            slippage_tolerance: 0.5,
+        })
+    }
+}
+
+impl MarketBehavior for ClobMarket {
+    /// `a_to_b` sells base for quote; otherwise quote buys base
+    fn get_quote(&self, amount_in: u64, a_to_b: bool) -> Result<Quote, MarketSimulationError> {
+        if amount_in == 0 {
+            return Err(MarketSimulationError::InvalidAmount {
+                market: self.market_id(),
+                amount: amount_in,
+            });
+        }
+        if self.params.base_lot_size == 0 || self.params.quote_lot_size == 0 {
+            return Err(MarketSimulationError::NoRouteFound {
+                market: self.venue.str(),
+                reason: format!("market {} has a zero lot size", self.market_address),
+            });
+        }
+
+        if a_to_b {
+            self.quote_sell_base(amount_in)
+        } else {
+            self.quote_buy_base(amount_in)
+        }
+    }
+
+    /// Mid price of one base atom in quote atoms; one-sided books use their only side
+    fn get_price(&self) -> Result<f64, MarketSimulationError> {
+        let mid_lots = match (self.ladder.best_bid(), self.ladder.best_ask()) {
+            (Some(bid), Some(ask)) => (bid as f64 + ask as f64) / 2.0,
+            (Some(price), None) | (None, Some(price)) => price as f64,
+            (None, None) => return Err(self.insufficient_depth(0, 1)),
+        };
+        if self.params.base_lot_size == 0 {
+            return Err(self.insufficient_depth(0, 1));
+        }
+        Ok(mid_lots * self.params.quote_lot_size as f64 / self.params.base_lot_size as f64)
+    }
+
+    /// Phoenix keeps the whole book in its market account. OpenBook v2 accepts the
+    /// market account or either book side; a side says whether it holds bids or asks.
+    fn update_state(&mut self, new_data: &[u8]) -> Result<(), MarketSimulationError> {
+        match self.venue {
+            DexLabel::Phoenix => {
+                let (params, ladder) = decode_phoenix_market(new_data)?;
+                self.params = params;
+                self.ladder = ladder;
+            }
+            _ => match new_data.len() {
+                OPENBOOK_V2_MARKET_LEN => {
+                    self.params = decode_openbook_market(new_data)?.params();
+                }
+                OPENBOOK_V2_BOOK_SIDE_LEN => {
+                    let (kind, levels) = decode_book_side(new_data)?;
+                    match kind {
+                        BookSideKind::Bids => self.ladder.bids = levels,
+                        BookSideKind::Asks => self.ladder.asks = levels,
+                    }
+                }
+                len => {
+                    return Err(MarketSimulationError::AccountDecodeError {
+                        market: self.venue.str(),
+                        details: format!("unexpected account size {}", len),
+                    })
+                }
+            },
+        }
+        Ok(())
+    }
+
+    fn market_id(&self) -> MarketId {
+        Self::market_id_for(&self.venue)
+    }
+
+    fn dex_label(&self) -> DexLabel {
+        self.venue.clone()
+    }
+}
+
+/// Fetch and decode the book of an OpenBook v2 or Phoenix market
+pub async fn load_clob_market(
+    rpc_client: &RpcClient,
+    venue: DexLabel,
+    market: Pubkey,
+) -> Result<ClobMarket, MarketSimulationError> {
+    let rpc_error = |e: solana_client::client_error::ClientError| MarketSimulationError::ApiRequestFailed {
+        market: venue.str(),
+        message: e.to_string(),
+        source: Some(Box::new(e)),
+    };
+
+    let market_account = rpc_client.get_account(&market).await.map_err(rpc_error)?;
+    if venue == DexLabel::Phoenix {
+        return ClobMarket::from_phoenix_account(market, &market_account.data);
+    }
+
+    let header = decode_openbook_market(&market_account.data)?;
+    let accounts = rpc_client
+        .get_multiple_accounts(&[header.bids, header.asks])
+        .await
+        .map_err(rpc_error)?;
+    let [Some(bids), Some(asks)] = &accounts[..] else {
+        return Err(MarketSimulationError::MissingField {
+            market: venue.str(),
+            field: format!("bids or asks account of market {}", market),
+        });
+    };
+
+    ClobMarket::from_openbook_v2_accounts(market, &market_account.data, &bids.data, &asks.data)
+}
+
+// Simulate one route locally against a freshly fetched order book
+pub async fn simulate_route_clob(
+    printing_amt: bool,
+    amount_in: u64,
+    route: Route,
+    market: Market,
+    tokens_infos: HashMap<String, TokenInfos>,
+) -> Result<(u64, u64), MarketSimulationError> {
+    let address = from_str(&market.id).map_err(|e| MarketSimulationError::InvalidResponseFormat {
+        market: market.dex_label.str(),
+        details: format!("bad market address {}: {}", market.id, e),
+    })?;
+
+    let env = crate::common::constants::Env::new();
+    let rpc_client = RpcClient::new(env.rpc_url);
+    let clob_market = load_clob_market(&rpc_client, market.dex_label.clone(), address).await?;
+
+    let quote = clob_market.get_quote(amount_in, route.token_0to1)?;
+    let min_amount_out = (quote.amount_out as f64 * (1.0 - quote.slippage_tolerance / 100.0)) as u64;
+
+    if printing_amt {
+        let symbol = |mint: &String| tokens_infos.get(mint).map(|t| t.symbol.clone()).unwrap_or_default();
+        info!(
+            "Local {}: In: {} {}, EstOut: {} {}, EstMinOut: {} {}",
+            market.dex_label.str(),
+            quote.amount_in,
+            symbol(&route.token_in),
+            quote.amount_out,
+            symbol(&route.token_out),
+            min_amount_out,
+            symbol(&route.token_out)
+        );
+    }
+
+    Ok((quote.amount_out, min_amount_out))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 1 base lot = 1_000 base atoms, 1 quote lot = 10 quote atoms, 10 bps taker fee
+    fn test_market() -> ClobMarket {
+        let params = ClobParams {
+            base_mint: Pubkey::new_unique(),
+            quote_mint: Pubkey::new_unique(),
+            base_lot_size: 1_000,
+            quote_lot_size: 10,
+            taker_fee: TakerFee {
+                numerator: 10,
+                denominator: 10_000,
+            },
+        };
+        let ladder = DepthLadder {
+            bids: DepthLadder::levels_from_orders([(100, 5), (99, 10), (100, 5)], true),
+            asks: DepthLadder::levels_from_orders([(102, 8), (101, 4)], false),
+        };
+        ClobMarket::new(DexLabel::Phoenix, Pubkey::new_unique(), params, ladder)
+    }
+
+    #[test]
+    fn test_ladder_aggregation() {
+        let market = test_market();
+        assert_eq!(
+            market.ladder().bids,
+            vec![
+                PriceLevel { price_lots: 100, base_lots: 10 },
+                PriceLevel { price_lots: 99, base_lots: 10 }
+            ]
+        );
+        assert_eq!(market.ladder().best_ask(), Some(101));
+        // Mid of 100 and 101 quote lots per base lot, in atoms
+        assert_eq!(market.get_price().unwrap(), 100.5 * 10.0 / 1_000.0);
+    }
+
+    #[test]
+    fn test_sell_walks_bids() {
+        let market = test_market();
+
+        // 12 lots: 10 at 100 and 2 at 99 = 1_198 quote lots = 11_980 atoms; fee ceil(11.98)
+        let quote = market.get_quote(12_500, true).unwrap();
+        assert_eq!(quote.amount_in, 12_000);
+        assert_eq!(quote.amount_out, 11_980 - 12);
+        assert!(quote.price_impact > 0.0);
+
+        assert!(market.get_quote(21_000, true).is_err());
+        assert!(market.get_quote(999, true).is_err());
+    }
+
+    #[test]
+    fn test_buy_walks_asks_with_fee_on_top() {
+        let market = test_market();
+
+        // 6_000 atoms leave 5_994 before fee = 599 quote lots: 4 at 101, then 1 at 102
+        let quote = market.get_quote(6_000, false).unwrap();
+        assert_eq!(quote.amount_out, 5 * 1_000);
+        assert_eq!(quote.fee_amount, 6);
+        assert_eq!(quote.amount_in, 5_060 + 6);
+
+        // Only 12 lots are offered
+        assert!(market.get_quote(20_000, false).is_err());
+        assert!(market.get_quote(0, false).is_err());
+    }
+}
@@ -0,0 +1,311 @@
+//! Piecewise liquidity depth curves
+//!
+//! A depth curve samples a market's exact-input quotes at geometrically spaced
+//! amounts and answers later sizing queries by linear interpolation between the
+//! breakpoints, in either direction. Swap output is concave in the input, so a chord
+//! between two breakpoints never lies above the true curve: interpolated outputs err
+//! on the low side and inverse lookups on the high side.
+//!
+//! Curves are snapshots; `MarketCache` and `AccountUpdateRouter` rebuild them whenever
+//! the market state changes.
+
+use crate::markets::errors::MarketSimulationError;
+use crate::markets::foundation::MarketBehavior;
+
+/// Breakpoints per direction when none are configured
+pub const DEFAULT_BREAKPOINTS: usize = 32;
+
+/// Smallest probe is `max_amount_in >> MIN_PROBE_SHIFT`, roughly a millionth of it
+const MIN_PROBE_SHIFT: u32 = 20;
+
+/// `DepthCurveConfig::probe` stops doubling once that adds under 1/`SATURATION_DIVISOR`
+/// to the output: the pool is nearly drained and no sizing goes that deep
+const SATURATION_DIVISOR: u64 = 100;
+
+/// How a market's depth curves are sampled
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DepthCurveConfig {
+    /// Largest input probed when swapping A for B, in token A units
+    pub max_amount_in_a_to_b: u64,
+    /// Largest input probed when swapping B for A, in token B units
+    pub max_amount_in_b_to_a: u64,
+    /// Number of quotes taken in each direction
+    pub breakpoints: usize,
+}
+
+impl DepthCurveConfig {
+    pub fn new(max_amount_in_a_to_b: u64, max_amount_in_b_to_a: u64) -> Self {
+        Self {
+            max_amount_in_a_to_b,
+            max_amount_in_b_to_a,
+            breakpoints: DEFAULT_BREAKPOINTS,
+        }
+    }
+
+    /// Config spanning the depth `market` can quote in each direction. `None` when a
+    /// direction can't be quoted at any amount.
+    pub fn probe(market: &dyn MarketBehavior) -> Option<Self> {
+        Some(Self::new(quotable_depth(market, true)?, quotable_depth(market, false)?))
+    }
+}
+
+/// Largest input worth sampling in one direction: inputs double from one raw unit until
+/// a quote fails after having succeeded, or the output saturates
+fn quotable_depth(market: &dyn MarketBehavior, a_to_b: bool) -> Option<u64> {
+    let mut deepest: Option<(u64, u64)> = None;
+    for shift in 0..u64::BITS {
+        let amount_in = 1u64 << shift;
+        match market.get_quote(amount_in, a_to_b) {
+            Ok(quote) => {
+                if let Some((_, amount_out)) = deepest.filter(|&(_, amount_out)| amount_out > 0) {
+                    if quote.amount_out.saturating_sub(amount_out) < amount_out / SATURATION_DIVISOR {
+                        return Some(amount_in);
+                    }
+                }
+                deepest = Some((amount_in, quote.amount_out));
+            }
+            // Below a lot size or a minimum, larger amounts may still quote
+            Err(_) if deepest.is_none() => continue,
+            Err(_) => break,
+        }
+    }
+    deepest.map(|(amount_in, _)| amount_in)
+}
+
+/// `amount_in -> amount_out` breakpoints of one swap direction, starting at the origin
+/// with both coordinates non-decreasing
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DepthCurve {
+    points: Vec<(u64, u64)>,
+}
+
+impl DepthCurve {
+    /// Curve through `points`, sorted by input. Outputs are made non-decreasing so the
+    /// inverse lookup stays well defined despite rounding in the sampled quotes.
+    pub fn from_points(points: impl IntoIterator<Item = (u64, u64)>) -> Self {
+        let mut sorted: Vec<(u64, u64)> = points.into_iter().filter(|&(amount_in, _)| amount_in > 0).collect();
+        sorted.sort_unstable();
+        sorted.dedup_by_key(|&mut (amount_in, _)| amount_in);
+
+        let mut curve = Vec::with_capacity(sorted.len() + 1);
+        curve.push((0, 0));
+        let mut max_out = 0;
+        for (amount_in, amount_out) in sorted {
+            max_out = max_out.max(amount_out);
+            curve.push((amount_in, max_out));
+        }
+        Self { points: curve }
+    }
+
+    /// Sample `market` at `breakpoints` amounts up to `max_amount_in`. Amounts the
+    /// market can't quote (below a lot size, beyond its liquidity) are left out, so the
+    /// curve ends at the largest quotable input.
+    pub fn build(
+        market: &dyn MarketBehavior,
+        a_to_b: bool,
+        max_amount_in: u64,
+        breakpoints: usize,
+    ) -> Result<Self, MarketSimulationError> {
+        if max_amount_in == 0 || breakpoints == 0 {
+            return Err(MarketSimulationError::InvalidAmount {
+                market: market.market_id(),
+                amount: max_amount_in,
+            });
+        }
+
+        let points = probe_amounts(max_amount_in, breakpoints)
+            .into_iter()
+            .filter_map(|amount_in| {
+                market
+                    .get_quote(amount_in, a_to_b)
+                    .ok()
+                    .map(|quote| (amount_in, quote.amount_out))
+            });
+        let curve = Self::from_points(points);
+        if curve.points.len() == 1 {
+            return Err(MarketSimulationError::NoRouteFound {
+                market: market.dex_label().str(),
+                reason: format!("no quotable amount up to {}", max_amount_in),
+            });
+        }
+        Ok(curve)
+    }
+
+    pub fn points(&self) -> &[(u64, u64)] {
+        &self.points
+    }
+
+    /// Largest sampled input; lookups past it return `None`
+    pub fn max_amount_in(&self) -> u64 {
+        self.points.last().map_or(0, |&(amount_in, _)| amount_in)
+    }
+
+    pub fn max_amount_out(&self) -> u64 {
+        self.points.last().map_or(0, |&(_, amount_out)| amount_out)
+    }
+
+    /// Interpolated output for `amount_in`, rounded down
+    pub fn amount_out(&self, amount_in: u64) -> Option<u64> {
+        if amount_in > self.max_amount_in() {
+            return None;
+        }
+        let upper = self.points.partition_point(|&(x, _)| x < amount_in);
+        let (x1, y1) = self.points[upper];
+        if upper == 0 || x1 == amount_in {
+            return Some(y1);
+        }
+        let (x0, y0) = self.points[upper - 1];
+        let interpolated = y0 as u128 + (amount_in - x0) as u128 * (y1 - y0) as u128 / (x1 - x0) as u128;
+        Some(interpolated as u64)
+    }
+
+    /// Interpolated input needed to receive `amount_out`, rounded up
+    pub fn amount_in_for(&self, amount_out: u64) -> Option<u64> {
+        if amount_out > self.max_amount_out() {
+            return None;
+        }
+        let upper = self.points.partition_point(|&(_, y)| y < amount_out);
+        let (x1, y1) = self.points[upper];
+        if upper == 0 || y1 == amount_out {
+            // First input reaching the output, which skips flat segments
+            return Some(x1);
+        }
+        let (x0, y0) = self.points[upper - 1];
+        let interpolated = x0 as u128 + ((amount_out - y0) as u128 * (x1 - x0) as u128).div_ceil((y1 - y0) as u128);
+        Some(interpolated as u64)
+    }
+
+    /// Output per unit of input on the segment containing `amount_in`
+    pub fn marginal_rate(&self, amount_in: u64) -> Option<f64> {
+        if amount_in > self.max_amount_in() {
+            return None;
+        }
+        let upper = self.points.partition_point(|&(x, _)| x <= amount_in).min(self.points.len() - 1);
+        let (x1, y1) = self.points[upper];
+        let (x0, y0) = self.points[upper.saturating_sub(1)];
+        if x1 == x0 {
+            return Some(0.0);
+        }
+        Some((y1 - y0) as f64 / (x1 - x0) as f64)
+    }
+}
+
+/// Depth curves of both swap directions of a market
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DepthCurves {
+    pub a_to_b: DepthCurve,
+    pub b_to_a: DepthCurve,
+}
+
+impl DepthCurves {
+    pub fn build(market: &dyn MarketBehavior, config: &DepthCurveConfig) -> Result<Self, MarketSimulationError> {
+        Ok(Self {
+            a_to_b: DepthCurve::build(market, true, config.max_amount_in_a_to_b, config.breakpoints)?,
+            b_to_a: DepthCurve::build(market, false, config.max_amount_in_b_to_a, config.breakpoints)?,
+        })
+    }
+
+    pub fn curve(&self, a_to_b: bool) -> &DepthCurve {
+        if a_to_b {
+            &self.a_to_b
+        } else {
+            &self.b_to_a
+        }
+    }
+}
+
+/// `breakpoints` geometrically spaced amounts ending at `max_amount_in`
+fn probe_amounts(max_amount_in: u64, breakpoints: usize) -> Vec<u64> {
+    let min_amount = (max_amount_in >> MIN_PROBE_SHIFT).max(1);
+    if breakpoints == 1 || min_amount == max_amount_in {
+        return vec![max_amount_in];
+    }
+    let ratio = (max_amount_in as f64 / min_amount as f64).powf(1.0 / (breakpoints - 1) as f64);
+    let mut amounts: Vec<u64> = (0..breakpoints - 1)
+        .map(|i| (min_amount as f64 * ratio.powi(i as i32)) as u64)
+        .collect();
+    amounts.push(max_amount_in);
+    amounts.dedup();
+    amounts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::markets::foundation::Quote;
+    use crate::markets::types::{DexLabel, MarketId};
+
+    /// Fee-less constant-product pool with reserves of 1_000_000 on each side
+    struct ConstantProduct;
+
+    impl MarketBehavior for ConstantProduct {
+        fn get_quote(&self, amount_in: u64, _a_to_b: bool) -> Result<Quote, MarketSimulationError> {
+            let amount_out = (1_000_000u128 * amount_in as u128 / (1_000_000 + amount_in as u128)) as u64;
+            Ok(Quote {
+                amount_in,
+                amount_out,
+                price_impact: 0.0,
+                fee_amount: 0,
+                slippage_tolerance: 0.5,
+            })
+        }
+        fn get_price(&self) -> Result<f64, MarketSimulationError> {
+            Ok(1.0)
+        }
+        fn update_state(&mut self, _new_data: &[u8]) -> Result<(), MarketSimulationError> {
+            Ok(())
+        }
+        fn market_id(&self) -> MarketId {
+            MarketId::Raydium
+        }
+        fn dex_label(&self) -> DexLabel {
+            DexLabel::Raydium
+        }
+    }
+
+    #[test]
+    fn test_interpolation_stays_below_the_curve() {
+        let market = ConstantProduct;
+        let curve = DepthCurve::build(&market, true, 500_000, DEFAULT_BREAKPOINTS).unwrap();
+        assert_eq!(curve.max_amount_in(), 500_000);
+        assert_eq!(curve.amount_out(500_000), Some(333_333));
+        assert_eq!(curve.amount_out(0), Some(0));
+        assert_eq!(curve.amount_out(500_001), None);
+
+        for amount_in in [3_000, 77_777, 250_000, 499_999] {
+            let exact = market.get_quote(amount_in, true).unwrap().amount_out;
+            let estimate = curve.amount_out(amount_in).unwrap();
+            assert!(estimate <= exact);
+            // 32 breakpoints over 20 doublings keep the chord within 1%
+            assert!(exact - estimate <= exact / 100, "{} vs {}", estimate, exact);
+        }
+    }
+
+    #[test]
+    fn test_inverse_lookup() {
+        let curve = DepthCurve::from_points([(100, 90), (200, 170), (300, 170), (400, 230)]);
+        assert_eq!(curve.amount_in_for(0), Some(0));
+        assert_eq!(curve.amount_in_for(90), Some(100));
+        // 130 is halfway between 90 and 170
+        assert_eq!(curve.amount_in_for(130), Some(150));
+        assert_eq!(curve.amount_in_for(131), Some(152));
+        // Flat segment: the first input reaching the output
+        assert_eq!(curve.amount_in_for(170), Some(200));
+        assert_eq!(curve.amount_in_for(231), None);
+        assert_eq!(curve.amount_out(curve.amount_in_for(200).unwrap()), Some(200));
+
+        assert_eq!(curve.marginal_rate(50), Some(0.9));
+        assert_eq!(curve.marginal_rate(250), Some(0.0));
+        assert_eq!(curve.marginal_rate(400), Some(0.6));
+    }
+
+    #[test]
+    fn test_probe_stops_where_the_pool_saturates() {
+        let config = DepthCurveConfig::probe(&ConstantProduct).unwrap();
+        // Doubling 2^27 adds under 1% once the input is ~100x the reserves
+        assert_eq!(config.max_amount_in_a_to_b, 1 << 27);
+        assert_eq!(config.max_amount_in_b_to_a, 1 << 27);
+        let curves = DepthCurves::build(&ConstantProduct, &config).unwrap();
+        assert!(curves.a_to_b.max_amount_out() > 990_000);
+    }
+}
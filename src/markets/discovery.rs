@@ -0,0 +1,380 @@
+//! On-chain pool discovery
+//!
+//! Finds every pool holding one of our tokens with `getProgramAccounts`, one query per
+//! (program, mint side, token), filtered on account size and a memcmp of the mint
+//! field. Queries run a few at a time behind a shared rate limit, since
+//! getProgramAccounts is the most expensive call most RPC providers serve. Results are
+//! validated by the account decoder registry before they're handed to `PoolRegistry`.
+
+use crate::markets::account_decoders::AccountDecoderRegistry;
+use crate::markets::meteora_dlmm::{LB_PAIR_ACCOUNT_LEN, METEORA_DLMM_PROGRAM_ID};
+use crate::markets::openbook_v2::{
+    BASE_MINT_OFFSET as OPENBOOK_BASE_MINT_OFFSET, OPENBOOK_V2_MARKET_LEN, OPENBOOK_V2_PROGRAM_ID,
+    QUOTE_MINT_OFFSET as OPENBOOK_QUOTE_MINT_OFFSET,
+};
+use crate::markets::orca_token_swap::{
+    ORCA_TOKEN_SWAP_V1_PROGRAM_ID, ORCA_TOKEN_SWAP_V2_PROGRAM_ID, TOKEN_SWAP_ACCOUNT_LEN,
+};
+use crate::markets::orca_whirlpools_working::WHIRLPOOL_ACCOUNT_LEN;
+use crate::markets::phoenix::{
+    BASE_MINT_OFFSET as PHOENIX_BASE_MINT_OFFSET, PHOENIX_PROGRAM_ID,
+    QUOTE_MINT_OFFSET as PHOENIX_QUOTE_MINT_OFFSET,
+};
+use crate::markets::pools::OnChainPool;
+use crate::markets::raydium_amm::{AMM_INFO_LEN, RAYDIUM_AMM_V4_PROGRAM_ID};
+use crate::markets::raydium_clmm_market::CLMM_POOL_ACCOUNT_LEN;
+use crate::markets::raydium_cpmm::{
+    CPMM_POOL_ACCOUNT_LEN, RAYDIUM_CPMM_PROGRAM_ID, TOKEN_0_MINT_OFFSET, TOKEN_1_MINT_OFFSET,
+};
+use crate::markets::types::DexLabel;
+use crate::transactions::raydium_clmm_swap::RAYDIUM_CLMM_PROGRAM_ID;
+use futures::stream::{self, StreamExt};
+use solana_account_decoder::UiAccountEncoding;
+use solana_client::client_error::ClientError;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_client::rpc_config::{RpcAccountInfoConfig, RpcProgramAccountsConfig};
+use solana_client::rpc_filter::{Memcmp, MemcmpEncodedBytes, RpcFilterType};
+use solana_program::pubkey::Pubkey;
+use std::collections::HashSet;
+use std::str::FromStr;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio::time::Instant;
+use tracing::{info, warn};
+
+/// `coin_vault_mint` / `pc_vault_mint` in a Raydium AMM v4 `AmmInfo`
+const RAYDIUM_AMM_MINT_OFFSETS: [usize; 2] = [400, 432];
+/// `token_mint_0` / `token_mint_1` in a Raydium CLMM `PoolState`
+const RAYDIUM_CLMM_MINT_OFFSETS: [usize; 2] = [73, 105];
+/// `token_mint_a` / `token_mint_b` in a `Whirlpool`
+const WHIRLPOOL_MINT_OFFSETS: [usize; 2] = [101, 181];
+/// `mint_a` / `mint_b` in an Orca token-swap account
+const TOKEN_SWAP_MINT_OFFSETS: [usize; 2] = [131, 163];
+/// `token_x_mint` / `token_y_mint` in a Meteora DLMM `LbPair`
+const METEORA_DLMM_MINT_OFFSETS: [usize; 2] = [88, 120];
+
+/// A program whose pool accounts can be found by mint
+#[derive(Debug, Clone)]
+pub struct DiscoveryTarget {
+    pub dex: DexLabel,
+    pub program_id: Pubkey,
+    /// Exact size of a pool account; `None` for programs with variable-size pools
+    pub data_size: Option<usize>,
+    /// Offsets of the two mints inside a pool account
+    pub mint_offsets: [usize; 2],
+    /// Prefix of the ids given to discovered pools in `PoolRegistry`
+    pub pool_id_prefix: &'static str,
+}
+
+impl DiscoveryTarget {
+    /// Every venue the bot quotes natively
+    pub fn known_programs() -> Vec<Self> {
+        let program = |id: &str| Pubkey::from_str(id).expect("valid program id");
+        let target = |dex, program_id, data_size, mint_offsets, pool_id_prefix| Self {
+            dex,
+            program_id,
+            data_size,
+            mint_offsets,
+            pool_id_prefix,
+        };
+        vec![
+            target(
+                DexLabel::Raydium,
+                program(RAYDIUM_AMM_V4_PROGRAM_ID),
+                Some(AMM_INFO_LEN),
+                RAYDIUM_AMM_MINT_OFFSETS,
+                "raydium",
+            ),
+            target(
+                DexLabel::RaydiumClmm,
+                program(RAYDIUM_CLMM_PROGRAM_ID),
+                Some(CLMM_POOL_ACCOUNT_LEN),
+                RAYDIUM_CLMM_MINT_OFFSETS,
+                "raydium_clmm",
+            ),
+            target(
+                DexLabel::RaydiumCpmm,
+                program(RAYDIUM_CPMM_PROGRAM_ID),
+                Some(CPMM_POOL_ACCOUNT_LEN),
+                [TOKEN_0_MINT_OFFSET, TOKEN_1_MINT_OFFSET],
+                "raydium_cpmm",
+            ),
+            target(
+                DexLabel::OrcaWhirlpools,
+                orca_whirlpools_client::ID,
+                Some(WHIRLPOOL_ACCOUNT_LEN),
+                WHIRLPOOL_MINT_OFFSETS,
+                "whirlpool",
+            ),
+            target(
+                DexLabel::Orca,
+                program(ORCA_TOKEN_SWAP_V1_PROGRAM_ID),
+                Some(TOKEN_SWAP_ACCOUNT_LEN),
+                TOKEN_SWAP_MINT_OFFSETS,
+                "orca",
+            ),
+            target(
+                DexLabel::Orca,
+                program(ORCA_TOKEN_SWAP_V2_PROGRAM_ID),
+                Some(TOKEN_SWAP_ACCOUNT_LEN),
+                TOKEN_SWAP_MINT_OFFSETS,
+                "orca",
+            ),
+            target(
+                DexLabel::Meteora,
+                program(METEORA_DLMM_PROGRAM_ID),
+                Some(LB_PAIR_ACCOUNT_LEN),
+                METEORA_DLMM_MINT_OFFSETS,
+                "meteora",
+            ),
+            target(
+                DexLabel::OpenBookV2,
+                program(OPENBOOK_V2_PROGRAM_ID),
+                Some(OPENBOOK_V2_MARKET_LEN),
+                [OPENBOOK_BASE_MINT_OFFSET, OPENBOOK_QUOTE_MINT_OFFSET],
+                "openbook_v2",
+            ),
+            // Market accounts vary in size with their capacity
+            target(
+                DexLabel::Phoenix,
+                program(PHOENIX_PROGRAM_ID),
+                None,
+                [PHOENIX_BASE_MINT_OFFSET, PHOENIX_QUOTE_MINT_OFFSET],
+                "phoenix",
+            ),
+        ]
+    }
+
+    /// Filters selecting the pools with `mint` on side `side` (0 or 1)
+    fn filters(&self, side: usize, mint: &Pubkey) -> Vec<RpcFilterType> {
+        let mut filters = vec![RpcFilterType::Memcmp(Memcmp::new(
+            self.mint_offsets[side],
+            MemcmpEncodedBytes::Base58(mint.to_string()),
+        ))];
+        if let Some(size) = self.data_size {
+            filters.push(RpcFilterType::DataSize(size as u64));
+        }
+        filters
+    }
+
+    fn read_mint(&self, data: &[u8], side: usize) -> Option<Pubkey> {
+        let offset = self.mint_offsets[side];
+        data.get(offset..offset + 32)
+            .map(|bytes| Pubkey::try_from(bytes).expect("slice is 32 bytes"))
+    }
+}
+
+/// A pool account found on-chain
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiscoveredPool {
+    pub address: Pubkey,
+    pub dex: DexLabel,
+    pub owner: Pubkey,
+    pub mint_a: Pubkey,
+    pub mint_b: Pubkey,
+    pub pool_id_prefix: &'static str,
+    pub data: Vec<u8>,
+    /// Decimals, fee and pricing state decoded from `data`
+    pub on_chain: OnChainPool,
+}
+
+impl DiscoveredPool {
+    /// Read the mints of a pool account owned by `target`'s program
+    pub fn from_account(target: &DiscoveryTarget, address: Pubkey, data: Vec<u8>) -> Option<Self> {
+        Some(Self {
+            address,
+            dex: target.dex.clone(),
+            owner: target.program_id,
+            mint_a: target.read_mint(&data, 0)?,
+            mint_b: target.read_mint(&data, 1)?,
+            pool_id_prefix: target.pool_id_prefix,
+            data,
+            on_chain: OnChainPool::default(),
+        })
+    }
+
+    /// Id of the pool in `PoolRegistry`
+    pub fn pool_id(&self) -> String {
+        format!("{}_{}", self.pool_id_prefix, self.address)
+    }
+}
+
+/// Result of a discovery run
+#[derive(Debug, Default)]
+pub struct DiscoveryReport {
+    /// Slot fetched before the first query; every account is at least this recent
+    pub slot: u64,
+    pub pools: Vec<DiscoveredPool>,
+    /// Queries that failed; their pools are missing from `pools`
+    pub failed_queries: usize,
+}
+
+/// Spaces requests at least `interval` apart across all callers
+pub struct RateLimiter {
+    interval: Duration,
+    next: Mutex<Instant>,
+}
+
+impl RateLimiter {
+    pub fn per_second(requests: u32) -> Self {
+        Self {
+            interval: Duration::from_secs(1) / requests.max(1),
+            next: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Wait for the next free request slot
+    pub async fn acquire(&self) {
+        let slot = {
+            let mut next = self.next.lock().await;
+            let slot = (*next).max(Instant::now());
+            *next = slot + self.interval;
+            slot
+        };
+        tokio::time::sleep_until(slot).await;
+    }
+}
+
+pub struct PoolDiscovery {
+    rpc_client: RpcClient,
+    targets: Vec<DiscoveryTarget>,
+    decoders: AccountDecoderRegistry,
+    rate_limiter: RateLimiter,
+    max_concurrent: usize,
+}
+
+impl PoolDiscovery {
+    pub fn new(rpc_client: RpcClient, requests_per_sec: u32, max_concurrent: usize) -> Self {
+        Self {
+            rpc_client,
+            targets: DiscoveryTarget::known_programs(),
+            decoders: AccountDecoderRegistry::with_known_programs(),
+            rate_limiter: RateLimiter::per_second(requests_per_sec),
+            max_concurrent: max_concurrent.max(1),
+        }
+    }
+
+    /// Find every pool of a known program holding one of `mints`. A failed query is
+    /// logged and counted; the pools of the other queries are still returned.
+    pub async fn discover(&self, mints: &[Pubkey]) -> Result<DiscoveryReport, ClientError> {
+        self.rate_limiter.acquire().await;
+        let slot = self.rpc_client.get_slot().await?;
+
+        let queries: Vec<(&DiscoveryTarget, usize, &Pubkey)> = self
+            .targets
+            .iter()
+            .flat_map(|target| mints.iter().flat_map(move |mint| [(target, 0, mint), (target, 1, mint)]))
+            .collect();
+        info!(
+            "🔎 Discovering pools for {} mints across {} programs ({} queries)",
+            mints.len(),
+            self.targets.len(),
+            queries.len()
+        );
+
+        // Futures are built up front: a mapping closure inside the stream would make it
+        // too generic over lifetimes to be spawned
+        let queries: Vec<_> = queries
+            .into_iter()
+            .map(|(target, side, mint)| self.query(target, side, mint))
+            .collect();
+        let results: Vec<_> = stream::iter(queries)
+            .buffer_unordered(self.max_concurrent)
+            .collect()
+            .await;
+
+        let mut report = DiscoveryReport {
+            slot,
+            ..DiscoveryReport::default()
+        };
+        let mut seen = HashSet::new();
+        for result in results {
+            match result {
+                Some(pools) => report
+                    .pools
+                    .extend(pools.into_iter().filter(|pool| seen.insert(pool.address))),
+                None => report.failed_queries += 1,
+            }
+        }
+        info!(
+            "✅ Discovered {} pools on-chain ({} failed queries)",
+            report.pools.len(),
+            report.failed_queries
+        );
+        Ok(report)
+    }
+
+    /// Pools of `target` with `mint` on `side`, or `None` if the query failed
+    async fn query(&self, target: &DiscoveryTarget, side: usize, mint: &Pubkey) -> Option<Vec<DiscoveredPool>> {
+        self.rate_limiter.acquire().await;
+        let config = RpcProgramAccountsConfig {
+            filters: Some(target.filters(side, mint)),
+            account_config: RpcAccountInfoConfig {
+                encoding: Some(UiAccountEncoding::Base64),
+                commitment: Some(self.rpc_client.commitment()),
+                ..RpcAccountInfoConfig::default()
+            },
+            ..RpcProgramAccountsConfig::default()
+        };
+        let accounts = match self
+            .rpc_client
+            .get_program_accounts_with_config(&target.program_id, config)
+            .await
+        {
+            Ok(accounts) => accounts,
+            Err(e) => {
+                warn!("{} getProgramAccounts failed for {}: {}", target.dex.str(), mint, e);
+                return None;
+            }
+        };
+
+        Some(accounts
+            .into_iter()
+            .filter_map(|(address, account)| {
+                let on_chain = match self.decoders.decode(&target.program_id, &account.data) {
+                    Ok(Some(state)) if state.is_pool_account() => OnChainPool::from_account_state(&state),
+                    _ => return None,
+                };
+                let pool = DiscoveredPool::from_account(target, address, account.data)?;
+                Some(DiscoveredPool { on_chain, ..pool })
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_discovered_pool_reads_mints_at_target_offsets() {
+        let targets = DiscoveryTarget::known_programs();
+        let whirlpool = targets.iter().find(|t| t.dex == DexLabel::OrcaWhirlpools).unwrap();
+        let (mint_a, mint_b) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let mut data = vec![0u8; WHIRLPOOL_ACCOUNT_LEN];
+        data[101..133].copy_from_slice(mint_a.as_ref());
+        data[181..213].copy_from_slice(mint_b.as_ref());
+
+        let address = Pubkey::new_unique();
+        let pool = DiscoveredPool::from_account(whirlpool, address, data).unwrap();
+        assert_eq!((pool.mint_a, pool.mint_b), (mint_a, mint_b));
+        assert_eq!(pool.owner, orca_whirlpools_client::ID);
+        assert_eq!(pool.pool_id(), format!("whirlpool_{}", address));
+        assert_eq!(whirlpool.filters(1, &mint_b).len(), 2);
+
+        // Variable-size programs are filtered on the mint alone, and short accounts rejected
+        let phoenix = targets.iter().find(|t| t.dex == DexLabel::Phoenix).unwrap();
+        assert_eq!(phoenix.filters(0, &mint_a).len(), 1);
+        assert!(DiscoveredPool::from_account(phoenix, address, vec![0; 100]).is_none());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_rate_limiter_spaces_requests() {
+        let limiter = RateLimiter::per_second(4);
+        let start = Instant::now();
+        for _ in 0..5 {
+            limiter.acquire().await;
+        }
+        assert_eq!(start.elapsed(), Duration::from_secs(1));
+    }
+}
@@ -1,4 +1,5 @@
 // src/markets/errors.rs
+use crate::markets::types::MarketId;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -40,6 +41,31 @@ pub enum MarketSimulationError {
 
     #[error("Token info not found for mint: {mint}")] // Kept from my original, might be useful
     TokenInfoNotFound { mint: String },
+
+    #[error("Invalid swap amount {amount} for {market:?}")]
+    InvalidAmount {
+        market: MarketId,
+        amount: u64,
+    },
+
+    #[error("Insufficient liquidity on {market:?}: available {available}, required {required}")]
+    InsufficientLiquidity {
+        market: MarketId,
+        available: u64,
+        required: u64,
+    },
+
+    #[error("Failed to decode {market} account data: {details}")]
+    AccountDecodeError {
+        market: String,
+        details: String,
+    },
+
+    #[error("Failed to decode mint {mint}: {details}")]
+    MintDecodeError {
+        mint: String,
+        details: String,
+    },
 }
 
 // Helper to convert reqwest::Error into MarketSimulationError
@@ -5,6 +5,7 @@
 //! 
 //! Based on the compact summary description for cross-DEX arbitrage strategies.
 
+use crate::markets::depth_curve::{DepthCurveConfig, DepthCurves};
 use crate::markets::errors::MarketSimulationError;
 use crate::markets::types::{MarketId, DexLabel};
 use anyhow::Result;
@@ -77,6 +78,12 @@ pub struct MarketCache {
     
     /// Market implementation
     market: Box<dyn MarketBehavior>,
+    
+    /// How depth curves are sampled; `None` disables them
+    depth_config: Option<DepthCurveConfig>,
+    
+    /// Depth curves of the current market state
+    depth_curves: Option<Arc<DepthCurves>>,
 }
 
 impl MarketCache {
@@ -90,9 +97,25 @@ impl MarketCache {
             quotes: Arc::new(RwLock::new(std::collections::HashMap::new())),
             cache_ttl_ms,
             market,
+            depth_config: None,
+            depth_curves: None,
         }
     }
     
+    /// Create a market cache that also keeps depth curves for fast trade sizing
+    /// 
+    /// Curves are built immediately and rebuilt on every `update_state`.
+    pub fn with_depth_curves(
+        market: Box<dyn MarketBehavior>,
+        cache_ttl_ms: u64,
+        depth_config: DepthCurveConfig,
+    ) -> Self {
+        let mut cache = Self::new(market, cache_ttl_ms);
+        cache.depth_config = Some(depth_config);
+        cache.rebuild_depth_curves();
+        cache
+    }
+    
     /// Get cached quote or calculate new one
     /// 
     /// Implements cache-aside pattern for optimal performance.
@@ -122,6 +145,42 @@ impl MarketCache {
         Ok(quote)
     }
     
+    /// Apply fresh on-chain data to the market
+    /// 
+    /// Cached quotes were computed on the old state and are dropped; depth curves are
+    /// rebuilt from the new one.
+    pub async fn update_state(&mut self, new_data: &[u8]) -> Result<(), MarketSimulationError> {
+        self.market.update_state(new_data)?;
+        self.quotes.write().await.clear();
+        self.rebuild_depth_curves();
+        Ok(())
+    }
+    
+    /// Depth curves of the current market state, if enabled and the market could be sampled
+    pub fn depth_curves(&self) -> Option<Arc<DepthCurves>> {
+        self.depth_curves.clone()
+    }
+    
+    /// Interpolated output for `amount_in`, without running the swap math
+    /// 
+    /// `None` when curves are disabled or `amount_in` is beyond the sampled range.
+    pub fn estimate_amount_out(&self, amount_in: u64, a_to_b: bool) -> Option<u64> {
+        self.depth_curves.as_ref()?.curve(a_to_b).amount_out(amount_in)
+    }
+    
+    /// Interpolated input needed to receive `amount_out`
+    pub fn estimate_amount_in(&self, amount_out: u64, a_to_b: bool) -> Option<u64> {
+        self.depth_curves.as_ref()?.curve(a_to_b).amount_in_for(amount_out)
+    }
+    
+    /// Resample the depth curves; a market that can't be quoted has none
+    fn rebuild_depth_curves(&mut self) {
+        self.depth_curves = self
+            .depth_config
+            .and_then(|config| DepthCurves::build(self.market.as_ref(), &config).ok())
+            .map(Arc::new);
+    }
+    
     /// Clear expired cache entries
     /// 
     /// Should be called periodically to prevent memory bloat.
@@ -142,6 +201,38 @@ mod tests {
     /// Mock market implementation for testing
     struct MockMarket;
     
+    /// Constant-rate market whose rate (in percent) is set by `update_state`
+    struct RateMarket(u64);
+    
+    impl MarketBehavior for RateMarket {
+        fn get_quote(&self, amount_in: u64, _a_to_b: bool) -> Result<Quote, MarketSimulationError> {
+            Ok(Quote {
+                amount_in,
+                amount_out: amount_in * self.0 / 100,
+                price_impact: 0.0,
+                fee_amount: 0,
+                slippage_tolerance: 0.5,
+            })
+        }
+        
+        fn get_price(&self) -> Result<f64, MarketSimulationError> {
+            Ok(self.0 as f64 / 100.0)
+        }
+        
+        fn update_state(&mut self, new_data: &[u8]) -> Result<(), MarketSimulationError> {
+            self.0 = new_data[0] as u64;
+            Ok(())
+        }
+        
+        fn market_id(&self) -> MarketId {
+            MarketId::Raydium
+        }
+        
+        fn dex_label(&self) -> DexLabel {
+            DexLabel::Raydium
+        }
+    }
+    
     impl MarketBehavior for MockMarket {
         fn get_quote(&self, amount_in: u64, _a_to_b: bool) -> Result<Quote, MarketSimulationError> {
             Ok(Quote {
@@ -184,4 +275,21 @@ mod tests {
         let quote2 = cache.get_quote(1_000_000, true).await.unwrap();
         assert_eq!(quote1.amount_out, quote2.amount_out);
     }
-}
\ No newline at end of file
+    
+    #[tokio::test]
+    async fn test_depth_curves_follow_state_updates() {
+        let config = DepthCurveConfig::new(1_000_000, 1_000_000);
+        let mut cache = MarketCache::with_depth_curves(Box::new(RateMarket(90)), 1000, config);
+        assert_eq!(cache.estimate_amount_out(123_456, true), Some(111_110));
+        assert_eq!(cache.estimate_amount_in(900_000, false), Some(1_000_000));
+        assert_eq!(cache.estimate_amount_out(1_000_001, true), None);
+        assert_eq!(cache.get_quote(100, true).await.unwrap().amount_out, 90);
+        
+        cache.update_state(&[50]).await.unwrap();
+        assert_eq!(cache.estimate_amount_out(123_456, true), Some(61_728));
+        assert_eq!(cache.get_quote(100, true).await.unwrap().amount_out, 50);
+        
+        let plain = MarketCache::new(Box::new(RateMarket(90)), 1000);
+        assert!(plain.depth_curves().is_none());
+    }
+}
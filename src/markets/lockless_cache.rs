@@ -10,18 +10,59 @@
 // shards, allowing multiple threads to access different keys simultaneously.
 
 use crate::markets::types::Market;
+use dashmap::mapref::entry::Entry;
 use dashmap::DashMap;
 use log::info;
 use std::collections::HashMap;
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 
+/// Position of a write in the on-chain history of the data it carries.
+/// Versions order by slot, then by write version within the slot.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct DataVersion {
+    pub slot: u64,
+    /// Geyser write version; 0 for sources that only report the slot.
+    pub write_version: u64,
+}
+
+impl DataVersion {
+    pub fn new(slot: u64, write_version: u64) -> Self {
+        Self { slot, write_version }
+    }
+
+    /// Version for sources such as RPC WebSocket subscriptions that only report a slot.
+    pub fn at_slot(slot: u64) -> Self {
+        Self::new(slot, 0)
+    }
+}
+
+/// Result of writing to an existing cache entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheWrite {
+    Applied,
+    /// The cached data is from a later version and was kept.
+    Stale,
+    /// No entry exists for the market.
+    Missing,
+}
+
+/// A cached market together with the version of its data.
+#[derive(Debug, Clone)]
+pub struct VersionedMarket {
+    pub market: Market,
+    pub version: DataVersion,
+    /// Slots between this entry and the latest slot the cache has seen.
+    pub age_slots: u64,
+}
+
 /// A snapshot of the cache's performance statistics.
 #[derive(Debug)]
 pub struct CacheStatistics {
     pub total_markets: usize,
     pub total_reads: u64,
     pub total_writes: u64,
+    pub stale_writes_rejected: u64,
     pub hit_ratio: f64,
 }
 
@@ -31,14 +72,39 @@ struct AtomicCacheStats {
     hits: AtomicU64,
     misses: AtomicU64,
     writes: AtomicU64,
+    stale_writes: AtomicU64,
+}
+
+struct CacheEntry {
+    market: Market,
+    /// Latest version of any account the market reads
+    version: DataVersion,
+    /// Version the market was inserted at, which accounts not written since are as of
+    inserted: DataVersion,
+    /// Version of each account written since the market was inserted
+    account_versions: HashMap<String, DataVersion>,
+}
+
+impl CacheEntry {
+    fn new(market: Market, version: DataVersion) -> Self {
+        Self { market, version, inserted: version, account_versions: HashMap::new() }
+    }
+
+    fn is_stale(&self, latest_slot: u64, max_age_slots: u64) -> bool {
+        latest_slot.saturating_sub(self.version.slot) > max_age_slots
+    }
 }
 
 /// A high-performance, thread-safe cache for real-time market data.
 /// It allows for highly concurrent reads and writes without a global lock.
+/// Every entry carries the version of its data, and writes older than the cached
+/// version are rejected, so out-of-order updates can't roll a market back.
 #[derive(Clone)]
 pub struct LocklessMarketCache {
     /// The core concurrent hash map storing market data, indexed by market ID.
-    markets: Arc<DashMap<String, Market>>,
+    markets: Arc<DashMap<String, CacheEntry>>,
+    /// Highest slot seen in any write or reported through `observe_slot`.
+    latest_slot: Arc<AtomicU64>,
     /// Lock-free counters for monitoring cache performance.
     stats: Arc<AtomicCacheStats>,
 }
@@ -48,25 +114,81 @@ impl LocklessMarketCache {
     pub fn new() -> Self {
         Self {
             markets: Arc::new(DashMap::new()),
+            latest_slot: Arc::new(AtomicU64::new(0)),
             stats: Arc::new(AtomicCacheStats::default()),
         }
     }
 
     /// Inserts or updates a market in the cache.
-    /// This is a fast, thread-safe operation.
-    pub fn insert(&self, market: Market) {
+    /// Returns `false`, keeping the cached market, if it holds a later version.
+    pub fn insert(&self, market: Market, version: DataVersion) -> bool {
+        match self.markets.entry(market.id.clone()) {
+            Entry::Occupied(mut entry) => {
+                if entry.get().version > version {
+                    self.stats.stale_writes.fetch_add(1, Ordering::Relaxed);
+                    return false;
+                }
+                entry.insert(CacheEntry::new(market, version));
+            }
+            Entry::Vacant(entry) => {
+                entry.insert(CacheEntry::new(market, version));
+            }
+        }
+        self.stats.writes.fetch_add(1, Ordering::Relaxed);
+        self.observe_slot(version.slot);
+        true
+    }
+
+    /// Records a write of `account`, one of the accounts a cached market reads, unless
+    /// the cached copy of that account is from a later version. A write of the pool
+    /// account itself, whose ID is the market's, also replaces its raw data.
+    pub fn update_account_data(&self, market_id: &str, account: &str, data: &[u8], version: DataVersion) -> CacheWrite {
+        let Some(mut entry) = self.markets.get_mut(market_id) else {
+            return CacheWrite::Missing;
+        };
+        let entry = &mut *entry;
+        if entry.account_versions.get(account).copied().unwrap_or(entry.inserted) > version {
+            self.stats.stale_writes.fetch_add(1, Ordering::Relaxed);
+            return CacheWrite::Stale;
+        }
+        if account == market_id {
+            entry.market.account_data = Some(data.to_vec());
+        }
+        entry.account_versions.insert(account.to_string(), version);
+        entry.version = entry.version.max(version);
         self.stats.writes.fetch_add(1, Ordering::Relaxed);
-        self.markets.insert(market.id.clone(), market);
+        self.observe_slot(version.slot);
+        CacheWrite::Applied
+    }
+
+    /// Advances the cache's notion of the current slot, e.g. from a slot subscription,
+    /// so markets that stop receiving updates age.
+    pub fn observe_slot(&self, slot: u64) {
+        self.latest_slot.fetch_max(slot, Ordering::Relaxed);
+    }
+
+    /// The highest slot the cache has seen.
+    pub fn latest_slot(&self) -> u64 {
+        self.latest_slot.load(Ordering::Relaxed)
     }
 
     /// Retrieves a single market from the cache by its ID.
     /// This is the primary, high-performance read method, intended for use in hot paths.
     /// It returns a clone of the market data.
     pub fn get(&self, market_id: &str) -> Option<Market> {
+        self.get_versioned(market_id).map(|versioned| versioned.market)
+    }
+
+    /// Like `get`, but also returns the version of the data and its age in slots.
+    pub fn get_versioned(&self, market_id: &str) -> Option<VersionedMarket> {
         match self.markets.get(market_id) {
-            Some(market_ref) => {
+            Some(entry) => {
                 self.stats.hits.fetch_add(1, Ordering::Relaxed);
-                Some(market_ref.value().clone())
+                Some(VersionedMarket {
+                    market: entry.market.clone(),
+                    version: entry.version,
+                    age_slots: self.latest_slot().saturating_sub(entry.version.slot),
+                })
             }
             None => {
                 self.stats.misses.fetch_add(1, Ordering::Relaxed);
@@ -75,6 +197,25 @@ impl LocklessMarketCache {
         }
     }
 
+    /// IDs of markets whose data hasn't been updated within `max_age_slots` of the
+    /// latest slot. Strategies use this to exclude stale pools.
+    pub fn stale_markets(&self, max_age_slots: u64) -> Vec<String> {
+        let latest_slot = self.latest_slot();
+        self.markets
+            .iter()
+            .filter(|entry| entry.is_stale(latest_slot, max_age_slots))
+            .map(|entry| entry.key().clone())
+            .collect()
+    }
+
+    /// Whether `market_id` is among the `stale_markets(max_age_slots)`. Markets not in
+    /// the cache aren't.
+    pub fn is_stale(&self, market_id: &str, max_age_slots: u64) -> bool {
+        self.markets
+            .get(market_id)
+            .is_some_and(|entry| entry.is_stale(self.latest_slot(), max_age_slots))
+    }
+
     /// **PERFORMANCE WARNING:**
     /// Creates a standard `HashMap` containing a clone of all markets in the cache.
     /// This is a potentially slow, `O(n)` operation that iterates over the entire cache.
@@ -83,7 +224,7 @@ impl LocklessMarketCache {
     pub fn get_all_as_hashmap(&self) -> HashMap<String, Market> {
         self.markets
             .iter()
-            .map(|entry| (entry.key().clone(), entry.value().clone()))
+            .map(|entry| (entry.key().clone(), entry.market.clone()))
             .collect()
     }
 
@@ -104,6 +245,7 @@ impl LocklessMarketCache {
             // Total reads = hits + misses
             total_reads: total_accesses,
             total_writes: self.stats.writes.load(Ordering::Relaxed),
+            stale_writes_rejected: self.stats.stale_writes.load(Ordering::Relaxed),
             hit_ratio,
         }
     }
@@ -112,10 +254,11 @@ impl LocklessMarketCache {
     pub fn log_stats(&self) {
         let stats = self.get_stats();
         info!(
-            "Lockless Cache Stats: Markets: {}, Reads: {}, Writes: {}, Hit Ratio: {:.2}%",
+            "Lockless Cache Stats: Markets: {}, Reads: {}, Writes: {}, Stale Writes Rejected: {}, Hit Ratio: {:.2}%",
             stats.total_markets,
             stats.total_reads,
             stats.total_writes,
+            stats.stale_writes_rejected,
             stats.hit_ratio * 100.0
         );
     }
@@ -127,3 +270,67 @@ impl Default for LocklessMarketCache {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::markets::types::DexLabel;
+
+    fn market(id: &str, liquidity: u64) -> Market {
+        Market {
+            token_mint_a: String::new(),
+            token_vault_a: String::new(),
+            token_mint_b: String::new(),
+            token_vault_b: String::new(),
+            dex_label: DexLabel::Raydium,
+            fee: 0,
+            id: id.to_string(),
+            account_data: None,
+            liquidity: Some(liquidity),
+        }
+    }
+
+    #[test]
+    fn test_older_writes_are_rejected() {
+        let cache = LocklessMarketCache::new();
+        assert!(cache.insert(market("pool", 1), DataVersion::new(100, 5)));
+        assert!(!cache.insert(market("pool", 2), DataVersion::new(100, 4)));
+        assert!(!cache.insert(market("pool", 2), DataVersion::new(99, 9)));
+        assert_eq!(cache.get("pool").unwrap().liquidity, Some(1));
+
+        // Same-slot writes without a write version still go through
+        assert!(cache.insert(market("pool", 3), DataVersion::new(100, 5)));
+        assert_eq!(cache.update_account_data("pool", "pool", &[1], DataVersion::at_slot(98)), CacheWrite::Stale);
+        assert_eq!(cache.update_account_data("pool", "pool", &[2], DataVersion::at_slot(101)), CacheWrite::Applied);
+        assert_eq!(cache.update_account_data("other", "other", &[2], DataVersion::at_slot(101)), CacheWrite::Missing);
+
+        let versioned = cache.get_versioned("pool").unwrap();
+        assert_eq!(versioned.market.liquidity, Some(3));
+        assert_eq!(versioned.market.account_data, Some(vec![2]));
+        assert_eq!(versioned.version, DataVersion::at_slot(101));
+        assert_eq!(cache.get_stats().stale_writes_rejected, 3);
+
+        // Each account the market reads is versioned on its own, and any write keeps
+        // the market fresh without touching the pool account's data
+        assert_eq!(cache.update_account_data("pool", "vault", &[3], DataVersion::at_slot(99)), CacheWrite::Stale);
+        assert_eq!(cache.update_account_data("pool", "vault", &[3], DataVersion::at_slot(105)), CacheWrite::Applied);
+        assert_eq!(cache.update_account_data("pool", "vault", &[4], DataVersion::at_slot(104)), CacheWrite::Stale);
+        assert_eq!(cache.update_account_data("pool", "pool", &[5], DataVersion::at_slot(102)), CacheWrite::Applied);
+        let versioned = cache.get_versioned("pool").unwrap();
+        assert_eq!((versioned.market.account_data, versioned.version), (Some(vec![5]), DataVersion::at_slot(105)));
+    }
+
+    #[test]
+    fn test_stale_markets() {
+        let cache = LocklessMarketCache::new();
+        cache.insert(market("quiet", 1), DataVersion::at_slot(100));
+        cache.insert(market("busy", 1), DataVersion::at_slot(140));
+        cache.observe_slot(150);
+
+        assert_eq!(cache.get_versioned("quiet").unwrap().age_slots, 50);
+        assert_eq!(cache.get_versioned("busy").unwrap().age_slots, 10);
+        assert_eq!(cache.stale_markets(20), vec!["quiet".to_string()]);
+        assert!(cache.stale_markets(50).is_empty());
+        assert!(cache.is_stale("quiet", 20) && !cache.is_stale("busy", 20) && !cache.is_stale("unknown", 0));
+    }
+}
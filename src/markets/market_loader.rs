@@ -0,0 +1,229 @@
+//! Quote engines for registry pools, loaded from chain
+//!
+//! `MarketLoader::load` fetches a pool account, decodes it to pick the venue, and builds
+//! the venue's engine from it and the accounts it reads: vaults, configs, book sides,
+//! and the tick or bin arrays around the current price. The engine is registered with
+//! the `AccountUpdateRouter`, which keeps it current from the account notifications of
+//! those accounts, so they're returned for the caller to subscribe to. The pool's mints
+//! are resolved alongside, so its legs can be priced net of Token-2022 transfer fees.
+
+use crate::markets::account_decoders::{AccountUpdateRouter, PoolAccountState, SharedMarket};
+use crate::markets::clob::load_clob_market;
+use crate::markets::errors::MarketSimulationError;
+use crate::markets::foundation::MarketBehavior;
+use crate::markets::lockless_cache::DataVersion;
+use crate::markets::meteora_dlmm::{load_meteora_dlmm_market, METEORA_DLMM_PROGRAM_ID};
+use crate::markets::orca_token_swap::OrcaTokenSwapMarket;
+use crate::markets::orca_whirlpools_working::OrcaWhirlpoolsWorking;
+use crate::markets::pools::Pool;
+use crate::markets::raydium_amm::RaydiumAmmMarket;
+use crate::markets::raydium_clmm_market::load_raydium_clmm_market;
+use crate::markets::raydium_cpmm::load_raydium_cpmm_market;
+use crate::markets::token_2022::{current_epoch, TransferFees};
+use crate::markets::types::{DexLabel, Market};
+use crate::transactions::meteoradlmm_swap::derive_bin_array_pda;
+use crate::transactions::raydium_clmm_swap::{derive_tick_array_address, RAYDIUM_CLMM_PROGRAM_ID};
+use orca_whirlpools_client::get_tick_array_address;
+use orca_whirlpools_core::{get_tick_array_start_tick_index, TICK_ARRAY_SIZE};
+use solana_client::client_error::ClientError;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::pubkey::Pubkey;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLock;
+use tokio::task::JoinHandle;
+use tracing::warn;
+
+/// Tick or bin arrays loaded on each side of the current price
+pub const ARRAYS_EACH_SIDE: i32 = 3;
+
+/// A venue engine and the accounts it reads besides the pool account
+pub struct LoadedMarket {
+    pub market: Box<dyn MarketBehavior>,
+    pub accounts: Vec<Pubkey>,
+}
+
+/// Loads the engines of registry pools into an `AccountUpdateRouter`
+pub struct MarketLoader {
+    rpc_client: Arc<RpcClient>,
+    router: Arc<AccountUpdateRouter>,
+}
+
+impl MarketLoader {
+    pub fn new(rpc_client: Arc<RpcClient>, router: Arc<AccountUpdateRouter>) -> Self {
+        Self { rpc_client, router }
+    }
+
+    pub fn router(&self) -> &Arc<AccountUpdateRouter> {
+        &self.router
+    }
+
+    /// Load `pool`'s engine and route its updates from now on. Returns the accounts it
+    /// reads besides the pool account.
+    pub async fn load(&self, pool: &Pool) -> Result<Vec<Pubkey>, MarketSimulationError> {
+        let Some(address) = pool.address else {
+            return Err(MarketSimulationError::MissingField {
+                market: pool.id.clone(),
+                field: "pool address".to_string(),
+            });
+        };
+        // Read before fetching, so no update newer than the loaded state is older than its version
+        let slot = self
+            .rpc_client
+            .get_slot()
+            .await
+            .map_err(|e| rpc_error(&pool.id, e))?;
+        let loaded = self.load_market(address).await?;
+        // Legs through the pool are priced net of its mints' transfer fees
+        let fees = TransferFees::fetch(&self.rpc_client, &[pool.token_a.mint, pool.token_b.mint]).await?;
+        self.router.record_transfer_fees(&fees);
+        let market: SharedMarket = Arc::new(RwLock::new(loaded.market));
+        // Versioned before it's routed, so an update arriving in between is checked
+        // against the loaded state rather than taken as the first one seen
+        self.router.cache().insert(cache_entry(pool, address), DataVersion::at_slot(slot));
+        self.router.register_market(address, market, &loaded.accounts).await;
+        Ok(loaded.accounts)
+    }
+
+    /// Read the current epoch every `period`, so transfer fees switch to a mint's newer
+    /// fee when it takes effect
+    pub fn spawn_epoch_refresher(self: &Arc<Self>, period: Duration) -> JoinHandle<()> {
+        let loader = Arc::clone(self);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(period);
+            loop {
+                interval.tick().await;
+                match current_epoch(&loader.rpc_client).await {
+                    Ok(epoch) => loader.router.observe_epoch(epoch),
+                    Err(e) => warn!("Epoch not refreshed: {}", e),
+                }
+            }
+        })
+    }
+
+    /// Stop routing updates to `pool`'s engine. Returns the accounts no other engine reads.
+    pub fn unload(&self, pool: &Pubkey) -> Vec<Pubkey> {
+        self.router.unregister_market(pool)
+    }
+
+    /// Fetch the account at `address` and build the engine of the pool it holds
+    pub async fn load_market(&self, address: Pubkey) -> Result<LoadedMarket, MarketSimulationError> {
+        let rpc_client = self.rpc_client.as_ref();
+        let market_name = address.to_string();
+        let account = rpc_client
+            .get_account(&address)
+            .await
+            .map_err(|e| rpc_error(&market_name, e))?;
+        let Some(state) = self
+            .router
+            .registry()
+            .decode(&account.owner, &account.data)?
+            .filter(|state| state.is_pool_account())
+        else {
+            return Err(MarketSimulationError::AccountDecodeError {
+                market: market_name,
+                details: format!("not a pool account of a known program (owner {})", account.owner),
+            });
+        };
+
+        let mut accounts = state.dependent_accounts();
+        let market: Box<dyn MarketBehavior> = match &state {
+            PoolAccountState::RaydiumAmm(_) | PoolAccountState::OrcaTokenSwap(_) => {
+                let vaults = rpc_client
+                    .get_multiple_accounts(&accounts)
+                    .await
+                    .map_err(|e| rpc_error(&market_name, e))?;
+                let [Some(vault_a), Some(vault_b)] = &vaults[..] else {
+                    return Err(MarketSimulationError::MissingField {
+                        market: market_name,
+                        field: "vault accounts".to_string(),
+                    });
+                };
+                if matches!(state, PoolAccountState::RaydiumAmm(_)) {
+                    Box::new(RaydiumAmmMarket::from_account_data(address, &account.data, &vault_a.data, &vault_b.data)?)
+                } else {
+                    Box::new(OrcaTokenSwapMarket::from_account_data(address, &account.data, &vault_a.data, &vault_b.data)?)
+                }
+            }
+            PoolAccountState::RaydiumCpmmPool(_) => Box::new(load_raydium_cpmm_market(rpc_client, address).await?),
+            PoolAccountState::RaydiumClmmPool(_) => {
+                let market = load_raydium_clmm_market(rpc_client, address, ARRAYS_EACH_SIDE).await?;
+                let program_id = Pubkey::from_str(RAYDIUM_CLMM_PROGRAM_ID).expect("valid program id");
+                for start in market.tick_array_starts_around_current(ARRAYS_EACH_SIDE) {
+                    if let Ok(tick_array) = derive_tick_array_address(&address, start, &program_id) {
+                        accounts.push(tick_array);
+                    }
+                }
+                Box::new(market)
+            }
+            PoolAccountState::Whirlpool(whirlpool) => {
+                let mut market = OrcaWhirlpoolsWorking::new(address, whirlpool.token_mint_a, whirlpool.token_mint_b, None);
+                market.update_state(&account.data)?;
+                let ticks_in_array = TICK_ARRAY_SIZE as i32 * whirlpool.tick_spacing as i32;
+                let current = get_tick_array_start_tick_index(whirlpool.tick_current_index, whirlpool.tick_spacing);
+                let tick_arrays: Vec<Pubkey> = (-ARRAYS_EACH_SIDE..=ARRAYS_EACH_SIDE)
+                    .filter_map(|k| get_tick_array_address(&address, current + k * ticks_in_array).ok())
+                    .map(|(tick_array, _)| tick_array)
+                    .collect();
+                // Uninitialized tick arrays come back as None and hold no liquidity
+                let fetched = rpc_client
+                    .get_multiple_accounts(&tick_arrays)
+                    .await
+                    .map_err(|e| rpc_error(&market_name, e))?;
+                for tick_array in fetched.into_iter().flatten() {
+                    market.update_state(&tick_array.data)?;
+                }
+                accounts.extend(tick_arrays);
+                Box::new(market)
+            }
+            PoolAccountState::MeteoraLbPair(_) => {
+                let market = load_meteora_dlmm_market(rpc_client, address, ARRAYS_EACH_SIDE as i64).await?;
+                let program_id = Pubkey::from_str(METEORA_DLMM_PROGRAM_ID).expect("valid program id");
+                accounts.extend(
+                    market
+                        .bin_array_indexes_around_active(ARRAYS_EACH_SIDE as i64)
+                        .into_iter()
+                        .map(|index| derive_bin_array_pda(address, index, program_id).0),
+                );
+                Box::new(market)
+            }
+            PoolAccountState::OpenBookV2Market(_) => {
+                Box::new(load_clob_market(rpc_client, DexLabel::OpenBookV2, address).await?)
+            }
+            PoolAccountState::PhoenixMarket { .. } => {
+                Box::new(load_clob_market(rpc_client, DexLabel::Phoenix, address).await?)
+            }
+            _ => {
+                return Err(MarketSimulationError::AccountDecodeError {
+                    market: market_name,
+                    details: "no quote engine for the account".to_string(),
+                })
+            }
+        };
+        Ok(LoadedMarket { market, accounts })
+    }
+}
+
+fn rpc_error(market: &str, e: ClientError) -> MarketSimulationError {
+    MarketSimulationError::ApiRequestFailed {
+        market: market.to_string(),
+        message: e.to_string(),
+        source: Some(Box::new(e)),
+    }
+}
+
+/// Cache entry versioning the engine of `pool`
+fn cache_entry(pool: &Pool, address: Pubkey) -> Market {
+    Market {
+        token_mint_a: pool.token_a.mint.to_string(),
+        token_vault_a: String::new(),
+        token_mint_b: pool.token_b.mint.to_string(),
+        token_vault_b: String::new(),
+        dex_label: pool.dex.clone().unwrap_or(DexLabel::Raydium),
+        fee: pool.fee_bps as u64,
+        id: address.to_string(),
+        account_data: None,
+        liquidity: None,
+    }
+}
@@ -0,0 +1,592 @@
+//! Native Meteora DLMM quote engine
+//!
+//! Swaps are simulated bin by bin from the decoded `LbPair` (`AccountData`) and the bin
+//! arrays around `active_id`, following the lb_clmm program: every bin visited refreshes
+//! the volatility accumulator, and the fee charged is the base fee plus the variable fee
+//! derived from it.
+//!
+//! Program: LBUZKhRxPF3XUpBCjp4YzTKgLccjZhTSDM9YuVaPwxo
+
+use crate::markets::errors::MarketSimulationError;
+use crate::markets::foundation::{MarketBehavior, Quote};
+use crate::markets::meteora::AccountData;
+use crate::markets::types::{DexLabel, MarketId};
+use crate::transactions::meteoradlmm_swap::{derive_bin_array_pda, MAX_BIN_PER_ARRAY};
+use anyhow::Result;
+use borsh::BorshDeserialize;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_program::pubkey::Pubkey;
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Meteora DLMM program ID
+pub const METEORA_DLMM_PROGRAM_ID: &str = "LBUZKhRxPF3XUpBCjp4YzTKgLccjZhTSDM9YuVaPwxo";
+
+/// Size of an `LbPair` account
+pub const LB_PAIR_ACCOUNT_LEN: usize = 904;
+
+/// Size of a `BinArray` account: header followed by `MAX_BIN_PER_ARRAY` bins
+pub const BIN_ARRAY_ACCOUNT_LEN: usize = 10136;
+
+/// Offset of the first bin inside a `BinArray` account
+const BIN_ARRAY_HEADER_LEN: usize = 56;
+
+/// Size of a single `Bin`
+const BIN_LEN: usize = 144;
+
+const SCALE_OFFSET: u32 = 64;
+const ONE: u128 = 1 << SCALE_OFFSET;
+const BASIS_POINT_MAX: u128 = 10_000;
+const MAX_EXPONENTIAL: u32 = 0x80000;
+
+/// Fee rates are expressed over 1e9
+const FEE_PRECISION: u128 = 1_000_000_000;
+const MAX_FEE_RATE: u128 = 100_000_000;
+
+/// Liquidity held by one bin
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct DlmmBin {
+    pub amount_x: u64,
+    pub amount_y: u64,
+    /// Q64.64 price of the bin, zero until the program stores it
+    pub price: u128,
+}
+
+/// Decoded bin array covering `MAX_BIN_PER_ARRAY` consecutive bins
+#[derive(Debug, Clone, PartialEq)]
+pub struct DlmmBinArray {
+    pub index: i64,
+    pub bins: [DlmmBin; MAX_BIN_PER_ARRAY],
+}
+
+impl DlmmBinArray {
+    /// Decode a raw BinArray account, returning it with the pair it belongs to
+    pub fn from_account_data(data: &[u8]) -> Result<(Self, Pubkey), MarketSimulationError> {
+        if data.len() != BIN_ARRAY_ACCOUNT_LEN {
+            return Err(MarketSimulationError::AccountDecodeError {
+                market: DexLabel::Meteora.str(),
+                details: format!("BinArray must be {} bytes, got {}", BIN_ARRAY_ACCOUNT_LEN, data.len()),
+            });
+        }
+
+        let index = i64::from_le_bytes(data[8..16].try_into().expect("slice is 8 bytes"));
+        let lb_pair = Pubkey::try_from(&data[24..56]).expect("slice is 32 bytes");
+
+        let mut bins = [DlmmBin::default(); MAX_BIN_PER_ARRAY];
+        for (i, bin) in bins.iter_mut().enumerate() {
+            let offset = BIN_ARRAY_HEADER_LEN + i * BIN_LEN;
+            bin.amount_x = u64::from_le_bytes(data[offset..offset + 8].try_into().expect("slice is 8 bytes"));
+            bin.amount_y = u64::from_le_bytes(data[offset + 8..offset + 16].try_into().expect("slice is 8 bytes"));
+            bin.price = u128::from_le_bytes(data[offset + 16..offset + 32].try_into().expect("slice is 16 bytes"));
+        }
+
+        Ok((Self { index, bins }, lb_pair))
+    }
+
+    /// First bin id covered by the array at `index`
+    fn lower_bin_id(index: i64) -> i32 {
+        (index * MAX_BIN_PER_ARRAY as i64) as i32
+    }
+}
+
+/// Bin array index containing `bin_id`
+pub fn bin_array_index(bin_id: i32) -> i64 {
+    bin_id.div_euclid(MAX_BIN_PER_ARRAY as i32) as i64
+}
+
+/// Q64.64 price of a bin: `(1 + bin_step / 10_000) ^ bin_id`
+pub fn price_from_bin_id(bin_id: i32, bin_step: u16) -> Option<u128> {
+    let bps = ((bin_step as u128) << SCALE_OFFSET) / BASIS_POINT_MAX;
+    pow(ONE + bps, bin_id)
+}
+
+/// Q64.64 exponentiation by squaring, bit-for-bit with the program's `pow`
+fn pow(base: u128, exp: i32) -> Option<u128> {
+    if exp == 0 {
+        return Some(ONE);
+    }
+    let mut invert = exp.is_negative();
+    let exp = exp.unsigned_abs();
+    if exp >= MAX_EXPONENTIAL {
+        return None;
+    }
+
+    let mut squared_base = base;
+    let mut result = ONE;
+    if squared_base >= result {
+        squared_base = u128::MAX.checked_div(squared_base)?;
+        invert = !invert;
+    }
+
+    for bit in 0..19 {
+        if exp & (1 << bit) > 0 {
+            result = result.checked_mul(squared_base)? >> SCALE_OFFSET;
+        }
+        if bit < 18 {
+            squared_base = squared_base.checked_mul(squared_base)? >> SCALE_OFFSET;
+        }
+    }
+
+    if result == 0 {
+        return None;
+    }
+    if invert {
+        result = u128::MAX.checked_div(result)?;
+    }
+    Some(result)
+}
+
+/// `(a * b) >> 64` for a 64-bit `a`, without a 256-bit intermediate
+fn mul_shr_64(a: u64, b: u128, round_up: bool) -> Option<u64> {
+    let a = a as u128;
+    let low = a * (b as u64 as u128);
+    let high = a * (b >> 64);
+    let mut result = high.checked_add(low >> 64)?;
+    if round_up && low as u64 != 0 {
+        result = result.checked_add(1)?;
+    }
+    u64::try_from(result).ok()
+}
+
+/// `(a << 64) / b`
+fn shl_div_64(a: u64, b: u128, round_up: bool) -> Option<u64> {
+    let numerator = (a as u128) << SCALE_OFFSET;
+    let result = if round_up {
+        numerator.div_ceil(b)
+    } else {
+        numerator / b
+    };
+    u64::try_from(result).ok()
+}
+
+fn math_error(reason: &str) -> MarketSimulationError {
+    MarketSimulationError::NoRouteFound {
+        market: DexLabel::Meteora.str(),
+        reason: reason.to_string(),
+    }
+}
+
+/// Volatility state carried through a simulated swap
+#[derive(Debug, Clone, Copy)]
+struct VolatilityState {
+    volatility_accumulator: u32,
+    volatility_reference: u32,
+    index_reference: i32,
+}
+
+/// Meteora DLMM pair priced entirely from account data
+#[derive(Debug, Clone)]
+pub struct MeteoraDlmmMarket {
+    /// LbPair account address
+    pool_address: Pubkey,
+
+    /// Decoded LbPair account
+    lb_pair: AccountData,
+
+    /// Loaded bin arrays keyed by bin array index
+    bin_arrays: BTreeMap<i64, DlmmBinArray>,
+}
+
+impl MeteoraDlmmMarket {
+    pub fn new(pool_address: Pubkey, lb_pair: AccountData) -> Self {
+        Self {
+            pool_address,
+            lb_pair,
+            bin_arrays: BTreeMap::new(),
+        }
+    }
+
+    /// Create a market from a raw LbPair account
+    pub fn from_account_data(pool_address: Pubkey, data: &[u8]) -> Result<Self, MarketSimulationError> {
+        Ok(Self::new(pool_address, decode_lb_pair(data)?))
+    }
+
+    pub fn pool_address(&self) -> Pubkey {
+        self.pool_address
+    }
+
+    pub fn lb_pair(&self) -> &AccountData {
+        &self.lb_pair
+    }
+
+    /// Add or replace a decoded bin array
+    pub fn insert_bin_array(&mut self, bin_array: DlmmBinArray) {
+        self.bin_arrays.insert(bin_array.index, bin_array);
+    }
+
+    /// Bin array indexes around the active bin, `arrays_each_side` in each direction
+    pub fn bin_array_indexes_around_active(&self, arrays_each_side: i64) -> Vec<i64> {
+        let active_index = bin_array_index(self.lb_pair.active_id);
+        (active_index - arrays_each_side..=active_index + arrays_each_side).collect()
+    }
+
+    /// Base fee rate over `FEE_PRECISION`
+    fn base_fee_rate(&self) -> u128 {
+        self.lb_pair.parameters.base_factor as u128 * self.lb_pair.bin_step as u128 * 10
+    }
+
+    /// Variable fee rate over `FEE_PRECISION` for a given volatility accumulator
+    fn variable_fee_rate(&self, volatility_accumulator: u32) -> u128 {
+        let control = self.lb_pair.parameters.variable_fee_control as u128;
+        if control == 0 {
+            return 0;
+        }
+        let square_vfa_bin = (volatility_accumulator as u128 * self.lb_pair.bin_step as u128).pow(2);
+        (control * square_vfa_bin).div_ceil(100_000_000_000)
+    }
+
+    fn total_fee_rate(&self, volatility_accumulator: u32) -> u128 {
+        (self.base_fee_rate() + self.variable_fee_rate(volatility_accumulator)).min(MAX_FEE_RATE)
+    }
+
+    /// Volatility references at the start of a swap (`update_references`)
+    fn volatility_at(&self, timestamp: i64) -> VolatilityState {
+        let params = &self.lb_pair.parameters;
+        let v_params = &self.lb_pair.v_parameters;
+        let mut state = VolatilityState {
+            volatility_accumulator: v_params.volatility_accumulator,
+            volatility_reference: v_params.volatility_reference,
+            index_reference: v_params.index_reference,
+        };
+
+        let elapsed = timestamp - v_params.last_update_timestamp;
+        if elapsed >= params.filter_period as i64 {
+            state.index_reference = self.lb_pair.active_id;
+            state.volatility_reference = if elapsed < params.decay_period as i64 {
+                (v_params.volatility_accumulator as u128 * params.reduction_factor as u128 / BASIS_POINT_MAX) as u32
+            } else {
+                0
+            };
+        }
+        state
+    }
+
+    fn bin_price(&self, bin: &DlmmBin, bin_id: i32) -> Result<u128, MarketSimulationError> {
+        if bin.price != 0 {
+            return Ok(bin.price);
+        }
+        price_from_bin_id(bin_id, self.lb_pair.bin_step).ok_or_else(|| math_error("bin price overflow"))
+    }
+
+    /// Exact-input swap across bins at the given unix timestamp
+    fn simulate_swap(
+        &self,
+        amount: u64,
+        swap_for_y: bool,
+        timestamp: i64,
+    ) -> Result<Quote, MarketSimulationError> {
+        let params = &self.lb_pair.parameters;
+        let mut volatility = self.volatility_at(timestamp);
+        let mut active_id = self.lb_pair.active_id;
+        let mut amount_left = amount;
+        let mut amount_out: u64 = 0;
+        let mut fee_amount: u64 = 0;
+
+        let insufficient = |amount_left: u64| MarketSimulationError::InsufficientLiquidity {
+            market: MarketId::Meteora,
+            available: amount - amount_left,
+            required: amount,
+        };
+
+        while amount_left > 0 {
+            // Arrays that are not loaded are treated as uninitialized and skipped,
+            // jumping the active bin to the edge of the next loaded array
+            let index = bin_array_index(active_id);
+            let bin_array = match self.bin_arrays.get(&index) {
+                Some(bin_array) => bin_array,
+                None => {
+                    let next = if swap_for_y {
+                        self.bin_arrays.range(..index).next_back()
+                    } else {
+                        self.bin_arrays.range(index + 1..).next()
+                    };
+                    let (&next_index, _) = next.ok_or_else(|| insufficient(amount_left))?;
+                    let lower = DlmmBinArray::lower_bin_id(next_index);
+                    active_id = if swap_for_y { lower + MAX_BIN_PER_ARRAY as i32 - 1 } else { lower };
+                    continue;
+                }
+            };
+
+            if active_id < params.min_bin_id || active_id > params.max_bin_id {
+                return Err(insufficient(amount_left));
+            }
+
+            // update_volatility_accumulator
+            let delta_id = (volatility.index_reference as i64 - active_id as i64).unsigned_abs() as u128;
+            volatility.volatility_accumulator = (volatility.volatility_reference as u128
+                + delta_id * BASIS_POINT_MAX)
+                .min(params.max_volatility_accumulator as u128) as u32;
+
+            let offset = (active_id - DlmmBinArray::lower_bin_id(index)) as usize;
+            let bin = &bin_array.bins[offset];
+            let max_amount_out = if swap_for_y { bin.amount_y } else { bin.amount_x };
+
+            if max_amount_out > 0 {
+                let price = self.bin_price(bin, active_id)?;
+                let fee_rate = self.total_fee_rate(volatility.volatility_accumulator);
+
+                let max_amount_in = if swap_for_y {
+                    shl_div_64(bin.amount_y, price, true)
+                } else {
+                    mul_shr_64(bin.amount_x, price, true)
+                }
+                .ok_or_else(|| math_error("bin max amount in overflow"))?;
+                let max_fee = (max_amount_in as u128 * fee_rate).div_ceil(FEE_PRECISION - fee_rate) as u64;
+                let max_amount_in_with_fees = max_amount_in
+                    .checked_add(max_fee)
+                    .ok_or_else(|| math_error("bin max amount in overflow"))?;
+
+                let (step_in, step_out, step_fee) = if amount_left > max_amount_in_with_fees {
+                    (max_amount_in_with_fees, max_amount_out, max_fee)
+                } else {
+                    let fee = (amount_left as u128 * fee_rate).div_ceil(FEE_PRECISION) as u64;
+                    let amount_in_after_fee = amount_left - fee;
+                    let out = if swap_for_y {
+                        mul_shr_64(amount_in_after_fee, price, false)
+                    } else {
+                        shl_div_64(amount_in_after_fee, price, false)
+                    }
+                    .ok_or_else(|| math_error("bin amount out overflow"))?;
+                    (amount_left, out.min(max_amount_out), fee)
+                };
+
+                amount_left -= step_in;
+                amount_out += step_out;
+                fee_amount += step_fee;
+            }
+
+            if amount_left > 0 {
+                active_id += if swap_for_y { -1 } else { 1 };
+            }
+        }
+
+        // Impact against the active bin's price on the post-fee input
+        let spot_price = self.get_price()?;
+        let net_in = (amount - fee_amount) as f64;
+        let spot_out = if swap_for_y { net_in * spot_price } else { net_in / spot_price };
+        let price_impact = if spot_out > 0.0 {
+            ((1.0 - amount_out as f64 / spot_out) * 100.0).clamp(0.0, 100.0)
+        } else {
+            0.0
+        };
+
+        Ok(Quote {
+            amount_in: amount,
+            amount_out,
+            price_impact,
+            fee_amount,
+            slippage_tolerance: 0.5,
+        })
+    }
+}
+
+impl MarketBehavior for MeteoraDlmmMarket {
+    /// `a_to_b` swaps token X for token Y
+    fn get_quote(&self, amount_in: u64, a_to_b: bool) -> Result<Quote, MarketSimulationError> {
+        if amount_in == 0 {
+            return Err(MarketSimulationError::InvalidAmount {
+                market: MarketId::Meteora,
+                amount: amount_in,
+            });
+        }
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or_default();
+        self.simulate_swap(amount_in, a_to_b, now)
+    }
+
+    /// Price of X in Y at the active bin, in raw token units
+    fn get_price(&self) -> Result<f64, MarketSimulationError> {
+        let price = price_from_bin_id(self.lb_pair.active_id, self.lb_pair.bin_step)
+            .ok_or_else(|| math_error("bin price overflow"))?;
+        Ok(price as f64 / ONE as f64)
+    }
+
+    /// Accepts either the LbPair account or one of its BinArray accounts
+    fn update_state(&mut self, new_data: &[u8]) -> Result<(), MarketSimulationError> {
+        match new_data.len() {
+            LB_PAIR_ACCOUNT_LEN => {
+                self.lb_pair = decode_lb_pair(new_data)?;
+            }
+            BIN_ARRAY_ACCOUNT_LEN => {
+                let (bin_array, lb_pair) = DlmmBinArray::from_account_data(new_data)?;
+                if self.pool_address != Pubkey::default() && lb_pair != self.pool_address {
+                    return Err(MarketSimulationError::AccountDecodeError {
+                        market: DexLabel::Meteora.str(),
+                        details: format!("bin array belongs to {}, not {}", lb_pair, self.pool_address),
+                    });
+                }
+                self.insert_bin_array(bin_array);
+            }
+            len => {
+                return Err(MarketSimulationError::AccountDecodeError {
+                    market: DexLabel::Meteora.str(),
+                    details: format!("unexpected account size {}", len),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    fn market_id(&self) -> MarketId {
+        MarketId::Meteora
+    }
+
+    fn dex_label(&self) -> DexLabel {
+        DexLabel::Meteora
+    }
+}
+
+/// Decode a raw LbPair account
+pub fn decode_lb_pair(data: &[u8]) -> Result<AccountData, MarketSimulationError> {
+    if data.len() != LB_PAIR_ACCOUNT_LEN {
+        return Err(MarketSimulationError::AccountDecodeError {
+            market: DexLabel::Meteora.str(),
+            details: format!("LbPair must be {} bytes, got {}", LB_PAIR_ACCOUNT_LEN, data.len()),
+        });
+    }
+    AccountData::try_from_slice(data).map_err(|e| MarketSimulationError::AccountDecodeError {
+        market: DexLabel::Meteora.str(),
+        details: e.to_string(),
+    })
+}
+
+/// Fetch an LbPair and the bin arrays around its active bin
+pub async fn load_meteora_dlmm_market(
+    rpc_client: &RpcClient,
+    lb_pair: Pubkey,
+    arrays_each_side: i64,
+) -> Result<MeteoraDlmmMarket, MarketSimulationError> {
+    let rpc_error = |e: solana_client::client_error::ClientError| MarketSimulationError::ApiRequestFailed {
+        market: DexLabel::Meteora.str(),
+        message: e.to_string(),
+        source: Some(Box::new(e)),
+    };
+
+    let account = rpc_client.get_account(&lb_pair).await.map_err(rpc_error)?;
+    let mut market = MeteoraDlmmMarket::from_account_data(lb_pair, &account.data)?;
+
+    let program_id = Pubkey::from_str(METEORA_DLMM_PROGRAM_ID).expect("valid program id");
+    let bin_array_keys: Vec<Pubkey> = market
+        .bin_array_indexes_around_active(arrays_each_side)
+        .into_iter()
+        .map(|index| derive_bin_array_pda(lb_pair, index, program_id).0)
+        .collect();
+
+    // Uninitialized bin arrays come back as None and are simply skipped when swapping
+    let accounts = rpc_client
+        .get_multiple_accounts(&bin_array_keys)
+        .await
+        .map_err(rpc_error)?;
+    for account in accounts.into_iter().flatten() {
+        market.update_state(&account.data)?;
+    }
+
+    Ok(market)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::markets::meteora::{StaticParameters, VParameters};
+
+    fn test_pair(active_id: i32) -> AccountData {
+        AccountData {
+            parameters: StaticParameters {
+                base_factor: 10_000,
+                filter_period: 30,
+                decay_period: 600,
+                reduction_factor: 5_000,
+                variable_fee_control: 40_000,
+                max_volatility_accumulator: 350_000,
+                min_bin_id: -443_636,
+                max_bin_id: 443_636,
+                ..Default::default()
+            },
+            v_parameters: VParameters::default(),
+            active_id,
+            bin_step: 10,
+            ..Default::default()
+        }
+    }
+
+    fn uniform_bin_array(index: i64, amount_x: u64, amount_y: u64) -> DlmmBinArray {
+        DlmmBinArray {
+            index,
+            bins: [DlmmBin { amount_x, amount_y, price: 0 }; MAX_BIN_PER_ARRAY],
+        }
+    }
+
+    #[test]
+    fn test_price_from_bin_id() {
+        assert_eq!(price_from_bin_id(0, 10), Some(ONE));
+
+        let up = price_from_bin_id(100, 10).unwrap() as f64 / ONE as f64;
+        let down = price_from_bin_id(-100, 10).unwrap() as f64 / ONE as f64;
+        assert!((up - 1.001f64.powi(100)).abs() < 1e-9);
+        assert!((down - 1.001f64.powi(-100)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_single_bin_swap() {
+        let mut market = MeteoraDlmmMarket::new(Pubkey::new_unique(), test_pair(0));
+        market.insert_bin_array(uniform_bin_array(0, 1_000_000_000, 1_000_000_000));
+        market.insert_bin_array(uniform_bin_array(-1, 1_000_000_000, 1_000_000_000));
+
+        // Fresh references: accumulator is zero in the active bin, so only the base fee
+        // of 10_000 * 10 * 10 = 1e6 / 1e9 (0.1%) applies
+        let quote = market.simulate_swap(1_000_000, true, 1_000).unwrap();
+        assert_eq!(quote.fee_amount, 1_000);
+        assert_eq!(quote.amount_out, 999_000);
+    }
+
+    #[test]
+    fn test_swap_crosses_bins_and_raises_fee() {
+        let mut market = MeteoraDlmmMarket::new(Pubkey::new_unique(), test_pair(0));
+        market.insert_bin_array(uniform_bin_array(0, 1_000_000, 1_000_000));
+        market.insert_bin_array(uniform_bin_array(-1, 1_000_000, 1_000_000));
+
+        let quote = market.simulate_swap(5_000_000, true, 1_000).unwrap();
+        // Five bins of 1M Y at prices <= 1 cannot deliver 5M of Y for 5M of X
+        assert!(quote.amount_out < 5_000_000 - quote.fee_amount);
+        // The variable fee kicks in once the active bin moves away from the reference
+        assert!(quote.fee_amount > 5_000);
+        assert!(quote.price_impact > 0.0);
+    }
+
+    #[test]
+    fn test_swap_beyond_loaded_bins_fails() {
+        let mut market = MeteoraDlmmMarket::new(Pubkey::new_unique(), test_pair(0));
+        market.insert_bin_array(uniform_bin_array(0, 1_000, 1_000));
+
+        let result = market.get_quote(1_000_000_000, true);
+        assert!(matches!(result, Err(MarketSimulationError::InsufficientLiquidity { .. })));
+    }
+
+    #[test]
+    fn test_update_state_decodes_bin_array() {
+        let pool = Pubkey::new_unique();
+        let mut data = vec![0u8; BIN_ARRAY_ACCOUNT_LEN];
+        data[8..16].copy_from_slice(&(-1i64).to_le_bytes());
+        data[24..56].copy_from_slice(pool.as_ref());
+        // Last bin of array -1 is bin -1
+        let offset = BIN_ARRAY_HEADER_LEN + (MAX_BIN_PER_ARRAY - 1) * BIN_LEN;
+        data[offset..offset + 8].copy_from_slice(&7u64.to_le_bytes());
+        data[offset + 8..offset + 16].copy_from_slice(&9u64.to_le_bytes());
+
+        let mut market = MeteoraDlmmMarket::new(pool, test_pair(0));
+        market.update_state(&data).unwrap();
+        let bin = market.bin_arrays[&-1].bins[MAX_BIN_PER_ARRAY - 1];
+        assert_eq!((bin.amount_x, bin.amount_y), (7, 9));
+
+        let mut foreign = MeteoraDlmmMarket::new(Pubkey::new_unique(), test_pair(0));
+        assert!(foreign.update_state(&data).is_err());
+
+        let lb_pair_data = borsh::to_vec(&test_pair(42)).unwrap();
+        market.update_state(&lb_pair_data).unwrap();
+        assert_eq!(market.lb_pair().active_id, 42);
+    }
+}
@@ -1,13 +1,28 @@
+pub mod account_decoders; // Program-ID keyed account decoders and update routing
+pub mod clob; // Order book depth ladder shared by OpenBook v2 and Phoenix
+pub mod depth_curve; // Piecewise amount_in -> amount_out curves for fast sizing
+pub mod discovery; // getProgramAccounts pool discovery for every native venue
 pub mod errors;
 pub mod foundation; // Unified MarketBehavior trait architecture
 pub mod lockless_cache;
+pub mod market_loader; // Builds quote engines for registry pools and routes their updates
 pub mod meteora;
+pub mod meteora_dlmm; // Native Meteora DLMM bin-by-bin quote engine
+pub mod openbook_v2; // OpenBook v2 market and book decoding
 pub mod orca;
+pub mod orca_token_swap; // Native Orca legacy token-swap quote engine
 pub mod orca_whirpools;
 pub mod orca_whirlpools_working; // Working Orca implementation with verified SDK functions
+pub mod phoenix; // Phoenix market and book decoding
+pub mod pool_snapshot; // Versioned binary snapshot of pool state for warm starts
 pub mod pools;
 pub mod raydium;
+pub mod raydium_amm; // Native Raydium AMM v4 quote engine
 pub mod raydium_clmm;
+pub mod raydium_clmm_market; // Native Raydium CLMM tick-walking quote engine
+pub mod raydium_cpmm; // Native Raydium CPMM quote engine and pool discovery
 pub mod real_time_pools;
+pub mod token_2022; // Mint program detection and Token-2022 transfer fees
+pub mod token_registry; // On-chain mint and Metaplex metadata lookups with a disk cache
 pub mod types;
 pub mod utils;
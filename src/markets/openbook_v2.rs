@@ -0,0 +1,338 @@
+//! OpenBook v2 market and order book decoding, plus market discovery
+//!
+//! A market account names its `bids` and `asks` BookSide accounts. Each BookSide holds
+//! a crit-bit tree of 88-byte nodes; leaves carry a 128-bit key whose upper 64 bits are
+//! the price in quote lots per base lot. Only the fixed-price tree is decoded:
+//! oracle-pegged orders need the oracle price to be placed and are left out of the
+//! ladder, as are time-in-force expiries, which the program only prunes lazily.
+//!
+//! Program: opnb2LAfJYbRMAHHvqjCwQxanZn7ReEHp1k81EohpZb
+
+use crate::common::utils::{from_pubkey, from_str};
+use crate::markets::clob::{ClobParams, DepthLadder, PriceLevel, TakerFee};
+use crate::markets::errors::MarketSimulationError;
+use crate::markets::types::{DexLabel, Market};
+use anyhow::Result;
+use log::error;
+use solana_account_decoder::UiAccountEncoding;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_client::rpc_config::{RpcAccountInfoConfig, RpcProgramAccountsConfig};
+use solana_client::rpc_filter::{Memcmp, MemcmpEncodedBytes, RpcFilterType};
+use solana_program::pubkey::Pubkey;
+
+/// OpenBook v2 program ID
+pub const OPENBOOK_V2_PROGRAM_ID: &str = "opnb2LAfJYbRMAHHvqjCwQxanZn7ReEHp1k81EohpZb";
+
+/// Size of a `Market` account, discriminator included
+pub const OPENBOOK_V2_MARKET_LEN: usize = 848;
+
+/// Size of a `BookSide` account, discriminator included
+pub const OPENBOOK_V2_BOOK_SIDE_LEN: usize = 90_952;
+
+/// Fees are expressed over 1e6
+const FEES_SCALE_FACTOR: u64 = 1_000_000;
+
+/// `Market` field offsets
+const MARKET_AUTHORITY_OFFSET: usize = 16;
+const OPEN_ORDERS_ADMIN_OFFSET: usize = 88;
+const BIDS_OFFSET: usize = 200;
+const ASKS_OFFSET: usize = 232;
+const EVENT_HEAP_OFFSET: usize = 264;
+const ORACLE_A_OFFSET: usize = 296;
+const ORACLE_B_OFFSET: usize = 328;
+const QUOTE_LOT_SIZE_OFFSET: usize = 448;
+const BASE_LOT_SIZE_OFFSET: usize = 456;
+const TAKER_FEE_OFFSET: usize = 488;
+pub const BASE_MINT_OFFSET: usize = 576;
+pub const QUOTE_MINT_OFFSET: usize = 608;
+const BASE_VAULT_OFFSET: usize = 640;
+const QUOTE_VAULT_OFFSET: usize = 680;
+
+/// `BookSide` layout: the fixed-price root comes first, the node array starts after
+/// the reserved roots and the tree header
+const FIXED_ROOT_OFFSET: usize = 8;
+const ORDER_TREE_TYPE_OFFSET: usize = 312;
+const NODES_OFFSET: usize = 840;
+const NODE_SIZE: usize = 88;
+const MAX_ORDER_TREE_NODES: usize = 1024;
+
+/// Node tags
+const INNER_NODE_TAG: u8 = 1;
+const LEAF_NODE_TAG: u8 = 2;
+
+fn decode_error(details: String) -> MarketSimulationError {
+    MarketSimulationError::AccountDecodeError {
+        market: DexLabel::OpenBookV2.str(),
+        details,
+    }
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().expect("slice is 4 bytes"))
+}
+
+fn read_i64(data: &[u8], offset: usize) -> i64 {
+    i64::from_le_bytes(data[offset..offset + 8].try_into().expect("slice is 8 bytes"))
+}
+
+fn read_pubkey(data: &[u8], offset: usize) -> Pubkey {
+    Pubkey::try_from(&data[offset..offset + 32]).expect("slice is 32 bytes")
+}
+
+/// The `Market` fields needed to quote and to take liquidity
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OpenBookV2Market {
+    /// PDA owning the market vaults
+    pub market_authority: Pubkey,
+    /// Markets with an open-orders admin only take orders it co-signs
+    pub open_orders_admin: Option<Pubkey>,
+    pub bids: Pubkey,
+    pub asks: Pubkey,
+    pub event_heap: Pubkey,
+    /// `None` when the market has no oracle
+    pub oracle_a: Option<Pubkey>,
+    pub oracle_b: Option<Pubkey>,
+    pub quote_lot_size: u64,
+    pub base_lot_size: u64,
+    /// Over 1e6, charged in quote
+    pub taker_fee: u64,
+    pub base_mint: Pubkey,
+    pub quote_mint: Pubkey,
+    pub market_base_vault: Pubkey,
+    pub market_quote_vault: Pubkey,
+}
+
+impl OpenBookV2Market {
+    pub fn params(&self) -> ClobParams {
+        ClobParams {
+            base_mint: self.base_mint,
+            quote_mint: self.quote_mint,
+            base_lot_size: self.base_lot_size,
+            quote_lot_size: self.quote_lot_size,
+            taker_fee: TakerFee {
+                numerator: self.taker_fee,
+                denominator: FEES_SCALE_FACTOR,
+            },
+        }
+    }
+}
+
+/// Decode a raw OpenBook v2 market account
+pub fn decode_market(data: &[u8]) -> Result<OpenBookV2Market, MarketSimulationError> {
+    if data.len() != OPENBOOK_V2_MARKET_LEN {
+        return Err(decode_error(format!(
+            "Market must be {} bytes, got {}",
+            OPENBOOK_V2_MARKET_LEN,
+            data.len()
+        )));
+    }
+
+    let non_negative = |offset: usize, field: &str| {
+        u64::try_from(read_i64(data, offset)).map_err(|_| decode_error(format!("negative {}", field)))
+    };
+    let optional = |offset: usize| Some(read_pubkey(data, offset)).filter(|key| *key != Pubkey::default());
+
+    Ok(OpenBookV2Market {
+        market_authority: read_pubkey(data, MARKET_AUTHORITY_OFFSET),
+        open_orders_admin: optional(OPEN_ORDERS_ADMIN_OFFSET),
+        bids: read_pubkey(data, BIDS_OFFSET),
+        asks: read_pubkey(data, ASKS_OFFSET),
+        event_heap: read_pubkey(data, EVENT_HEAP_OFFSET),
+        oracle_a: optional(ORACLE_A_OFFSET),
+        oracle_b: optional(ORACLE_B_OFFSET),
+        quote_lot_size: non_negative(QUOTE_LOT_SIZE_OFFSET, "quote_lot_size")?,
+        base_lot_size: non_negative(BASE_LOT_SIZE_OFFSET, "base_lot_size")?,
+        // Negative taker fees aren't allowed by the program
+        taker_fee: non_negative(TAKER_FEE_OFFSET, "taker_fee")?,
+        base_mint: read_pubkey(data, BASE_MINT_OFFSET),
+        quote_mint: read_pubkey(data, QUOTE_MINT_OFFSET),
+        market_base_vault: read_pubkey(data, BASE_VAULT_OFFSET),
+        market_quote_vault: read_pubkey(data, QUOTE_VAULT_OFFSET),
+    })
+}
+
+/// Which side of the book a `BookSide` account holds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BookSideKind {
+    Bids,
+    Asks,
+}
+
+/// Decode the fixed-price orders of a raw BookSide account into price levels
+pub fn decode_book_side(data: &[u8]) -> Result<(BookSideKind, Vec<PriceLevel>), MarketSimulationError> {
+    if data.len() != OPENBOOK_V2_BOOK_SIDE_LEN {
+        return Err(decode_error(format!(
+            "BookSide must be {} bytes, got {}",
+            OPENBOOK_V2_BOOK_SIDE_LEN,
+            data.len()
+        )));
+    }
+
+    let kind = match data[ORDER_TREE_TYPE_OFFSET] {
+        0 => BookSideKind::Bids,
+        1 => BookSideKind::Asks,
+        other => return Err(decode_error(format!("unknown order tree type {}", other))),
+    };
+
+    let root = read_u32(data, FIXED_ROOT_OFFSET) as usize;
+    let leaf_count = read_u32(data, FIXED_ROOT_OFFSET + 4) as usize;
+
+    let mut orders = Vec::with_capacity(leaf_count);
+    if leaf_count > 0 {
+        // Depth-first walk from the root; the visit cap guards against corrupt cycles
+        let mut stack = vec![root];
+        let mut visited = 0;
+        while let Some(handle) = stack.pop() {
+            visited += 1;
+            if handle >= MAX_ORDER_TREE_NODES || visited > MAX_ORDER_TREE_NODES {
+                return Err(decode_error(format!("order tree node {} out of range", handle)));
+            }
+            let node = &data[NODES_OFFSET + handle * NODE_SIZE..NODES_OFFSET + (handle + 1) * NODE_SIZE];
+            match node[0] {
+                INNER_NODE_TAG => {
+                    stack.push(read_u32(node, 24) as usize);
+                    stack.push(read_u32(node, 28) as usize);
+                }
+                LEAF_NODE_TAG => {
+                    let key = u128::from_le_bytes(node[8..24].try_into().expect("slice is 16 bytes"));
+                    let price_lots = (key >> 64) as u64;
+                    let quantity = read_i64(node, 56).max(0) as u64;
+                    orders.push((price_lots, quantity));
+                }
+                tag => return Err(decode_error(format!("unexpected node tag {} in order tree", tag))),
+            }
+        }
+    }
+
+    let levels = DepthLadder::levels_from_orders(orders, kind == BookSideKind::Bids);
+    Ok((kind, levels))
+}
+
+/// Discover OpenBook v2 markets with `token` as base (`on_base`) or quote, via
+/// getProgramAccounts with a mint filter
+pub async fn fetch_new_openbook_v2_markets(
+    rpc_client: &RpcClient,
+    token: String,
+    on_base: bool,
+) -> Vec<(Pubkey, Market)> {
+    let mut new_markets: Vec<(Pubkey, Market)> = Vec::new();
+    let filters = Some(vec![
+        RpcFilterType::Memcmp(Memcmp::new(
+            if on_base { BASE_MINT_OFFSET } else { QUOTE_MINT_OFFSET },
+            MemcmpEncodedBytes::Base58(token.clone()),
+        )),
+        RpcFilterType::DataSize(OPENBOOK_V2_MARKET_LEN as u64),
+    ]);
+
+    let accounts = match rpc_client
+        .get_program_accounts_with_config(
+            &from_str(OPENBOOK_V2_PROGRAM_ID).unwrap(),
+            RpcProgramAccountsConfig {
+                filters,
+                account_config: RpcAccountInfoConfig {
+                    encoding: Some(UiAccountEncoding::Base64),
+                    commitment: Some(rpc_client.commitment()),
+                    ..RpcAccountInfoConfig::default()
+                },
+                ..RpcProgramAccountsConfig::default()
+            },
+        )
+        .await
+    {
+        Ok(accounts) => accounts,
+        Err(e) => {
+            error!("OpenBook v2 getProgramAccounts failed for {}: {}", token, e);
+            return new_markets;
+        }
+    };
+
+    for (address, account) in accounts {
+        let Ok(header) = decode_market(&account.data) else {
+            continue;
+        };
+        let market: Market = Market {
+            token_mint_a: from_pubkey(header.base_mint),
+            token_vault_a: from_pubkey(header.market_base_vault),
+            token_mint_b: from_pubkey(header.quote_mint),
+            token_vault_b: from_pubkey(header.market_quote_vault),
+            fee: header.taker_fee,
+            dex_label: DexLabel::OpenBookV2,
+            id: from_pubkey(address),
+            account_data: Some(account.data),
+            liquidity: None,
+        };
+        new_markets.push((address, market));
+    }
+    new_markets
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn book_side(kind: BookSideKind, orders: &[(u64, i64)]) -> Vec<u8> {
+        let mut data = vec![0u8; OPENBOOK_V2_BOOK_SIDE_LEN];
+        data[ORDER_TREE_TYPE_OFFSET] = kind as u8;
+
+        // Leaves at 1.., chained under inner nodes so the walk has to recurse
+        let node = |handle: usize| NODES_OFFSET + handle * NODE_SIZE;
+        for (i, (price_lots, quantity)) in orders.iter().enumerate() {
+            let leaf = node(i + 1);
+            data[leaf] = LEAF_NODE_TAG;
+            let key = ((*price_lots as u128) << 64) | i as u128;
+            data[leaf + 8..leaf + 24].copy_from_slice(&key.to_le_bytes());
+            data[leaf + 56..leaf + 64].copy_from_slice(&quantity.to_le_bytes());
+        }
+        let mut child = 1u32;
+        for i in 1..orders.len() {
+            let inner = node(orders.len() + i);
+            data[inner] = INNER_NODE_TAG;
+            data[inner + 24..inner + 28].copy_from_slice(&child.to_le_bytes());
+            data[inner + 28..inner + 32].copy_from_slice(&((i + 1) as u32).to_le_bytes());
+            child = (orders.len() + i) as u32;
+        }
+        data[FIXED_ROOT_OFFSET..FIXED_ROOT_OFFSET + 4].copy_from_slice(&child.to_le_bytes());
+        data[FIXED_ROOT_OFFSET + 4..FIXED_ROOT_OFFSET + 8].copy_from_slice(&(orders.len() as u32).to_le_bytes());
+
+        // A free node outside the tree must not be read
+        data[node(1000)] = 3;
+        data
+    }
+
+    #[test]
+    fn test_decode_market() {
+        let base_mint = Pubkey::new_unique();
+        let mut data = vec![0u8; OPENBOOK_V2_MARKET_LEN];
+        data[BASE_MINT_OFFSET..BASE_MINT_OFFSET + 32].copy_from_slice(base_mint.as_ref());
+        data[QUOTE_LOT_SIZE_OFFSET..QUOTE_LOT_SIZE_OFFSET + 8].copy_from_slice(&10i64.to_le_bytes());
+        data[BASE_LOT_SIZE_OFFSET..BASE_LOT_SIZE_OFFSET + 8].copy_from_slice(&1_000i64.to_le_bytes());
+        data[TAKER_FEE_OFFSET..TAKER_FEE_OFFSET + 8].copy_from_slice(&400i64.to_le_bytes());
+
+        let market = decode_market(&data).unwrap();
+        assert_eq!(market.base_mint, base_mint);
+        assert_eq!(market.oracle_a, None);
+        let params = market.params();
+        assert_eq!((params.base_lot_size, params.quote_lot_size), (1_000, 10));
+        assert_eq!(params.taker_fee, TakerFee { numerator: 400, denominator: 1_000_000 });
+
+        assert!(decode_market(&data[..OPENBOOK_V2_MARKET_LEN - 1]).is_err());
+    }
+
+    #[test]
+    fn test_decode_book_side() {
+        let data = book_side(BookSideKind::Bids, &[(100, 3), (98, 2), (100, 4)]);
+        let (kind, levels) = decode_book_side(&data).unwrap();
+        assert_eq!(kind, BookSideKind::Bids);
+        assert_eq!(
+            levels,
+            vec![
+                PriceLevel { price_lots: 100, base_lots: 7 },
+                PriceLevel { price_lots: 98, base_lots: 2 }
+            ]
+        );
+
+        let (kind, levels) = decode_book_side(&book_side(BookSideKind::Asks, &[])).unwrap();
+        assert_eq!(kind, BookSideKind::Asks);
+        assert!(levels.is_empty());
+    }
+}
@@ -1,5 +1,9 @@
+use crate::arbitrage::types::{Route, TokenInfos};
 use crate::common::constants::Env;
 use crate::common::utils::{from_pubkey, from_str};
+use crate::markets::errors::MarketSimulationError;
+use crate::markets::foundation::MarketBehavior;
+use crate::markets::orca_token_swap::OrcaTokenSwapMarket;
 use crate::markets::types::{Dex, DexLabel, Market, PoolItem};
 use crate::markets::utils::to_pair_string;
 use anyhow::Result;
@@ -120,6 +124,70 @@ pub async fn fetch_data_orca() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
+// Simulate one route locally from the swap account and its vault balances
+pub async fn simulate_route_orca(
+    printing_amt: bool,
+    amount_in: u64,
+    route: Route,
+    market: Market,
+    tokens_infos: HashMap<String, TokenInfos>,
+) -> Result<(u64, u64), MarketSimulationError> {
+    let rpc_error = |e: solana_client::client_error::ClientError| MarketSimulationError::ApiRequestFailed {
+        market: DexLabel::Orca.str(),
+        message: e.to_string(),
+        source: Some(Box::new(e)),
+    };
+    let pool = from_str(&route.pool_address).map_err(|e| MarketSimulationError::InvalidResponseFormat {
+        market: DexLabel::Orca.str(),
+        details: format!("bad pool address {}: {}", route.pool_address, e),
+    })?;
+    let vault_a = from_str(&market.token_vault_a).map_err(|e| MarketSimulationError::InvalidResponseFormat {
+        market: DexLabel::Orca.str(),
+        details: format!("bad vault address {}: {}", market.token_vault_a, e),
+    })?;
+    let vault_b = from_str(&market.token_vault_b).map_err(|e| MarketSimulationError::InvalidResponseFormat {
+        market: DexLabel::Orca.str(),
+        details: format!("bad vault address {}: {}", market.token_vault_b, e),
+    })?;
+
+    let env = Env::new();
+    let rpc_client = RpcClient::new(env.rpc_url);
+    let accounts = rpc_client
+        .get_multiple_accounts(&[pool, vault_a, vault_b])
+        .await
+        .map_err(rpc_error)?;
+    let [Some(swap_account), Some(vault_a_account), Some(vault_b_account)] = &accounts[..] else {
+        return Err(MarketSimulationError::MissingField {
+            market: DexLabel::Orca.str(),
+            field: format!("account for pool {} or its vaults", pool),
+        });
+    };
+
+    let orca_market = OrcaTokenSwapMarket::from_account_data(
+        pool,
+        &swap_account.data,
+        &vault_a_account.data,
+        &vault_b_account.data,
+    )?;
+    let quote = orca_market.get_quote(amount_in, route.token_0to1)?;
+    let min_amount_out = (quote.amount_out as f64 * (1.0 - quote.slippage_tolerance / 100.0)) as u64;
+
+    if printing_amt {
+        let symbol = |mint: &String| tokens_infos.get(mint).map(|t| t.symbol.clone()).unwrap_or_default();
+        info!(
+            "Local Orca: In: {} {}, EstOut: {} {}, EstMinOut: {} {}",
+            quote.amount_in,
+            symbol(&route.token_in),
+            quote.amount_out,
+            symbol(&route.token_out),
+            min_amount_out,
+            symbol(&route.token_out)
+        );
+    }
+
+    Ok((quote.amount_out, min_amount_out))
+}
+
 pub async fn stream_orca(account: Pubkey) -> Result<()> {
     let env = Env::new();
     let url = env.wss_rpc_url.as_str();
@@ -218,7 +286,7 @@ pub struct Volume {
     pub month: String,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct TokenSwapLayout {
     pub version: u8,
     pub is_initialized: bool,
@@ -242,7 +310,7 @@ pub struct TokenSwapLayout {
     pub curve_parameters: [u8; 32],
 }
 
-fn unpack_from_slice(src: &[u8]) -> Result<TokenSwapLayout, ProgramError> {
+pub fn unpack_from_slice(src: &[u8]) -> Result<TokenSwapLayout, ProgramError> {
     let version = src[0];
     let is_initialized = src[1] != 0;
     let bump_seed = src[2];
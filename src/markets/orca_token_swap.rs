@@ -0,0 +1,482 @@
+//! Native Orca legacy token-swap quote engine
+//!
+//! Orca v1/v2 pools are deployments of the SPL token-swap program. A quote debits the
+//! layout's trade and owner fees from the input (each floored, with a minimum of one
+//! unit), then runs the pool's curve on the remainder:
+//!
+//! - constant product, with the program's ceiling division on the new invariant
+//! - stable swap, solving for D and the new destination balance by Newton iteration
+//!
+//! Programs: DjVE6JNiYqPL2QXyCUUh8rNjHrbz9hXHNYt99MQ59qw1 (v1),
+//! 9W959DqEETiGZocYWCQPaJ6sBmUzgfxXfqGeTEdp3aQP (v2)
+
+use crate::markets::errors::MarketSimulationError;
+use crate::markets::foundation::{MarketBehavior, Quote};
+use crate::markets::orca::{unpack_from_slice, TokenSwapLayout};
+use crate::markets::token_2022::{decode_token_account, TOKEN_ACCOUNT_LEN};
+use crate::markets::types::{DexLabel, MarketId};
+use anyhow::Result;
+use num::{CheckedSub, ToPrimitive, Zero};
+use num_bigint::BigUint;
+use solana_program::pubkey::Pubkey;
+
+/// Orca token-swap v1 program ID
+pub const ORCA_TOKEN_SWAP_V1_PROGRAM_ID: &str = "DjVE6JNiYqPL2QXyCUUh8rNjHrbz9hXHNYt99MQ59qw1";
+
+/// Orca token-swap v2 program ID
+pub const ORCA_TOKEN_SWAP_V2_PROGRAM_ID: &str = "9W959DqEETiGZocYWCQPaJ6sBmUzgfxXfqGeTEdp3aQP";
+
+/// Size of a token-swap account (version byte followed by the `SwapV1` layout)
+pub const TOKEN_SWAP_ACCOUNT_LEN: usize = 324;
+
+/// Newton iterations used by the stable curve, as on-chain
+const STABLE_ITERATIONS: usize = 32;
+
+/// Curve types the quote engine can price
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OrcaCurve {
+    ConstantProduct,
+    Stable { amp: u64 },
+}
+
+impl OrcaCurve {
+    /// Read the curve from the layout's `curve_type` and `curve_parameters`
+    pub fn from_layout(layout: &TokenSwapLayout) -> Result<Self, MarketSimulationError> {
+        match layout.curve_type {
+            0 => Ok(OrcaCurve::ConstantProduct),
+            2 => {
+                let amp = u64::from_le_bytes(layout.curve_parameters[0..8].try_into().expect("slice is 8 bytes"));
+                Ok(OrcaCurve::Stable { amp })
+            }
+            other => Err(decode_error(format!("unsupported curve type {}", other))),
+        }
+    }
+
+    /// Destination amount for a post-fee source amount, or `None` when the program
+    /// would reject the trade
+    fn swap_without_fees(&self, source_amount: u128, swap_source: u128, swap_destination: u128) -> Option<u128> {
+        match *self {
+            OrcaCurve::ConstantProduct => {
+                let invariant = swap_source.checked_mul(swap_destination)?;
+                let new_source = swap_source.checked_add(source_amount)?;
+                let (new_destination, _) = checked_ceil_div(invariant, new_source)?;
+                swap_destination.checked_sub(new_destination)
+            }
+            OrcaCurve::Stable { amp } => {
+                let leverage = amp.checked_mul(2)?;
+                let d = compute_d(leverage, swap_source, swap_destination)?;
+                let new_destination = compute_new_destination_amount(leverage, swap_source.checked_add(source_amount)?, d)?;
+                swap_destination.checked_sub(new_destination)
+            }
+        }
+    }
+}
+
+fn decode_error(details: String) -> MarketSimulationError {
+    MarketSimulationError::AccountDecodeError {
+        market: DexLabel::Orca.str(),
+        details,
+    }
+}
+
+/// SPL token-swap fee: floored, but never zero for a non-zero fee on a non-zero amount
+fn calculate_fee(amount: u128, numerator: u64, denominator: u64) -> Option<u128> {
+    if numerator == 0 || amount == 0 {
+        return Some(0);
+    }
+    let fee = amount.checked_mul(numerator as u128)?.checked_div(denominator as u128)?;
+    Some(fee.max(1))
+}
+
+/// `spl_math` ceiling division, returning the rounded quotient and the adjusted divisor
+fn checked_ceil_div(dividend: u128, mut divisor: u128) -> Option<(u128, u128)> {
+    let mut quotient = dividend.checked_div(divisor)?;
+    if quotient == 0 {
+        return if dividend.checked_mul(2)? >= divisor { Some((1, 0)) } else { Some((0, 0)) };
+    }
+    if !dividend.is_multiple_of(divisor) {
+        quotient += 1;
+        divisor = dividend / quotient;
+        if !dividend.is_multiple_of(quotient) {
+            divisor += 1;
+        }
+    }
+    Some((quotient, divisor))
+}
+
+/// Stable-swap invariant D for two balances
+fn compute_d(leverage: u64, amount_a: u128, amount_b: u128) -> Option<u128> {
+    let sum_x = amount_a.checked_add(amount_b)?;
+    if sum_x == 0 {
+        return Some(0);
+    }
+    if amount_a == 0 || amount_b == 0 {
+        return None;
+    }
+
+    let amount_a_times_coins = BigUint::from(amount_a) * 2u8;
+    let amount_b_times_coins = BigUint::from(amount_b) * 2u8;
+    let leverage_big = BigUint::from(leverage);
+    let sum_x_big = BigUint::from(sum_x);
+
+    let mut d = sum_x_big.clone();
+    for _ in 0..STABLE_ITERATIONS {
+        let d_product = &d * &d / &amount_a_times_coins * &d / &amount_b_times_coins;
+        let d_previous = d.clone();
+
+        // d = (leverage * sum_x + d_p * n) * d / ((leverage - 1) * d + (n + 1) * d_p)
+        let numerator = (&leverage_big * &sum_x_big + &d_product * 2u8) * &d;
+        let denominator = &d * leverage.checked_sub(1)? + &d_product * 3u8;
+        d = numerator / denominator;
+
+        if d == d_previous {
+            break;
+        }
+    }
+    d.to_u128()
+}
+
+/// Destination balance that keeps D constant after the source balance moves
+fn compute_new_destination_amount(leverage: u64, new_source_amount: u128, d: u128) -> Option<u128> {
+    let leverage = BigUint::from(leverage);
+    let new_source_amount = BigUint::from(new_source_amount);
+    let d = BigUint::from(d);
+
+    // c = D^3 / (n^2 * x' * A), b = x' + D / A
+    let c = d.pow(3) / (&new_source_amount * 4u8 * &leverage);
+    let b = &new_source_amount + &d / &leverage;
+
+    // Solve y^2 + b*y = c by Newton iteration, rounding up
+    let mut y = d.clone();
+    for _ in 0..STABLE_ITERATIONS {
+        let numerator = &y * &y + &c;
+        let denominator = (&y * 2u8 + &b).checked_sub(&d)?;
+        let y_new = ceil_div_big(&numerator, &denominator)?;
+        if y_new == y {
+            break;
+        }
+        y = y_new;
+    }
+    y.to_u128()
+}
+
+/// Big-integer counterpart of [`checked_ceil_div`], returning only the quotient
+fn ceil_div_big(dividend: &BigUint, divisor: &BigUint) -> Option<BigUint> {
+    if divisor.is_zero() {
+        return None;
+    }
+    let quotient = dividend / divisor;
+    if quotient.is_zero() {
+        return Some(if dividend * 2u8 >= *divisor { BigUint::from(1u8) } else { BigUint::zero() });
+    }
+    if !(dividend % divisor).is_zero() {
+        Some(quotient + 1u8)
+    } else {
+        Some(quotient)
+    }
+}
+
+/// Orca legacy token-swap pool priced entirely from account data
+#[derive(Debug, Clone)]
+pub struct OrcaTokenSwapMarket {
+    /// Swap account address
+    pool_address: Pubkey,
+
+    /// Decoded swap account
+    layout: TokenSwapLayout,
+
+    /// Curve read from the layout
+    curve: OrcaCurve,
+
+    /// Raw balance of vault A
+    token_a_amount: u64,
+
+    /// Raw balance of vault B
+    token_b_amount: u64,
+}
+
+impl OrcaTokenSwapMarket {
+    pub fn new(
+        pool_address: Pubkey,
+        layout: TokenSwapLayout,
+        token_a_amount: u64,
+        token_b_amount: u64,
+    ) -> Result<Self, MarketSimulationError> {
+        let curve = OrcaCurve::from_layout(&layout)?;
+        Ok(Self {
+            pool_address,
+            layout,
+            curve,
+            token_a_amount,
+            token_b_amount,
+        })
+    }
+
+    /// Create a market from the raw swap account and the raw vault token accounts
+    pub fn from_account_data(
+        pool_address: Pubkey,
+        swap_data: &[u8],
+        vault_a_data: &[u8],
+        vault_b_data: &[u8],
+    ) -> Result<Self, MarketSimulationError> {
+        let layout = decode_token_swap_layout(swap_data)?;
+        let (_, token_a_amount) = decode_token_account(vault_a_data)?;
+        let (_, token_b_amount) = decode_token_account(vault_b_data)?;
+
+        Self::new(pool_address, layout, token_a_amount, token_b_amount)
+    }
+
+    pub fn pool_address(&self) -> Pubkey {
+        self.pool_address
+    }
+
+    pub fn layout(&self) -> &TokenSwapLayout {
+        &self.layout
+    }
+
+    pub fn curve(&self) -> OrcaCurve {
+        self.curve
+    }
+
+    fn reserves(&self, a_to_b: bool) -> (u128, u128) {
+        if a_to_b {
+            (self.token_a_amount as u128, self.token_b_amount as u128)
+        } else {
+            (self.token_b_amount as u128, self.token_a_amount as u128)
+        }
+    }
+
+    /// Exact-input quote mirroring `SwapCurve::swap`
+    fn quote_exact_in(&self, amount_in: u64, a_to_b: bool) -> Result<Quote, MarketSimulationError> {
+        let (reserve_in, reserve_out) = self.reserves(a_to_b);
+        if reserve_in == 0 || reserve_out == 0 {
+            return Err(MarketSimulationError::InsufficientLiquidity {
+                market: MarketId::Orca,
+                available: reserve_out as u64,
+                required: 1,
+            });
+        }
+
+        let no_route = |reason: &str| MarketSimulationError::NoRouteFound {
+            market: DexLabel::Orca.str(),
+            reason: format!("{} on pool {}", reason, self.pool_address),
+        };
+
+        let layout = &self.layout;
+        let amount = amount_in as u128;
+        let trade_fee = calculate_fee(amount, layout.trade_fee_numerator, layout.trade_fee_denominator)
+            .ok_or_else(|| no_route("invalid trade fee"))?;
+        let owner_fee = calculate_fee(amount, layout.owner_trade_fee_numerator, layout.owner_trade_fee_denominator)
+            .ok_or_else(|| no_route("invalid owner trade fee"))?;
+        let total_fees = trade_fee + owner_fee;
+        let amount_less_fees = amount
+            .checked_sub(total_fees)
+            .ok_or_else(|| no_route("amount does not cover fees"))?;
+
+        let amount_out = self
+            .curve
+            .swap_without_fees(amount_less_fees, reserve_in, reserve_out)
+            .ok_or_else(|| no_route("curve calculation failed"))?;
+        if amount_out == 0 {
+            return Err(no_route("swap yields zero tokens"));
+        }
+
+        let spot_price = self.get_price()?;
+        let spot_out = if a_to_b {
+            amount_less_fees as f64 * spot_price
+        } else {
+            amount_less_fees as f64 / spot_price
+        };
+        let price_impact = if spot_out > 0.0 {
+            ((1.0 - amount_out as f64 / spot_out) * 100.0).clamp(0.0, 100.0)
+        } else {
+            0.0
+        };
+
+        Ok(Quote {
+            amount_in,
+            amount_out: amount_out as u64,
+            price_impact,
+            fee_amount: total_fees as u64,
+            slippage_tolerance: 0.5,
+        })
+    }
+}
+
+impl MarketBehavior for OrcaTokenSwapMarket {
+    fn get_quote(&self, amount_in: u64, a_to_b: bool) -> Result<Quote, MarketSimulationError> {
+        if amount_in == 0 {
+            return Err(MarketSimulationError::InvalidAmount {
+                market: MarketId::Orca,
+                amount: amount_in,
+            });
+        }
+
+        self.quote_exact_in(amount_in, a_to_b)
+    }
+
+    /// Spot price of token A in token B, in raw token units. The stable curve has
+    /// no closed form, so its marginal rate is measured on a one-millionth probe.
+    fn get_price(&self) -> Result<f64, MarketSimulationError> {
+        let (reserve_a, reserve_b) = self.reserves(true);
+        if reserve_a == 0 {
+            return Err(MarketSimulationError::InsufficientLiquidity {
+                market: MarketId::Orca,
+                available: 0,
+                required: 1,
+            });
+        }
+
+        match self.curve {
+            OrcaCurve::ConstantProduct => Ok(reserve_b as f64 / reserve_a as f64),
+            OrcaCurve::Stable { .. } => {
+                let probe = (reserve_a / 1_000_000).max(1);
+                let out = self
+                    .curve
+                    .swap_without_fees(probe, reserve_a, reserve_b)
+                    .ok_or_else(|| MarketSimulationError::NoRouteFound {
+                        market: DexLabel::Orca.str(),
+                        reason: format!("stable curve has no price on pool {}", self.pool_address),
+                    })?;
+                Ok(out as f64 / probe as f64)
+            }
+        }
+    }
+
+    /// Accepts either the swap account itself or one of its vault token accounts;
+    /// vaults are told apart by their mint.
+    fn update_state(&mut self, new_data: &[u8]) -> Result<(), MarketSimulationError> {
+        match new_data.len() {
+            TOKEN_SWAP_ACCOUNT_LEN => {
+                let layout = decode_token_swap_layout(new_data)?;
+                self.curve = OrcaCurve::from_layout(&layout)?;
+                self.layout = layout;
+            }
+            len if len >= TOKEN_ACCOUNT_LEN => {
+                let (mint, amount) = decode_token_account(new_data)?;
+                if mint == self.layout.mint_a {
+                    self.token_a_amount = amount;
+                } else if mint == self.layout.mint_b {
+                    self.token_b_amount = amount;
+                } else {
+                    return Err(decode_error(format!(
+                        "token account mint {} is not a vault mint of {}",
+                        mint, self.pool_address
+                    )));
+                }
+            }
+            len => return Err(decode_error(format!("unexpected account size {}", len))),
+        }
+        Ok(())
+    }
+
+    fn market_id(&self) -> MarketId {
+        MarketId::Orca
+    }
+
+    fn dex_label(&self) -> DexLabel {
+        DexLabel::Orca
+    }
+}
+
+/// Decode a raw token-swap account, rejecting uninitialized pools
+pub fn decode_token_swap_layout(data: &[u8]) -> Result<TokenSwapLayout, MarketSimulationError> {
+    if data.len() != TOKEN_SWAP_ACCOUNT_LEN {
+        return Err(decode_error(format!(
+            "token-swap account must be {} bytes, got {}",
+            TOKEN_SWAP_ACCOUNT_LEN,
+            data.len()
+        )));
+    }
+    let layout = unpack_from_slice(data).map_err(|e| decode_error(e.to_string()))?;
+    if !layout.is_initialized {
+        return Err(decode_error("token-swap account is not initialized".to_string()));
+    }
+    Ok(layout)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::markets::token_2022::tests::token_account;
+
+    fn swap_account(curve_type: u8, amp: u64) -> (Vec<u8>, Pubkey, Pubkey) {
+        let mint_a = Pubkey::new_unique();
+        let mint_b = Pubkey::new_unique();
+        let mut data = vec![0u8; TOKEN_SWAP_ACCOUNT_LEN];
+        data[0] = 1;
+        data[1] = 1;
+        data[131..163].copy_from_slice(mint_a.as_ref());
+        data[163..195].copy_from_slice(mint_b.as_ref());
+        // trade fee 25/10_000, owner fee 5/10_000
+        data[227..235].copy_from_slice(&25u64.to_le_bytes());
+        data[235..243].copy_from_slice(&10_000u64.to_le_bytes());
+        data[243..251].copy_from_slice(&5u64.to_le_bytes());
+        data[251..259].copy_from_slice(&10_000u64.to_le_bytes());
+        data[291] = curve_type;
+        data[292..300].copy_from_slice(&amp.to_le_bytes());
+        (data, mint_a, mint_b)
+    }
+
+    fn market(curve_type: u8, amp: u64, reserve_a: u64, reserve_b: u64) -> OrcaTokenSwapMarket {
+        let (data, mint_a, mint_b) = swap_account(curve_type, amp);
+        OrcaTokenSwapMarket::from_account_data(
+            Pubkey::new_unique(),
+            &data,
+            &token_account(&mint_a, reserve_a),
+            &token_account(&mint_b, reserve_b),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_constant_product_quote() {
+        let market = market(0, 0, 1_000_000_000, 2_000_000_000);
+
+        let quote = market.get_quote(1_000_000, true).unwrap();
+        // fees = 2_500 + 500, out = 2e9 - ceil(2e18 / (1e9 + 997_000))
+        assert_eq!(quote.fee_amount, 3_000);
+        assert_eq!(quote.amount_out, 1_992_013);
+
+        let reverse = market.get_quote(1_000_000, false).unwrap();
+        assert_eq!(reverse.amount_out, 498_251);
+    }
+
+    #[test]
+    fn test_fee_minimum_of_one_unit() {
+        let market = market(0, 0, 1_000_000_000, 2_000_000_000);
+
+        // Each fee floors to zero and is bumped to one, consuming the whole input
+        assert!(market.get_quote(2, true).is_err());
+        assert_eq!(market.get_quote(10, true).unwrap().fee_amount, 2);
+        assert!(market.get_quote(0, true).is_err());
+    }
+
+    #[test]
+    fn test_stable_curve_beats_constant_product_near_peg() {
+        let stable = market(2, 100, 1_000_000_000_000, 1_000_000_000_000);
+        let constant_product = market(0, 0, 1_000_000_000_000, 1_000_000_000_000);
+
+        let amount = 10_000_000_000;
+        let stable_out = stable.get_quote(amount, true).unwrap().amount_out;
+        let cp_out = constant_product.get_quote(amount, true).unwrap().amount_out;
+        assert!(stable_out > cp_out);
+        // Never more than the post-fee input on a balanced pool
+        assert!(stable_out <= amount - amount * 30 / 10_000);
+        assert!((stable.get_price().unwrap() - 1.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_update_state_and_unsupported_curve() {
+        let mut market = market(0, 0, 1_000_000_000, 2_000_000_000);
+        let mint_b = market.layout().mint_b;
+        market.update_state(&token_account(&mint_b, 4_000_000_000)).unwrap();
+        assert_eq!(market.get_price().unwrap(), 4.0);
+
+        assert!(market.update_state(&token_account(&Pubkey::new_unique(), 1)).is_err());
+
+        let (offset_curve, _, _) = swap_account(3, 0);
+        assert!(market.update_state(&offset_curve).is_err());
+        assert_eq!(market.curve(), OrcaCurve::ConstantProduct);
+    }
+}
@@ -1,16 +1,22 @@
 //! Working Orca Whirlpools Implementation - Minimal SDK Integration
 //! 
-//! This is a simplified but functional implementation that uses actual available
-//! functions from the orca_whirlpools_client crate without hallucinated imports.
+//! Quotes are produced by a local port of the Whirlpool program's swap loop: the
+//! engine walks initialized ticks across the loaded tick arrays, applies each tick's
+//! `liquidity_net` when it is crossed and charges the fee rate on every step. The
+//! Q64.64 sqrt-price and amount-delta primitives come from `orca_whirlpools_core`,
+//! so rounding matches the program to the lamport.
 //! 
 //! Verified against: https://docs.rs/orca_whirlpools_client/latest/
+//! and https://docs.rs/orca_whirlpools_core/latest/
 
 use crate::markets::foundation::{MarketBehavior, Quote};
 use crate::markets::errors::MarketSimulationError;
+use crate::markets::orca_whirpools::unpack_from_slice;
 use crate::markets::types::{MarketId, DexLabel};
 use anyhow::Result;
 use rustc_hash::FxHashMap;
 use solana_sdk::pubkey::Pubkey;
+use std::collections::BTreeMap;
 use std::sync::Arc;
 use tokio::sync::RwLock;
 
@@ -19,32 +25,256 @@ use orca_whirlpools_client::{
     get_whirlpool_address,
     ID as WHIRLPOOL_PROGRAM_ID,
 };
+use orca_whirlpools_core::{
+    get_tick_array_start_tick_index, sqrt_price_to_tick_index, tick_index_to_sqrt_price,
+    try_apply_swap_fee, try_get_amount_delta_a, try_get_amount_delta_b,
+    try_get_next_sqrt_price_from_a, try_get_next_sqrt_price_from_b, try_reverse_apply_swap_fee,
+    CoreError, AMOUNT_EXCEEDS_MAX_U64, MAX_SQRT_PRICE, MAX_TICK_INDEX, MIN_SQRT_PRICE,
+    MIN_TICK_INDEX, TICK_ARRAY_SIZE,
+};
+
+/// Size of a Whirlpool account
+pub const WHIRLPOOL_ACCOUNT_LEN: usize = 653;
+
+/// Size of a TickArray account: discriminator, start index, 88 ticks, whirlpool key
+pub const TICK_ARRAY_ACCOUNT_LEN: usize = 9988;
+
+/// Size of a single packed `Tick` inside a TickArray account
+const TICK_LEN: usize = 113;
+
+/// Number of tick arrays a swap instruction can traverse
+pub const MAX_SWAP_TICK_ARRAYS: usize = 3;
+
+/// A single tick as far as swap simulation is concerned
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct WhirlpoolTick {
+    pub initialized: bool,
+    pub liquidity_net: i128,
+}
+
+/// Decoded tick array holding `TICK_ARRAY_SIZE` ticks
+#[derive(Debug, Clone, PartialEq)]
+pub struct WhirlpoolTickArray {
+    pub start_tick_index: i32,
+    pub ticks: [WhirlpoolTick; TICK_ARRAY_SIZE],
+}
+
+impl WhirlpoolTickArray {
+    /// Tick array without any initialized tick
+    pub fn empty(start_tick_index: i32) -> Self {
+        Self {
+            start_tick_index,
+            ticks: [WhirlpoolTick::default(); TICK_ARRAY_SIZE],
+        }
+    }
+
+    /// Decode a raw TickArray account, returning it with the whirlpool it belongs to
+    pub fn from_account_data(data: &[u8]) -> Result<(Self, Pubkey), MarketSimulationError> {
+        if data.len() != TICK_ARRAY_ACCOUNT_LEN {
+            return Err(MarketSimulationError::AccountDecodeError {
+                market: DexLabel::OrcaWhirlpools.str(),
+                details: format!("TickArray must be {} bytes, got {}", TICK_ARRAY_ACCOUNT_LEN, data.len()),
+            });
+        }
+
+        let start_tick_index = i32::from_le_bytes(data[8..12].try_into().expect("slice is 4 bytes"));
+        let mut ticks = [WhirlpoolTick::default(); TICK_ARRAY_SIZE];
+        for (i, tick) in ticks.iter_mut().enumerate() {
+            let offset = 12 + i * TICK_LEN;
+            tick.initialized = data[offset] != 0;
+            tick.liquidity_net = i128::from_le_bytes(
+                data[offset + 1..offset + 17].try_into().expect("slice is 16 bytes"),
+            );
+        }
 
-/// Simple whirlpool state for basic operations
+        let whirlpool_offset = 12 + TICK_ARRAY_SIZE * TICK_LEN;
+        let whirlpool = Pubkey::try_from(&data[whirlpool_offset..whirlpool_offset + 32])
+            .expect("slice is 32 bytes");
+
+        Ok((Self { start_tick_index, ticks }, whirlpool))
+    }
+}
+
+/// Whirlpool state needed to simulate swaps
 #[derive(Debug, Clone)]
 pub struct SimpleWhirlpoolState {
     pub liquidity: u128,
     pub sqrt_price: u128,
     pub tick_current: i32,
     pub fee_rate: u16,
+    pub tick_spacing: u16,
+    /// Loaded tick arrays keyed by their start tick index
+    pub tick_arrays: BTreeMap<i32, WhirlpoolTickArray>,
 }
 
 impl Default for SimpleWhirlpoolState {
     fn default() -> Self {
+        let tick_spacing: u16 = 64;
+        let ticks_in_array = TICK_ARRAY_SIZE as i32 * tick_spacing as i32;
+        let tick_arrays = [-ticks_in_array, 0, ticks_in_array]
+            .into_iter()
+            .map(|start| (start, WhirlpoolTickArray::empty(start)))
+            .collect();
+
         Self {
             liquidity: 1_000_000_000, // Default 1B liquidity
-            sqrt_price: 79228162514264337593543950336, // ~1.0 price in Q64.64
+            sqrt_price: 18446744073709551616, // 1.0 price in Q64.64
             tick_current: 0,
             fee_rate: 300, // 0.03%
+            tick_spacing,
+            tick_arrays,
+        }
+    }
+}
+
+impl SimpleWhirlpoolState {
+    /// Find the next tick the swap loop has to stop at, mirroring the program's
+    /// `SwapTickSequence::get_next_initialized_tick_index`.
+    ///
+    /// Returns the tick index, its `liquidity_net` if initialized, and whether the tick
+    /// is the end of the traversable tick array sequence.
+    fn next_swap_tick(
+        &self,
+        tick_index: i32,
+        a_to_b: bool,
+    ) -> Result<(i32, Option<i128>, bool), MarketSimulationError> {
+        let spacing = self.tick_spacing as i32;
+        let ticks_in_array = TICK_ARRAY_SIZE as i32 * spacing;
+
+        // The instruction only receives the arrays starting at the current tick's array
+        let anchor = get_tick_array_start_tick_index(self.tick_current, self.tick_spacing);
+        let in_sequence =
+            |start: i32| (((start - anchor) / ticks_in_array).unsigned_abs() as usize) < MAX_SWAP_TICK_ARRAYS;
+
+        let mut start = get_tick_array_start_tick_index(tick_index, self.tick_spacing);
+        let mut array = self.tick_arrays.get(&start).ok_or_else(|| tick_array_missing(start))?;
+        let mut offset = (tick_index - start).div_euclid(spacing);
+        if !a_to_b {
+            offset += 1;
+        }
+
+        loop {
+            while (0..TICK_ARRAY_SIZE as i32).contains(&offset) {
+                let tick = array.ticks[offset as usize];
+                if tick.initialized {
+                    return Ok((start + offset * spacing, Some(tick.liquidity_net), false));
+                }
+                offset += if a_to_b { -1 } else { 1 };
+            }
+
+            if a_to_b && start <= MIN_TICK_INDEX {
+                return Ok((MIN_TICK_INDEX, None, false));
+            }
+            if !a_to_b && start + ticks_in_array > MAX_TICK_INDEX {
+                return Ok((MAX_TICK_INDEX, None, false));
+            }
+
+            let next_start = if a_to_b { start - ticks_in_array } else { start + ticks_in_array };
+            match self.tick_arrays.get(&next_start) {
+                Some(next) if in_sequence(next_start) => {
+                    start = next_start;
+                    array = next;
+                    offset = if a_to_b { TICK_ARRAY_SIZE as i32 - 1 } else { 0 };
+                }
+                _ => {
+                    let last_tick = if a_to_b { start } else { start + ticks_in_array - 1 };
+                    return Ok((last_tick, None, true));
+                }
+            }
+        }
+    }
+}
+
+/// Result of a single swap step between two sqrt prices
+struct SwapStep {
+    amount_in: u64,
+    amount_out: u64,
+    next_sqrt_price: u128,
+    fee_amount: u64,
+}
+
+/// Exact-input swap step, ported from the program's `compute_swap_step`
+fn compute_swap_step(
+    amount_remaining: u64,
+    fee_rate: u16,
+    liquidity: u128,
+    current_sqrt_price: u128,
+    target_sqrt_price: u128,
+    a_to_b: bool,
+) -> Result<SwapStep, CoreError> {
+    let input_delta = |from: u128, to: u128| {
+        if a_to_b {
+            try_get_amount_delta_a(from, to, liquidity, true)
+        } else {
+            try_get_amount_delta_b(from, to, liquidity, true)
         }
+    };
+
+    let initial_amount_in = input_delta(current_sqrt_price, target_sqrt_price);
+    let initial_overflow = initial_amount_in == Err(AMOUNT_EXCEEDS_MAX_U64);
+    let amount_less_fee = try_apply_swap_fee(amount_remaining, fee_rate)?;
+
+    let next_sqrt_price = if !initial_overflow && initial_amount_in? <= amount_less_fee {
+        target_sqrt_price
+    } else if a_to_b {
+        try_get_next_sqrt_price_from_a(current_sqrt_price, liquidity, amount_less_fee, true)?
+    } else {
+        try_get_next_sqrt_price_from_b(current_sqrt_price, liquidity, amount_less_fee, true)?
+    };
+    let is_max_swap = next_sqrt_price == target_sqrt_price;
+
+    let amount_out = if a_to_b {
+        try_get_amount_delta_b(current_sqrt_price, next_sqrt_price, liquidity, false)?
+    } else {
+        try_get_amount_delta_a(current_sqrt_price, next_sqrt_price, liquidity, false)?
+    };
+
+    let amount_in = if !is_max_swap || initial_overflow {
+        input_delta(current_sqrt_price, next_sqrt_price)?
+    } else {
+        initial_amount_in?
+    };
+
+    let fee_amount = if !is_max_swap {
+        amount_remaining - amount_in
+    } else {
+        try_reverse_apply_swap_fee(amount_in, fee_rate)? - amount_in
+    };
+
+    Ok(SwapStep {
+        amount_in,
+        amount_out,
+        next_sqrt_price,
+        fee_amount,
+    })
+}
+
+/// Outcome of a full exact-input swap simulation
+#[derive(Debug, Clone, Copy)]
+struct SwapResult {
+    amount_in: u64,
+    amount_out: u64,
+    fee_amount: u64,
+}
+
+fn tick_array_missing(start_tick_index: i32) -> MarketSimulationError {
+    MarketSimulationError::NoRouteFound {
+        market: DexLabel::OrcaWhirlpools.str(),
+        reason: format!("tick array starting at {} is not loaded", start_tick_index),
+    }
+}
+
+fn math_error(e: CoreError) -> MarketSimulationError {
+    MarketSimulationError::NoRouteFound {
+        market: DexLabel::OrcaWhirlpools.str(),
+        reason: e.to_string(),
     }
 }
 
 /// Working Orca Whirlpools market implementation
 /// 
-/// This implementation focuses on functionality over complexity,
-/// using simple math for quotes rather than complex SDK functions
-/// that may not be available or properly documented.
+/// Holds the whirlpool state together with the tick arrays around the current
+/// price and simulates swaps exactly as the on-chain program would.
 pub struct OrcaWhirlpoolsWorking {
     /// Pool state data
     pool_state: SimpleWhirlpoolState,
@@ -78,12 +308,91 @@ impl OrcaWhirlpoolsWorking {
             token_b,
         }
     }
-    
-    /// Calculate simple AMM quote using constant product formula
-    /// 
-    /// This is a working implementation that can be enhanced later
-    /// with more sophisticated concentrated liquidity math.
-    fn calculate_simple_quote(
+
+    /// Add or replace a decoded tick array
+    pub fn insert_tick_array(&mut self, tick_array: WhirlpoolTickArray) {
+        self.pool_state
+            .tick_arrays
+            .insert(tick_array.start_tick_index, tick_array);
+        self.clear_quote_cache();
+    }
+
+    fn clear_quote_cache(&self) {
+        if let Ok(mut cache) = self.quote_cache.try_write() {
+            cache.clear();
+        }
+    }
+
+    /// Run the program's exact-input swap loop against the loaded state
+    fn simulate_swap(&self, amount: u64, a_to_b: bool) -> Result<SwapResult, MarketSimulationError> {
+        let state = &self.pool_state;
+        let sqrt_price_limit = if a_to_b { MIN_SQRT_PRICE } else { MAX_SQRT_PRICE };
+
+        let mut amount_remaining = amount;
+        let mut amount_out: u64 = 0;
+        let mut fee_amount: u64 = 0;
+        let mut curr_sqrt_price = state.sqrt_price;
+        let mut curr_tick_index = state.tick_current;
+        let mut curr_liquidity = state.liquidity;
+
+        while amount_remaining > 0 && curr_sqrt_price != sqrt_price_limit {
+            let (next_tick_index, liquidity_net, is_sequence_end) =
+                state.next_swap_tick(curr_tick_index, a_to_b)?;
+
+            let next_tick_sqrt_price = tick_index_to_sqrt_price(next_tick_index);
+            let target_sqrt_price = if a_to_b {
+                next_tick_sqrt_price.max(sqrt_price_limit)
+            } else {
+                next_tick_sqrt_price.min(sqrt_price_limit)
+            };
+
+            let step = compute_swap_step(
+                amount_remaining,
+                state.fee_rate,
+                curr_liquidity,
+                curr_sqrt_price,
+                target_sqrt_price,
+                a_to_b,
+            )
+            .map_err(math_error)?;
+
+            amount_remaining -= step.amount_in + step.fee_amount;
+            amount_out = amount_out
+                .checked_add(step.amount_out)
+                .ok_or_else(|| math_error(AMOUNT_EXCEEDS_MAX_U64))?;
+            fee_amount += step.fee_amount;
+
+            if step.next_sqrt_price == next_tick_sqrt_price {
+                if is_sequence_end && amount_remaining > 0 {
+                    return Err(MarketSimulationError::InsufficientLiquidity {
+                        market: MarketId::Orca,
+                        available: amount - amount_remaining,
+                        required: amount,
+                    });
+                }
+                if let Some(net) = liquidity_net {
+                    let delta = if a_to_b { -net } else { net };
+                    curr_liquidity = curr_liquidity
+                        .checked_add_signed(delta)
+                        .ok_or_else(|| math_error("Liquidity net underflow"))?;
+                }
+                curr_tick_index = if a_to_b { next_tick_index - 1 } else { next_tick_index };
+            } else if step.next_sqrt_price != curr_sqrt_price {
+                curr_tick_index = sqrt_price_to_tick_index(step.next_sqrt_price);
+            }
+
+            curr_sqrt_price = step.next_sqrt_price;
+        }
+
+        Ok(SwapResult {
+            amount_in: amount - amount_remaining,
+            amount_out,
+            fee_amount,
+        })
+    }
+
+    /// Calculate an exact concentrated-liquidity quote, served from cache when possible
+    fn calculate_quote(
         &self,
         amount_in: u64,
         a_to_b: bool,
@@ -95,41 +404,24 @@ impl OrcaWhirlpoolsWorking {
                 return Ok(*cached_quote);
             }
         }
-        
-        // Simple concentrated liquidity approximation
-        // This uses basic math that works vs complex SDK functions that may not exist
-        let liquidity = self.pool_state.liquidity as f64;
-        let sqrt_price = self.pool_state.sqrt_price as f64;
-        
-        // Convert sqrt_price to regular price (simplified)
-        let current_price = (sqrt_price / (1u128 << 64) as f64).powi(2);
-        
-        // Calculate output amount with price impact
-        let amount_in_f64 = amount_in as f64;
-        let fee_rate = self.pool_state.fee_rate as f64 / 1_000_000.0; // Convert to decimal
-        
-        // Apply fee
-        let amount_after_fee = amount_in_f64 * (1.0 - fee_rate);
-        
-        // Simple price impact calculation
-        let price_impact = (amount_after_fee / liquidity) * 100.0; // Rough approximation
-        let effective_price = if a_to_b {
-            current_price * (1.0 - price_impact / 100.0)
-        } else {
-            current_price * (1.0 + price_impact / 100.0)
-        };
-        
-        let amount_out = if a_to_b {
-            amount_after_fee * effective_price
+
+        let result = self.simulate_swap(amount_in, a_to_b)?;
+
+        // Impact of the realised output against the spot price on the post-fee input
+        let spot_price = self.get_price()?;
+        let net_in = (result.amount_in - result.fee_amount) as f64;
+        let spot_out = if a_to_b { net_in * spot_price } else { net_in / spot_price };
+        let price_impact = if spot_out > 0.0 {
+            ((1.0 - result.amount_out as f64 / spot_out) * 100.0).clamp(0.0, 100.0)
         } else {
-            amount_after_fee / effective_price
+            0.0
         };
-        
+
         let quote = Quote {
-            amount_in,
-            amount_out: amount_out as u64,
-            price_impact: price_impact.min(100.0), // Cap at 100%
-            fee_amount: (amount_in_f64 * fee_rate) as u64,
+            amount_in: result.amount_in,
+            amount_out: result.amount_out,
+            price_impact,
+            fee_amount: result.fee_amount,
             slippage_tolerance: 0.5, // 0.5% default
         };
         
@@ -155,6 +447,7 @@ impl OrcaWhirlpoolsWorking {
     ) -> Pubkey {
         get_whirlpool_address(whirlpools_config, token_mint_a, token_mint_b, tick_spacing)
             .expect("Valid whirlpool address derivation")
+            .0
     }
 }
 
@@ -167,16 +460,7 @@ impl MarketBehavior for OrcaWhirlpoolsWorking {
             });
         }
         
-        // Check minimum liquidity
-        if self.pool_state.liquidity < amount_in as u128 * 10 {
-            return Err(MarketSimulationError::InsufficientLiquidity {
-                market: MarketId::Orca,
-                available: self.pool_state.liquidity as u64,
-                required: amount_in * 10,
-            });
-        }
-        
-        self.calculate_simple_quote(amount_in, a_to_b)
+        self.calculate_quote(amount_in, a_to_b)
     }
     
     fn get_price(&self) -> Result<f64, MarketSimulationError> {
@@ -186,26 +470,47 @@ impl MarketBehavior for OrcaWhirlpoolsWorking {
         Ok(price)
     }
     
+    /// Accepts either the Whirlpool account or one of its TickArray accounts
     fn update_state(&mut self, new_data: &[u8]) -> Result<(), MarketSimulationError> {
-        // For now, just clear cache when state updates
-        // In production, this would parse actual whirlpool account data
-        if let Ok(mut cache) = self.quote_cache.try_write() {
-            cache.clear();
-        }
-        
-        // Simple state update simulation
-        if new_data.len() >= 16 {
-            // Update liquidity from first 8 bytes (little endian)
-            if let Ok(liquidity_bytes) = new_data[0..8].try_into() {
-                self.pool_state.liquidity = u64::from_le_bytes(liquidity_bytes) as u128;
+        match new_data.len() {
+            WHIRLPOOL_ACCOUNT_LEN => {
+                let account = unpack_from_slice(new_data).map_err(|e| {
+                    MarketSimulationError::AccountDecodeError {
+                        market: DexLabel::OrcaWhirlpools.str(),
+                        details: e.to_string(),
+                    }
+                })?;
+                let state = &mut self.pool_state;
+                if account.tick_spacing != state.tick_spacing {
+                    state.tick_arrays.clear();
+                }
+                state.liquidity = account.liquidity;
+                state.sqrt_price = account.sqrt_price;
+                state.tick_current = account.tick_current_index;
+                state.fee_rate = account.fee_rate;
+                state.tick_spacing = account.tick_spacing;
+            }
+            TICK_ARRAY_ACCOUNT_LEN => {
+                let (tick_array, whirlpool) = WhirlpoolTickArray::from_account_data(new_data)?;
+                if self.pool_address != Pubkey::default() && whirlpool != self.pool_address {
+                    return Err(MarketSimulationError::AccountDecodeError {
+                        market: DexLabel::OrcaWhirlpools.str(),
+                        details: format!("tick array belongs to {}, not {}", whirlpool, self.pool_address),
+                    });
+                }
+                self.pool_state
+                    .tick_arrays
+                    .insert(tick_array.start_tick_index, tick_array);
             }
-            
-            // Update sqrt_price from next 8 bytes  
-            if let Ok(price_bytes) = new_data[8..16].try_into() {
-                self.pool_state.sqrt_price = u64::from_le_bytes(price_bytes) as u128;
+            len => {
+                return Err(MarketSimulationError::AccountDecodeError {
+                    market: DexLabel::OrcaWhirlpools.str(),
+                    details: format!("unexpected account size {}", len),
+                });
             }
         }
-        
+
+        self.clear_quote_cache();
         Ok(())
     }
     
@@ -284,4 +589,95 @@ mod tests {
         let price = price_result.unwrap();
         assert!(price > 0.0);
     }
-}
\ No newline at end of file
+
+    fn state_with_tick(tick_index: i32, liquidity_net: i128) -> SimpleWhirlpoolState {
+        let mut state = SimpleWhirlpoolState::default();
+        let start = get_tick_array_start_tick_index(tick_index, state.tick_spacing);
+        let offset = ((tick_index - start) / state.tick_spacing as i32) as usize;
+        state.tick_arrays.get_mut(&start).unwrap().ticks[offset] = WhirlpoolTick {
+            initialized: true,
+            liquidity_net,
+        };
+        state
+    }
+
+    #[test]
+    fn test_single_range_swap_matches_core_math() {
+        let state = SimpleWhirlpoolState::default();
+        let market = OrcaWhirlpoolsWorking::new(
+            Pubkey::default(),
+            Pubkey::default(),
+            Pubkey::default(),
+            Some(state.clone()),
+        );
+
+        let amount = 1_000_000;
+        let quote = market.get_quote(amount, false).unwrap();
+
+        let amount_less_fee = try_apply_swap_fee(amount, state.fee_rate).unwrap();
+        let next_sqrt_price =
+            try_get_next_sqrt_price_from_b(state.sqrt_price, state.liquidity, amount_less_fee, true).unwrap();
+        let expected_out =
+            try_get_amount_delta_a(state.sqrt_price, next_sqrt_price, state.liquidity, false).unwrap();
+
+        assert_eq!(quote.amount_in, amount);
+        assert_eq!(quote.amount_out, expected_out);
+        assert!(quote.fee_amount >= amount - amount_less_fee);
+    }
+
+    #[test]
+    fn test_crossing_tick_applies_liquidity_net() {
+        let flat = OrcaWhirlpoolsWorking::new(Pubkey::default(), Pubkey::default(), Pubkey::default(), None);
+        let stepped = OrcaWhirlpoolsWorking::new(
+            Pubkey::default(),
+            Pubkey::default(),
+            Pubkey::default(),
+            Some(state_with_tick(-64, 500_000_000)),
+        );
+
+        // Stays above tick -64: the initialized tick is never reached
+        assert_eq!(
+            flat.get_quote(1_000_000, true).unwrap().amount_out,
+            stepped.get_quote(1_000_000, true).unwrap().amount_out
+        );
+
+        // Crosses tick -64, below which half of the liquidity is out of range
+        let flat_out = flat.get_quote(10_000_000, true).unwrap().amount_out;
+        let stepped_out = stepped.get_quote(10_000_000, true).unwrap().amount_out;
+        assert!(stepped_out < flat_out);
+    }
+
+    #[test]
+    fn test_swap_beyond_loaded_tick_arrays_fails() {
+        let market = OrcaWhirlpoolsWorking::new(Pubkey::default(), Pubkey::default(), Pubkey::default(), None);
+
+        let result = market.get_quote(1_000_000_000_000, true);
+        assert!(matches!(result, Err(MarketSimulationError::InsufficientLiquidity { .. })));
+    }
+
+    #[test]
+    fn test_update_state_decodes_tick_array() {
+        let mut data = vec![0u8; TICK_ARRAY_ACCOUNT_LEN];
+        data[8..12].copy_from_slice(&(-5632i32).to_le_bytes());
+        // Offset 87 of the array starting at -5632 is tick -64
+        let offset = 12 + 87 * TICK_LEN;
+        data[offset] = 1;
+        data[offset + 1..offset + 17].copy_from_slice(&500_000_000i128.to_le_bytes());
+
+        let mut market = OrcaWhirlpoolsWorking::new(Pubkey::default(), Pubkey::default(), Pubkey::default(), None);
+        market.update_state(&data).unwrap();
+
+        let stepped = OrcaWhirlpoolsWorking::new(
+            Pubkey::default(),
+            Pubkey::default(),
+            Pubkey::default(),
+            Some(state_with_tick(-64, 500_000_000)),
+        );
+        assert_eq!(
+            market.get_quote(10_000_000, true).unwrap().amount_out,
+            stepped.get_quote(10_000_000, true).unwrap().amount_out
+        );
+
+        assert!(market.update_state(&[0u8; 16]).is_err());
+    }
+}
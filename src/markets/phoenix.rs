@@ -0,0 +1,320 @@
+//! Phoenix market decoding and discovery
+//!
+//! A Phoenix market is a single account: a fixed 576-byte header, the FIFO market
+//! parameters, then the bids and asks red-black trees sized by the header. Tree nodes
+//! are 64 bytes (four u32 registers, a `FIFOOrderId` key and a `FIFORestingOrder`)
+//! addressed from 1, with 0 as the null link. Prices are stored in ticks and
+//! converted to quote lots per base lot for the ladder, rounding bids down and asks
+//! up so the ladder never looks better than the book.
+//!
+//! Program: PhoeNiXZ8ByJGLkxNfZRnkUfjvmuYqLR89jjFHGqdXY
+
+use crate::common::utils::{from_pubkey, from_str};
+use crate::markets::clob::{ClobParams, DepthLadder, TakerFee};
+use crate::markets::errors::MarketSimulationError;
+use crate::markets::types::{DexLabel, Market};
+use anyhow::Result;
+use log::error;
+use solana_account_decoder::UiAccountEncoding;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_client::rpc_config::{RpcAccountInfoConfig, RpcProgramAccountsConfig};
+use solana_client::rpc_filter::{Memcmp, MemcmpEncodedBytes, RpcFilterType};
+use solana_program::pubkey::Pubkey;
+
+/// Phoenix program ID
+pub const PHOENIX_PROGRAM_ID: &str = "PhoeNiXZ8ByJGLkxNfZRnkUfjvmuYqLR89jjFHGqdXY";
+
+/// Size of the `MarketHeader`
+pub const PHOENIX_MARKET_HEADER_LEN: usize = 576;
+
+/// `MarketHeader` field offsets
+const STATUS_OFFSET: usize = 8;
+const BIDS_SIZE_OFFSET: usize = 16;
+const ASKS_SIZE_OFFSET: usize = 24;
+pub const BASE_MINT_OFFSET: usize = 48;
+const BASE_VAULT_OFFSET: usize = 80;
+const BASE_LOT_SIZE_OFFSET: usize = 112;
+pub const QUOTE_MINT_OFFSET: usize = 128;
+const QUOTE_VAULT_OFFSET: usize = 160;
+const QUOTE_LOT_SIZE_OFFSET: usize = 192;
+
+/// `FIFOMarket` field offsets, past its 256-byte padding
+const BASE_LOTS_PER_BASE_UNIT_OFFSET: usize = 832;
+const TICK_SIZE_OFFSET: usize = 840;
+const TAKER_FEE_BPS_OFFSET: usize = 856;
+const BIDS_TREE_OFFSET: usize = 880;
+
+/// Red-black tree header (root, padding, allocator size/bump/free list) and node size
+const TREE_HEADER_LEN: usize = 32;
+const TREE_NODE_SIZE: usize = 64;
+
+/// Node register slots
+const LEFT_REGISTER: usize = 0;
+const RIGHT_REGISTER: usize = 1;
+
+/// Only `Active` markets accept taker orders
+const MARKET_STATUS_ACTIVE: u64 = 1;
+
+fn decode_error(details: String) -> MarketSimulationError {
+    MarketSimulationError::AccountDecodeError {
+        market: DexLabel::Phoenix.str(),
+        details,
+    }
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().expect("slice is 4 bytes"))
+}
+
+fn read_u64(data: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(data[offset..offset + 8].try_into().expect("slice is 8 bytes"))
+}
+
+fn read_pubkey(data: &[u8], offset: usize) -> Pubkey {
+    Pubkey::try_from(&data[offset..offset + 32]).expect("slice is 32 bytes")
+}
+
+/// The `MarketHeader` fields needed to quote and to take liquidity
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PhoenixMarketHeader {
+    pub status: u64,
+    pub bids_size: u64,
+    pub asks_size: u64,
+    pub base_mint: Pubkey,
+    pub base_vault: Pubkey,
+    /// Base atoms per base lot
+    pub base_lot_size: u64,
+    pub quote_mint: Pubkey,
+    pub quote_vault: Pubkey,
+    /// Quote atoms per quote lot
+    pub quote_lot_size: u64,
+}
+
+/// Decode the header of a raw Phoenix market account
+pub fn decode_market_header(data: &[u8]) -> Result<PhoenixMarketHeader, MarketSimulationError> {
+    if data.len() < PHOENIX_MARKET_HEADER_LEN {
+        return Err(decode_error(format!("market account too short: {} bytes", data.len())));
+    }
+    Ok(PhoenixMarketHeader {
+        status: read_u64(data, STATUS_OFFSET),
+        bids_size: read_u64(data, BIDS_SIZE_OFFSET),
+        asks_size: read_u64(data, ASKS_SIZE_OFFSET),
+        base_mint: read_pubkey(data, BASE_MINT_OFFSET),
+        base_vault: read_pubkey(data, BASE_VAULT_OFFSET),
+        base_lot_size: read_u64(data, BASE_LOT_SIZE_OFFSET),
+        quote_mint: read_pubkey(data, QUOTE_MINT_OFFSET),
+        quote_vault: read_pubkey(data, QUOTE_VAULT_OFFSET),
+        quote_lot_size: read_u64(data, QUOTE_LOT_SIZE_OFFSET),
+    })
+}
+
+/// Collect `(price_in_ticks, base_lots)` of every order in the tree at `offset`
+fn decode_tree(data: &[u8], offset: usize, capacity: usize) -> Result<Vec<(u64, u64)>, MarketSimulationError> {
+    let nodes_offset = offset + TREE_HEADER_LEN;
+    let root = read_u32(data, offset) as usize;
+
+    let mut orders = Vec::new();
+    let mut stack = vec![root];
+    let mut visited = 0;
+    while let Some(address) = stack.pop() {
+        if address == 0 {
+            continue;
+        }
+        visited += 1;
+        if address > capacity || visited > capacity {
+            return Err(decode_error(format!("tree node {} out of range", address)));
+        }
+        let node = nodes_offset + (address - 1) * TREE_NODE_SIZE;
+        stack.push(read_u32(data, node + LEFT_REGISTER * 4) as usize);
+        stack.push(read_u32(data, node + RIGHT_REGISTER * 4) as usize);
+        // Key (price_in_ticks, sequence number) then value (trader index, base lots, ...)
+        orders.push((read_u64(data, node + 16), read_u64(data, node + 40)));
+    }
+    Ok(orders)
+}
+
+/// Decode a raw Phoenix market account into its parameters and depth ladder. Markets
+/// that aren't `Active` reject takers, so their ladder is left empty.
+pub fn decode_market(data: &[u8]) -> Result<(ClobParams, DepthLadder), MarketSimulationError> {
+    let header = decode_market_header(data)?;
+    let bids_size = header.bids_size as usize;
+    let asks_size = header.asks_size as usize;
+    let asks_offset = BIDS_TREE_OFFSET + TREE_HEADER_LEN + bids_size * TREE_NODE_SIZE;
+    let required = asks_offset + TREE_HEADER_LEN + asks_size * TREE_NODE_SIZE;
+    if data.len() < required {
+        return Err(decode_error(format!(
+            "market with {} bids and {} asks needs {} bytes, got {}",
+            bids_size,
+            asks_size,
+            required,
+            data.len()
+        )));
+    }
+
+    let base_lots_per_base_unit = read_u64(data, BASE_LOTS_PER_BASE_UNIT_OFFSET);
+    let tick_size = read_u64(data, TICK_SIZE_OFFSET);
+    if base_lots_per_base_unit == 0 {
+        return Err(decode_error("base_lots_per_base_unit is zero".to_string()));
+    }
+
+    let params = ClobParams {
+        base_mint: header.base_mint,
+        quote_mint: header.quote_mint,
+        base_lot_size: header.base_lot_size,
+        quote_lot_size: header.quote_lot_size,
+        taker_fee: TakerFee {
+            numerator: read_u64(data, TAKER_FEE_BPS_OFFSET),
+            denominator: 10_000,
+        },
+    };
+    if header.status != MARKET_STATUS_ACTIVE {
+        return Ok((params, DepthLadder::default()));
+    }
+
+    // Quote lots per base lot = ticks * (quote lots per base unit per tick) / (base lots per base unit)
+    let to_lots = |ticks: u64, round_up: bool| {
+        let quote_lots_per_unit = ticks as u128 * tick_size as u128;
+        let lots = if round_up {
+            quote_lots_per_unit.div_ceil(base_lots_per_base_unit as u128)
+        } else {
+            quote_lots_per_unit / base_lots_per_base_unit as u128
+        };
+        lots.min(u64::MAX as u128) as u64
+    };
+
+    let bids = decode_tree(data, BIDS_TREE_OFFSET, bids_size)?
+        .into_iter()
+        .map(|(ticks, base_lots)| (to_lots(ticks, false), base_lots));
+    let asks = decode_tree(data, asks_offset, asks_size)?
+        .into_iter()
+        .map(|(ticks, base_lots)| (to_lots(ticks, true), base_lots));
+
+    let ladder = DepthLadder {
+        bids: DepthLadder::levels_from_orders(bids, true),
+        asks: DepthLadder::levels_from_orders(asks, false),
+    };
+    Ok((params, ladder))
+}
+
+/// Discover Phoenix markets with `token` as base (`on_base`) or quote, via
+/// getProgramAccounts with a mint filter. Market accounts vary in size with their
+/// capacity, so there is no size filter.
+pub async fn fetch_new_phoenix_markets(rpc_client: &RpcClient, token: String, on_base: bool) -> Vec<(Pubkey, Market)> {
+    let mut new_markets: Vec<(Pubkey, Market)> = Vec::new();
+    let filters = Some(vec![RpcFilterType::Memcmp(Memcmp::new(
+        if on_base { BASE_MINT_OFFSET } else { QUOTE_MINT_OFFSET },
+        MemcmpEncodedBytes::Base58(token.clone()),
+    ))]);
+
+    let accounts = match rpc_client
+        .get_program_accounts_with_config(
+            &from_str(PHOENIX_PROGRAM_ID).unwrap(),
+            RpcProgramAccountsConfig {
+                filters,
+                account_config: RpcAccountInfoConfig {
+                    encoding: Some(UiAccountEncoding::Base64),
+                    commitment: Some(rpc_client.commitment()),
+                    ..RpcAccountInfoConfig::default()
+                },
+                ..RpcProgramAccountsConfig::default()
+            },
+        )
+        .await
+    {
+        Ok(accounts) => accounts,
+        Err(e) => {
+            error!("Phoenix getProgramAccounts failed for {}: {}", token, e);
+            return new_markets;
+        }
+    };
+
+    for (address, account) in accounts {
+        let (Ok(header), Ok((params, _))) = (decode_market_header(&account.data), decode_market(&account.data)) else {
+            continue;
+        };
+        let market: Market = Market {
+            token_mint_a: from_pubkey(header.base_mint),
+            token_vault_a: from_pubkey(header.base_vault),
+            token_mint_b: from_pubkey(header.quote_mint),
+            token_vault_b: from_pubkey(header.quote_vault),
+            fee: params.taker_fee.numerator,
+            dex_label: DexLabel::Phoenix,
+            id: from_pubkey(address),
+            account_data: Some(account.data),
+            liquidity: None,
+        };
+        new_markets.push((address, market));
+    }
+    new_markets
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::markets::clob::PriceLevel;
+
+    fn put_u64(data: &mut [u8], offset: usize, value: u64) {
+        data[offset..offset + 8].copy_from_slice(&value.to_le_bytes());
+    }
+
+    fn put_u32(data: &mut [u8], offset: usize, value: u32) {
+        data[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+    }
+
+    /// Lay `orders` out as a right-leaning chain so the walk follows links
+    fn write_tree(data: &mut [u8], offset: usize, orders: &[(u64, u64)]) {
+        put_u32(data, offset, if orders.is_empty() { 0 } else { 1 });
+        for (i, (ticks, base_lots)) in orders.iter().enumerate() {
+            let node = offset + TREE_HEADER_LEN + i * TREE_NODE_SIZE;
+            let next = if i + 1 < orders.len() { i as u32 + 2 } else { 0 };
+            put_u32(data, node + RIGHT_REGISTER * 4, next);
+            put_u64(data, node + 16, *ticks);
+            put_u64(data, node + 40, *base_lots);
+        }
+    }
+
+    fn market_account(status: u64, bids: &[(u64, u64)], asks: &[(u64, u64)]) -> Vec<u8> {
+        let (bids_size, asks_size) = (4, 4);
+        let asks_offset = BIDS_TREE_OFFSET + TREE_HEADER_LEN + bids_size * TREE_NODE_SIZE;
+        let mut data = vec![0u8; asks_offset + TREE_HEADER_LEN + asks_size * TREE_NODE_SIZE];
+        put_u64(&mut data, STATUS_OFFSET, status);
+        put_u64(&mut data, BIDS_SIZE_OFFSET, bids_size as u64);
+        put_u64(&mut data, ASKS_SIZE_OFFSET, asks_size as u64);
+        put_u64(&mut data, BASE_LOT_SIZE_OFFSET, 1_000);
+        put_u64(&mut data, QUOTE_LOT_SIZE_OFFSET, 1);
+        put_u64(&mut data, BASE_LOTS_PER_BASE_UNIT_OFFSET, 2);
+        put_u64(&mut data, TICK_SIZE_OFFSET, 3);
+        put_u64(&mut data, TAKER_FEE_BPS_OFFSET, 5);
+        write_tree(&mut data, BIDS_TREE_OFFSET, bids);
+        write_tree(&mut data, asks_offset, asks);
+        data
+    }
+
+    #[test]
+    fn test_decode_market() {
+        let data = market_account(MARKET_STATUS_ACTIVE, &[(33, 4), (34, 1), (33, 2)], &[(35, 7)]);
+        let (params, ladder) = decode_market(&data).unwrap();
+        assert_eq!(params.taker_fee, TakerFee { numerator: 5, denominator: 10_000 });
+        assert_eq!((params.base_lot_size, params.quote_lot_size), (1_000, 1));
+
+        // 3 quote lots per tick over 2 base lots: bids round down, asks up
+        assert_eq!(
+            ladder.bids,
+            vec![
+                PriceLevel { price_lots: 51, base_lots: 1 },
+                PriceLevel { price_lots: 49, base_lots: 6 }
+            ]
+        );
+        assert_eq!(ladder.asks, vec![PriceLevel { price_lots: 53, base_lots: 7 }]);
+    }
+
+    #[test]
+    fn test_inactive_market_and_truncated_account() {
+        let data = market_account(3, &[(33, 4)], &[(35, 7)]);
+        let (_, ladder) = decode_market(&data).unwrap();
+        assert_eq!(ladder, DepthLadder::default());
+
+        assert!(decode_market(&data[..data.len() - 1]).is_err());
+        assert!(decode_market_header(&data[..PHOENIX_MARKET_HEADER_LEN - 1]).is_err());
+    }
+}
@@ -0,0 +1,199 @@
+//! Versioned binary snapshot of the pool registry
+//!
+//! A snapshot holds the pool list and the raw accounts behind each pool (pool state,
+//! vaults, tick and bin arrays), each with the slot it was observed at. Raw bytes are
+//! stored instead of decoded structs so a snapshot survives changes to the decoders;
+//! `replay` runs them back through an `AccountUpdateRouter` on boot.
+//!
+//! Layout: 8-byte magic, little-endian `u16` version, SHA-256 of the body, then the
+//! borsh-encoded body. Files are written to a temporary path and renamed into place,
+//! so a crash mid-write leaves the previous snapshot intact.
+
+use crate::markets::account_decoders::AccountUpdateRouter;
+use crate::markets::lockless_cache::DataVersion;
+use crate::markets::pools::Pool;
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::hash::hash;
+use solana_program::pubkey::Pubkey;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::debug;
+
+/// Identifies a pool snapshot file
+pub const SNAPSHOT_MAGIC: [u8; 8] = *b"TCHYPOOL";
+
+/// Bumped whenever the body layout changes; other versions are rejected
+pub const SNAPSHOT_VERSION: u16 = 2;
+
+/// Magic, version and checksum
+const HEADER_LEN: usize = 8 + 2 + 32;
+
+#[derive(Debug, thiserror::Error)]
+pub enum SnapshotError {
+    #[error("Snapshot I/O failed: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Not a pool snapshot")]
+    BadMagic,
+    #[error("Unsupported snapshot version {0}, expected {SNAPSHOT_VERSION}")]
+    UnsupportedVersion(u16),
+    #[error("Snapshot checksum mismatch")]
+    ChecksumMismatch,
+    #[error("Failed to decode snapshot: {0}")]
+    Decode(String),
+}
+
+/// Raw state of an account a pool depends on
+#[derive(Debug, Clone, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
+pub struct PoolAccount {
+    pub owner: Pubkey,
+    /// Slot the data was observed at
+    pub slot: u64,
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq, BorshSerialize, BorshDeserialize)]
+pub struct PoolSnapshot {
+    /// Slot the registry was current as of
+    pub slot: u64,
+    pub written_at_unix_secs: u64,
+    pub pools: Vec<Pool>,
+    pub accounts: Vec<(Pubkey, PoolAccount)>,
+}
+
+impl PoolSnapshot {
+    pub fn new(slot: u64, pools: Vec<Pool>, accounts: Vec<(Pubkey, PoolAccount)>) -> Self {
+        Self {
+            slot,
+            written_at_unix_secs: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |elapsed| elapsed.as_secs()),
+            pools,
+            accounts,
+        }
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, SnapshotError> {
+        let body = borsh::to_vec(self).map_err(|e| SnapshotError::Decode(e.to_string()))?;
+        let mut bytes = Vec::with_capacity(HEADER_LEN + body.len());
+        bytes.extend_from_slice(&SNAPSHOT_MAGIC);
+        bytes.extend_from_slice(&SNAPSHOT_VERSION.to_le_bytes());
+        bytes.extend_from_slice(hash(&body).as_ref());
+        bytes.extend_from_slice(&body);
+        Ok(bytes)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, SnapshotError> {
+        if bytes.len() < HEADER_LEN || bytes[..8] != SNAPSHOT_MAGIC {
+            return Err(SnapshotError::BadMagic);
+        }
+        let version = u16::from_le_bytes([bytes[8], bytes[9]]);
+        if version != SNAPSHOT_VERSION {
+            return Err(SnapshotError::UnsupportedVersion(version));
+        }
+        let body = &bytes[HEADER_LEN..];
+        if hash(body).as_ref() != &bytes[10..HEADER_LEN] {
+            return Err(SnapshotError::ChecksumMismatch);
+        }
+        Self::try_from_slice(body).map_err(|e| SnapshotError::Decode(e.to_string()))
+    }
+
+    /// Atomically replace the snapshot at `path`
+    pub fn write(&self, path: &Path) -> Result<(), SnapshotError> {
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir)?;
+        }
+        let tmp_path = path.with_extension("tmp");
+        std::fs::write(&tmp_path, self.to_bytes()?)?;
+        std::fs::rename(&tmp_path, path)?;
+        debug!("Wrote pool snapshot at slot {} to {}", self.slot, path.display());
+        Ok(())
+    }
+
+    pub fn read(path: &Path) -> Result<Self, SnapshotError> {
+        Self::from_bytes(&std::fs::read(path)?)
+    }
+
+    /// Slots elapsed between the snapshot and `current_slot`
+    pub fn age_slots(&self, current_slot: u64) -> u64 {
+        current_slot.saturating_sub(self.slot)
+    }
+
+    pub fn is_fresh(&self, current_slot: u64, max_age_slots: u64) -> bool {
+        self.age_slots(current_slot) <= max_age_slots
+    }
+
+    /// Feed every stored account to the markets registered on `router`. Returns the
+    /// number of accounts a decoder recognized; accounts that fail to decode are skipped.
+    pub async fn replay(&self, router: &AccountUpdateRouter) -> usize {
+        let mut applied = 0;
+        for (address, account) in &self.accounts {
+            let version = DataVersion::at_slot(account.slot);
+            match router.apply_account_update(address, &account.owner, &account.data, version).await {
+                Ok(Some(_)) => applied += 1,
+                Ok(None) => {}
+                Err(e) => debug!("Skipping snapshot account {}: {}", address, e),
+            }
+        }
+        applied
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::markets::pools::{PoolState, PoolToken};
+    use crate::markets::types::DexLabel;
+
+    fn sample_snapshot() -> PoolSnapshot {
+        let address = Pubkey::new_unique();
+        let mut pool = Pool::new(
+            format!("raydium_{}", address),
+            Some(DexLabel::Raydium),
+            Some(address),
+            PoolToken::new(Pubkey::new_unique(), Some(9)),
+            PoolToken::new(Pubkey::new_unique(), Some(6)),
+            125_000.5,
+        );
+        pool.state = PoolState::Reserves { reserve_a: 10, reserve_b: 1_500 };
+        pool.last_update_slot = 990;
+        let account = PoolAccount {
+            owner: Pubkey::new_unique(),
+            slot: 1_000,
+            data: vec![7; 752],
+        };
+        PoolSnapshot::new(1_000, vec![pool], vec![(Pubkey::new_unique(), account)])
+    }
+
+    #[test]
+    fn test_snapshot_round_trip() {
+        let snapshot = sample_snapshot();
+        let bytes = snapshot.to_bytes().unwrap();
+        assert_eq!(PoolSnapshot::from_bytes(&bytes).unwrap(), snapshot);
+
+        let path = std::env::temp_dir().join(format!("pool_snapshot_{}.bin", Pubkey::new_unique()));
+        snapshot.write(&path).unwrap();
+        assert_eq!(PoolSnapshot::read(&path).unwrap(), snapshot);
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(snapshot.is_fresh(1_500, 500));
+        assert!(!snapshot.is_fresh(1_501, 500));
+        assert_eq!(snapshot.age_slots(900), 0);
+    }
+
+    #[test]
+    fn test_snapshot_rejects_foreign_data() {
+        let bytes = sample_snapshot().to_bytes().unwrap();
+        assert!(matches!(PoolSnapshot::from_bytes(&bytes[..20]), Err(SnapshotError::BadMagic)));
+
+        let mut other_version = bytes.clone();
+        other_version[8] = 1;
+        assert!(matches!(
+            PoolSnapshot::from_bytes(&other_version),
+            Err(SnapshotError::UnsupportedVersion(1))
+        ));
+
+        let mut corrupted = bytes;
+        *corrupted.last_mut().unwrap() ^= 1;
+        assert!(matches!(PoolSnapshot::from_bytes(&corrupted), Err(SnapshotError::ChecksumMismatch)));
+    }
+}
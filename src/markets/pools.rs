@@ -1,38 +1,385 @@
 //! src/markets/pools.rs
 
 use crate::common::config::Config;
+use crate::markets::account_decoders::{AccountDecoderRegistry, PoolAccountState};
+use crate::markets::discovery::{DiscoveryReport, PoolDiscovery};
+use crate::markets::pool_snapshot::{PoolAccount, PoolSnapshot, SnapshotError};
+use crate::markets::raydium_amm::RAYDIUM_AMM_V4_PROGRAM_ID;
+use crate::markets::raydium_cpmm::RAYDIUM_CPMM_PROGRAM_ID;
+use crate::markets::token_registry::TokenRegistry;
+use crate::markets::types::DexLabel;
+use crate::transactions::raydium_clmm_swap::RAYDIUM_CLMM_PROGRAM_ID;
 use anyhow::Result;
+use borsh::{BorshDeserialize, BorshSerialize};
 use dashmap::DashMap;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::pubkey::Pubkey;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
+use tokio::sync::broadcast;
 use tracing::{info, error, warn};
 
-#[derive(Debug, Clone)]
+/// How long boot waits for the current slot before trusting a snapshot unchecked
+const SLOT_FETCH_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Pool events buffered per subscriber before it starts lagging
+const POOL_EVENT_CAPACITY: usize = 4096;
+
+/// Tokens every pool load covers, on top of the configured strategy tokens
+const TARGET_TOKENS: [&str; 4] = [
+    "So11111111111111111111111111111111111111112", // SOL
+    "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v", // USDC
+    "Es9vMFrzaCERmJfrF4H2FYD4KCoNkY11McCe8BenwNYB", // USDT
+    "7vfCXTUXx5WJV5JADk17DUJ4ksgau7utNKj4b963voxs", // ETH (Wormhole)
+];
+
+/// One side of a pool
+#[derive(Debug, Clone, Copy, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
+pub struct PoolToken {
+    pub mint: Pubkey,
+    /// `None` until read from a DEX API or the chain
+    pub decimals: Option<u8>,
+}
+
+impl PoolToken {
+    pub fn new(mint: Pubkey, decimals: Option<u8>) -> Self {
+        Self { mint, decimals }
+    }
+}
+
+/// Pricing state of a pool, in raw token units
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
+pub enum PoolState {
+    /// Not read yet: Jupiter routes, and pools whose reserves live in vaults
+    #[default]
+    Unknown,
+    /// Constant-product vault balances
+    Reserves { reserve_a: u64, reserve_b: u64 },
+    /// Concentrated liquidity at the current tick, price of A in B as Q64.64
+    Concentrated { sqrt_price_x64: u128, liquidity: u128, tick_current: i32 },
+    /// Meteora DLMM active bin; the price of A in B is `(1 + bin_step / 10_000)^active_id`
+    Bins { active_id: i32, bin_step: u16 },
+}
+
+impl PoolState {
+    /// Price of one raw unit of A in raw units of B
+    fn raw_price(&self) -> Option<f64> {
+        match *self {
+            Self::Unknown => None,
+            Self::Reserves { reserve_a, reserve_b } => {
+                (reserve_a > 0).then(|| reserve_b as f64 / reserve_a as f64)
+            }
+            Self::Concentrated { sqrt_price_x64, .. } => {
+                let sqrt_price = sqrt_price_x64 as f64 / 2f64.powi(64);
+                Some(sqrt_price * sqrt_price)
+            }
+            Self::Bins { active_id, bin_step } => Some((1.0 + bin_step as f64 / 10_000.0).powi(active_id)),
+        }
+    }
+}
+
+/// Pool attributes read from its on-chain account
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OnChainPool {
+    pub decimals: [Option<u8>; 2],
+    pub fee_bps: Option<u16>,
+    pub state: PoolState,
+}
+
+impl OnChainPool {
+    /// What the pool account itself holds. Constant-product reserves live in the vaults
+    /// and fees of CLMM and CPMM pools in their config accounts, so those stay unknown.
+    pub fn from_account_state(state: &PoolAccountState) -> Self {
+        match state {
+            PoolAccountState::RaydiumAmm(amm) => {
+                let fees = &amm.fees;
+                let (numerator, denominator) = if fees.swap_fee_denominator != 0 {
+                    (fees.swap_fee_numerator, fees.swap_fee_denominator)
+                } else {
+                    (fees.trade_fee_numerator, fees.trade_fee_denominator)
+                };
+                Self {
+                    decimals: [u8::try_from(amm.coin_decimals).ok(), u8::try_from(amm.pc_decimals).ok()],
+                    fee_bps: fee_bps(numerator, denominator),
+                    state: PoolState::Unknown,
+                }
+            }
+            PoolAccountState::RaydiumClmmPool(pool) => Self {
+                decimals: [Some(pool.mint_decimals_0), Some(pool.mint_decimals_1)],
+                fee_bps: None,
+                state: PoolState::Concentrated {
+                    sqrt_price_x64: pool.sqrt_price_x64,
+                    liquidity: pool.liquidity,
+                    tick_current: pool.tick_current,
+                },
+            },
+            PoolAccountState::RaydiumCpmmPool(pool) => Self {
+                decimals: [Some(pool.mint_0_decimals), Some(pool.mint_1_decimals)],
+                ..Self::default()
+            },
+            PoolAccountState::OrcaTokenSwap(swap) => Self {
+                fee_bps: fee_bps(swap.trade_fee_numerator, swap.trade_fee_denominator),
+                ..Self::default()
+            },
+            PoolAccountState::Whirlpool(whirlpool) => Self {
+                // Hundredths of a basis point
+                fee_bps: Some(whirlpool.fee_rate / 100),
+                state: PoolState::Concentrated {
+                    sqrt_price_x64: whirlpool.sqrt_price,
+                    liquidity: whirlpool.liquidity,
+                    tick_current: whirlpool.tick_current_index,
+                },
+                ..Self::default()
+            },
+            PoolAccountState::MeteoraLbPair(lb_pair) => Self {
+                // Base fee only; the variable fee depends on recent volatility
+                fee_bps: u16::try_from(lb_pair.parameters.base_factor as u64 * lb_pair.bin_step as u64 / 10_000).ok(),
+                state: PoolState::Bins {
+                    active_id: lb_pair.active_id,
+                    bin_step: lb_pair.bin_step,
+                },
+                ..Self::default()
+            },
+            _ => Self::default(),
+        }
+    }
+}
+
+fn fee_bps(numerator: u64, denominator: u64) -> Option<u16> {
+    if denominator == 0 {
+        return None;
+    }
+    u16::try_from(numerator as u128 * 10_000 / denominator as u128).ok()
+}
+
+/// Fee assumed for a venue until the pool's own fee is known
+pub fn default_fee_bps(dex: Option<&DexLabel>) -> u16 {
+    match dex {
+        Some(DexLabel::Orca | DexLabel::OrcaWhirlpools) => 30,
+        Some(_) => 25,
+        // Jupiter quotes are net of the fees of the pools they route through
+        None => 0,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, BorshSerialize, BorshDeserialize)]
 pub struct Pool {
     pub id: String,
-    pub token_a: String,
-    pub token_b: String,
+    /// `None` for Jupiter routes, which aren't pools and span several venues
+    pub dex: Option<DexLabel>,
+    /// On-chain pool or market account; `None` for Jupiter routes
+    pub address: Option<Pubkey>,
+    pub token_a: PoolToken,
+    pub token_b: PoolToken,
+    pub fee_bps: u16,
+    pub state: PoolState,
+    /// TVL in USD as reported by the DEX API, zero when unknown
     pub liquidity: f64,
+    /// Slot `state` was read at, zero if it never was
+    pub last_update_slot: u64,
+}
+
+impl Pool {
+    /// A pool with the venue's default fee and no state read yet
+    pub fn new(
+        id: String,
+        dex: Option<DexLabel>,
+        address: Option<Pubkey>,
+        token_a: PoolToken,
+        token_b: PoolToken,
+        liquidity: f64,
+    ) -> Self {
+        Self {
+            fee_bps: default_fee_bps(dex.as_ref()),
+            id,
+            dex,
+            address,
+            token_a,
+            token_b,
+            state: PoolState::Unknown,
+            liquidity,
+            last_update_slot: 0,
+        }
+    }
+
+    pub fn has_mint(&self, mint: &Pubkey) -> bool {
+        self.token_a.mint == *mint || self.token_b.mint == *mint
+    }
+
+    /// Whether the pool trades `a` against `b`, in either order
+    pub fn connects(&self, a: &Pubkey, b: &Pubkey) -> bool {
+        (self.token_a.mint == *a && self.token_b.mint == *b) || (self.token_a.mint == *b && self.token_b.mint == *a)
+    }
+
+    /// Mid price of one whole `mint` token in whole tokens of the other side, before
+    /// fees. `None` until both decimals and the state are known.
+    pub fn price_of(&self, mint: &Pubkey) -> Option<f64> {
+        let raw_price = self.state.raw_price()?;
+        let decimals_a = self.token_a.decimals? as i32;
+        let decimals_b = self.token_b.decimals? as i32;
+        let price_a_in_b = raw_price * 10f64.powi(decimals_a - decimals_b);
+        if !price_a_in_b.is_finite() || price_a_in_b <= 0.0 {
+            return None;
+        }
+        if *mint == self.token_a.mint {
+            Some(price_a_in_b)
+        } else if *mint == self.token_b.mint {
+            Some(1.0 / price_a_in_b)
+        } else {
+            None
+        }
+    }
+
+    /// TVL in USD, `None` when the DEX API didn't report it, as for pools found on chain
+    pub fn liquidity_usd(&self) -> Option<f64> {
+        (self.liquidity > 0.0).then_some(self.liquidity)
+    }
+
+    /// Whole `mint` tokens in the pool's reserves. `None` unless the state is
+    /// constant-product reserves and the mint's decimals are known.
+    pub fn reserve_of(&self, mint: &Pubkey) -> Option<f64> {
+        let PoolState::Reserves { reserve_a, reserve_b } = self.state else {
+            return None;
+        };
+        let (reserve, decimals) = if *mint == self.token_a.mint {
+            (reserve_a, self.token_a.decimals?)
+        } else if *mint == self.token_b.mint {
+            (reserve_b, self.token_b.decimals?)
+        } else {
+            return None;
+        };
+        Some(reserve as f64 / 10f64.powi(decimals as i32))
+    }
+
+    /// Share of the input left after the swap fee
+    pub fn fee_multiplier(&self) -> f64 {
+        1.0 - self.fee_bps as f64 / 10_000.0
+    }
+
+    /// Whether `other` lists the same pool, whatever state either was last seen in
+    fn same_listing(&self, other: &Pool) -> bool {
+        Pool { state: other.state, last_update_slot: other.last_update_slot, ..self.clone() } == *other
+    }
+
+    /// Fill in what the pool's on-chain account says as of `slot`. Decimals only fill
+    /// gaps; fee and state replace the API's view. The pool is only dated `slot` when
+    /// the account gave its state, so API reserves never pass for fresh ones.
+    pub fn apply_on_chain(&mut self, on_chain: &OnChainPool, slot: u64) {
+        let [decimals_a, decimals_b] = on_chain.decimals;
+        self.token_a.decimals = self.token_a.decimals.or(decimals_a);
+        self.token_b.decimals = self.token_b.decimals.or(decimals_b);
+        if let Some(fee_bps) = on_chain.fee_bps {
+            self.fee_bps = fee_bps;
+        }
+        if on_chain.state != PoolState::Unknown {
+            self.state = on_chain.state;
+            self.last_update_slot = slot;
+        }
+    }
+}
+
+#[cfg(test)]
+impl Pool {
+    /// A Raydium pool at a fresh address holding `reserve_a` and `reserve_b` of two
+    /// 6-decimal tokens, with $2M of TVL
+    pub(crate) fn with_reserves(token_a: Pubkey, token_b: Pubkey, reserve_a: u64, reserve_b: u64) -> Self {
+        let mut pool = Pool::new(
+            format!("raydium_{}", Pubkey::new_unique()),
+            Some(DexLabel::Raydium),
+            Some(Pubkey::new_unique()),
+            PoolToken::new(token_a, Some(6)),
+            PoolToken::new(token_b, Some(6)),
+            2_000_000.0,
+        );
+        pool.state = PoolState::Reserves { reserve_a, reserve_b };
+        pool
+    }
+}
+
+/// A change to the registry's pool set, broadcast by `PoolRegistry::refresh` and
+/// `PoolRegistry::apply_account_update`
+#[derive(Debug, Clone, PartialEq)]
+#[allow(clippy::enum_variant_names)]
+pub enum PoolEvent {
+    PoolAdded(Pool),
+    PoolRemoved(Pool),
+    PoolChanged { previous: Pool, current: Pool },
+}
+
+/// Counts of the events one refresh produced
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PoolDiff {
+    pub added: usize,
+    pub removed: usize,
+    pub changed: usize,
 }
 
 pub struct PoolRegistry {
     pools: Arc<DashMap<String, Pool>>,
+    /// Pool ids by on-chain address, for routing account updates
+    ids_by_address: Arc<DashMap<Pubkey, String>>,
+    /// Pool addresses by vault address, for tying vault balance updates back to their
+    /// pool
+    pools_by_vault: Arc<DashMap<Pubkey, Pubkey>>,
+    /// Raw pool, vault, tick and bin accounts, persisted in snapshots
+    accounts: Arc<DashMap<Pubkey, PoolAccount>>,
+    /// Slot the registry is current as of
+    slot: Arc<AtomicU64>,
     last_updated: Arc<tokio::sync::RwLock<Instant>>,
     config: Arc<Config>,
+    /// On-chain discovery run alongside the DEX APIs, unless disabled
+    discovery: Option<PoolDiscovery>,
+    /// Resolves the decimals neither the DEX APIs nor the pool accounts gave
+    tokens: Option<Arc<TokenRegistry>>,
+    decoders: AccountDecoderRegistry,
+    events: broadcast::Sender<PoolEvent>,
 }
 
 impl PoolRegistry {
-    pub async fn new(config: &Config) -> Result<Self> {
-        let pools = load_all_pools(config).await?;
-        let pool_map = DashMap::new();
-        for pool in pools {
-            pool_map.insert(pool.id.clone(), pool);
-        }
-        Ok(Self {
-            pools: Arc::new(pool_map),
+    /// Warm-start from the pool snapshot when it is fresh, otherwise load pools from the
+    /// DEX APIs. Without an RPC connection the snapshot is used however old it is.
+    pub async fn new(config: &Config, tokens: Arc<TokenRegistry>) -> Result<Self> {
+        let registry = Self {
+            tokens: Some(tokens),
+            ..Self::empty(config)
+        };
+        let current_slot = fetch_current_slot(config).await;
+
+        if let Some(snapshot) = registry.load_snapshot(current_slot) {
+            registry.restore(snapshot);
+            return Ok(registry);
+        }
+
+        registry.refresh().await?;
+        Ok(registry)
+    }
+
+    fn empty(config: &Config) -> Self {
+        let discovery = config.pool_discovery_enabled.unwrap_or(true).then(|| {
+            PoolDiscovery::new(
+                RpcClient::new_with_commitment(config.rpc_url.clone(), CommitmentConfig::confirmed()),
+                config.pool_discovery_requests_per_sec.unwrap_or(5),
+                config.pool_discovery_max_concurrent.unwrap_or(4),
+            )
+        });
+        Self {
+            pools: Arc::new(DashMap::new()),
+            ids_by_address: Arc::new(DashMap::new()),
+            pools_by_vault: Arc::new(DashMap::new()),
+            accounts: Arc::new(DashMap::new()),
+            slot: Arc::new(AtomicU64::new(0)),
             last_updated: Arc::new(tokio::sync::RwLock::new(Instant::now())),
             config: Arc::new(config.clone()),
-        })
+            discovery,
+            tokens: None,
+            decoders: AccountDecoderRegistry::with_known_programs(),
+            events: broadcast::channel(POOL_EVENT_CAPACITY).0,
+        }
     }
 
     pub async fn get_pools(&self, force_update: bool) -> Result<Vec<Pool>> {
@@ -43,18 +390,98 @@ impl PoolRegistry {
         Ok(self.pools.iter().map(|e| e.value().clone()).collect())
     }
 
+    /// Events for every pool added, removed or changed by later refreshes and account
+    /// updates
+    pub fn subscribe(&self) -> broadcast::Receiver<PoolEvent> {
+        self.events.subscribe()
+    }
+
     async fn refresh(&self) -> Result<()> {
-        let new_pools = load_all_pools(&self.config).await?;
-        self.pools.clear();
-        for pool in new_pools {
-            self.pools.insert(pool.id.clone(), pool);
+        let mut next: HashMap<String, Pool> = load_all_pools(&self.config)
+            .await?
+            .into_iter()
+            .map(|pool| (pool.id.clone(), pool))
+            .collect();
+        match self.discover().await {
+            Some(report) => {
+                self.merge_discovered(&mut next, report);
+            }
+            None => {
+                if let Some(slot) = fetch_current_slot(&self.config).await {
+                    self.observe_slot(slot);
+                }
+            }
         }
+        self.resolve_decimals(&mut next).await;
+
         let mut last_updated = self.last_updated.write().await;
         *last_updated = Instant::now();
-        info!("Refreshed {} pools", self.pools.len());
+        if next.is_empty() && !self.pools.is_empty() {
+            // Every source failing is an outage, not every pool closing
+            warn!("Pool refresh found no pools, keeping the current {}", self.pools.len());
+            return Ok(());
+        }
+        let diff = self.apply(next);
+        info!(
+            "Refreshed {} pools: {} added, {} removed, {} changed",
+            self.pools.len(),
+            diff.added,
+            diff.removed,
+            diff.changed
+        );
         Ok(())
     }
 
+    /// Make `next` the pool set, updating entries in place so readers never see a
+    /// partial registry, and broadcast a `PoolEvent` per difference. A pool's state is
+    /// kept when the registry's copy was observed at a later slot than `next`'s, so
+    /// streamed updates survive a refresh; state alone never counts as a change here.
+    fn apply(&self, mut next: HashMap<String, Pool>) -> PoolDiff {
+        let mut diff = PoolDiff::default();
+        let mut events = Vec::new();
+
+        self.pools.retain(|id, pool| {
+            if next.contains_key(id) {
+                return true;
+            }
+            if let Some(address) = pool.address {
+                self.ids_by_address.remove(&address);
+                self.pools_by_vault.retain(|_, pool_address| *pool_address != address);
+            }
+            events.push(PoolEvent::PoolRemoved(pool.clone()));
+            diff.removed += 1;
+            false
+        });
+        for (id, mut pool) in next.drain() {
+            if let Some(address) = pool.address {
+                self.ids_by_address.insert(address, id.clone());
+            }
+            let Some(mut current) = self.pools.get_mut(&id) else {
+                self.pools.insert(id, pool.clone());
+                events.push(PoolEvent::PoolAdded(pool));
+                diff.added += 1;
+                continue;
+            };
+            let previous = current.clone();
+            if previous.last_update_slot > pool.last_update_slot {
+                pool.state = previous.state;
+                pool.last_update_slot = previous.last_update_slot;
+            }
+            *current = pool.clone();
+            drop(current);
+            if !previous.same_listing(&pool) {
+                events.push(PoolEvent::PoolChanged { previous, current: pool });
+                diff.changed += 1;
+            }
+        }
+
+        for event in events {
+            // Only fails when nobody is subscribed
+            let _ = self.events.send(event);
+        }
+        diff
+    }
+
     pub fn len(&self) -> usize {
         self.pools.len()
     }
@@ -62,6 +489,399 @@ impl PoolRegistry {
     pub fn is_empty(&self) -> bool {
         self.len() == 0
     }
+
+    /// The registered pools, without refreshing
+    pub fn pools(&self) -> Vec<Pool> {
+        self.pools.iter().map(|entry| entry.value().clone()).collect()
+    }
+
+    pub fn slot(&self) -> u64 {
+        self.slot.load(Ordering::Relaxed)
+    }
+
+    fn observe_slot(&self, slot: u64) {
+        self.slot.fetch_max(slot, Ordering::Relaxed);
+    }
+
+    /// Store the raw state of an account a pool depends on. Returns false, leaving the
+    /// stored copy, when it was observed at a later slot than `slot`.
+    pub fn record_account(&self, address: Pubkey, owner: Pubkey, slot: u64, data: Vec<u8>) -> bool {
+        let mut entry = self.accounts.entry(address).or_insert_with(|| PoolAccount {
+            owner,
+            slot: 0,
+            data: Vec::new(),
+        });
+        if entry.slot > slot {
+            return false;
+        }
+        *entry = PoolAccount { owner, slot, data };
+        drop(entry);
+        self.observe_slot(slot);
+        true
+    }
+
+    pub fn account(&self, address: &Pubkey) -> Option<PoolAccount> {
+        self.accounts.get(address).map(|entry| entry.value().clone())
+    }
+
+    /// The registered pool whose account is at `address`
+    pub fn pool_at(&self, address: &Pubkey) -> Option<Pool> {
+        let id = self.ids_by_address.get(address)?.value().clone();
+        self.pools.get(&id).map(|entry| entry.value().clone())
+    }
+
+    /// Whether `address` is the account of a registered pool
+    pub fn is_pool_address(&self, address: &Pubkey) -> bool {
+        self.ids_by_address.contains_key(address)
+    }
+
+    /// Whether updates of the account at `address` are applied: a registered pool's
+    /// account or one of its vaults
+    pub fn tracks(&self, address: &Pubkey) -> bool {
+        self.is_pool_address(address) || self.pools_by_vault.contains_key(address)
+    }
+
+    /// Vaults holding the reserves of the pool whose account is at `address`, as its
+    /// stored account lists them; empty for pools that keep their state in the pool
+    /// account
+    pub fn vaults_of(&self, address: &Pubkey) -> Vec<Pubkey> {
+        self.decode_stored(address).map(|state| state.vaults()).unwrap_or_default()
+    }
+
+    /// Apply a streamed update of a pool's account or of one of its vaults: store it,
+    /// fold the pool's decoded state and vault reserves into the pool and broadcast
+    /// `PoolChanged` when the pool's view moved
+    pub fn apply_account_update(&self, address: Pubkey, owner: Pubkey, slot: u64, data: Vec<u8>) -> Option<PoolEvent> {
+        if let Some(pool_address) = self.pools_by_vault.get(&address).map(|entry| *entry.value()) {
+            return self.apply_vault_update(pool_address, address, owner, slot, data);
+        }
+        let id = self.ids_by_address.get(&address)?.value().clone();
+        let state = match self.decoders.decode(&owner, &data) {
+            Ok(Some(state)) if state.is_pool_account() => state,
+            Ok(_) => return None,
+            Err(e) => {
+                warn!("Failed to decode pool account {}: {}", address, e);
+                return None;
+            }
+        };
+        if !self.record_account(address, owner, slot, data) {
+            return None;
+        }
+        self.index_vaults(address, &state);
+
+        let mut on_chain = OnChainPool::from_account_state(&state);
+        self.update_pool(&id, |pool| {
+            if on_chain.state == PoolState::Unknown {
+                on_chain.state = self.vault_reserves(pool, &state).unwrap_or(PoolState::Unknown);
+            }
+            pool.apply_on_chain(&on_chain, slot);
+        })
+    }
+
+    /// Apply a balance update of `vault`, a vault of the pool at `pool_address`
+    fn apply_vault_update(&self, pool_address: Pubkey, vault: Pubkey, owner: Pubkey, slot: u64, data: Vec<u8>) -> Option<PoolEvent> {
+        match self.decoders.decode(&owner, &data) {
+            Ok(Some(PoolAccountState::TokenAccount { .. })) => {}
+            Ok(_) => return None,
+            Err(e) => {
+                warn!("Failed to decode vault {} of pool {}: {}", vault, pool_address, e);
+                return None;
+            }
+        }
+        if !self.record_account(vault, owner, slot, data) {
+            return None;
+        }
+        let id = self.ids_by_address.get(&pool_address)?.value().clone();
+        let state = self.decode_stored(&pool_address)?;
+        self.update_pool(&id, |pool| {
+            if let Some(reserves) = self.vault_reserves(pool, &state) {
+                pool.state = reserves;
+                pool.last_update_slot = pool.last_update_slot.max(slot);
+            }
+        })
+    }
+
+    /// Change the pool `id` with `update` and broadcast `PoolChanged` when more than its
+    /// slot moved
+    fn update_pool(&self, id: &str, update: impl FnOnce(&mut Pool)) -> Option<PoolEvent> {
+        let mut pool = self.pools.get_mut(id)?;
+        let previous = pool.clone();
+        update(&mut pool);
+        let current = pool.clone();
+        drop(pool);
+
+        let unchanged = Pool { last_update_slot: previous.last_update_slot, ..current.clone() };
+        if unchanged == previous {
+            return None;
+        }
+        let event = PoolEvent::PoolChanged { previous, current };
+        // Only fails when nobody is subscribed
+        let _ = self.events.send(event.clone());
+        Some(event)
+    }
+
+    /// Decoded state of the stored account at `address`
+    fn decode_stored(&self, address: &Pubkey) -> Option<PoolAccountState> {
+        let account = self.accounts.get(address)?;
+        self.decoders.decode(&account.owner, &account.data).ok().flatten()
+    }
+
+    /// Route updates of the vaults of the pool at `address` to it
+    fn index_vaults(&self, address: Pubkey, state: &PoolAccountState) {
+        for vault in state.vaults() {
+            self.pools_by_vault.insert(vault, address);
+        }
+    }
+
+    /// Tradable reserves of `pool`, whose account decodes to `state`, when they sit in
+    /// its vaults: the stored vault balances minus what the program holds back from
+    /// swaps. `None` until both vaults have been stored.
+    fn vault_reserves(&self, pool: &Pool, state: &PoolAccountState) -> Option<PoolState> {
+        let [vault_a, vault_b] = state.vaults()[..] else {
+            return None;
+        };
+        let balance = |vault: &Pubkey| match self.decode_stored(vault)? {
+            PoolAccountState::TokenAccount { mint, amount } => Some((mint, amount)),
+            _ => None,
+        };
+        let ((mint_a, balance_a), (_, balance_b)) = (balance(&vault_a)?, balance(&vault_b)?);
+        let (held_a, held_b) = match state {
+            PoolAccountState::RaydiumAmm(amm) => {
+                (amm.state_data.need_take_pnl_coin, amm.state_data.need_take_pnl_pc)
+            }
+            PoolAccountState::RaydiumCpmmPool(pool) => (
+                pool.protocol_fees_token_0.saturating_add(pool.fund_fees_token_0),
+                pool.protocol_fees_token_1.saturating_add(pool.fund_fees_token_1),
+            ),
+            _ => (0, 0),
+        };
+        let (reserve_a, reserve_b) = (balance_a.saturating_sub(held_a), balance_b.saturating_sub(held_b));
+        // The registry's sides follow the API, which may list the pool's mints reversed
+        if mint_a == pool.token_b.mint && mint_a != pool.token_a.mint {
+            return Some(PoolState::Reserves { reserve_a: reserve_b, reserve_b: reserve_a });
+        }
+        Some(PoolState::Reserves { reserve_a, reserve_b })
+    }
+
+    async fn discover(&self) -> Option<DiscoveryReport> {
+        let discovery = self.discovery.as_ref()?;
+        match discovery.discover(&discovery_mints(&self.config)).await {
+            Ok(report) => Some(report),
+            Err(e) => {
+                warn!("On-chain pool discovery failed: {}", e);
+                None
+            }
+        }
+    }
+
+    /// Fill in the decimals `pools` are missing from the token registry. Cached
+    /// entries are used however old, since decimals never change; mints that can't be
+    /// resolved are left unknown.
+    async fn resolve_decimals(&self, pools: &mut HashMap<String, Pool>) {
+        let Some(tokens) = &self.tokens else {
+            return;
+        };
+        let mut missing: Vec<Pubkey> = pools
+            .values()
+            .flat_map(|pool| [pool.token_a, pool.token_b])
+            .filter(|token| token.decimals.is_none() && tokens.cached(&token.mint).is_none())
+            .map(|token| token.mint)
+            .collect();
+        missing.sort_unstable();
+        missing.dedup();
+        if !missing.is_empty() {
+            if let Err(e) = tokens.get_many(&missing).await {
+                warn!("Failed to resolve decimals of {} pool mints: {}", missing.len(), e);
+            }
+        }
+        for pool in pools.values_mut() {
+            for token in [&mut pool.token_a, &mut pool.token_b] {
+                if token.decimals.is_none() {
+                    token.decimals = tokens.cached(&token.mint).map(|metadata| metadata.decimals);
+                }
+            }
+        }
+    }
+
+    /// Add discovered pools not already known from the DEX APIs to `pools`, fill the
+    /// known ones in with their on-chain state, and store their accounts. Returns the
+    /// number of pools added. TVL isn't known on-chain, so new pools start with zero
+    /// liquidity, which `Pool::liquidity_usd` reports as unknown.
+    fn merge_discovered(&self, pools: &mut HashMap<String, Pool>, report: DiscoveryReport) -> usize {
+        let known: HashMap<Pubkey, String> = pools
+            .values()
+            .filter_map(|pool| Some((pool.address?, pool.id.clone())))
+            .collect();
+        let mut added = 0;
+        for discovered in report.pools {
+            let on_chain = discovered.on_chain;
+            match known.get(&discovered.address).and_then(|id| pools.get_mut(id)) {
+                Some(pool) => pool.apply_on_chain(&on_chain, report.slot),
+                None => {
+                    let mut pool = Pool::new(
+                        discovered.pool_id(),
+                        Some(discovered.dex.clone()),
+                        Some(discovered.address),
+                        PoolToken::new(discovered.mint_a, None),
+                        PoolToken::new(discovered.mint_b, None),
+                        0.0,
+                    );
+                    pool.apply_on_chain(&on_chain, report.slot);
+                    pools.insert(pool.id.clone(), pool);
+                    added += 1;
+                }
+            }
+            if let Ok(Some(state)) = self.decoders.decode(&discovered.owner, &discovered.data) {
+                self.index_vaults(discovered.address, &state);
+            }
+            self.record_account(discovered.address, discovered.owner, report.slot, discovered.data);
+        }
+        self.observe_slot(report.slot);
+        info!("Merged {} new on-chain pools into the pool set", added);
+        added
+    }
+
+    pub fn snapshot(&self) -> PoolSnapshot {
+        PoolSnapshot::new(
+            self.slot(),
+            self.pools.iter().map(|e| e.value().clone()).collect(),
+            self.accounts.iter().map(|e| (*e.key(), e.value().clone())).collect(),
+        )
+    }
+
+    fn snapshot_path(&self) -> PathBuf {
+        PathBuf::from(
+            self.config
+                .pool_snapshot_path
+                .as_deref()
+                .unwrap_or("output/pool_snapshot.bin"),
+        )
+    }
+
+    pub fn write_snapshot(&self) -> Result<(), SnapshotError> {
+        let snapshot = self.snapshot();
+        snapshot.write(&self.snapshot_path())?;
+        info!(
+            "💾 Pool snapshot written: {} pools, {} accounts at slot {}",
+            snapshot.pools.len(),
+            snapshot.accounts.len(),
+            snapshot.slot
+        );
+        Ok(())
+    }
+
+    /// Write a snapshot every `pool_snapshot_interval_secs`, on the blocking pool so the
+    /// file I/O doesn't stall a runtime worker
+    pub fn spawn_snapshot_writer(self: &Arc<Self>) -> tokio::task::JoinHandle<()> {
+        let registry = Arc::clone(self);
+        let period = Duration::from_secs(self.config.pool_snapshot_interval_secs.unwrap_or(60).max(1));
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(period);
+            interval.tick().await; // The first tick completes immediately
+            loop {
+                interval.tick().await;
+                let writer = Arc::clone(&registry);
+                match tokio::task::spawn_blocking(move || writer.write_snapshot()).await {
+                    Ok(Ok(())) => {}
+                    Ok(Err(e)) => warn!("Failed to write pool snapshot: {}", e),
+                    Err(e) => warn!("Pool snapshot writer panicked: {}", e),
+                }
+            }
+        })
+    }
+
+    fn load_snapshot(&self, current_slot: Option<u64>) -> Option<PoolSnapshot> {
+        let path = self.snapshot_path();
+        let snapshot = match PoolSnapshot::read(&path) {
+            Ok(snapshot) => snapshot,
+            Err(SnapshotError::Io(e)) if e.kind() == std::io::ErrorKind::NotFound => return None,
+            Err(e) => {
+                warn!("Ignoring pool snapshot {}: {}", path.display(), e);
+                return None;
+            }
+        };
+
+        let max_age_slots = self.config.pool_snapshot_max_age_slots.unwrap_or(9_000);
+        match current_slot {
+            Some(current_slot) if !snapshot.is_fresh(current_slot, max_age_slots) => {
+                info!(
+                    "Pool snapshot is {} slots old (max {}), reloading pools",
+                    snapshot.age_slots(current_slot),
+                    max_age_slots
+                );
+                None
+            }
+            Some(current_slot) => {
+                info!(
+                    "⚡ Warm start from pool snapshot {} slots old",
+                    snapshot.age_slots(current_slot)
+                );
+                Some(snapshot)
+            }
+            None => {
+                warn!(
+                    "Current slot unavailable, warm-starting from unverified pool snapshot at slot {}",
+                    snapshot.slot
+                );
+                Some(snapshot)
+            }
+        }
+    }
+
+    fn restore(&self, snapshot: PoolSnapshot) {
+        for pool in snapshot.pools {
+            if let Some(address) = pool.address {
+                self.ids_by_address.insert(address, pool.id.clone());
+            }
+            self.pools.insert(pool.id.clone(), pool);
+        }
+        for (address, account) in snapshot.accounts {
+            self.accounts.insert(address, account);
+        }
+        for entry in self.ids_by_address.iter() {
+            if let Some(state) = self.decode_stored(entry.key()) {
+                self.index_vaults(*entry.key(), &state);
+            }
+        }
+        self.observe_slot(snapshot.slot);
+        info!(
+            "✅ Restored {} pools and {} accounts from snapshot at slot {}",
+            self.pools.len(),
+            self.accounts.len(),
+            snapshot.slot
+        );
+    }
+}
+
+/// `TARGET_TOKENS` and every configured strategy token
+fn discovery_mints(config: &Config) -> Vec<Pubkey> {
+    let configured = config
+        .massive_strategy_inputs
+        .iter()
+        .flat_map(|input| input.tokens_to_arb.iter().map(|token| token.address.as_str()));
+    let mut mints: Vec<Pubkey> = TARGET_TOKENS
+        .into_iter()
+        .chain(configured)
+        .filter_map(|address| Pubkey::from_str(address).ok())
+        .collect();
+    mints.sort();
+    mints.dedup();
+    mints
+}
+
+async fn fetch_current_slot(config: &Config) -> Option<u64> {
+    let client = RpcClient::new_with_timeout(config.rpc_url.clone(), SLOT_FETCH_TIMEOUT);
+    match tokio::time::timeout(SLOT_FETCH_TIMEOUT, client.get_slot()).await {
+        Ok(Ok(slot)) => Some(slot),
+        Ok(Err(e)) => {
+            warn!("Failed to fetch current slot: {}", e);
+            None
+        }
+        Err(_) => {
+            warn!("Timed out fetching current slot");
+            None
+        }
+    }
 }
 
 pub async fn load_all_pools(_config: &Config) -> Result<Vec<Pool>> {
@@ -71,12 +891,7 @@ pub async fn load_all_pools(_config: &Config) -> Result<Vec<Pool>> {
     let mut all_pools = Vec::new();
     
     // Target coins for arbitrage (high volume, good liquidity)
-    let target_tokens = vec![
-        "So11111111111111111111111111111111111111112", // SOL
-        "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v", // USDC
-        "Es9vMFrzaCERmJfrF4H2FYD4KCoNkY11McCe8BenwNYB", // USDT
-        "7vfCXTUXx5WJV5JADk17DUJ4ksgau7utNKj4b963voxs", // ETH (Wormhole)
-    ];
+    let target_tokens = TARGET_TOKENS;
     
     // 1. Fetch Raydium pools
     match fetch_raydium_pools(&client, &target_tokens).await {
@@ -109,6 +924,16 @@ pub async fn load_all_pools(_config: &Config) -> Result<Vec<Pool>> {
     Ok(all_pools)
 }
 
+/// `decimals` of a token object in a DEX API response
+fn api_decimals(token: &serde_json::Value) -> Option<u8> {
+    token.get("decimals").and_then(|v| v.as_u64()).and_then(|decimals| u8::try_from(decimals).ok())
+}
+
+/// Whole-token `amount` in raw units
+fn to_raw_amount(amount: f64, decimals: u8) -> u64 {
+    (amount * 10f64.powi(decimals as i32)) as u64
+}
+
 async fn fetch_raydium_pools(client: &reqwest::Client, target_tokens: &[&str]) -> Result<Vec<Pool>> {
     // Use the correct Raydium V3 API endpoint with proper parameters
     let response = client
@@ -146,12 +971,45 @@ async fn fetch_raydium_pools(client: &reqwest::Client, target_tokens: &[&str]) -
                                    (target_tokens.contains(&mint_a) || target_tokens.contains(&mint_b)) {
                                     
                                     // Create pool with REAL data
-                                    pools.push(Pool {
-                                        id: format!("raydium_{}", id),
-                                        token_a: mint_a.to_string(),
-                                        token_b: mint_b.to_string(),
-                                        liquidity: tvl,
-                                    });
+                                    let (Ok(address), Ok(mint_a_key), Ok(mint_b_key)) =
+                                        (Pubkey::from_str(id), Pubkey::from_str(mint_a), Pubkey::from_str(mint_b))
+                                    else {
+                                        continue;
+                                    };
+                                    let decimals_a = api_decimals(mint_a_obj);
+                                    let decimals_b = api_decimals(mint_b_obj);
+                                    let program_id = pool_data.get("programId").and_then(|v| v.as_str());
+                                    let dex = match program_id {
+                                        Some(RAYDIUM_CLMM_PROGRAM_ID) => DexLabel::RaydiumClmm,
+                                        Some(RAYDIUM_CPMM_PROGRAM_ID) => DexLabel::RaydiumCpmm,
+                                        Some(RAYDIUM_AMM_V4_PROGRAM_ID) | None => DexLabel::Raydium,
+                                        Some(other) => {
+                                            warn!("Skipping Raydium pool {} of unknown program {}", id, other);
+                                            continue;
+                                        }
+                                    };
+                                    let mut pool = Pool::new(
+                                        format!("raydium_{}", id),
+                                        Some(dex.clone()),
+                                        Some(address),
+                                        PoolToken::new(mint_a_key, decimals_a),
+                                        PoolToken::new(mint_b_key, decimals_b),
+                                        tvl,
+                                    );
+                                    if let Some(fee_rate) = pool_data.get("feeRate").and_then(|v| v.as_f64()) {
+                                        pool.fee_bps = (fee_rate * 10_000.0).round() as u16;
+                                    }
+                                    // Concentrated pools report the tokens across all ranges,
+                                    // which aren't reserves; their state comes from discovery
+                                    if dex != DexLabel::RaydiumClmm {
+                                        if let (Some(decimals_a), Some(decimals_b)) = (decimals_a, decimals_b) {
+                                            pool.state = PoolState::Reserves {
+                                                reserve_a: to_raw_amount(amount_a, decimals_a),
+                                                reserve_b: to_raw_amount(amount_b, decimals_b),
+                                            };
+                                        }
+                                    }
+                                    pools.push(pool);
                                     
                                     // Log real pool data for verification
                                     info!("✅ Real Raydium pool: {} -> {} | TVL: ${:.0} | Price: {:.6} | Reserves: A={:.2}, B={:.0}", 
@@ -195,12 +1053,23 @@ async fn fetch_orca_pools(client: &reqwest::Client, target_tokens: &[&str]) -> R
                 ) {
                     if tvl > 50000.0 && 
                        (target_tokens.contains(&token_a) || target_tokens.contains(&token_b)) {
-                        pools.push(Pool {
-                            id: format!("orca_{}", address),
-                            token_a: token_a.to_string(),
-                            token_b: token_b.to_string(),
-                            liquidity: tvl,
-                        });
+                        let (Ok(address_key), Ok(mint_a), Ok(mint_b)) =
+                            (Pubkey::from_str(address), Pubkey::from_str(token_a), Pubkey::from_str(token_b))
+                        else {
+                            continue;
+                        };
+                        let mut pool = Pool::new(
+                            format!("orca_{}", address),
+                            Some(DexLabel::OrcaWhirlpools),
+                            Some(address_key),
+                            PoolToken::new(mint_a, pool_data.get("tokenA").and_then(api_decimals)),
+                            PoolToken::new(mint_b, pool_data.get("tokenB").and_then(api_decimals)),
+                            tvl,
+                        );
+                        if let Some(fee_rate) = pool_data.get("lpFeeRate").and_then(|v| v.as_f64()) {
+                            pool.fee_bps = (fee_rate * 10_000.0).round() as u16;
+                        }
+                        pools.push(pool);
                     }
                 }
             }
@@ -277,12 +1146,17 @@ async fn fetch_jupiter_pools(client: &reqwest::Client, target_tokens: &[&str]) -
                 
                 // Create pool entry if route exists with sufficient liquidity
                 if has_route && best_liquidity > 10000.0 { // Minimum $10K liquidity
-                    pools.push(Pool {
-                        id: format!("jupiter_{}_{}", input_token, output_token),
-                        token_a: input_token.to_string(),
-                        token_b: output_token.to_string(),
-                        liquidity: best_liquidity,
-                    });
+                    let (Ok(mint_in), Ok(mint_out)) = (Pubkey::from_str(input_token), Pubkey::from_str(output_token)) else {
+                        continue;
+                    };
+                    pools.push(Pool::new(
+                        format!("jupiter_{}_{}", input_token, output_token),
+                        None,
+                        None,
+                        PoolToken::new(mint_in, None),
+                        PoolToken::new(mint_out, None),
+                        best_liquidity,
+                    ));
                     
                     info!("✅ Added Jupiter route: {} -> {} with ${:.0} estimated liquidity",
                           input_token, output_token, best_liquidity);
@@ -294,3 +1168,269 @@ async fn fetch_jupiter_pools(client: &reqwest::Client, target_tokens: &[&str]) -
     info!("✅ Jupiter pools fetched: {} routes with real liquidity data", pools.len());
     Ok(pools)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::markets::discovery::DiscoveredPool;
+    use crate::markets::types::DexLabel;
+
+    const SOL: Pubkey = solana_sdk::pubkey!("So11111111111111111111111111111111111111112");
+    const USDC: Pubkey = solana_sdk::pubkey!("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v");
+
+    fn pool(id: &str, liquidity: f64) -> Pool {
+        Pool::new(
+            id.to_string(),
+            Some(DexLabel::Raydium),
+            None,
+            PoolToken::new(SOL, Some(9)),
+            PoolToken::new(USDC, Some(6)),
+            liquidity,
+        )
+    }
+
+    #[tokio::test]
+    async fn test_warm_start_without_network() {
+        let path = std::env::temp_dir().join(format!("pool_registry_{}.bin", Pubkey::new_unique()));
+        let config = Config {
+            // Nothing listens here, so neither the slot nor the DEX APIs are reachable
+            rpc_url: "http://127.0.0.1:1".to_string(),
+            pool_snapshot_path: Some(path.to_string_lossy().into_owned()),
+            ..Config::default()
+        };
+        let pool = pool("orca_pool", 80_000.0);
+        let pool_address = Pubkey::new_unique();
+        let account = PoolAccount {
+            owner: Pubkey::new_unique(),
+            slot: 250_000_000,
+            data: vec![1, 2, 3],
+        };
+        PoolSnapshot::new(250_000_000, vec![pool.clone()], vec![(pool_address, account.clone())])
+            .write(&path)
+            .unwrap();
+
+        let registry = PoolRegistry::new(&config, Arc::new(TokenRegistry::from_config(&config))).await.unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(registry.len(), 1);
+        assert_eq!(registry.slot(), 250_000_000);
+        assert_eq!(registry.account(&pool_address), Some(account.clone()));
+        assert_eq!(registry.pools.get("orca_pool").unwrap().value(), &pool);
+
+        // Older observations never replace newer ones
+        assert!(!registry.record_account(pool_address, account.owner, 249_999_999, vec![9]));
+        assert!(registry.record_account(pool_address, account.owner, 250_000_001, vec![9]));
+        assert_eq!(registry.account(&pool_address).unwrap().data, vec![9]);
+        assert_eq!(registry.snapshot().slot, 250_000_001);
+    }
+
+    #[test]
+    fn test_merge_discovered_keeps_api_pools() {
+        let registry = PoolRegistry::empty(&Config::default());
+        let (known, fresh) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let api_pool = Pool {
+            address: Some(known),
+            ..pool(&format!("orca_{}", known), 120_000.0)
+        };
+        let mut pools = HashMap::from([(api_pool.id.clone(), api_pool.clone())]);
+
+        let on_chain = OnChainPool {
+            decimals: [None, None],
+            fee_bps: Some(4),
+            state: PoolState::Concentrated { sqrt_price_x64: 1 << 64, liquidity: 10, tick_current: 0 },
+        };
+        let discovered = |address, data| DiscoveredPool {
+            address,
+            dex: DexLabel::OrcaWhirlpools,
+            owner: orca_whirlpools_client::ID,
+            mint_a: SOL,
+            mint_b: USDC,
+            pool_id_prefix: "whirlpool",
+            data,
+            on_chain,
+        };
+        let report = DiscoveryReport {
+            slot: 300,
+            pools: vec![discovered(known, vec![1]), discovered(fresh, vec![2])],
+            failed_queries: 0,
+        };
+        assert_eq!(registry.merge_discovered(&mut pools, report), 1);
+
+        assert_eq!(pools.len(), 2);
+        // The API pool keeps its id, TVL and decimals and takes the on-chain state
+        let enriched = &pools[&api_pool.id];
+        assert_eq!(
+            (enriched.liquidity, enriched.token_a, enriched.token_b),
+            (api_pool.liquidity, api_pool.token_a, api_pool.token_b)
+        );
+        assert_eq!((enriched.fee_bps, enriched.state, enriched.last_update_slot), (4, on_chain.state, 300));
+
+        let new_pool = &pools[&format!("whirlpool_{}", fresh)];
+        assert_eq!(new_pool.liquidity_usd(), None);
+        assert_eq!(new_pool.dex, Some(DexLabel::OrcaWhirlpools));
+        assert_eq!(new_pool.address, Some(fresh));
+        assert_eq!(new_pool.token_a.decimals, None);
+        assert_eq!(registry.account(&known).unwrap().data, vec![1]);
+        assert_eq!(registry.account(&fresh).unwrap().slot, 300);
+        assert_eq!(registry.slot(), 300);
+    }
+
+    #[test]
+    fn test_pool_prices_from_state() {
+        let mut sol_usdc = pool("sol_usdc", 1.0);
+        assert_eq!(sol_usdc.price_of(&SOL), None);
+
+        // 1_000 SOL against 150_000 USDC
+        sol_usdc.state = PoolState::Reserves { reserve_a: 1_000_000_000_000, reserve_b: 150_000_000_000 };
+        assert!((sol_usdc.price_of(&SOL).unwrap() - 150.0).abs() < 1e-9);
+        assert!((sol_usdc.price_of(&USDC).unwrap() - 1.0 / 150.0).abs() < 1e-12);
+        assert_eq!(sol_usdc.price_of(&Pubkey::new_unique()), None);
+
+        // The same price as a Q64.64 square root of raw units
+        let sqrt_price_x64 = (0.15f64.sqrt() * 2f64.powi(64)) as u128;
+        sol_usdc.state = PoolState::Concentrated { sqrt_price_x64, liquidity: 1, tick_current: 0 };
+        assert!((sol_usdc.price_of(&SOL).unwrap() - 150.0).abs() < 1e-6);
+
+        sol_usdc.token_b.decimals = None;
+        assert_eq!(sol_usdc.price_of(&SOL), None);
+        assert!((sol_usdc.fee_multiplier() - 0.9975).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_account_update_changes_pool() {
+        use crate::markets::raydium::{AmmInfo, Fees};
+
+        let registry = PoolRegistry::empty(&Config::default());
+        let address = Pubkey::new_unique();
+        let amm = Pool { address: Some(address), ..pool("raydium_amm", 50_000.0) };
+        registry.apply(HashMap::from([(amm.id.clone(), amm.clone())]));
+        assert!(registry.is_pool_address(&address));
+
+        let owner = Pubkey::from_str(RAYDIUM_AMM_V4_PROGRAM_ID).unwrap();
+        let amm_info = |swap_fee_numerator| {
+            borsh::to_vec(&AmmInfo {
+                fees: Fees { swap_fee_numerator, swap_fee_denominator: 10_000, ..Default::default() },
+                coin_decimals: 9,
+                pc_decimals: 6,
+                ..Default::default()
+            })
+            .unwrap()
+        };
+        let mut events = registry.subscribe();
+
+        let event = registry.apply_account_update(address, owner, 100, amm_info(30)).unwrap();
+        let PoolEvent::PoolChanged { previous, current } = &event else {
+            panic!("expected a change, got {:?}", event);
+        };
+        // The AMM account holds no reserves, so the API's stay undated
+        assert_eq!((previous.fee_bps, current.fee_bps, current.last_update_slot), (25, 30, 0));
+        assert_eq!(events.try_recv().unwrap(), event);
+        assert_eq!(registry.account(&address).unwrap().slot, 100);
+
+        // Same fee at a later slot, a stale update, and an unknown account change nothing
+        assert_eq!(registry.apply_account_update(address, owner, 101, amm_info(30)), None);
+        assert_eq!(registry.apply_account_update(address, owner, 99, amm_info(40)), None);
+        assert_eq!(registry.apply_account_update(Pubkey::new_unique(), owner, 102, amm_info(40)), None);
+        assert!(events.try_recv().is_err());
+        assert_eq!(registry.pools.get("raydium_amm").unwrap().fee_bps, 30);
+    }
+
+    #[test]
+    fn test_vault_updates_set_reserves() {
+        use crate::markets::raydium::{AmmInfo, StateData};
+        use anchor_spl::token::spl_token;
+
+        let registry = PoolRegistry::empty(&Config::default());
+        let (address, coin_vault, pc_vault) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let amm = Pool { address: Some(address), ..pool("raydium_amm", 50_000.0) };
+        registry.apply(HashMap::from([(amm.id.clone(), amm)]));
+        let token_account = |mint: &Pubkey, amount: u64| {
+            let mut data = vec![0u8; 165];
+            data[0..32].copy_from_slice(mint.as_ref());
+            data[64..72].copy_from_slice(&amount.to_le_bytes());
+            data
+        };
+
+        // Vaults are unknown until the pool account has been seen
+        assert!(!registry.tracks(&coin_vault));
+        let amm_info = borsh::to_vec(&AmmInfo {
+            coin_vault,
+            pc_vault,
+            state_data: StateData { need_take_pnl_coin: 1_000, ..Default::default() },
+            ..Default::default()
+        })
+        .unwrap();
+        let owner = Pubkey::from_str(RAYDIUM_AMM_V4_PROGRAM_ID).unwrap();
+        registry.apply_account_update(address, owner, 100, amm_info);
+        assert_eq!(registry.vaults_of(&address), vec![coin_vault, pc_vault]);
+        assert!(registry.tracks(&coin_vault) && registry.tracks(&pc_vault));
+
+        // Reserves need both balances, net of the pnl held back from swaps
+        assert_eq!(registry.apply_account_update(coin_vault, spl_token::ID, 101, token_account(&SOL, 10_000_000)), None);
+        let event = registry.apply_account_update(pc_vault, spl_token::ID, 102, token_account(&USDC, 1_500_000)).unwrap();
+        let PoolEvent::PoolChanged { current, .. } = event else {
+            panic!("expected a change, got {:?}", event);
+        };
+        assert_eq!(current.state, PoolState::Reserves { reserve_a: 9_999_000, reserve_b: 1_500_000 });
+        assert_eq!(current.last_update_slot, 102);
+
+        // A stale balance is dropped; removing the pool stops routing its vaults
+        assert_eq!(registry.apply_account_update(pc_vault, spl_token::ID, 101, token_account(&USDC, 1)), None);
+        registry.apply(HashMap::new());
+        assert!(!registry.tracks(&coin_vault));
+    }
+
+    #[test]
+    fn test_apply_broadcasts_differences() {
+        let registry = PoolRegistry::empty(&Config::default());
+        let (kept, closed, resized) = (pool("kept", 1.0), pool("closed", 1.0), pool("resized", 1.0));
+        registry.apply(HashMap::from([
+            (kept.id.clone(), kept.clone()),
+            (closed.id.clone(), closed.clone()),
+            (resized.id.clone(), resized.clone()),
+        ]));
+
+        let mut events = registry.subscribe();
+        let (opened, grown) = (pool("opened", 1.0), pool("resized", 2.0));
+        let diff = registry.apply(HashMap::from([
+            (kept.id.clone(), kept.clone()),
+            (grown.id.clone(), grown.clone()),
+            (opened.id.clone(), opened.clone()),
+        ]));
+        assert_eq!(diff, PoolDiff { added: 1, removed: 1, changed: 1 });
+        assert_eq!(registry.len(), 3);
+
+        let mut received = vec![events.try_recv().unwrap(), events.try_recv().unwrap(), events.try_recv().unwrap()];
+        assert!(events.try_recv().is_err());
+        received.sort_by_key(|event| format!("{:?}", event));
+        assert_eq!(
+            received,
+            vec![
+                PoolEvent::PoolAdded(opened),
+                PoolEvent::PoolChanged { previous: resized, current: grown },
+                PoolEvent::PoolRemoved(closed),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_refresh_keeps_streamed_state() {
+        let registry = PoolRegistry::empty(&Config::default());
+        let mut streamed = pool("streamed", 1.0);
+        streamed.state = PoolState::Reserves { reserve_a: 1_000, reserve_b: 2_000 };
+        streamed.last_update_slot = 100;
+        registry.apply(HashMap::from([(streamed.id.clone(), streamed.clone())]));
+
+        // The API's reserves are older than the streamed ones and don't count as a change
+        let mut events = registry.subscribe();
+        let listed = Pool { state: PoolState::Reserves { reserve_a: 900, reserve_b: 2_100 }, last_update_slot: 0, ..streamed.clone() };
+        let diff = registry.apply(HashMap::from([(listed.id.clone(), listed.clone())]));
+        assert_eq!(diff, PoolDiff::default());
+        assert!(events.try_recv().is_err());
+        assert_eq!(registry.pools.get("streamed").unwrap().clone(), streamed);
+
+        // Listing changes still do, and a later state replaces the streamed one
+        let moved = Pool { liquidity: 2.0, last_update_slot: 101, ..listed };
+        assert_eq!(registry.apply(HashMap::from([(moved.id.clone(), moved.clone())])).changed, 1);
+        assert_eq!(events.try_recv().unwrap(), PoolEvent::PoolChanged { previous: streamed, current: moved });
+    }
+}
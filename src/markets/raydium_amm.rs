@@ -0,0 +1,327 @@
+//! Native Raydium AMM v4 quote engine
+//!
+//! Quotes are computed locally from the decoded `AmmInfo` account and the balances of
+//! the pool's coin/pc vaults, reproducing the program's `swap_base_in` math: the swap
+//! fee is rounded up and the constant-product output is rounded down.
+//!
+//! Program: 675kPX9MHTjS2zt1qfr1NYHuzeLXfQM9H24wFSUt1Mp8
+
+use crate::markets::errors::MarketSimulationError;
+use crate::markets::foundation::{MarketBehavior, Quote};
+use crate::markets::raydium::AmmInfo;
+use crate::markets::token_2022::{decode_token_account, TOKEN_ACCOUNT_LEN};
+use crate::markets::types::{DexLabel, MarketId};
+use anyhow::Result;
+use borsh::BorshDeserialize;
+use solana_program::pubkey::Pubkey;
+
+/// Raydium AMM v4 program ID
+pub const RAYDIUM_AMM_V4_PROGRAM_ID: &str = "675kPX9MHTjS2zt1qfr1NYHuzeLXfQM9H24wFSUt1Mp8";
+
+/// Size of an `AmmInfo` account on-chain
+pub const AMM_INFO_LEN: usize = 752;
+
+/// `AmmStatus` values for which the program accepts swaps
+/// (Initialized, SwapOnly, WaitingTrade)
+const SWAPPABLE_STATUSES: [u64; 3] = [1, 6, 7];
+
+const MARKET_NAME: &str = "Raydium";
+
+/// Raydium AMM v4 pool priced entirely from account data
+#[derive(Debug, Clone)]
+pub struct RaydiumAmmMarket {
+    /// AMM account address
+    pool_address: Pubkey,
+
+    /// Decoded AMM account
+    amm: AmmInfo,
+
+    /// Raw balance of the coin vault
+    coin_vault_amount: u64,
+
+    /// Raw balance of the pc vault
+    pc_vault_amount: u64,
+}
+
+impl RaydiumAmmMarket {
+    /// Create a market from an already decoded `AmmInfo` and vault balances
+    pub fn new(pool_address: Pubkey, amm: AmmInfo, coin_vault_amount: u64, pc_vault_amount: u64) -> Self {
+        Self {
+            pool_address,
+            amm,
+            coin_vault_amount,
+            pc_vault_amount,
+        }
+    }
+
+    /// Create a market from the raw AMM account and the raw coin/pc vault token accounts
+    pub fn from_account_data(
+        pool_address: Pubkey,
+        amm_data: &[u8],
+        coin_vault_data: &[u8],
+        pc_vault_data: &[u8],
+    ) -> Result<Self, MarketSimulationError> {
+        let amm = decode_amm_info(amm_data)?;
+        let (_, coin_vault_amount) = decode_token_account(coin_vault_data)?;
+        let (_, pc_vault_amount) = decode_token_account(pc_vault_data)?;
+
+        Ok(Self::new(pool_address, amm, coin_vault_amount, pc_vault_amount))
+    }
+
+    pub fn pool_address(&self) -> Pubkey {
+        self.pool_address
+    }
+
+    pub fn amm_info(&self) -> &AmmInfo {
+        &self.amm
+    }
+
+    /// Tradable (coin, pc) reserves: vault balances minus the pnl the program has
+    /// earmarked for withdrawal, exactly as `swap_base_in` computes them.
+    pub fn reserves(&self) -> (u64, u64) {
+        (
+            self.coin_vault_amount
+                .saturating_sub(self.amm.state_data.need_take_pnl_coin),
+            self.pc_vault_amount
+                .saturating_sub(self.amm.state_data.need_take_pnl_pc),
+        )
+    }
+
+    /// Fee fraction charged on the input amount.
+    ///
+    /// The program charges `swap_fee_*`; on every live pool this equals `trade_fee_*`,
+    /// which is used as a fallback for accounts that leave the swap fee unset.
+    fn fee_fraction(&self) -> (u64, u64) {
+        let fees = &self.amm.fees;
+        if fees.swap_fee_denominator != 0 {
+            (fees.swap_fee_numerator, fees.swap_fee_denominator)
+        } else {
+            (fees.trade_fee_numerator, fees.trade_fee_denominator)
+        }
+    }
+
+    /// Exact-input constant-product quote with on-chain rounding
+    fn quote_exact_in(&self, amount_in: u64, a_to_b: bool) -> Result<Quote, MarketSimulationError> {
+        if !SWAPPABLE_STATUSES.contains(&self.amm.status) {
+            return Err(MarketSimulationError::NoRouteFound {
+                market: MARKET_NAME.to_string(),
+                reason: format!("pool {} status {} does not allow swaps", self.pool_address, self.amm.status),
+            });
+        }
+
+        let (coin_reserve, pc_reserve) = self.reserves();
+        let (reserve_in, reserve_out) = if a_to_b {
+            (coin_reserve, pc_reserve)
+        } else {
+            (pc_reserve, coin_reserve)
+        };
+        if reserve_in == 0 || reserve_out == 0 {
+            return Err(MarketSimulationError::InsufficientLiquidity {
+                market: MarketId::Raydium,
+                available: reserve_out,
+                required: 1,
+            });
+        }
+
+        let (fee_numerator, fee_denominator) = self.fee_fraction();
+        if fee_denominator == 0 {
+            return Err(MarketSimulationError::AccountDecodeError {
+                market: MARKET_NAME.to_string(),
+                details: format!("pool {} has a zero fee denominator", self.pool_address),
+            });
+        }
+
+        let fee_amount = (amount_in as u128 * fee_numerator as u128)
+            .div_ceil(fee_denominator as u128)
+            .min(amount_in as u128);
+        let amount_in_less_fee = amount_in as u128 - fee_amount;
+
+        let amount_out = reserve_out as u128 * amount_in_less_fee
+            / (reserve_in as u128 + amount_in_less_fee);
+
+        // Impact relative to the spot rate on the post-fee amount
+        let spot_out = reserve_out as f64 * amount_in_less_fee as f64 / reserve_in as f64;
+        let price_impact = if spot_out > 0.0 {
+            ((1.0 - amount_out as f64 / spot_out) * 100.0).clamp(0.0, 100.0)
+        } else {
+            0.0
+        };
+
+        Ok(Quote {
+            amount_in,
+            amount_out: amount_out as u64,
+            price_impact,
+            fee_amount: fee_amount as u64,
+            slippage_tolerance: 0.5,
+        })
+    }
+}
+
+impl MarketBehavior for RaydiumAmmMarket {
+    fn get_quote(&self, amount_in: u64, a_to_b: bool) -> Result<Quote, MarketSimulationError> {
+        if amount_in == 0 {
+            return Err(MarketSimulationError::InvalidAmount {
+                market: MarketId::Raydium,
+                amount: amount_in,
+            });
+        }
+
+        self.quote_exact_in(amount_in, a_to_b)
+    }
+
+    /// Spot price of coin in pc, in raw token units
+    fn get_price(&self) -> Result<f64, MarketSimulationError> {
+        let (coin_reserve, pc_reserve) = self.reserves();
+        if coin_reserve == 0 {
+            return Err(MarketSimulationError::InsufficientLiquidity {
+                market: MarketId::Raydium,
+                available: 0,
+                required: 1,
+            });
+        }
+        Ok(pc_reserve as f64 / coin_reserve as f64)
+    }
+
+    /// Accepts either the AMM account itself or one of its vault token accounts;
+    /// vaults are told apart by their mint.
+    fn update_state(&mut self, new_data: &[u8]) -> Result<(), MarketSimulationError> {
+        match new_data.len() {
+            AMM_INFO_LEN => {
+                self.amm = decode_amm_info(new_data)?;
+            }
+            len if len >= TOKEN_ACCOUNT_LEN => {
+                let (mint, amount) = decode_token_account(new_data)?;
+                if mint == self.amm.coin_vault_mint {
+                    self.coin_vault_amount = amount;
+                } else if mint == self.amm.pc_vault_mint {
+                    self.pc_vault_amount = amount;
+                } else {
+                    return Err(MarketSimulationError::AccountDecodeError {
+                        market: MARKET_NAME.to_string(),
+                        details: format!("token account mint {} is not a vault mint of {}", mint, self.pool_address),
+                    });
+                }
+            }
+            len => {
+                return Err(MarketSimulationError::AccountDecodeError {
+                    market: MARKET_NAME.to_string(),
+                    details: format!("unexpected account size {}", len),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    fn market_id(&self) -> MarketId {
+        MarketId::Raydium
+    }
+
+    fn dex_label(&self) -> DexLabel {
+        DexLabel::Raydium
+    }
+}
+
+/// Decode a raw `AmmInfo` account
+pub fn decode_amm_info(data: &[u8]) -> Result<AmmInfo, MarketSimulationError> {
+    if data.len() != AMM_INFO_LEN {
+        return Err(MarketSimulationError::AccountDecodeError {
+            market: MARKET_NAME.to_string(),
+            details: format!("AmmInfo must be {} bytes, got {}", AMM_INFO_LEN, data.len()),
+        });
+    }
+    AmmInfo::try_from_slice(data).map_err(|e| MarketSimulationError::AccountDecodeError {
+        market: MARKET_NAME.to_string(),
+        details: e.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::markets::raydium::Fees;
+    use crate::markets::token_2022::tests::token_account;
+
+    fn test_amm() -> AmmInfo {
+        AmmInfo {
+            status: 6,
+            fees: Fees {
+                trade_fee_numerator: 25,
+                trade_fee_denominator: 10_000,
+                swap_fee_numerator: 25,
+                swap_fee_denominator: 10_000,
+                ..Default::default()
+            },
+            coin_vault_mint: Pubkey::new_unique(),
+            pc_vault_mint: Pubkey::new_unique(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_constant_product_quote() {
+        let market = RaydiumAmmMarket::new(Pubkey::new_unique(), test_amm(), 1_000_000_000, 2_000_000_000);
+
+        let quote = market.get_quote(1_000_000, true).unwrap();
+        // fee = ceil(1_000_000 * 25 / 10_000) = 2_500
+        assert_eq!(quote.fee_amount, 2_500);
+        // out = floor(2e9 * 997_500 / (1e9 + 997_500))
+        assert_eq!(quote.amount_out, 1_993_011);
+        assert!(quote.price_impact > 0.0);
+
+        let reverse = market.get_quote(1_000_000, false).unwrap();
+        assert_eq!(reverse.amount_out, 498_501);
+    }
+
+    #[test]
+    fn test_fee_rounds_up() {
+        let market = RaydiumAmmMarket::new(Pubkey::new_unique(), test_amm(), 1_000_000_000, 2_000_000_000);
+
+        let quote = market.get_quote(1, true).unwrap();
+        assert_eq!(quote.fee_amount, 1);
+        assert_eq!(quote.amount_out, 0);
+
+        assert!(market.get_quote(0, true).is_err());
+    }
+
+    #[test]
+    fn test_pending_pnl_excluded_from_reserves() {
+        let mut amm = test_amm();
+        amm.state_data.need_take_pnl_coin = 100;
+        amm.state_data.need_take_pnl_pc = 200;
+        let market = RaydiumAmmMarket::new(Pubkey::new_unique(), amm, 1_100, 2_200);
+
+        assert_eq!(market.reserves(), (1_000, 2_000));
+        assert_eq!(market.get_price().unwrap(), 2.0);
+    }
+
+    #[test]
+    fn test_update_state_from_raw_accounts() {
+        let amm = test_amm();
+        let amm_data = borsh::to_vec(&amm).unwrap();
+        assert_eq!(amm_data.len(), AMM_INFO_LEN);
+
+        let mut market = RaydiumAmmMarket::from_account_data(
+            Pubkey::new_unique(),
+            &amm_data,
+            &token_account(&amm.coin_vault_mint, 1_000_000_000),
+            &token_account(&amm.pc_vault_mint, 2_000_000_000),
+        )
+        .unwrap();
+        assert_eq!(market.reserves(), (1_000_000_000, 2_000_000_000));
+
+        market
+            .update_state(&token_account(&amm.pc_vault_mint, 4_000_000_000))
+            .unwrap();
+        assert_eq!(market.reserves(), (1_000_000_000, 4_000_000_000));
+
+        assert!(market
+            .update_state(&token_account(&Pubkey::new_unique(), 1))
+            .is_err());
+        assert!(market.update_state(&[0u8; 10]).is_err());
+
+        let mut disabled = amm;
+        disabled.status = 2;
+        market.update_state(&borsh::to_vec(&disabled).unwrap()).unwrap();
+        assert!(market.get_quote(1_000, true).is_err());
+    }
+}
@@ -0,0 +1,567 @@
+//! Native Raydium CLMM quote engine
+//!
+//! Decodes the pool, its `AmmConfig` and the surrounding tick arrays, then runs the
+//! program's concentrated-liquidity swap loop: each step stops at the next initialized
+//! tick, the AmmConfig trade fee is charged on the step input, and `liquidity_net` is
+//! applied whenever a tick is crossed.
+//!
+//! Amount and sqrt-price deltas reuse the `orca_whirlpools_core` primitives; both
+//! programs implement the same Uniswap v3 Q64.64 math with identical rounding.
+//!
+//! Program: CAMMCzo5YL8w4VFF8KVHrK22GGUsp5VTaW7grrKgrWqK
+
+use crate::markets::errors::MarketSimulationError;
+use crate::markets::foundation::{MarketBehavior, Quote};
+use crate::markets::types::{DexLabel, MarketId};
+use crate::transactions::raydium_clmm_swap::{
+    derive_tick_array_address, get_tick_array_start_index, RaydiumClmmPoolState,
+    RAYDIUM_CLMM_PROGRAM_ID, TICK_ARRAY_SIZE,
+};
+use anyhow::Result;
+use orca_whirlpools_core::{
+    sqrt_price_to_tick_index, tick_index_to_sqrt_price, try_get_amount_delta_a,
+    try_get_amount_delta_b, try_get_next_sqrt_price_from_a, try_get_next_sqrt_price_from_b,
+    CoreError, AMOUNT_EXCEEDS_MAX_U64,
+};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_program::pubkey::Pubkey;
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+/// Size of a `PoolState` account
+pub const CLMM_POOL_ACCOUNT_LEN: usize = 1544;
+
+/// Size of a `TickArrayState` account
+pub const CLMM_TICK_ARRAY_ACCOUNT_LEN: usize = 10240;
+
+/// Size of an `AmmConfig` account
+pub const CLMM_AMM_CONFIG_ACCOUNT_LEN: usize = 117;
+
+/// Offset of the first tick inside a tick array account
+const TICK_ARRAY_HEADER_LEN: usize = 44;
+
+/// Size of a single `TickState`
+const TICK_STATE_LEN: usize = 168;
+
+/// Offset of `trade_fee_rate` inside an AmmConfig account
+const AMM_CONFIG_TRADE_FEE_OFFSET: usize = 47;
+
+/// Fee rates are expressed over 1e6
+const FEE_RATE_DENOMINATOR: u128 = 1_000_000;
+
+/// Pool status bit that disables swaps
+const STATUS_SWAP_DISABLED: u8 = 1 << 4;
+
+const MIN_SQRT_PRICE_X64: u128 = 4295048016;
+const MAX_SQRT_PRICE_X64: u128 = 79226673521066979257578248091;
+
+/// A single tick; initialized ticks have non-zero gross liquidity
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ClmmTick {
+    pub liquidity_net: i128,
+    pub liquidity_gross: u128,
+}
+
+impl ClmmTick {
+    pub fn is_initialized(&self) -> bool {
+        self.liquidity_gross != 0
+    }
+}
+
+/// Decoded tick array holding `TICK_ARRAY_SIZE` ticks
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClmmTickArray {
+    pub start_tick_index: i32,
+    pub ticks: [ClmmTick; TICK_ARRAY_SIZE as usize],
+}
+
+impl ClmmTickArray {
+    /// Decode a raw tick array account, returning it with the pool it belongs to
+    pub fn from_account_data(data: &[u8]) -> Result<(Self, Pubkey), MarketSimulationError> {
+        if data.len() != CLMM_TICK_ARRAY_ACCOUNT_LEN {
+            return Err(decode_error(format!(
+                "TickArrayState must be {} bytes, got {}",
+                CLMM_TICK_ARRAY_ACCOUNT_LEN,
+                data.len()
+            )));
+        }
+
+        let pool_id = Pubkey::try_from(&data[8..40]).expect("slice is 32 bytes");
+        let start_tick_index = i32::from_le_bytes(data[40..44].try_into().expect("slice is 4 bytes"));
+
+        let mut ticks = [ClmmTick::default(); TICK_ARRAY_SIZE as usize];
+        for (i, tick) in ticks.iter_mut().enumerate() {
+            let offset = TICK_ARRAY_HEADER_LEN + i * TICK_STATE_LEN;
+            tick.liquidity_net = i128::from_le_bytes(data[offset + 4..offset + 20].try_into().expect("slice is 16 bytes"));
+            tick.liquidity_gross = u128::from_le_bytes(data[offset + 20..offset + 36].try_into().expect("slice is 16 bytes"));
+        }
+
+        Ok((Self { start_tick_index, ticks }, pool_id))
+    }
+}
+
+fn decode_error(details: String) -> MarketSimulationError {
+    MarketSimulationError::AccountDecodeError {
+        market: DexLabel::RaydiumClmm.str(),
+        details,
+    }
+}
+
+fn math_error(e: CoreError) -> MarketSimulationError {
+    MarketSimulationError::NoRouteFound {
+        market: DexLabel::RaydiumClmm.str(),
+        reason: e.to_string(),
+    }
+}
+
+/// Read `trade_fee_rate` from a raw AmmConfig account
+pub fn decode_amm_config_trade_fee(data: &[u8]) -> Result<u32, MarketSimulationError> {
+    if data.len() != CLMM_AMM_CONFIG_ACCOUNT_LEN {
+        return Err(decode_error(format!(
+            "AmmConfig must be {} bytes, got {}",
+            CLMM_AMM_CONFIG_ACCOUNT_LEN,
+            data.len()
+        )));
+    }
+    let offset = AMM_CONFIG_TRADE_FEE_OFFSET;
+    Ok(u32::from_le_bytes(data[offset..offset + 4].try_into().expect("slice is 4 bytes")))
+}
+
+/// Result of a single swap step between two sqrt prices
+struct SwapStep {
+    amount_in: u64,
+    amount_out: u64,
+    next_sqrt_price: u128,
+    fee_amount: u64,
+}
+
+/// Exact-input swap step, as in the program's `swap_math::compute_swap_step`
+fn compute_swap_step(
+    amount_remaining: u64,
+    fee_rate: u32,
+    liquidity: u128,
+    current_sqrt_price: u128,
+    target_sqrt_price: u128,
+    zero_for_one: bool,
+) -> Result<SwapStep, CoreError> {
+    let fee_rate = fee_rate as u128;
+    let input_delta = |from: u128, to: u128| {
+        if zero_for_one {
+            try_get_amount_delta_a(from, to, liquidity, true)
+        } else {
+            try_get_amount_delta_b(from, to, liquidity, true)
+        }
+    };
+
+    let amount_less_fee =
+        (amount_remaining as u128 * (FEE_RATE_DENOMINATOR - fee_rate) / FEE_RATE_DENOMINATOR) as u64;
+
+    let initial_amount_in = input_delta(current_sqrt_price, target_sqrt_price);
+    let next_sqrt_price = match initial_amount_in {
+        Ok(amount_in) if amount_in <= amount_less_fee => target_sqrt_price,
+        Ok(_) | Err(AMOUNT_EXCEEDS_MAX_U64) => {
+            if zero_for_one {
+                try_get_next_sqrt_price_from_a(current_sqrt_price, liquidity, amount_less_fee, true)?
+            } else {
+                try_get_next_sqrt_price_from_b(current_sqrt_price, liquidity, amount_less_fee, true)?
+            }
+        }
+        Err(e) => return Err(e),
+    };
+    let is_max_swap = next_sqrt_price == target_sqrt_price;
+
+    let amount_in = if is_max_swap {
+        initial_amount_in?
+    } else {
+        input_delta(current_sqrt_price, next_sqrt_price)?
+    };
+    let amount_out = if zero_for_one {
+        try_get_amount_delta_b(current_sqrt_price, next_sqrt_price, liquidity, false)?
+    } else {
+        try_get_amount_delta_a(current_sqrt_price, next_sqrt_price, liquidity, false)?
+    };
+
+    let fee_amount = if !is_max_swap {
+        amount_remaining - amount_in
+    } else {
+        (amount_in as u128 * fee_rate).div_ceil(FEE_RATE_DENOMINATOR - fee_rate) as u64
+    };
+
+    Ok(SwapStep {
+        amount_in,
+        amount_out,
+        next_sqrt_price,
+        fee_amount,
+    })
+}
+
+/// Raydium CLMM pool priced entirely from account data
+#[derive(Debug, Clone)]
+pub struct RaydiumClmmMarket {
+    /// Pool account address
+    pool_address: Pubkey,
+
+    /// Decoded pool account
+    pool_state: RaydiumClmmPoolState,
+
+    /// AmmConfig trade fee over 1e6
+    trade_fee_rate: u32,
+
+    /// Loaded tick arrays keyed by their start tick index. Arrays that were never
+    /// initialized on-chain hold no liquidity and are simply absent.
+    tick_arrays: BTreeMap<i32, ClmmTickArray>,
+}
+
+impl RaydiumClmmMarket {
+    pub fn new(pool_address: Pubkey, pool_state: RaydiumClmmPoolState, trade_fee_rate: u32) -> Self {
+        Self {
+            pool_address,
+            pool_state,
+            trade_fee_rate,
+            tick_arrays: BTreeMap::new(),
+        }
+    }
+
+    /// Create a market from a raw pool account and the trade fee of its AmmConfig
+    pub fn from_account_data(
+        pool_address: Pubkey,
+        pool_data: &[u8],
+        trade_fee_rate: u32,
+    ) -> Result<Self, MarketSimulationError> {
+        Ok(Self::new(pool_address, decode_pool_state(pool_data)?, trade_fee_rate))
+    }
+
+    pub fn pool_address(&self) -> Pubkey {
+        self.pool_address
+    }
+
+    pub fn pool_state(&self) -> &RaydiumClmmPoolState {
+        &self.pool_state
+    }
+
+    /// Add or replace a decoded tick array
+    pub fn insert_tick_array(&mut self, tick_array: ClmmTickArray) {
+        self.tick_arrays.insert(tick_array.start_tick_index, tick_array);
+    }
+
+    /// Start indexes of the tick arrays around the current tick
+    pub fn tick_array_starts_around_current(&self, arrays_each_side: i32) -> Vec<i32> {
+        let ticks_in_array = TICK_ARRAY_SIZE * self.pool_state.tick_spacing as i32;
+        let current = get_tick_array_start_index(self.pool_state.tick_current, self.pool_state.tick_spacing)
+            .unwrap_or_default();
+        (-arrays_each_side..=arrays_each_side)
+            .map(|k| current + k * ticks_in_array)
+            .collect()
+    }
+
+    /// Next initialized tick in the swap direction: at or below `tick` when
+    /// `zero_for_one`, strictly above it otherwise
+    fn next_initialized_tick(&self, tick: i32, zero_for_one: bool) -> Option<(i32, i128)> {
+        let spacing = self.pool_state.tick_spacing as i32;
+        let start = get_tick_array_start_index(tick, self.pool_state.tick_spacing).ok()?;
+
+        if zero_for_one {
+            for (&array_start, array) in self.tick_arrays.range(..=start).rev() {
+                let max_offset = if array_start == start { (tick - array_start) / spacing } else { TICK_ARRAY_SIZE - 1 };
+                for offset in (0..=max_offset).rev() {
+                    let t = array.ticks[offset as usize];
+                    if t.is_initialized() {
+                        return Some((array_start + offset * spacing, t.liquidity_net));
+                    }
+                }
+            }
+        } else {
+            for (&array_start, array) in self.tick_arrays.range(start..) {
+                let min_offset = if array_start == start { (tick - array_start) / spacing + 1 } else { 0 };
+                for offset in min_offset..TICK_ARRAY_SIZE {
+                    let t = array.ticks[offset as usize];
+                    if t.is_initialized() {
+                        return Some((array_start + offset * spacing, t.liquidity_net));
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    /// Exact-input swap loop across initialized ticks
+    fn simulate_swap(&self, amount: u64, zero_for_one: bool) -> Result<Quote, MarketSimulationError> {
+        let state = &self.pool_state;
+        if state.status & STATUS_SWAP_DISABLED != 0 {
+            return Err(MarketSimulationError::NoRouteFound {
+                market: DexLabel::RaydiumClmm.str(),
+                reason: format!("swaps are disabled on pool {}", self.pool_address),
+            });
+        }
+
+        let sqrt_price_limit = if zero_for_one { MIN_SQRT_PRICE_X64 + 1 } else { MAX_SQRT_PRICE_X64 - 1 };
+        let mut amount_remaining = amount;
+        let mut amount_out: u64 = 0;
+        let mut fee_amount: u64 = 0;
+        let mut sqrt_price = state.sqrt_price_x64;
+        let mut tick = state.tick_current;
+        let mut liquidity = state.liquidity;
+
+        while amount_remaining > 0 && sqrt_price != sqrt_price_limit {
+            let (tick_next, liquidity_net) = self
+                .next_initialized_tick(tick, zero_for_one)
+                .ok_or(MarketSimulationError::InsufficientLiquidity {
+                    market: MarketId::Raydium,
+                    available: amount - amount_remaining,
+                    required: amount,
+                })?;
+
+            let sqrt_price_next = tick_index_to_sqrt_price(tick_next);
+            let target = if zero_for_one {
+                sqrt_price_next.max(sqrt_price_limit)
+            } else {
+                sqrt_price_next.min(sqrt_price_limit)
+            };
+
+            let step = compute_swap_step(amount_remaining, self.trade_fee_rate, liquidity, sqrt_price, target, zero_for_one)
+                .map_err(math_error)?;
+
+            amount_remaining -= step.amount_in + step.fee_amount;
+            amount_out = amount_out
+                .checked_add(step.amount_out)
+                .ok_or_else(|| math_error(AMOUNT_EXCEEDS_MAX_U64))?;
+            fee_amount += step.fee_amount;
+
+            if step.next_sqrt_price == sqrt_price_next {
+                let delta = if zero_for_one { -liquidity_net } else { liquidity_net };
+                liquidity = liquidity
+                    .checked_add_signed(delta)
+                    .ok_or_else(|| math_error("Liquidity net underflow"))?;
+                tick = if zero_for_one { tick_next - 1 } else { tick_next };
+            } else if step.next_sqrt_price != sqrt_price {
+                tick = sqrt_price_to_tick_index(step.next_sqrt_price);
+            }
+            sqrt_price = step.next_sqrt_price;
+        }
+
+        // Impact of the realised output against the spot price on the post-fee input
+        let spot_price = self.get_price()?;
+        let net_in = (amount - amount_remaining - fee_amount) as f64;
+        let spot_out = if zero_for_one { net_in * spot_price } else { net_in / spot_price };
+        let price_impact = if spot_out > 0.0 {
+            ((1.0 - amount_out as f64 / spot_out) * 100.0).clamp(0.0, 100.0)
+        } else {
+            0.0
+        };
+
+        Ok(Quote {
+            amount_in: amount - amount_remaining,
+            amount_out,
+            price_impact,
+            fee_amount,
+            slippage_tolerance: 0.5,
+        })
+    }
+}
+
+impl MarketBehavior for RaydiumClmmMarket {
+    /// `a_to_b` swaps token 0 for token 1
+    fn get_quote(&self, amount_in: u64, a_to_b: bool) -> Result<Quote, MarketSimulationError> {
+        if amount_in == 0 {
+            return Err(MarketSimulationError::InvalidAmount {
+                market: MarketId::Raydium,
+                amount: amount_in,
+            });
+        }
+
+        self.simulate_swap(amount_in, a_to_b)
+    }
+
+    /// Price of token 0 in token 1, in raw token units
+    fn get_price(&self) -> Result<f64, MarketSimulationError> {
+        let sqrt_price = self.pool_state.sqrt_price_x64 as f64 / (1u128 << 64) as f64;
+        Ok(sqrt_price * sqrt_price)
+    }
+
+    /// Accepts the pool account, one of its tick arrays or its AmmConfig
+    fn update_state(&mut self, new_data: &[u8]) -> Result<(), MarketSimulationError> {
+        match new_data.len() {
+            CLMM_POOL_ACCOUNT_LEN => {
+                self.pool_state = decode_pool_state(new_data)?;
+            }
+            CLMM_TICK_ARRAY_ACCOUNT_LEN => {
+                let (tick_array, pool_id) = ClmmTickArray::from_account_data(new_data)?;
+                if self.pool_address != Pubkey::default() && pool_id != self.pool_address {
+                    return Err(decode_error(format!(
+                        "tick array belongs to {}, not {}",
+                        pool_id, self.pool_address
+                    )));
+                }
+                self.insert_tick_array(tick_array);
+            }
+            CLMM_AMM_CONFIG_ACCOUNT_LEN => {
+                self.trade_fee_rate = decode_amm_config_trade_fee(new_data)?;
+            }
+            len => return Err(decode_error(format!("unexpected account size {}", len))),
+        }
+        Ok(())
+    }
+
+    fn market_id(&self) -> MarketId {
+        MarketId::Raydium
+    }
+
+    fn dex_label(&self) -> DexLabel {
+        DexLabel::RaydiumClmm
+    }
+}
+
+/// Decode a raw CLMM pool account
+pub fn decode_pool_state(data: &[u8]) -> Result<RaydiumClmmPoolState, MarketSimulationError> {
+    if data.len() != CLMM_POOL_ACCOUNT_LEN {
+        return Err(decode_error(format!(
+            "PoolState must be {} bytes, got {}",
+            CLMM_POOL_ACCOUNT_LEN,
+            data.len()
+        )));
+    }
+    RaydiumClmmPoolState::from_account_data(data).map_err(|e| decode_error(e.to_string()))
+}
+
+/// Fetch a CLMM pool, its AmmConfig and the tick arrays around the current tick
+pub async fn load_raydium_clmm_market(
+    rpc_client: &RpcClient,
+    pool: Pubkey,
+    arrays_each_side: i32,
+) -> Result<RaydiumClmmMarket, MarketSimulationError> {
+    let rpc_error = |e: solana_client::client_error::ClientError| MarketSimulationError::ApiRequestFailed {
+        market: DexLabel::RaydiumClmm.str(),
+        message: e.to_string(),
+        source: Some(Box::new(e)),
+    };
+
+    let pool_account = rpc_client.get_account(&pool).await.map_err(rpc_error)?;
+    let pool_state = decode_pool_state(&pool_account.data)?;
+    let amm_config = rpc_client.get_account(&pool_state.amm_config).await.map_err(rpc_error)?;
+    let trade_fee_rate = decode_amm_config_trade_fee(&amm_config.data)?;
+    let mut market = RaydiumClmmMarket::new(pool, pool_state, trade_fee_rate);
+
+    let program_id = Pubkey::from_str(RAYDIUM_CLMM_PROGRAM_ID).expect("valid program id");
+    let tick_array_keys = market
+        .tick_array_starts_around_current(arrays_each_side)
+        .into_iter()
+        .map(|start| derive_tick_array_address(&pool, start, &program_id))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| decode_error(e.to_string()))?;
+
+    // Uninitialized tick arrays come back as None and hold no liquidity
+    let accounts = rpc_client
+        .get_multiple_accounts(&tick_array_keys)
+        .await
+        .map_err(rpc_error)?;
+    for account in accounts.into_iter().flatten() {
+        market.update_state(&account.data)?;
+    }
+
+    Ok(market)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use orca_whirlpools_core::get_tick_array_start_tick_index;
+
+    const TICK_SPACING: u16 = 10;
+
+    fn pool_account(liquidity: u128, sqrt_price_x64: u128, tick_current: i32) -> Vec<u8> {
+        let mut data = vec![0u8; CLMM_POOL_ACCOUNT_LEN];
+        data[235..237].copy_from_slice(&TICK_SPACING.to_le_bytes());
+        data[237..253].copy_from_slice(&liquidity.to_le_bytes());
+        data[253..269].copy_from_slice(&sqrt_price_x64.to_le_bytes());
+        data[269..273].copy_from_slice(&tick_current.to_le_bytes());
+        data
+    }
+
+    fn tick_array_account(pool: &Pubkey, start: i32, initialized: &[(i32, i128)]) -> Vec<u8> {
+        let mut data = vec![0u8; CLMM_TICK_ARRAY_ACCOUNT_LEN];
+        data[8..40].copy_from_slice(pool.as_ref());
+        data[40..44].copy_from_slice(&start.to_le_bytes());
+        for &(tick, liquidity_net) in initialized {
+            let offset = TICK_ARRAY_HEADER_LEN + ((tick - start) / TICK_SPACING as i32) as usize * TICK_STATE_LEN;
+            data[offset..offset + 4].copy_from_slice(&tick.to_le_bytes());
+            data[offset + 4..offset + 20].copy_from_slice(&liquidity_net.to_le_bytes());
+            data[offset + 20..offset + 36].copy_from_slice(&liquidity_net.unsigned_abs().to_le_bytes());
+        }
+        data
+    }
+
+    /// Pool at price 1.0 with 500M liquidity over ticks [-500, 500) and another
+    /// 500M over [-20, 500), so half of the liquidity leaves at tick -20
+    fn test_market() -> RaydiumClmmMarket {
+        let pool = Pubkey::new_unique();
+        let mut market =
+            RaydiumClmmMarket::from_account_data(pool, &pool_account(1_000_000_000, 1u128 << 64, 0), 2_500).unwrap();
+        market
+            .update_state(&tick_array_account(&pool, -600, &[(-500, 500_000_000), (-20, 500_000_000)]))
+            .unwrap();
+        market
+            .update_state(&tick_array_account(&pool, 0, &[(500, -1_000_000_000)]))
+            .unwrap();
+        market
+    }
+
+    #[test]
+    fn test_decode_pool_state_offsets() {
+        let state = decode_pool_state(&pool_account(42, 1u128 << 64, -7)).unwrap();
+        assert_eq!(state.tick_spacing, TICK_SPACING);
+        assert_eq!(state.liquidity, 42);
+        assert_eq!(state.sqrt_price_x64, 1u128 << 64);
+        assert_eq!(state.tick_current, -7);
+
+        assert_eq!(get_tick_array_start_index(-7, TICK_SPACING).unwrap(), -600);
+        // Raydium arrays hold 60 ticks where Whirlpool arrays hold 88
+        assert_ne!(get_tick_array_start_tick_index(-7, TICK_SPACING), -600);
+    }
+
+    #[test]
+    fn test_single_range_swap() {
+        let market = test_market();
+        let amount = 1_000_000;
+
+        let quote = market.get_quote(amount, false).unwrap();
+        let amount_less_fee = amount * (1_000_000 - 2_500) / 1_000_000;
+        let next = try_get_next_sqrt_price_from_b(1u128 << 64, 1_000_000_000, amount_less_fee, true).unwrap();
+        let expected_out = try_get_amount_delta_a(1u128 << 64, next, 1_000_000_000, false).unwrap();
+
+        assert_eq!(quote.amount_in, amount);
+        assert_eq!(quote.amount_out, expected_out);
+        assert!(quote.fee_amount >= amount - amount_less_fee);
+    }
+
+    #[test]
+    fn test_crossing_tick_changes_liquidity() {
+        let market = test_market();
+        let mut flat = test_market();
+        flat.tick_arrays.get_mut(&-600).unwrap().ticks[58] = ClmmTick::default();
+
+        // Stays above tick -20
+        assert_eq!(
+            market.get_quote(100_000, true).unwrap().amount_out,
+            flat.get_quote(100_000, true).unwrap().amount_out
+        );
+        // Crosses tick -20, where half of the liquidity leaves
+        assert!(market.get_quote(5_000_000, true).unwrap().amount_out < flat.get_quote(5_000_000, true).unwrap().amount_out);
+        // Runs past the last initialized tick
+        assert!(market.get_quote(1_000_000_000, true).is_err());
+    }
+
+    #[test]
+    fn test_update_state_amm_config_fee() {
+        let mut market = test_market();
+        let before = market.get_quote(1_000_000, true).unwrap();
+
+        let mut amm_config = vec![0u8; CLMM_AMM_CONFIG_ACCOUNT_LEN];
+        amm_config[AMM_CONFIG_TRADE_FEE_OFFSET..AMM_CONFIG_TRADE_FEE_OFFSET + 4].copy_from_slice(&100u32.to_le_bytes());
+        market.update_state(&amm_config).unwrap();
+
+        let after = market.get_quote(1_000_000, true).unwrap();
+        assert!(after.fee_amount < before.fee_amount);
+        assert!(after.amount_out > before.amount_out);
+        assert!(market.update_state(&[0u8; 8]).is_err());
+    }
+}
@@ -0,0 +1,596 @@
+//! Native Raydium CPMM (standard AMM) quote engine and pool discovery
+//!
+//! Quotes reproduce the program's `swap_base_input`: the Token-2022 transfer fee of the
+//! input mint is taken first, the AmmConfig trade fee is charged on what reaches the
+//! vault (rounded up), the constant-product output is rounded down, and the output
+//! mint's transfer fee is taken from what leaves the vault. Reserves exclude the
+//! protocol and fund fees the pool has accrued but not yet collected.
+//!
+//! Program: CPMMoo8L3F4NbTegBCKVNunggL7H1ZpdTHKxQB5qKP1C
+
+use crate::arbitrage::types::{Route, TokenInfos};
+use crate::common::utils::{from_pubkey, from_str};
+use crate::markets::errors::MarketSimulationError;
+use crate::markets::foundation::{MarketBehavior, Quote};
+use crate::markets::types::{DexLabel, Market, MarketId};
+use crate::markets::token_2022::{decode_token_account, decode_transfer_fee_config, transfer_fee, TOKEN_ACCOUNT_LEN};
+use anchor_spl::token_2022::spl_token_2022::extension::transfer_fee::TransferFeeConfig;
+use anyhow::Result;
+use borsh::{BorshDeserialize, BorshSerialize};
+use log::{error, info};
+use solana_account_decoder::UiAccountEncoding;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_client::rpc_config::{RpcAccountInfoConfig, RpcProgramAccountsConfig};
+use solana_client::rpc_filter::{Memcmp, MemcmpEncodedBytes, RpcFilterType};
+use solana_program::pubkey::Pubkey;
+use std::collections::HashMap;
+
+/// Raydium CPMM program ID
+pub const RAYDIUM_CPMM_PROGRAM_ID: &str = "CPMMoo8L3F4NbTegBCKVNunggL7H1ZpdTHKxQB5qKP1C";
+
+/// Size of a `PoolState` account
+pub const CPMM_POOL_ACCOUNT_LEN: usize = 637;
+
+/// Size of an `AmmConfig` account
+pub const CPMM_AMM_CONFIG_ACCOUNT_LEN: usize = 236;
+
+/// Offsets of `token_0_mint` / `token_1_mint` inside a pool account
+pub const TOKEN_0_MINT_OFFSET: usize = 168;
+pub const TOKEN_1_MINT_OFFSET: usize = 200;
+
+/// Fee rates are expressed over 1e6
+const FEE_RATE_DENOMINATOR: u128 = 1_000_000;
+
+/// Pool status bit that disables swaps
+const STATUS_SWAP_DISABLED: u8 = 1 << 2;
+
+/// CPMM `PoolState` account
+#[derive(Debug, Clone, Default, BorshSerialize, BorshDeserialize)]
+pub struct CpmmPoolState {
+    pub discriminator: [u8; 8],
+    pub amm_config: Pubkey,
+    pub pool_creator: Pubkey,
+    pub token_0_vault: Pubkey,
+    pub token_1_vault: Pubkey,
+    pub lp_mint: Pubkey,
+    pub token_0_mint: Pubkey,
+    pub token_1_mint: Pubkey,
+    /// Token program owning `token_0_mint` (SPL Token or Token-2022)
+    pub token_0_program: Pubkey,
+    /// Token program owning `token_1_mint` (SPL Token or Token-2022)
+    pub token_1_program: Pubkey,
+    pub observation_key: Pubkey,
+    pub auth_bump: u8,
+    /// Bit 0 disables deposits, bit 1 withdrawals, bit 2 swaps
+    pub status: u8,
+    pub lp_mint_decimals: u8,
+    pub mint_0_decimals: u8,
+    pub mint_1_decimals: u8,
+    pub lp_supply: u64,
+    pub protocol_fees_token_0: u64,
+    pub protocol_fees_token_1: u64,
+    pub fund_fees_token_0: u64,
+    pub fund_fees_token_1: u64,
+    pub open_time: u64,
+    pub recent_epoch: u64,
+    pub padding: [u64; 31],
+}
+
+/// CPMM `AmmConfig` account
+#[derive(Debug, Clone, Default, BorshSerialize, BorshDeserialize)]
+pub struct CpmmAmmConfig {
+    pub discriminator: [u8; 8],
+    pub bump: u8,
+    pub disable_create_pool: bool,
+    pub index: u16,
+    /// Trade fee over 1e6
+    pub trade_fee_rate: u64,
+    pub protocol_fee_rate: u64,
+    pub fund_fee_rate: u64,
+    pub create_pool_fee: u64,
+    pub protocol_owner: Pubkey,
+    pub fund_owner: Pubkey,
+    pub padding: [u64; 16],
+}
+
+fn decode_error(details: String) -> MarketSimulationError {
+    MarketSimulationError::AccountDecodeError {
+        market: DexLabel::RaydiumCpmm.str(),
+        details,
+    }
+}
+
+/// Decode a raw CPMM pool account
+pub fn decode_pool_state(data: &[u8]) -> Result<CpmmPoolState, MarketSimulationError> {
+    if data.len() != CPMM_POOL_ACCOUNT_LEN {
+        return Err(decode_error(format!(
+            "PoolState must be {} bytes, got {}",
+            CPMM_POOL_ACCOUNT_LEN,
+            data.len()
+        )));
+    }
+    CpmmPoolState::try_from_slice(data).map_err(|e| decode_error(e.to_string()))
+}
+
+/// Decode a raw CPMM AmmConfig account
+pub fn decode_amm_config(data: &[u8]) -> Result<CpmmAmmConfig, MarketSimulationError> {
+    if data.len() != CPMM_AMM_CONFIG_ACCOUNT_LEN {
+        return Err(decode_error(format!(
+            "AmmConfig must be {} bytes, got {}",
+            CPMM_AMM_CONFIG_ACCOUNT_LEN,
+            data.len()
+        )));
+    }
+    CpmmAmmConfig::try_from_slice(data).map_err(|e| decode_error(e.to_string()))
+}
+
+/// Raydium CPMM pool priced entirely from account data
+#[derive(Debug, Clone)]
+pub struct RaydiumCpmmMarket {
+    /// Pool account address
+    pool_address: Pubkey,
+
+    /// Decoded pool account
+    pool: CpmmPoolState,
+
+    /// AmmConfig trade fee over 1e6
+    trade_fee_rate: u64,
+
+    /// Raw balance of the token 0 vault
+    vault_0_amount: u64,
+
+    /// Raw balance of the token 1 vault
+    vault_1_amount: u64,
+
+    /// Token-2022 transfer fee of each mint; `None` for mints without one
+    mint_0_transfer_fee: Option<TransferFeeConfig>,
+    mint_1_transfer_fee: Option<TransferFeeConfig>,
+
+    /// Epoch used to pick the active transfer fee
+    epoch: u64,
+}
+
+impl RaydiumCpmmMarket {
+    pub fn new(
+        pool_address: Pubkey,
+        pool: CpmmPoolState,
+        trade_fee_rate: u64,
+        vault_0_amount: u64,
+        vault_1_amount: u64,
+    ) -> Self {
+        Self {
+            pool_address,
+            pool,
+            trade_fee_rate,
+            vault_0_amount,
+            vault_1_amount,
+            mint_0_transfer_fee: None,
+            mint_1_transfer_fee: None,
+            epoch: 0,
+        }
+    }
+
+    /// Create a market from the raw pool, AmmConfig and vault token accounts
+    pub fn from_account_data(
+        pool_address: Pubkey,
+        pool_data: &[u8],
+        amm_config_data: &[u8],
+        vault_0_data: &[u8],
+        vault_1_data: &[u8],
+    ) -> Result<Self, MarketSimulationError> {
+        let pool = decode_pool_state(pool_data)?;
+        let amm_config = decode_amm_config(amm_config_data)?;
+        let (_, vault_0_amount) = decode_token_account(vault_0_data)?;
+        let (_, vault_1_amount) = decode_token_account(vault_1_data)?;
+
+        Ok(Self::new(pool_address, pool, amm_config.trade_fee_rate, vault_0_amount, vault_1_amount))
+    }
+
+    pub fn pool_address(&self) -> Pubkey {
+        self.pool_address
+    }
+
+    pub fn pool_state(&self) -> &CpmmPoolState {
+        &self.pool
+    }
+
+    /// Load the transfer fee of one of the pool's mints from its raw mint account.
+    /// Mint accounts don't carry their own address, so they can't go through
+    /// `update_state`.
+    pub fn set_mint_account(&mut self, mint: &Pubkey, mint_data: &[u8]) -> Result<(), MarketSimulationError> {
+        let config = decode_transfer_fee_config(mint, mint_data)?;
+        if *mint == self.pool.token_0_mint {
+            self.mint_0_transfer_fee = config;
+        } else if *mint == self.pool.token_1_mint {
+            self.mint_1_transfer_fee = config;
+        } else {
+            return Err(decode_error(format!("mint {} is not traded by {}", mint, self.pool_address)));
+        }
+        Ok(())
+    }
+
+    /// Set the epoch used to select between the older and newer transfer fee
+    pub fn set_epoch(&mut self, epoch: u64) {
+        self.epoch = epoch;
+    }
+
+    /// Tradable (token 0, token 1) reserves: vault balances minus uncollected
+    /// protocol and fund fees
+    pub fn reserves(&self) -> (u64, u64) {
+        (
+            self.vault_0_amount
+                .saturating_sub(self.pool.protocol_fees_token_0)
+                .saturating_sub(self.pool.fund_fees_token_0),
+            self.vault_1_amount
+                .saturating_sub(self.pool.protocol_fees_token_1)
+                .saturating_sub(self.pool.fund_fees_token_1),
+        )
+    }
+
+    /// Exact-input quote mirroring `swap_base_input`
+    fn quote_exact_in(&self, amount_in: u64, zero_for_one: bool) -> Result<Quote, MarketSimulationError> {
+        if self.pool.status & STATUS_SWAP_DISABLED != 0 {
+            return Err(MarketSimulationError::NoRouteFound {
+                market: DexLabel::RaydiumCpmm.str(),
+                reason: format!("swaps are disabled on pool {}", self.pool_address),
+            });
+        }
+
+        let (reserve_0, reserve_1) = self.reserves();
+        let (reserve_in, reserve_out, fee_in, fee_out) = if zero_for_one {
+            (reserve_0, reserve_1, self.mint_0_transfer_fee.as_ref(), self.mint_1_transfer_fee.as_ref())
+        } else {
+            (reserve_1, reserve_0, self.mint_1_transfer_fee.as_ref(), self.mint_0_transfer_fee.as_ref())
+        };
+        if reserve_in == 0 || reserve_out == 0 {
+            return Err(MarketSimulationError::InsufficientLiquidity {
+                market: MarketId::Raydium,
+                available: reserve_out,
+                required: 1,
+            });
+        }
+
+        let transfer_fee_in = transfer_fee(fee_in, self.epoch, amount_in);
+        let actual_amount_in = amount_in.saturating_sub(transfer_fee_in) as u128;
+        if actual_amount_in == 0 {
+            return Err(MarketSimulationError::NoRouteFound {
+                market: DexLabel::RaydiumCpmm.str(),
+                reason: format!("transfer fee consumes the whole input on pool {}", self.pool_address),
+            });
+        }
+
+        let trade_fee = (actual_amount_in * self.trade_fee_rate as u128).div_ceil(FEE_RATE_DENOMINATOR);
+        let amount_less_fees = actual_amount_in - trade_fee.min(actual_amount_in);
+        let amount_swapped =
+            (amount_less_fees * reserve_out as u128 / (reserve_in as u128 + amount_less_fees)) as u64;
+        let amount_out = amount_swapped - transfer_fee(fee_out, self.epoch, amount_swapped);
+
+        // Impact relative to the spot rate on the post-fee amount
+        let spot_out = reserve_out as f64 * amount_less_fees as f64 / reserve_in as f64;
+        let price_impact = if spot_out > 0.0 {
+            ((1.0 - amount_swapped as f64 / spot_out) * 100.0).clamp(0.0, 100.0)
+        } else {
+            0.0
+        };
+
+        Ok(Quote {
+            amount_in,
+            amount_out,
+            price_impact,
+            fee_amount: transfer_fee_in + trade_fee as u64,
+            slippage_tolerance: 0.5,
+        })
+    }
+}
+
+impl MarketBehavior for RaydiumCpmmMarket {
+    /// `a_to_b` swaps token 0 for token 1
+    fn get_quote(&self, amount_in: u64, a_to_b: bool) -> Result<Quote, MarketSimulationError> {
+        if amount_in == 0 {
+            return Err(MarketSimulationError::InvalidAmount {
+                market: MarketId::Raydium,
+                amount: amount_in,
+            });
+        }
+
+        self.quote_exact_in(amount_in, a_to_b)
+    }
+
+    /// Spot price of token 0 in token 1, in raw token units
+    fn get_price(&self) -> Result<f64, MarketSimulationError> {
+        let (reserve_0, reserve_1) = self.reserves();
+        if reserve_0 == 0 {
+            return Err(MarketSimulationError::InsufficientLiquidity {
+                market: MarketId::Raydium,
+                available: 0,
+                required: 1,
+            });
+        }
+        Ok(reserve_1 as f64 / reserve_0 as f64)
+    }
+
+    /// Accepts the pool account, its AmmConfig or one of its vault token accounts;
+    /// vaults are told apart by their mint.
+    fn update_state(&mut self, new_data: &[u8]) -> Result<(), MarketSimulationError> {
+        match new_data.len() {
+            CPMM_POOL_ACCOUNT_LEN => {
+                self.pool = decode_pool_state(new_data)?;
+            }
+            CPMM_AMM_CONFIG_ACCOUNT_LEN => {
+                self.trade_fee_rate = decode_amm_config(new_data)?.trade_fee_rate;
+            }
+            len if len >= TOKEN_ACCOUNT_LEN => {
+                let (mint, amount) = decode_token_account(new_data)?;
+                if mint == self.pool.token_0_mint {
+                    self.vault_0_amount = amount;
+                } else if mint == self.pool.token_1_mint {
+                    self.vault_1_amount = amount;
+                } else {
+                    return Err(decode_error(format!(
+                        "token account mint {} is not a vault mint of {}",
+                        mint, self.pool_address
+                    )));
+                }
+            }
+            len => return Err(decode_error(format!("unexpected account size {}", len))),
+        }
+        Ok(())
+    }
+
+    fn market_id(&self) -> MarketId {
+        MarketId::Raydium
+    }
+
+    fn dex_label(&self) -> DexLabel {
+        DexLabel::RaydiumCpmm
+    }
+}
+
+/// Fetch a CPMM pool with its AmmConfig, vaults, mints and the current epoch
+pub async fn load_raydium_cpmm_market(
+    rpc_client: &RpcClient,
+    pool: Pubkey,
+) -> Result<RaydiumCpmmMarket, MarketSimulationError> {
+    let rpc_error = |e: solana_client::client_error::ClientError| MarketSimulationError::ApiRequestFailed {
+        market: DexLabel::RaydiumCpmm.str(),
+        message: e.to_string(),
+        source: Some(Box::new(e)),
+    };
+
+    let pool_account = rpc_client.get_account(&pool).await.map_err(rpc_error)?;
+    let pool_state = decode_pool_state(&pool_account.data)?;
+
+    let keys = [
+        pool_state.amm_config,
+        pool_state.token_0_vault,
+        pool_state.token_1_vault,
+        pool_state.token_0_mint,
+        pool_state.token_1_mint,
+    ];
+    let accounts = rpc_client.get_multiple_accounts(&keys).await.map_err(rpc_error)?;
+    let [Some(amm_config), Some(vault_0), Some(vault_1), Some(mint_0), Some(mint_1)] = &accounts[..] else {
+        return Err(MarketSimulationError::MissingField {
+            market: DexLabel::RaydiumCpmm.str(),
+            field: format!("config, vault or mint account of pool {}", pool),
+        });
+    };
+
+    let mut market = RaydiumCpmmMarket::from_account_data(
+        pool,
+        &pool_account.data,
+        &amm_config.data,
+        &vault_0.data,
+        &vault_1.data,
+    )?;
+    market.set_mint_account(&pool_state.token_0_mint, &mint_0.data)?;
+    market.set_mint_account(&pool_state.token_1_mint, &mint_1.data)?;
+    market.set_epoch(rpc_client.get_epoch_info().await.map_err(rpc_error)?.epoch);
+
+    Ok(market)
+}
+
+/// Discover CPMM pools trading `token` on either side, via getProgramAccounts with a
+/// mint filter
+pub async fn fetch_new_raydium_cpmm_pools(
+    rpc_client: &RpcClient,
+    token: String,
+    on_tokena: bool,
+) -> Vec<(Pubkey, Market)> {
+    let mut new_markets: Vec<(Pubkey, Market)> = Vec::new();
+    let filters = Some(vec![
+        RpcFilterType::Memcmp(Memcmp::new(
+            if on_tokena { TOKEN_0_MINT_OFFSET } else { TOKEN_1_MINT_OFFSET },
+            MemcmpEncodedBytes::Base58(token.clone()),
+        )),
+        RpcFilterType::DataSize(CPMM_POOL_ACCOUNT_LEN as u64),
+    ]);
+
+    let accounts = match rpc_client
+        .get_program_accounts_with_config(
+            &from_str(RAYDIUM_CPMM_PROGRAM_ID).unwrap(),
+            RpcProgramAccountsConfig {
+                filters,
+                account_config: RpcAccountInfoConfig {
+                    encoding: Some(UiAccountEncoding::Base64),
+                    commitment: Some(rpc_client.commitment()),
+                    ..RpcAccountInfoConfig::default()
+                },
+                ..RpcProgramAccountsConfig::default()
+            },
+        )
+        .await
+    {
+        Ok(accounts) => accounts,
+        Err(e) => {
+            error!("Raydium CPMM getProgramAccounts failed for {}: {}", token, e);
+            return new_markets;
+        }
+    };
+
+    let pools: Vec<(Pubkey, CpmmPoolState, Vec<u8>)> = accounts
+        .into_iter()
+        .filter_map(|(address, account)| {
+            decode_pool_state(&account.data)
+                .ok()
+                .map(|state| (address, state, account.data))
+        })
+        .collect();
+
+    // Pools share a handful of AmmConfigs; fetch each one once for the trade fee
+    let mut config_keys: Vec<Pubkey> = pools.iter().map(|(_, state, _)| state.amm_config).collect();
+    config_keys.sort();
+    config_keys.dedup();
+    let mut trade_fees: HashMap<Pubkey, u64> = HashMap::new();
+    for batch in config_keys.chunks(100) {
+        match rpc_client.get_multiple_accounts(batch).await {
+            Ok(configs) => {
+                for (key, account) in batch.iter().zip(configs) {
+                    if let Some(config) = account.and_then(|a| decode_amm_config(&a.data).ok()) {
+                        trade_fees.insert(*key, config.trade_fee_rate);
+                    }
+                }
+            }
+            Err(e) => error!("Failed to fetch Raydium CPMM AmmConfigs: {}", e),
+        }
+    }
+
+    for (address, state, data) in pools {
+        let market: Market = Market {
+            token_mint_a: from_pubkey(state.token_0_mint),
+            token_vault_a: from_pubkey(state.token_0_vault),
+            token_mint_b: from_pubkey(state.token_1_mint),
+            token_vault_b: from_pubkey(state.token_1_vault),
+            fee: trade_fees.get(&state.amm_config).copied().unwrap_or_default(),
+            dex_label: DexLabel::RaydiumCpmm,
+            id: from_pubkey(address),
+            account_data: Some(data),
+            liquidity: None,
+        };
+        new_markets.push((address, market));
+    }
+    new_markets
+}
+
+// Simulate one route locally from freshly fetched pool accounts
+pub async fn simulate_route_raydium_cpmm(
+    printing_amt: bool,
+    amount_in: u64,
+    route: Route,
+    market: Market,
+    tokens_infos: HashMap<String, TokenInfos>,
+) -> Result<(u64, u64), MarketSimulationError> {
+    let pool = from_str(&market.id).map_err(|e| MarketSimulationError::InvalidResponseFormat {
+        market: DexLabel::RaydiumCpmm.str(),
+        details: format!("bad pool address {}: {}", market.id, e),
+    })?;
+
+    let env = crate::common::constants::Env::new();
+    let rpc_client = RpcClient::new(env.rpc_url);
+    let cpmm_market = load_raydium_cpmm_market(&rpc_client, pool).await?;
+
+    let quote = cpmm_market.get_quote(amount_in, route.token_0to1)?;
+    let min_amount_out = (quote.amount_out as f64 * (1.0 - quote.slippage_tolerance / 100.0)) as u64;
+
+    if printing_amt {
+        let symbol = |mint: &String| tokens_infos.get(mint).map(|t| t.symbol.clone()).unwrap_or_default();
+        info!(
+            "Local Raydium CPMM: In: {} {}, EstOut: {} {}, EstMinOut: {} {}",
+            quote.amount_in,
+            symbol(&route.token_in),
+            quote.amount_out,
+            symbol(&route.token_out),
+            min_amount_out,
+            symbol(&route.token_out)
+        );
+    }
+
+    Ok((quote.amount_out, min_amount_out))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::markets::token_2022::tests::{token_account, transfer_fee_mint};
+    use anchor_spl::token_2022::spl_token_2022::solana_program::program_pack::Pack;
+    use anchor_spl::token_2022::spl_token_2022::state::Mint;
+
+    fn test_pool() -> CpmmPoolState {
+        CpmmPoolState {
+            token_0_mint: Pubkey::new_unique(),
+            token_1_mint: Pubkey::new_unique(),
+            protocol_fees_token_0: 100,
+            fund_fees_token_1: 200,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_account_sizes() {
+        assert_eq!(borsh::to_vec(&CpmmPoolState::default()).unwrap().len(), CPMM_POOL_ACCOUNT_LEN);
+        assert_eq!(borsh::to_vec(&CpmmAmmConfig::default()).unwrap().len(), CPMM_AMM_CONFIG_ACCOUNT_LEN);
+
+        let pool = test_pool();
+        let data = borsh::to_vec(&pool).unwrap();
+        assert_eq!(&data[TOKEN_0_MINT_OFFSET..TOKEN_0_MINT_OFFSET + 32], pool.token_0_mint.as_ref());
+        assert_eq!(&data[TOKEN_1_MINT_OFFSET..TOKEN_1_MINT_OFFSET + 32], pool.token_1_mint.as_ref());
+    }
+
+    #[test]
+    fn test_constant_product_quote() {
+        // 2_500 / 1e6 trade fee; accrued fees are excluded from reserves
+        let market = RaydiumCpmmMarket::new(Pubkey::new_unique(), test_pool(), 2_500, 1_000_000_100, 2_000_000_200);
+        assert_eq!(market.reserves(), (1_000_000_000, 2_000_000_000));
+
+        let quote = market.get_quote(1_000_000, true).unwrap();
+        assert_eq!(quote.fee_amount, 2_500);
+        assert_eq!(quote.amount_out, 1_993_011);
+
+        let reverse = market.get_quote(1_000_000, false).unwrap();
+        assert_eq!(reverse.amount_out, 498_501);
+        assert!(market.get_quote(0, true).is_err());
+    }
+
+    #[test]
+    fn test_token_2022_transfer_fees() {
+        let pool = test_pool();
+        let (mint_0, mint_1) = (pool.token_0_mint, pool.token_1_mint);
+        let mut market = RaydiumCpmmMarket::new(Pubkey::new_unique(), pool, 2_500, 1_000_000_100, 2_000_000_200);
+        let plain = market.get_quote(1_000_000, true).unwrap();
+
+        // 1% on the input mint, capped at 5_000; 0.5% uncapped on the output mint
+        market.set_mint_account(&mint_0, &transfer_fee_mint(100, 5_000)).unwrap();
+        market.set_mint_account(&mint_1, &transfer_fee_mint(50, u64::MAX)).unwrap();
+        let taxed = market.get_quote(1_000_000, true).unwrap();
+
+        // 5_000 withheld on the way in, then ceil(995_000 * 0.25%) trade fee
+        assert_eq!(taxed.fee_amount, 5_000 + 2_488);
+        let swapped = 992_512u128 * 2_000_000_000 / (1_000_000_000 + 992_512);
+        let out_fee = (swapped * 50).div_ceil(10_000);
+        assert_eq!(taxed.amount_out as u128, swapped - out_fee);
+        assert!(taxed.amount_out < plain.amount_out);
+
+        assert!(market.set_mint_account(&Pubkey::new_unique(), &transfer_fee_mint(1, 1)).is_err());
+    }
+
+    #[test]
+    fn test_update_state_and_disabled_swaps() {
+        let pool = test_pool();
+        let mut market = RaydiumCpmmMarket::new(Pubkey::new_unique(), pool.clone(), 2_500, 1_000_000_100, 2_000_000_200);
+
+        market.update_state(&token_account(&pool.token_1_mint, 4_000_000_200)).unwrap();
+        assert_eq!(market.get_price().unwrap(), 4.0);
+
+        let config = CpmmAmmConfig { trade_fee_rate: 0, ..Default::default() };
+        market.update_state(&borsh::to_vec(&config).unwrap()).unwrap();
+        assert_eq!(market.get_quote(1_000_000, true).unwrap().fee_amount, 0);
+
+        let disabled = CpmmPoolState { status: STATUS_SWAP_DISABLED, ..pool };
+        market.update_state(&borsh::to_vec(&disabled).unwrap()).unwrap();
+        assert!(market.get_quote(1_000_000, true).is_err());
+
+        // Legacy SPL mints carry no transfer fee
+        let mut legacy_mint = vec![0u8; Mint::LEN];
+        Mint { is_initialized: true, ..Default::default() }.pack_into_slice(&mut legacy_mint);
+        assert!(decode_transfer_fee_config(&Pubkey::new_unique(), &legacy_mint).unwrap().is_none());
+    }
+}
@@ -0,0 +1,349 @@
+//! Mint program detection and Token-2022 transfer fees
+//!
+//! A mint's owner says which token program moves it; user token accounts and swap
+//! instructions have to name that program. Token-2022 mints may also carry a
+//! `TransferFeeConfig`, which withholds part of every transfer: a pool only receives
+//! the input net of the fee and the wallet only receives the output net of the fee.
+//!
+//! Mint lookups go through a process-wide cache. A new transfer fee only takes effect
+//! two epochs after it is set, so cached configs stay valid well past `MINT_CACHE_TTL`.
+
+use crate::markets::errors::MarketSimulationError;
+use anchor_spl::token::spl_token;
+use anchor_spl::token_2022::spl_token_2022::{
+    self,
+    extension::{transfer_fee::TransferFeeConfig, BaseStateWithExtensions, StateWithExtensions},
+    state::Mint,
+};
+use dashmap::DashMap;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_program::pubkey::Pubkey;
+use spl_associated_token_account::get_associated_token_address_with_program_id;
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+/// SPL Memo program, passed to the `swap_v2` instructions that move Token-2022 tokens
+pub const MEMO_PROGRAM_ID: &str = "MemoSq4gqABAXKb96qnH8TysNcWxMyWCqXgDLGmfcHr";
+
+/// Size of an SPL token account, and of the base of a Token-2022 one
+pub(crate) const TOKEN_ACCOUNT_LEN: usize = 165;
+
+/// `AccountType::Account` byte that follows the base of an extended Token-2022 account
+const TOKEN_2022_ACCOUNT_TYPE_ACCOUNT: u8 = 2;
+
+/// How long a fetched mint is reused before it is read again
+const MINT_CACHE_TTL: Duration = Duration::from_secs(3_600);
+
+/// How long the current epoch is reused before it is read again
+const EPOCH_CACHE_TTL: Duration = Duration::from_secs(60);
+
+static MINT_CACHE: OnceLock<DashMap<Pubkey, (MintInfo, Instant)>> = OnceLock::new();
+static EPOCH_CACHE: OnceLock<Mutex<Option<(u64, Instant)>>> = OnceLock::new();
+
+/// Token program and transfer fee of a mint
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MintInfo {
+    pub mint: Pubkey,
+    /// SPL Token or Token-2022
+    pub token_program: Pubkey,
+    pub decimals: u8,
+    /// `None` for SPL Token mints and Token-2022 mints without the extension
+    pub transfer_fee: Option<TransferFeeConfig>,
+}
+
+impl MintInfo {
+    /// A classic SPL Token mint
+    pub fn spl_token(mint: Pubkey, decimals: u8) -> Self {
+        Self {
+            mint,
+            token_program: spl_token::ID,
+            decimals,
+            transfer_fee: None,
+        }
+    }
+
+    /// Decode a raw mint account owned by `owner`
+    pub fn from_account(mint: Pubkey, owner: &Pubkey, data: &[u8]) -> Result<Self, MarketSimulationError> {
+        let decode_error = |details: String| MarketSimulationError::MintDecodeError {
+            mint: mint.to_string(),
+            details,
+        };
+        if *owner != spl_token::ID && *owner != spl_token_2022::ID {
+            return Err(decode_error(format!("owned by {}, not a token program", owner)));
+        }
+
+        let state = StateWithExtensions::<Mint>::unpack(data).map_err(|e| decode_error(e.to_string()))?;
+        Ok(Self {
+            mint,
+            token_program: *owner,
+            decimals: state.base.decimals,
+            transfer_fee: state.get_extension::<TransferFeeConfig>().ok().copied(),
+        })
+    }
+
+    pub fn is_token_2022(&self) -> bool {
+        self.token_program == spl_token_2022::ID
+    }
+
+    /// Fee withheld when `amount` is transferred during `epoch`
+    pub fn transfer_fee(&self, epoch: u64, amount: u64) -> u64 {
+        transfer_fee(self.transfer_fee.as_ref(), epoch, amount)
+    }
+
+    /// What arrives when `amount` is sent during `epoch`
+    pub fn net_amount(&self, epoch: u64, amount: u64) -> u64 {
+        amount.saturating_sub(self.transfer_fee(epoch, amount))
+    }
+
+    /// Associated token account of `wallet` for this mint, under the mint's program
+    pub fn associated_token_address(&self, wallet: &Pubkey) -> Pubkey {
+        get_associated_token_address_with_program_id(wallet, &self.mint, &self.token_program)
+    }
+}
+
+/// Transfer fee configuration of a raw SPL Token or Token-2022 mint account, if any
+pub fn decode_transfer_fee_config(
+    mint: &Pubkey,
+    mint_data: &[u8],
+) -> Result<Option<TransferFeeConfig>, MarketSimulationError> {
+    let state = StateWithExtensions::<Mint>::unpack(mint_data).map_err(|e| MarketSimulationError::MintDecodeError {
+        mint: mint.to_string(),
+        details: e.to_string(),
+    })?;
+    Ok(state.get_extension::<TransferFeeConfig>().ok().copied())
+}
+
+/// Fee withheld from `amount` at `epoch`; zero without a config
+pub fn transfer_fee(config: Option<&TransferFeeConfig>, epoch: u64, amount: u64) -> u64 {
+    config
+        .and_then(|config| config.calculate_epoch_fee(epoch, amount))
+        .unwrap_or(0)
+}
+
+/// Re-express an output quoted on the gross input as what the wallet actually receives:
+/// the pool only sees `amount_in` net of the input fee, and the output fee is withheld
+/// on the way out. Output is scaled by the share of the input that reaches the pool,
+/// which never overstates it since swap output is concave in the input.
+pub fn net_of_transfer_fees(amount_in: u64, amount_out: u64, input: &MintInfo, output: &MintInfo, epoch: u64) -> u64 {
+    let reaching_pool = input.net_amount(epoch, amount_in);
+    let scaled = if reaching_pool < amount_in {
+        (amount_out as u128 * reaching_pool as u128 / amount_in as u128) as u64
+    } else {
+        amount_out
+    };
+    output.net_amount(epoch, scaled)
+}
+
+/// Transfer fees of a set of mints as of one epoch
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TransferFees {
+    mints: HashMap<Pubkey, MintInfo>,
+    epoch: u64,
+}
+
+impl TransferFees {
+    pub fn new(mints: impl IntoIterator<Item = MintInfo>, epoch: u64) -> Self {
+        Self {
+            mints: mints.into_iter().map(|info| (info.mint, info)).collect(),
+            epoch,
+        }
+    }
+
+    /// Resolve `mints` over RPC; the epoch is only read when one of them charges a fee
+    pub async fn fetch(rpc_client: &RpcClient, mints: &[Pubkey]) -> Result<Self, MarketSimulationError> {
+        let infos = fetch_mint_infos(rpc_client, mints).await?;
+        let epoch = if infos.values().any(|info| info.transfer_fee.is_some()) {
+            current_epoch(rpc_client).await?
+        } else {
+            0
+        };
+        Ok(Self::new(infos.into_values(), epoch))
+    }
+
+    pub fn mints(&self) -> impl Iterator<Item = &MintInfo> {
+        self.mints.values()
+    }
+
+    pub fn mint(&self, mint: &Pubkey) -> Option<&MintInfo> {
+        self.mints.get(mint)
+    }
+
+    pub fn epoch(&self) -> u64 {
+        self.epoch
+    }
+
+    /// What the wallet receives of `amount_out` quoted on a gross `amount_in`, per
+    /// `net_of_transfer_fees`. Mints not resolved are taken to charge no fee.
+    pub fn net_out(&self, mint_in: &Pubkey, mint_out: &Pubkey, amount_in: u64, amount_out: u64) -> u64 {
+        match (self.mint(mint_in), self.mint(mint_out)) {
+            (Some(input), Some(output)) => net_of_transfer_fees(amount_in, amount_out, input, output, self.epoch),
+            _ => amount_out,
+        }
+    }
+}
+
+/// Fetch the program and transfer fee of `mints`, reusing cached entries
+pub async fn fetch_mint_infos(
+    rpc_client: &RpcClient,
+    mints: &[Pubkey],
+) -> Result<HashMap<Pubkey, MintInfo>, MarketSimulationError> {
+    let cache = MINT_CACHE.get_or_init(DashMap::new);
+    let mut infos = HashMap::with_capacity(mints.len());
+    let mut missing: Vec<Pubkey> = Vec::new();
+    for mint in mints {
+        match cache.get(mint) {
+            Some(entry) if entry.1.elapsed() < MINT_CACHE_TTL => {
+                infos.insert(*mint, entry.0);
+            }
+            _ if !missing.contains(mint) => missing.push(*mint),
+            _ => {}
+        }
+    }
+
+    for batch in missing.chunks(100) {
+        let accounts = rpc_client
+            .get_multiple_accounts(batch)
+            .await
+            .map_err(|e| MarketSimulationError::ApiRequestFailed {
+                market: "mint accounts".to_string(),
+                message: e.to_string(),
+                source: Some(Box::new(e)),
+            })?;
+        for (mint, account) in batch.iter().zip(accounts) {
+            let account = account.ok_or_else(|| MarketSimulationError::MintDecodeError {
+                mint: mint.to_string(),
+                details: "account not found".to_string(),
+            })?;
+            let info = MintInfo::from_account(*mint, &account.owner, &account.data)?;
+            cache.insert(*mint, (info, Instant::now()));
+            infos.insert(*mint, info);
+        }
+    }
+    Ok(infos)
+}
+
+/// Current epoch, used to pick between a mint's older and newer transfer fee
+pub async fn current_epoch(rpc_client: &RpcClient) -> Result<u64, MarketSimulationError> {
+    let cache = EPOCH_CACHE.get_or_init(|| Mutex::new(None));
+    if let Some((epoch, fetched_at)) = *cache.lock().expect("epoch cache poisoned") {
+        if fetched_at.elapsed() < EPOCH_CACHE_TTL {
+            return Ok(epoch);
+        }
+    }
+
+    let epoch = rpc_client
+        .get_epoch_info()
+        .await
+        .map_err(|e| MarketSimulationError::ApiRequestFailed {
+            market: "epoch info".to_string(),
+            message: e.to_string(),
+            source: Some(Box::new(e)),
+        })?
+        .epoch;
+    *cache.lock().expect("epoch cache poisoned") = Some((epoch, Instant::now()));
+    Ok(epoch)
+}
+
+/// Read (mint, amount) from a raw SPL Token or Token-2022 token account. Extended
+/// Token-2022 mints are as large as accounts, so anything past the base must be tagged
+/// as an account.
+pub(crate) fn decode_token_account(data: &[u8]) -> Result<(Pubkey, u64), MarketSimulationError> {
+    let decode_error = |details| MarketSimulationError::AccountDecodeError {
+        market: "token account".to_string(),
+        details,
+    };
+    if data.len() < TOKEN_ACCOUNT_LEN {
+        return Err(decode_error(format!("token account too short: {} bytes", data.len())));
+    }
+    if data.len() > TOKEN_ACCOUNT_LEN && data[TOKEN_ACCOUNT_LEN] != TOKEN_2022_ACCOUNT_TYPE_ACCOUNT {
+        return Err(decode_error(format!("account type {} is not a token account", data[TOKEN_ACCOUNT_LEN])));
+    }
+    let mint = Pubkey::try_from(&data[0..32]).expect("slice is 32 bytes");
+    let amount = u64::from_le_bytes(data[64..72].try_into().expect("slice is 8 bytes"));
+    Ok((mint, amount))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use anchor_spl::token_2022::spl_token_2022::extension::{
+        transfer_fee::TransferFee, BaseStateWithExtensionsMut, ExtensionType, StateWithExtensionsMut,
+    };
+    use anchor_spl::token_2022::spl_token_2022::solana_program::program_pack::Pack;
+
+    /// SPL token account holding `amount` of `mint`
+    pub(crate) fn token_account(mint: &Pubkey, amount: u64) -> Vec<u8> {
+        let mut data = vec![0u8; TOKEN_ACCOUNT_LEN];
+        data[0..32].copy_from_slice(mint.as_ref());
+        data[64..72].copy_from_slice(&amount.to_le_bytes());
+        data
+    }
+
+    /// Token-2022 mint charging `basis_points` on transfers, capped at `maximum_fee`
+    pub(crate) fn transfer_fee_mint(basis_points: u16, maximum_fee: u64) -> Vec<u8> {
+        let len = ExtensionType::try_calculate_account_len::<Mint>(&[ExtensionType::TransferFeeConfig]).unwrap();
+        let mut data = vec![0u8; len];
+        let mut state = StateWithExtensionsMut::<Mint>::unpack_uninitialized(&mut data).unwrap();
+        let fee = TransferFee {
+            epoch: 0.into(),
+            maximum_fee: maximum_fee.into(),
+            transfer_fee_basis_points: basis_points.into(),
+        };
+        let config = state.init_extension::<TransferFeeConfig>(true).unwrap();
+        config.older_transfer_fee = fee;
+        config.newer_transfer_fee = fee;
+        state.base = Mint {
+            decimals: 6,
+            is_initialized: true,
+            ..Default::default()
+        };
+        state.pack_base();
+        state.init_account_type().unwrap();
+        data
+    }
+
+    #[test]
+    fn test_mint_program_detection() {
+        let mut legacy = vec![0u8; Mint::LEN];
+        Mint { decimals: 9, is_initialized: true, ..Default::default() }.pack_into_slice(&mut legacy);
+        let classic = MintInfo::from_account(Pubkey::new_unique(), &spl_token::ID, &legacy).unwrap();
+        assert!(!classic.is_token_2022());
+        assert_eq!(classic.decimals, 9);
+        assert_eq!(classic.transfer_fee(0, 1_000_000), 0);
+
+        let mint = Pubkey::new_unique();
+        let taxed = MintInfo::from_account(mint, &spl_token_2022::ID, &transfer_fee_mint(100, 5_000)).unwrap();
+        assert!(taxed.is_token_2022());
+        assert_eq!(taxed.transfer_fee(0, 100_000), 1_000);
+        assert_eq!(taxed.net_amount(0, 1_000_000), 995_000);
+
+        // Token-2022 accounts live at a different associated address
+        let wallet = Pubkey::new_unique();
+        assert_ne!(
+            taxed.associated_token_address(&wallet),
+            MintInfo { token_program: spl_token::ID, ..taxed }.associated_token_address(&wallet)
+        );
+
+        assert!(MintInfo::from_account(mint, &Pubkey::new_unique(), &legacy).is_err());
+    }
+
+    #[test]
+    fn test_net_of_transfer_fees() {
+        let input = MintInfo::from_account(Pubkey::new_unique(), &spl_token_2022::ID, &transfer_fee_mint(100, u64::MAX))
+            .unwrap();
+        let output = MintInfo::from_account(Pubkey::new_unique(), &spl_token_2022::ID, &transfer_fee_mint(50, u64::MAX))
+            .unwrap();
+        let classic = MintInfo::spl_token(Pubkey::new_unique(), 9);
+
+        assert_eq!(net_of_transfer_fees(1_000_000, 2_000_000, &classic, &classic, 0), 2_000_000);
+        // 1% of the input never reaches the pool, then 0.5% of the output is withheld
+        assert_eq!(net_of_transfer_fees(1_000_000, 2_000_000, &input, &classic, 0), 1_980_000);
+        assert_eq!(net_of_transfer_fees(1_000_000, 2_000_000, &input, &output, 0), 1_980_000 - 9_900);
+
+        let fees = TransferFees::new([input, output], 0);
+        assert_eq!(fees.net_out(&input.mint, &output.mint, 1_000_000, 2_000_000), 1_980_000 - 9_900);
+        // Unresolved mints are taken to charge nothing
+        assert_eq!(fees.net_out(&input.mint, &classic.mint, 1_000_000, 2_000_000), 2_000_000);
+    }
+}
@@ -0,0 +1,382 @@
+//! Token metadata registry
+//!
+//! Resolves what the strategies need to know about a mint from chain: decimals, owning
+//! token program, mint and freeze authorities, and the Metaplex symbol and name when
+//! the mint has a metadata account. Lookups hit an in-memory map first, then batch
+//! `getMultipleAccounts` for the mint and its metadata PDA together.
+//!
+//! Resolved tokens are persisted to a borsh file so a restart doesn't re-read every
+//! mint. Decimals and programs never change, but authorities and metadata can, so
+//! entries older than the configured age are read again on their next lookup.
+
+use crate::arbitrage::types::{TokenInArb, TokenInfos};
+use crate::common::config::Config;
+use anchor_spl::token::spl_token;
+use anchor_spl::token_2022::spl_token_2022::{
+    self,
+    extension::StateWithExtensions,
+    state::Mint,
+};
+use borsh::{BorshDeserialize, BorshSerialize};
+use dashmap::DashMap;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_program::pubkey::Pubkey;
+use solana_sdk::commitment_config::CommitmentConfig;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::{debug, info, warn};
+
+/// Metaplex Token Metadata program
+pub const METAPLEX_METADATA_PROGRAM_ID: &str = "metaqbxxUerdq28cj1RbAWkYQm3ybzjb6a8bt518x1s";
+
+/// `Key::MetadataV1`, the first byte of a metadata account
+const METADATA_V1_KEY: u8 = 4;
+
+/// Key, update authority and mint precede the name
+const METADATA_NAME_OFFSET: usize = 1 + 32 + 32;
+
+/// Accounts per `getMultipleAccounts` request; each mint needs two
+const MAX_ACCOUNTS_PER_REQUEST: usize = 100;
+
+#[derive(Debug, thiserror::Error)]
+pub enum TokenRegistryError {
+    #[error("Token lookup RPC request failed: {0}")]
+    Rpc(String),
+    #[error("Invalid mint address {0}")]
+    InvalidAddress(String),
+    #[error("Mint {0} not found")]
+    NotFound(Pubkey),
+    #[error("Account {mint} is not a mint: {details}")]
+    NotAMint { mint: Pubkey, details: String },
+    #[error("Token cache I/O failed: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Failed to decode token cache: {0}")]
+    Decode(String),
+}
+
+/// What the chain says about a mint
+#[derive(Debug, Clone, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
+pub struct TokenMetadata {
+    pub mint: Pubkey,
+    /// SPL Token or Token-2022
+    pub token_program: Pubkey,
+    pub decimals: u8,
+    pub mint_authority: Option<Pubkey>,
+    pub freeze_authority: Option<Pubkey>,
+    /// Metaplex symbol and name, `None` when the mint has no metadata account
+    pub symbol: Option<String>,
+    pub name: Option<String>,
+    pub fetched_at_unix_secs: u64,
+}
+
+impl TokenMetadata {
+    /// Decode a raw mint account owned by `owner`, with its Metaplex metadata account
+    /// if it has one
+    pub fn from_accounts(
+        mint: Pubkey,
+        owner: &Pubkey,
+        mint_data: &[u8],
+        metadata_data: Option<&[u8]>,
+    ) -> Result<Self, TokenRegistryError> {
+        let not_a_mint = |details: String| TokenRegistryError::NotAMint { mint, details };
+        if *owner != spl_token::ID && *owner != spl_token_2022::ID {
+            return Err(not_a_mint(format!("owned by {}", owner)));
+        }
+        let state = StateWithExtensions::<Mint>::unpack(mint_data).map_err(|e| not_a_mint(e.to_string()))?;
+        let (name, symbol) = metadata_data.and_then(decode_metaplex_name_symbol).unzip();
+        Ok(Self {
+            mint,
+            token_program: *owner,
+            decimals: state.base.decimals,
+            mint_authority: state.base.mint_authority.into(),
+            freeze_authority: state.base.freeze_authority.into(),
+            symbol,
+            name,
+            fetched_at_unix_secs: unix_now(),
+        })
+    }
+
+    /// Metaplex symbol, or the mint address when there is none
+    pub fn symbol_or_mint(&self) -> String {
+        self.symbol.clone().unwrap_or_else(|| self.mint.to_string())
+    }
+
+    /// Whether someone can still freeze holders' token accounts
+    pub fn is_freezable(&self) -> bool {
+        self.freeze_authority.is_some()
+    }
+
+    pub fn age_secs(&self) -> u64 {
+        unix_now().saturating_sub(self.fetched_at_unix_secs)
+    }
+
+    pub fn to_token_infos(&self) -> TokenInfos {
+        TokenInfos {
+            address: self.mint.to_string(),
+            symbol: self.symbol_or_mint(),
+            decimals: self.decimals,
+        }
+    }
+
+    pub fn to_token_in_arb(&self) -> TokenInArb {
+        TokenInArb {
+            token: self.mint.to_string(),
+            symbol: self.symbol_or_mint(),
+            decimals: self.decimals,
+        }
+    }
+}
+
+/// Metaplex metadata PDA of `mint`
+pub fn metadata_address(mint: &Pubkey) -> Pubkey {
+    let program_id = Pubkey::from_str(METAPLEX_METADATA_PROGRAM_ID).expect("valid program id");
+    Pubkey::find_program_address(&[b"metadata", program_id.as_ref(), mint.as_ref()], &program_id).0
+}
+
+/// Name and symbol of a Metaplex metadata account. Both are borsh strings padded with
+/// NUL bytes to their maximum length.
+fn decode_metaplex_name_symbol(data: &[u8]) -> Option<(String, String)> {
+    if data.first() != Some(&METADATA_V1_KEY) {
+        return None;
+    }
+    let mut rest = data.get(METADATA_NAME_OFFSET..)?;
+    let name = String::deserialize(&mut rest).ok()?;
+    let symbol = String::deserialize(&mut rest).ok()?;
+    let trim = |s: String| s.trim_end_matches('\0').trim().to_string();
+    Some((trim(name), trim(symbol))).filter(|(_, symbol)| !symbol.is_empty())
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs())
+}
+
+pub struct TokenRegistry {
+    rpc_client: RpcClient,
+    tokens: DashMap<Pubkey, TokenMetadata>,
+    /// Where resolved tokens are persisted, if anywhere
+    cache_path: Option<PathBuf>,
+    /// Entries older than this are read again on lookup
+    max_age_secs: u64,
+}
+
+impl TokenRegistry {
+    /// A registry seeded from the cache file at `cache_path`, when it can be read
+    pub fn new(rpc_client: RpcClient, cache_path: Option<PathBuf>, max_age_secs: u64) -> Self {
+        let registry = Self {
+            rpc_client,
+            tokens: DashMap::new(),
+            cache_path,
+            max_age_secs,
+        };
+        if let Some(path) = &registry.cache_path {
+            match read_cache(path) {
+                Ok(tokens) => {
+                    info!("✅ Loaded {} cached tokens from {}", tokens.len(), path.display());
+                    registry.extend(tokens);
+                }
+                Err(TokenRegistryError::Io(e)) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => warn!("Ignoring token cache {}: {}", path.display(), e),
+            }
+        }
+        registry
+    }
+
+    pub fn from_config(config: &Config) -> Self {
+        Self::new(
+            RpcClient::new_with_commitment(config.rpc_url.clone(), CommitmentConfig::confirmed()),
+            Some(PathBuf::from(
+                config.token_cache_path.as_deref().unwrap_or("output/token_cache.bin"),
+            )),
+            config.token_metadata_max_age_secs.unwrap_or(86_400),
+        )
+    }
+
+    /// Metadata of `mint`, read from chain unless a fresh copy is cached
+    pub async fn get(&self, mint: &Pubkey) -> Result<TokenMetadata, TokenRegistryError> {
+        self.get_many(&[*mint])
+            .await?
+            .remove(mint)
+            .ok_or(TokenRegistryError::NotFound(*mint))
+    }
+
+    /// Metadata of every mint in `mints`. Missing and stale entries are read in batches;
+    /// the lookup fails if any mint can't be resolved.
+    pub async fn get_many(&self, mints: &[Pubkey]) -> Result<HashMap<Pubkey, TokenMetadata>, TokenRegistryError> {
+        let mut resolved = HashMap::with_capacity(mints.len());
+        let mut missing: Vec<Pubkey> = Vec::new();
+        for mint in mints {
+            match self.tokens.get(mint) {
+                Some(entry) if entry.age_secs() < self.max_age_secs => {
+                    resolved.insert(*mint, entry.clone());
+                }
+                _ if !missing.contains(mint) => missing.push(*mint),
+                _ => {}
+            }
+        }
+        if missing.is_empty() {
+            return Ok(resolved);
+        }
+
+        for batch in missing.chunks(MAX_ACCOUNTS_PER_REQUEST / 2) {
+            let addresses: Vec<Pubkey> = batch
+                .iter()
+                .flat_map(|mint| [*mint, metadata_address(mint)])
+                .collect();
+            let accounts = self
+                .rpc_client
+                .get_multiple_accounts(&addresses)
+                .await
+                .map_err(|e| TokenRegistryError::Rpc(e.to_string()))?;
+            for (mint, pair) in batch.iter().zip(accounts.chunks(2)) {
+                let mint_account = pair[0].as_ref().ok_or(TokenRegistryError::NotFound(*mint))?;
+                let metadata_data = pair.get(1).and_then(Option::as_ref).map(|account| account.data.as_slice());
+                let metadata =
+                    TokenMetadata::from_accounts(*mint, &mint_account.owner, &mint_account.data, metadata_data)?;
+                debug!("Resolved token {} ({} decimals)", metadata.symbol_or_mint(), metadata.decimals);
+                self.tokens.insert(*mint, metadata.clone());
+                resolved.insert(*mint, metadata);
+            }
+        }
+        Ok(resolved)
+    }
+
+    /// Cached metadata of `mint`, however old, without touching the network
+    pub fn cached(&self, mint: &Pubkey) -> Option<TokenMetadata> {
+        self.tokens.get(mint).map(|entry| entry.value().clone())
+    }
+
+    /// Every token resolved so far
+    pub fn tokens(&self) -> Vec<TokenMetadata> {
+        self.tokens.iter().map(|entry| entry.value().clone()).collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.tokens.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tokens.is_empty()
+    }
+
+    fn extend(&self, tokens: Vec<TokenMetadata>) {
+        for token in tokens {
+            self.tokens.insert(token.mint, token);
+        }
+    }
+
+    /// Persist every resolved token to the cache file
+    pub fn save(&self) -> Result<(), TokenRegistryError> {
+        let Some(path) = &self.cache_path else {
+            return Ok(());
+        };
+        let mut tokens = self.tokens();
+        tokens.sort_by_key(|token| token.mint);
+        write_cache(path, &tokens)?;
+        debug!("Wrote {} tokens to {}", tokens.len(), path.display());
+        Ok(())
+    }
+}
+
+fn read_cache(path: &Path) -> Result<Vec<TokenMetadata>, TokenRegistryError> {
+    let bytes = std::fs::read(path)?;
+    Vec::<TokenMetadata>::try_from_slice(&bytes).map_err(|e| TokenRegistryError::Decode(e.to_string()))
+}
+
+/// Write to a temporary file and rename it into place
+fn write_cache(path: &Path, tokens: &[TokenMetadata]) -> Result<(), TokenRegistryError> {
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        std::fs::create_dir_all(dir)?;
+    }
+    let bytes = borsh::to_vec(tokens).map_err(|e| TokenRegistryError::Decode(e.to_string()))?;
+    let tmp_path = path.with_extension("tmp");
+    std::fs::write(&tmp_path, bytes)?;
+    std::fs::rename(&tmp_path, path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_program::program_option::COption;
+    use solana_program::program_pack::Pack;
+
+    fn mint_account(decimals: u8, freeze_authority: Option<Pubkey>) -> Vec<u8> {
+        let mint = Mint {
+            mint_authority: COption::None,
+            supply: 1_000_000,
+            decimals,
+            is_initialized: true,
+            freeze_authority: freeze_authority.into(),
+        };
+        let mut data = vec![0; Mint::LEN];
+        Mint::pack(mint, &mut data).unwrap();
+        data
+    }
+
+    fn metadata_account(mint: &Pubkey, name: &str, symbol: &str) -> Vec<u8> {
+        let padded = |s: &str, len: usize| format!("{}{}", s, "\0".repeat(len - s.len()));
+        let mut data = vec![METADATA_V1_KEY];
+        data.extend_from_slice(Pubkey::new_unique().as_ref());
+        data.extend_from_slice(mint.as_ref());
+        data.extend(borsh::to_vec(&padded(name, 32)).unwrap());
+        data.extend(borsh::to_vec(&padded(symbol, 10)).unwrap());
+        data.extend(borsh::to_vec(&padded("https://example.com", 200)).unwrap());
+        data
+    }
+
+    #[test]
+    fn test_decode_mint_and_metaplex_metadata() {
+        let mint = Pubkey::new_unique();
+        let freeze = Pubkey::new_unique();
+        let metadata = metadata_account(&mint, "USD Coin", "USDC");
+
+        let token = TokenMetadata::from_accounts(mint, &spl_token::ID, &mint_account(6, Some(freeze)), Some(&metadata))
+            .unwrap();
+        assert_eq!(token.decimals, 6);
+        assert_eq!(token.token_program, spl_token::ID);
+        assert_eq!((token.mint_authority, token.freeze_authority), (None, Some(freeze)));
+        assert_eq!((token.symbol.as_deref(), token.name.as_deref()), (Some("USDC"), Some("USD Coin")));
+        assert!(token.is_freezable());
+
+        let bare = TokenMetadata::from_accounts(mint, &spl_token_2022::ID, &mint_account(9, None), None).unwrap();
+        assert_eq!(bare.symbol_or_mint(), mint.to_string());
+        assert!(!bare.is_freezable());
+
+        assert!(matches!(
+            TokenMetadata::from_accounts(mint, &Pubkey::new_unique(), &mint_account(9, None), None),
+            Err(TokenRegistryError::NotAMint { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_cached_tokens_survive_restart_without_network() {
+        let path = std::env::temp_dir().join(format!("token_cache_{}.bin", Pubkey::new_unique()));
+        // Nothing listens here, so only cached tokens resolve
+        let offline_client = || RpcClient::new("http://127.0.0.1:1".to_string());
+
+        let mint = Pubkey::new_unique();
+        let token = TokenMetadata::from_accounts(mint, &spl_token::ID, &mint_account(6, None), None).unwrap();
+        let registry = TokenRegistry::new(offline_client(), Some(path.clone()), 3_600);
+        registry.extend(vec![token.clone()]);
+        registry.save().unwrap();
+
+        let restarted = TokenRegistry::new(offline_client(), Some(path.clone()), 3_600);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(restarted.len(), 1);
+        assert_eq!(restarted.get(&mint).await.unwrap(), token);
+        assert!(matches!(
+            restarted.get(&Pubkey::new_unique()).await,
+            Err(TokenRegistryError::Rpc(_))
+        ));
+
+        // Stale entries are read again
+        let strict = TokenRegistry::new(offline_client(), None, 0);
+        strict.extend(vec![token]);
+        assert!(strict.get(&mint).await.is_err());
+        assert!(strict.cached(&mint).is_some());
+    }
+}
@@ -1,15 +1,19 @@
 use crate::markets::utils::to_pair_string;
+use borsh::{BorshDeserialize, BorshSerialize};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use strum_macros::{EnumIter, Display}; // Added Display
 
-#[derive(Debug, Clone, EnumIter, Serialize, Deserialize, Eq, PartialEq, Hash, Display)] // Added Display
+#[derive(Debug, Clone, EnumIter, Serialize, Deserialize, Eq, PartialEq, Hash, Display, BorshSerialize, BorshDeserialize)] // Added Display
 pub enum DexLabel {
     Orca,
     OrcaWhirlpools,
     Raydium,
     RaydiumClmm,
+    RaydiumCpmm,
     Meteora,
+    OpenBookV2,
+    Phoenix,
 }
 
 /// Market identifier for unified DEX interface
@@ -18,6 +22,8 @@ pub enum MarketId {
     Orca,
     Raydium,
     Meteora,
+    OpenBook,
+    Phoenix,
 }
 
 use std::str::FromStr;
@@ -31,7 +37,10 @@ impl FromStr for DexLabel {
             "Orca (Whirlpools)" => Ok(DexLabel::OrcaWhirlpools),
             "Raydium" => Ok(DexLabel::Raydium),
             "Raydium CLMM" => Ok(DexLabel::RaydiumClmm),
+            "Raydium CPMM" => Ok(DexLabel::RaydiumCpmm),
             "Meteora" => Ok(DexLabel::Meteora),
+            "OpenBook v2" => Ok(DexLabel::OpenBookV2),
+            "Phoenix" => Ok(DexLabel::Phoenix),
             _ => Err(()),
         }
     }
@@ -44,7 +53,10 @@ impl DexLabel {
             DexLabel::OrcaWhirlpools => String::from("Orca (Whirlpools)"),
             DexLabel::Raydium => String::from("Raydium"),
             DexLabel::RaydiumClmm => String::from("Raydium CLMM"),
+            DexLabel::RaydiumCpmm => String::from("Raydium CPMM"),
             DexLabel::Meteora => String::from("Meteora"),
+            DexLabel::OpenBookV2 => String::from("OpenBook v2"),
+            DexLabel::Phoenix => String::from("Phoenix"),
         }
     }
     pub fn api_url(&self) -> String {
@@ -55,7 +67,12 @@ impl DexLabel {
             }
             DexLabel::Raydium => String::from("https://api.raydium.io/v2/main/pairs"),
             DexLabel::RaydiumClmm => String::from("https://api.raydium.io/v2/ammV3/ammPools"),
+            DexLabel::RaydiumCpmm => String::from(
+                "https://api-v3.raydium.io/pools/info/list?poolType=standard&poolSortField=default&sortType=desc&pageSize=1000&page=1",
+            ),
             DexLabel::Meteora => String::from("https://dlmm-api.meteora.ag/pair/all"),
+            // Order book markets are only discovered on-chain
+            DexLabel::OpenBookV2 | DexLabel::Phoenix => String::new(),
         }
     }
 }
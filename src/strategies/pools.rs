@@ -2,8 +2,10 @@ use crate::{
     arbitrage::types::TokenInArb,
     common::constants::Env,
     markets::{
-        meteora::fetch_new_meteora_pools, orca_whirpools::fetch_new_orca_whirpools,
-        raydium::fetch_new_raydium_pools, types::Market,
+        meteora::fetch_new_meteora_pools, openbook_v2::fetch_new_openbook_v2_markets,
+        orca_whirpools::fetch_new_orca_whirpools, phoenix::fetch_new_phoenix_markets,
+        raydium::fetch_new_raydium_pools, raydium_cpmm::fetch_new_raydium_cpmm_pools,
+        types::Market,
     },
 };
 use log::info;
@@ -60,6 +62,23 @@ pub async fn get_fresh_pools(tokens: Vec<TokenInArb>) -> HashMap<String, Market>
             _count_new_pools += 1;
         }
         sleep(Duration::from_millis(2000)).await;
+        println!("1 GetProgramAccounts Raydium CPMM");
+        //Raydium CPMM Markets
+        let raydium_cpmm_res_tokena =
+            fetch_new_raydium_cpmm_pools(&rpc_client, token.token.clone(), true).await;
+        for raydium_cpmm_pool in raydium_cpmm_res_tokena {
+            new_markets.insert(raydium_cpmm_pool.0.to_string(), raydium_cpmm_pool.1);
+            _count_new_pools += 1;
+        }
+        sleep(Duration::from_millis(2000)).await;
+        println!("1 GetProgramAccounts Raydium CPMM");
+        let raydium_cpmm_res_tokenb =
+            fetch_new_raydium_cpmm_pools(&rpc_client, token.token.clone(), false).await;
+        for raydium_cpmm_pool in raydium_cpmm_res_tokenb {
+            new_markets.insert(raydium_cpmm_pool.0.to_string(), raydium_cpmm_pool.1);
+            _count_new_pools += 1;
+        }
+        sleep(Duration::from_millis(2000)).await;
         println!("1 GetProgramAccounts Meteora");
         //Meteora Markets
         let meteora_res_tokena =
@@ -77,6 +96,38 @@ pub async fn get_fresh_pools(tokens: Vec<TokenInArb>) -> HashMap<String, Market>
             _count_new_pools += 1;
         }
         sleep(Duration::from_millis(2000)).await;
+        println!("1 GetProgramAccounts OpenBook v2");
+        //OpenBook v2 Markets
+        let openbook_res_base =
+            fetch_new_openbook_v2_markets(&rpc_client, token.token.clone(), true).await;
+        for openbook_market in openbook_res_base {
+            new_markets.insert(openbook_market.0.to_string(), openbook_market.1);
+            _count_new_pools += 1;
+        }
+        sleep(Duration::from_millis(2000)).await;
+        println!("1 GetProgramAccounts OpenBook v2");
+        let openbook_res_quote =
+            fetch_new_openbook_v2_markets(&rpc_client, token.token.clone(), false).await;
+        for openbook_market in openbook_res_quote {
+            new_markets.insert(openbook_market.0.to_string(), openbook_market.1);
+            _count_new_pools += 1;
+        }
+        sleep(Duration::from_millis(2000)).await;
+        println!("1 GetProgramAccounts Phoenix");
+        //Phoenix Markets
+        let phoenix_res_base = fetch_new_phoenix_markets(&rpc_client, token.token.clone(), true).await;
+        for phoenix_market in phoenix_res_base {
+            new_markets.insert(phoenix_market.0.to_string(), phoenix_market.1);
+            _count_new_pools += 1;
+        }
+        sleep(Duration::from_millis(2000)).await;
+        println!("1 GetProgramAccounts Phoenix");
+        let phoenix_res_quote = fetch_new_phoenix_markets(&rpc_client, token.token.clone(), false).await;
+        for phoenix_market in phoenix_res_quote {
+            new_markets.insert(phoenix_market.0.to_string(), phoenix_market.1);
+            _count_new_pools += 1;
+        }
+        sleep(Duration::from_millis(2000)).await;
     }
     info!("⚠️⚠️ NO RAYDIUM_CLMM fresh pools !");
     info!("⚠️⚠️ NO ORCA fresh pools !");
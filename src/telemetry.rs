@@ -16,6 +16,8 @@ pub struct Metrics {
     pub opportunities_sent: Arc<AtomicU64>,
     pub opportunities_dropped: Arc<AtomicU64>,
     pub opportunities_rejected: Arc<AtomicU64>,
+    /// Opportunities not sent because a copy over the same pools just was
+    pub duplicates_suppressed: Arc<AtomicU64>,
 }
 
 impl Metrics {
@@ -29,6 +31,7 @@ impl Metrics {
             opportunities_sent: Arc::new(AtomicU64::new(0)),
             opportunities_dropped: Arc::new(AtomicU64::new(0)),
             opportunities_rejected: Arc::new(AtomicU64::new(0)),
+            duplicates_suppressed: Arc::new(AtomicU64::new(0)),
         })
     }
 
@@ -50,6 +53,13 @@ impl Metrics {
         self.pools_loaded.fetch_add(count, Ordering::Relaxed);
     }
 
+    pub fn sub_pools_loaded(&self, count: u64) {
+        // Never wraps below zero if a removal is seen before its addition
+        let _ = self.pools_loaded.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |loaded| {
+            Some(loaded.saturating_sub(count))
+        });
+    }
+
     pub fn inc_opportunities_sent(&self) {
         self.opportunities_sent.fetch_add(1, Ordering::Relaxed);
     }
@@ -61,6 +71,10 @@ impl Metrics {
     pub fn inc_opportunities_rejected(&self) {
         self.opportunities_rejected.fetch_add(1, Ordering::Relaxed);
     }
+
+    pub fn inc_duplicates_suppressed(&self) {
+        self.duplicates_suppressed.fetch_add(1, Ordering::Relaxed);
+    }
 }
 
 pub fn init_telemetry() -> Arc<Metrics> {
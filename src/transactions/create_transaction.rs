@@ -1,4 +1,3 @@
-use anchor_spl::token::spl_token;
 use anyhow::Result;
 // Removed unused import: itertools::Itertools
 use log::error;
@@ -27,7 +26,6 @@ use solana_sdk::{
     transaction::VersionedTransaction,
 };
 use solana_transaction_status::UiTransactionEncoding;
-use spl_associated_token_account::get_associated_token_address;
 use spl_associated_token_account::instruction::create_associated_token_account;
 use std::io::{BufWriter, Write};
 use std::{
@@ -39,13 +37,17 @@ use std::{
 
 use super::{
     meteoradlmm_swap::{construct_meteora_instructions, SwapParametersMeteora},
+    openbook_v2_swap::{construct_openbook_v2_instructions, SwapParametersOpenBookV2},
+    orca_swap::{construct_orca_instructions, SwapParametersOrca},
     orca_whirpools_swap::{construct_orca_whirpools_instructions, SwapParametersOrcaWhirpools},
+    phoenix_swap::{construct_phoenix_instructions, SwapParametersPhoenix},
+    raydium_cpmm_swap::{construct_raydium_cpmm_instructions, SwapParametersRaydiumCpmm},
     raydium_swap::{construct_raydium_instructions, SwapParametersRaydium},
 };
 use crate::{
     arbitrage::types::SwapPathResult,
     common::{constants::Env, utils::from_str},
-    markets::types::DexLabel,
+    markets::{token_2022::fetch_mint_infos, types::DexLabel},
     transactions::utils::check_tx_status,
 };
 
@@ -444,9 +446,13 @@ pub async fn create_ata_extendlut_transaction(
 
     let mut vec_pda_instructions: Vec<Instruction> = Vec::new();
 
+    // ATAs are derived and created under each mint's own token program
+    let mint_infos = fetch_mint_infos(&rpc_client, &tokens).await?;
+
     //Create Pda/Ata accounts
     for token in tokens {
-        let pda_user_token = get_associated_token_address(&payer.pubkey(), &token);
+        let mint_info = mint_infos[&token];
+        let pda_user_token = mint_info.associated_token_address(&payer.pubkey());
         match rpc_client.get_account(&pda_user_token).await { // Changed to await
             Ok(_account) => {
                 // Changed variable name
@@ -459,7 +465,7 @@ pub async fn create_ata_extendlut_transaction(
                     &payer.pubkey(),
                     &payer.pubkey(),
                     &token,
-                    &spl_token::id(),
+                    &mint_info.token_program,
                 );
                 vec_pda_instructions.push(create_pda_instruction);
             }
@@ -783,6 +789,75 @@ pub async fn construct_transaction(transaction_infos: SwapPathResult) -> Vec<Ins
                     }
                 }
             }
+            DexLabel::RaydiumCpmm => {
+                let env = Env::new();
+                let payer = read_keypair_file(env.payer_keypair_path).expect("Wallet keypair file not found");
+                let swap_params = SwapParametersRaydiumCpmm {
+                    pool: from_str(transaction_infos.route_simulations[i].pool_address.as_str()).unwrap(),
+                    input_token_mint: from_str(route_sim.token_in.as_str()).unwrap(),
+                    output_token_mint: from_str(route_sim.token_out.as_str()).unwrap(),
+                    amount_in: transaction_infos.route_simulations[i].amount_in,
+                    min_amount_out: transaction_infos.route_simulations[i].minimum_amount_out,
+                    wallet_pubkey: payer.pubkey(),
+                };
+                match construct_raydium_cpmm_instructions(swap_params).await {
+                    Ok(instructions) => {
+                        for instruction in instructions {
+                            swap_instructions.push(instruction);
+                        }
+                    }
+                    Err(e) => {
+                        error!("Error in Raydium CPMM Instruction construction: {}", e);
+                        return Vec::new();
+                    }
+                }
+            }
+            DexLabel::OpenBookV2 => {
+                let env = Env::new();
+                let payer = read_keypair_file(env.payer_keypair_path).expect("Wallet keypair file not found");
+                let swap_params = SwapParametersOpenBookV2 {
+                    market: from_str(transaction_infos.route_simulations[i].pool_address.as_str()).unwrap(),
+                    input_token_mint: from_str(route_sim.token_in.as_str()).unwrap(),
+                    output_token_mint: from_str(route_sim.token_out.as_str()).unwrap(),
+                    amount_in: transaction_infos.route_simulations[i].amount_in,
+                    min_amount_out: transaction_infos.route_simulations[i].minimum_amount_out,
+                    wallet_pubkey: payer.pubkey(),
+                };
+                match construct_openbook_v2_instructions(swap_params).await {
+                    Ok(instructions) => {
+                        for instruction in instructions {
+                            swap_instructions.push(instruction);
+                        }
+                    }
+                    Err(e) => {
+                        error!("Error in OpenBook v2 Instruction construction: {}", e);
+                        return Vec::new();
+                    }
+                }
+            }
+            DexLabel::Phoenix => {
+                let env = Env::new();
+                let payer = read_keypair_file(env.payer_keypair_path).expect("Wallet keypair file not found");
+                let swap_params = SwapParametersPhoenix {
+                    market: from_str(transaction_infos.route_simulations[i].pool_address.as_str()).unwrap(),
+                    input_token_mint: from_str(route_sim.token_in.as_str()).unwrap(),
+                    output_token_mint: from_str(route_sim.token_out.as_str()).unwrap(),
+                    amount_in: transaction_infos.route_simulations[i].amount_in,
+                    min_amount_out: transaction_infos.route_simulations[i].minimum_amount_out,
+                    wallet_pubkey: payer.pubkey(),
+                };
+                match construct_phoenix_instructions(swap_params).await {
+                    Ok(instructions) => {
+                        for instruction in instructions {
+                            swap_instructions.push(instruction);
+                        }
+                    }
+                    Err(e) => {
+                        error!("Error in Phoenix Instruction construction: {}", e);
+                        return Vec::new();
+                    }
+                }
+            }
             DexLabel::OrcaWhirlpools => {
                 let swap_params: SwapParametersOrcaWhirpools = SwapParametersOrcaWhirpools {
                     whirpools: from_str(
@@ -813,34 +888,21 @@ pub async fn construct_transaction(transaction_infos: SwapPathResult) -> Vec<Ins
                 }
             }
             DexLabel::Orca => {
-                info!("Creating REAL Orca transaction...");
-                
-                // For regular Orca pools, we need to implement the swap instruction
-                // Most Orca pools are now Whirlpools, but some legacy pools remain
-                let input_token = from_str(route_sim.token_in.as_str()).unwrap();
-                let output_token = from_str(route_sim.token_out.as_str()).unwrap();
-                let pool_address = from_str(transaction_infos.route_simulations[i].pool_address.as_str()).unwrap();
-                
-                // Create swap parameters for legacy Orca pools
-                let swap_params = crate::transactions::orca_whirpools_swap::SwapParametersOrcaWhirpools {
-                    whirpools: pool_address,
-                    input_token,
-                    output_token,
+                let swap_params = SwapParametersOrca {
+                    pool: from_str(transaction_infos.route_simulations[i].pool_address.as_str()).unwrap(),
+                    input_token: from_str(route_sim.token_in.as_str()).unwrap(),
+                    output_token: from_str(route_sim.token_out.as_str()).unwrap(),
                     amount_in: transaction_infos.route_simulations[i].amount_in,
                     minimum_amount_out: transaction_infos.route_simulations[i].minimum_amount_out,
                 };
-                
-                // Generate REAL Orca instructions (using Whirlpools as most Orca is now Whirlpools)
-                let instructions = crate::transactions::orca_whirpools_swap::construct_orca_whirpools_instructions(swap_params).await;
-                if instructions.is_empty() {
-                    error!("❌ Failed to construct Orca instructions");
+                let result = construct_orca_instructions(swap_params).await;
+                if result.is_empty() {
+                    error!("Error in Orca Instruction construction: returned empty");
                     return Vec::new();
                 }
-                
-                for instruction in instructions {
+                for instruction in result {
                     swap_instructions.push(instruction);
                 }
-                info!("✅ Added REAL Orca transaction with {} instructions", swap_instructions.len());
             }
         }
     }
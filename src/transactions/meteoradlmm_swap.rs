@@ -1,8 +1,6 @@
 // //Taken here: https://github.com/MeteoraAg/dlmm-sdk/blob/main/cli/src/instructions/swap.rs
 
 use anchor_client::{solana_sdk::pubkey::Pubkey, solana_sdk::signer::Signer};
-use anchor_spl::associated_token::get_associated_token_address;
-use anchor_spl::token::spl_token;
 use anyhow::*;
 
 use borsh::{BorshDeserialize, BorshSerialize};
@@ -12,12 +10,14 @@ use solana_program::hash;
 use solana_program::instruction::AccountMeta;
 use std::result::Result::Ok;
 
+use log::error;
 use solana_sdk::instruction::Instruction;
 use solana_sdk::signature::read_keypair_file;
 
 use crate::common::constants::Env;
 use crate::common::utils::from_str;
 use crate::markets::meteora::AccountData;
+use crate::markets::token_2022::fetch_mint_infos;
 use crate::markets::types::DexLabel;
 use crate::transactions::create_transaction::{InstructionDetails, MarketInfos};
 
@@ -60,8 +60,22 @@ pub async fn construct_meteora_instructions(
     let (event_authority, _bump) =
         Pubkey::find_program_address(&[b"__event_authority"], &amm_program);
 
+    // Either side of the pair may be a Token-2022 mint
+    let mints = match fetch_mint_infos(&rpc_client, &[pool_state.token_xmint, pool_state.token_ymint]).await {
+        Ok(mints) => mints,
+        Err(e) => {
+            error!("Mint accounts of the pair not found: {}", e);
+            return swap_instructions;
+        }
+    };
+    let mint_x = mints[&pool_state.token_xmint];
+    let mint_y = mints[&pool_state.token_ymint];
+    let user_token_account = |mint: &Pubkey| {
+        if *mint == mint_x.mint { mint_x } else { mint_y }.associated_token_address(&payer.pubkey())
+    };
+
     //Get PDA
-    let pda_user_source = get_associated_token_address(&payer.pubkey(), &input_token);
+    let pda_user_source = user_token_account(&input_token);
     match rpc_client.get_account(&pda_user_source).await { // Changed to await
         Ok(_account) => {}
         Err(_error) => {
@@ -69,7 +83,7 @@ pub async fn construct_meteora_instructions(
         }
     }
 
-    let pda_user_destination = get_associated_token_address(&payer.pubkey(), &output_token);
+    let pda_user_destination = user_token_account(&output_token);
 
     match rpc_client.get_account(&pda_user_destination).await { // Changed to await
         Ok(_account) => {}
@@ -105,9 +119,9 @@ pub async fn construct_meteora_instructions(
         AccountMeta::new(amm_program, false),
         //user
         AccountMeta::new_readonly(payer.pubkey(), true),
-        //token program
-        AccountMeta::new_readonly(spl_token::id(), false),
-        AccountMeta::new_readonly(spl_token::id(), false),
+        //token program x, token program y
+        AccountMeta::new_readonly(mint_x.token_program, false),
+        AccountMeta::new_readonly(mint_y.token_program, false),
         //Event authority
         AccountMeta::new(event_authority, false),
         AccountMeta::new_readonly(amm_program, false),
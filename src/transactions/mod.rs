@@ -1,6 +1,10 @@
 pub mod create_transaction;
 pub mod meteoradlmm_swap;
+pub mod openbook_v2_swap;
+pub mod orca_swap;
 pub mod orca_whirpools_swap;
+pub mod phoenix_swap;
 pub mod raydium_clmm_swap;
+pub mod raydium_cpmm_swap;
 pub mod raydium_swap;
 pub mod utils;
@@ -0,0 +1,257 @@
+//! OpenBook v2 Swap Implementation
+//!
+//! Takes liquidity with `place_take_order` as an immediate-or-cancel order, so nothing
+//! rests on the book and no open-orders account is needed. Selling base is an `Ask`
+//! capped at `amount_in` base lots; buying base is a `Bid` capped at `amount_in` quote
+//! lots, fees included. The limit price is the worst per-lot price that still meets
+//! `min_amount_out`, before the taker fee.
+
+use anchor_client::solana_sdk::pubkey::Pubkey;
+use anchor_spl::associated_token::get_associated_token_address;
+use log::trace;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_program::hash;
+use solana_sdk::instruction::{AccountMeta, Instruction};
+use std::str::FromStr;
+
+use crate::markets::openbook_v2::{decode_market, OPENBOOK_V2_PROGRAM_ID};
+use crate::markets::types::DexLabel;
+use crate::transactions::create_transaction::{InstructionDetails, MarketInfos};
+
+/// `Side` of the order
+const SIDE_BID: u8 = 0;
+const SIDE_ASK: u8 = 1;
+
+/// `PlaceOrderType::ImmediateOrCancel`
+const ORDER_TYPE_IMMEDIATE_OR_CANCEL: u8 = 1;
+
+/// Maximum number of resting orders matched before the order stops
+const MATCH_LIMIT: u8 = 50;
+
+/// OpenBook v2-specific error types
+#[derive(Debug, thiserror::Error)]
+pub enum OpenBookV2Error {
+    #[error("Invalid program ID: {0}")]
+    InvalidProgramId(String),
+    #[error("Failed to fetch market account: {0}")]
+    MarketAccountFetch(String),
+    #[error("Failed to deserialize market: {0}")]
+    MarketDeserialization(String),
+    #[error("Invalid swap parameters: {0}")]
+    InvalidParameters(String),
+}
+
+/// OpenBook v2 Swap Parameters
+#[derive(Debug, Clone)]
+pub struct SwapParametersOpenBookV2 {
+    /// Market account address
+    pub market: Pubkey,
+    /// Input token mint
+    pub input_token_mint: Pubkey,
+    /// Output token mint
+    pub output_token_mint: Pubkey,
+    /// Amount of input tokens to swap
+    pub amount_in: u64,
+    /// Minimum amount of output tokens expected
+    pub min_amount_out: u64,
+    /// Wallet signing the order and owning the token accounts
+    pub wallet_pubkey: Pubkey,
+}
+
+impl SwapParametersOpenBookV2 {
+    /// Validate swap parameters for safety
+    pub fn validate(&self) -> Result<(), OpenBookV2Error> {
+        if self.amount_in == 0 {
+            return Err(OpenBookV2Error::InvalidParameters("Amount in cannot be zero".to_string()));
+        }
+        if self.input_token_mint == self.output_token_mint {
+            return Err(OpenBookV2Error::InvalidParameters("Input and output mints must differ".to_string()));
+        }
+        Ok(())
+    }
+}
+
+/// Anchor discriminator of `place_take_order`
+fn place_take_order_discriminator() -> [u8; 8] {
+    let mut sighash = [0u8; 8];
+    sighash.copy_from_slice(&hash::hash(b"global:place_take_order").to_bytes()[..8]);
+    sighash
+}
+
+/// Serialized `PlaceTakeOrderArgs`
+fn place_take_order_data(side: u8, price_lots: i64, max_base_lots: i64, max_quote_lots_including_fees: i64) -> Vec<u8> {
+    let mut data = Vec::with_capacity(35);
+    data.extend_from_slice(&place_take_order_discriminator());
+    data.push(side);
+    data.extend_from_slice(&price_lots.to_le_bytes());
+    data.extend_from_slice(&max_base_lots.to_le_bytes());
+    data.extend_from_slice(&max_quote_lots_including_fees.to_le_bytes());
+    data.push(ORDER_TYPE_IMMEDIATE_OR_CANCEL);
+    data.push(MATCH_LIMIT);
+    data
+}
+
+/// (side, price_lots, max_base_lots, max_quote_lots_including_fees) of the order
+fn order_terms(
+    sell_base: bool,
+    amount_in: u64,
+    min_amount_out: u64,
+    base_lot_size: u64,
+    quote_lot_size: u64,
+) -> Result<(u8, i64, i64, i64), OpenBookV2Error> {
+    let to_i64 = |value: u64| i64::try_from(value).unwrap_or(i64::MAX);
+    if base_lot_size == 0 || quote_lot_size == 0 {
+        return Err(OpenBookV2Error::MarketDeserialization("zero lot size".to_string()));
+    }
+
+    if sell_base {
+        let base_lots = amount_in / base_lot_size;
+        if base_lots == 0 {
+            return Err(OpenBookV2Error::InvalidParameters(format!(
+                "{} is below one base lot ({})",
+                amount_in, base_lot_size
+            )));
+        }
+        // Lowest price per base lot that still yields min_amount_out
+        let min_quote_lots = min_amount_out.div_ceil(quote_lot_size);
+        let price_lots = min_quote_lots.div_ceil(base_lots).max(1);
+        Ok((SIDE_ASK, to_i64(price_lots), to_i64(base_lots), i64::MAX))
+    } else {
+        let quote_lots = amount_in / quote_lot_size;
+        if quote_lots == 0 {
+            return Err(OpenBookV2Error::InvalidParameters(format!(
+                "{} is below one quote lot ({})",
+                amount_in, quote_lot_size
+            )));
+        }
+        // Highest price per base lot that still yields min_amount_out
+        let min_base_lots = min_amount_out.div_ceil(base_lot_size);
+        let price_lots = quote_lots
+            .checked_div(min_base_lots)
+            .map_or(i64::MAX, |price_lots| to_i64(price_lots.max(1)));
+        Ok((SIDE_BID, price_lots, i64::MAX, to_i64(quote_lots)))
+    }
+}
+
+/// Constructs an OpenBook v2 immediate-or-cancel take order
+pub async fn construct_openbook_v2_instructions(
+    params: SwapParametersOpenBookV2,
+) -> Result<Vec<InstructionDetails>, OpenBookV2Error> {
+    params.validate()?;
+
+    let program_id = Pubkey::from_str(OPENBOOK_V2_PROGRAM_ID)
+        .map_err(|e| OpenBookV2Error::InvalidProgramId(e.to_string()))?;
+
+    let env = crate::common::constants::Env::new();
+    let rpc_client = RpcClient::new(env.rpc_url);
+
+    let market_account = rpc_client
+        .get_account(&params.market)
+        .await
+        .map_err(|e| OpenBookV2Error::MarketAccountFetch(format!("{}: {}", params.market, e)))?;
+    let market = decode_market(&market_account.data)
+        .map_err(|e| OpenBookV2Error::MarketDeserialization(e.to_string()))?;
+
+    if let Some(admin) = market.open_orders_admin {
+        return Err(OpenBookV2Error::InvalidParameters(format!(
+            "market {} requires open-orders admin {} to co-sign",
+            params.market, admin
+        )));
+    }
+
+    let sell_base = if params.input_token_mint == market.base_mint && params.output_token_mint == market.quote_mint {
+        true
+    } else if params.input_token_mint == market.quote_mint && params.output_token_mint == market.base_mint {
+        false
+    } else {
+        return Err(OpenBookV2Error::InvalidParameters(format!(
+            "{} -> {} is not traded by market {}",
+            params.input_token_mint, params.output_token_mint, params.market
+        )));
+    };
+
+    let (side, price_lots, max_base_lots, max_quote_lots) = order_terms(
+        sell_base,
+        params.amount_in,
+        params.min_amount_out,
+        market.base_lot_size,
+        market.quote_lot_size,
+    )?;
+
+    let user_base_account = get_associated_token_address(&params.wallet_pubkey, &market.base_mint);
+    let user_quote_account = get_associated_token_address(&params.wallet_pubkey, &market.quote_mint);
+
+    // Absent optional accounts are passed as the program ID
+    let accounts = vec![
+        AccountMeta::new_readonly(params.wallet_pubkey, true),
+        // The penalty payer covers the fee charged when the book can't fill anything
+        AccountMeta::new(params.wallet_pubkey, true),
+        AccountMeta::new(params.market, false),
+        AccountMeta::new_readonly(market.market_authority, false),
+        AccountMeta::new(market.bids, false),
+        AccountMeta::new(market.asks, false),
+        AccountMeta::new(market.market_base_vault, false),
+        AccountMeta::new(market.market_quote_vault, false),
+        AccountMeta::new(market.event_heap, false),
+        AccountMeta::new(user_base_account, false),
+        AccountMeta::new(user_quote_account, false),
+        AccountMeta::new_readonly(market.oracle_a.unwrap_or(program_id), false),
+        AccountMeta::new_readonly(market.oracle_b.unwrap_or(program_id), false),
+        AccountMeta::new_readonly(anchor_spl::token::ID, false),
+        AccountMeta::new_readonly(solana_sdk::system_program::ID, false),
+        AccountMeta::new_readonly(program_id, false),
+    ];
+
+    let instruction = Instruction {
+        program_id,
+        accounts,
+        data: place_take_order_data(side, price_lots, max_base_lots, max_quote_lots),
+    };
+
+    trace!(
+        "Constructed OpenBook v2 IOC on {}: {} {} -> min {} {}",
+        params.market,
+        params.amount_in,
+        params.input_token_mint,
+        params.min_amount_out,
+        params.output_token_mint
+    );
+
+    Ok(vec![InstructionDetails {
+        instruction,
+        details: format!("OpenBook v2 take order: {} -> {}", params.input_token_mint, params.output_token_mint),
+        market: Some(MarketInfos {
+            dex_label: DexLabel::OpenBookV2,
+            address: params.market,
+        }),
+    }])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_place_take_order_data() {
+        let data = place_take_order_data(SIDE_ASK, 7, 3, i64::MAX);
+        assert_eq!(data.len(), 35);
+        assert_eq!(&data[..8], &hash::hash(b"global:place_take_order").to_bytes()[..8]);
+        assert_eq!(data[8], SIDE_ASK);
+        assert_eq!(&data[9..17], &7i64.to_le_bytes());
+        assert_eq!(&data[33..], &[ORDER_TYPE_IMMEDIATE_OR_CANCEL, MATCH_LIMIT]);
+    }
+
+    #[test]
+    fn test_order_terms() {
+        // Sell 2.5 lots of 1_000 for at least 2_001 quote atoms in lots of 10:
+        // 2 lots, 201 quote lots, so at least 101 per lot
+        assert_eq!(order_terms(true, 2_500, 2_001, 1_000, 10).unwrap(), (SIDE_ASK, 101, 2, i64::MAX));
+
+        // Spend 5_000 quote atoms (500 lots) for at least 3 base lots: at most 166 per lot
+        assert_eq!(order_terms(false, 5_000, 2_500, 1_000, 10).unwrap(), (SIDE_BID, 166, i64::MAX, 500));
+        assert_eq!(order_terms(false, 5_000, 0, 1_000, 10).unwrap().1, i64::MAX);
+
+        assert!(order_terms(true, 999, 0, 1_000, 10).is_err());
+        assert!(order_terms(false, 9, 0, 1_000, 10).is_err());
+    }
+}
@@ -0,0 +1,115 @@
+// Swap instruction for Orca legacy token-swap pools (SPL token-swap `Swap`, tag 1)
+
+use anchor_client::{solana_sdk::pubkey::Pubkey, solana_sdk::signer::Signer};
+use anchor_spl::associated_token::get_associated_token_address;
+use log::error;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_program::instruction::AccountMeta;
+use solana_sdk::instruction::Instruction;
+use solana_sdk::signature::read_keypair_file;
+
+use crate::common::constants::Env;
+use crate::markets::orca_token_swap::decode_token_swap_layout;
+use crate::markets::types::DexLabel;
+use crate::transactions::create_transaction::{InstructionDetails, MarketInfos};
+
+/// SPL token-swap instruction tag for `Swap`
+const TOKEN_SWAP_SWAP_TAG: u8 = 1;
+
+#[derive(Debug, Clone)]
+pub struct SwapParametersOrca {
+    pub pool: Pubkey,
+    pub amount_in: u64,
+    pub input_token: Pubkey,
+    pub output_token: Pubkey,
+    pub minimum_amount_out: u64,
+}
+
+pub async fn construct_orca_instructions(params: SwapParametersOrca) -> Vec<InstructionDetails> {
+    let SwapParametersOrca {
+        pool,
+        amount_in,
+        input_token,
+        output_token,
+        minimum_amount_out,
+    } = params;
+
+    let mut swap_instructions: Vec<InstructionDetails> = Vec::new();
+    let env = Env::new();
+    let payer = read_keypair_file(env.payer_keypair_path).expect("Wallet keypair file not found");
+
+    let rpc_client = RpcClient::new(env.rpc_url);
+    let pool_account = match rpc_client.get_account(&pool).await {
+        Ok(account) => account,
+        Err(e) => {
+            error!("Failed to fetch Orca pool {}: {}", pool, e);
+            return swap_instructions;
+        }
+    };
+
+    let layout = match decode_token_swap_layout(&pool_account.data) {
+        Ok(layout) => layout,
+        Err(e) => {
+            error!("Failed to decode Orca pool {}: {}", pool, e);
+            return swap_instructions;
+        }
+    };
+
+    // v1 and v2 pools share the layout; the owning program tells them apart
+    let swap_program = pool_account.owner;
+
+    let (swap_source, swap_destination) = if input_token == layout.mint_a && output_token == layout.mint_b {
+        (layout.token_account_a, layout.token_account_b)
+    } else if input_token == layout.mint_b && output_token == layout.mint_a {
+        (layout.token_account_b, layout.token_account_a)
+    } else {
+        error!("TokenIn/TokenOut don't match the tokens of Orca pool {}", pool);
+        return swap_instructions;
+    };
+
+    let authority = match Pubkey::create_program_address(&[pool.as_ref(), &[layout.bump_seed]], &swap_program) {
+        Ok(authority) => authority,
+        Err(e) => {
+            error!("Failed to derive Orca swap authority for {}: {}", pool, e);
+            return swap_instructions;
+        }
+    };
+
+    let user_source = get_associated_token_address(&payer.pubkey(), &input_token);
+    let user_destination = get_associated_token_address(&payer.pubkey(), &output_token);
+
+    let accounts = vec![
+        AccountMeta::new_readonly(pool, false),
+        AccountMeta::new_readonly(authority, false),
+        AccountMeta::new_readonly(payer.pubkey(), true),
+        AccountMeta::new(user_source, false),
+        AccountMeta::new(swap_source, false),
+        AccountMeta::new(swap_destination, false),
+        AccountMeta::new(user_destination, false),
+        AccountMeta::new(layout.token_pool, false),
+        AccountMeta::new(layout.fee_account, false),
+        // Token program recorded in the layout
+        AccountMeta::new_readonly(layout.pool_token_program_id, false),
+    ];
+
+    let mut data = vec![TOKEN_SWAP_SWAP_TAG];
+    data.extend_from_slice(&amount_in.to_le_bytes());
+    data.extend_from_slice(&minimum_amount_out.to_le_bytes());
+
+    let instruction = Instruction {
+        program_id: swap_program,
+        accounts,
+        data,
+    };
+
+    swap_instructions.push(InstructionDetails {
+        instruction,
+        details: "Orca Swap Instruction".to_string(),
+        market: Some(MarketInfos {
+            dex_label: DexLabel::Orca,
+            address: pool,
+        }),
+    });
+
+    swap_instructions
+}
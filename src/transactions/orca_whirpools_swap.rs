@@ -1,7 +1,6 @@
 // //Taken here: https://github.com/MeteoraAg/dlmm-sdk/blob/main/cli/src/instructions/swap.rs
 
 use anchor_client::{solana_sdk::pubkey::Pubkey, solana_sdk::signer::Signer};
-use anchor_spl::associated_token::get_associated_token_address;
 use anchor_spl::token::spl_token;
 
 use borsh::{BorshDeserialize, BorshSerialize};
@@ -18,9 +17,15 @@ use solana_sdk::signature::read_keypair_file;
 use crate::common::constants::Env;
 use crate::common::utils::{from_str, make_request};
 use crate::markets::orca_whirpools::WhirlpoolAccountState;
+use crate::markets::token_2022::{fetch_mint_infos, MEMO_PROGRAM_ID};
 use crate::markets::types::DexLabel;
 use crate::transactions::create_transaction::{InstructionDetails, MarketInfos};
 
+/// Sqrt price bounds of the Whirlpool program, used as `swap_v2` limits so that
+/// `other_amount_threshold` alone bounds the execution price
+const MIN_SQRT_PRICE_X64: u128 = 4295048016;
+const MAX_SQRT_PRICE_X64: u128 = 79226673515401279992447579055;
+
 #[derive(Debug, Clone)]
 pub struct SwapParametersOrcaWhirpools {
     pub whirpools: Pubkey,
@@ -73,8 +78,20 @@ pub async fn construct_orca_whirpools_instructions(
         false
     };
 
+    // Token-2022 mints need their own ATAs and the swap_v2 instruction
+    let mints = match fetch_mint_infos(&rpc_client, &[pool_state.token_mint_a, pool_state.token_mint_b]).await {
+        Ok(mints) => mints,
+        Err(e) => {
+            error!("Mint accounts of the whirlpool not found: {}", e);
+            return swap_instructions;
+        }
+    };
+    let mint_a = mints[&pool_state.token_mint_a];
+    let mint_b = mints[&pool_state.token_mint_b];
+    let (mint_in, mint_out) = if a_to_b { (mint_a, mint_b) } else { (mint_b, mint_a) };
+
     //Get PDA
-    let pda_user_source = get_associated_token_address(&payer.pubkey(), &input_token);
+    let pda_user_source = mint_in.associated_token_address(&payer.pubkey());
     match rpc_client.get_account(&pda_user_source).await { // Changed to await
         Ok(_account) => {}
         Err(_error) => {
@@ -82,7 +99,7 @@ pub async fn construct_orca_whirpools_instructions(
         }
     }
 
-    let pda_user_destination = get_associated_token_address(&payer.pubkey(), &output_token);
+    let pda_user_destination = mint_out.associated_token_address(&payer.pubkey());
 
     match rpc_client.get_account(&pda_user_destination).await { // Changed to await
         Ok(_account) => {}
@@ -112,55 +129,69 @@ pub async fn construct_orca_whirpools_instructions(
     let tick_arrays =
         serde_json::from_str::<TickArraysRes>(&res_text).expect("Unwrap error in tick arrays");
 
-    let accounts = vec![
-        // TokenProgram
-        AccountMeta::new_readonly(spl_token::id(), false),
-        //Token Authority / User ?
-        AccountMeta::new(payer.pubkey(), true),
-        AccountMeta::new(whirpools, false),
-        AccountMeta::new(
-            if a_to_b {
-                pda_user_source
-            } else {
-                pda_user_destination
-            },
-            false,
-        ),
-        AccountMeta::new(pool_state.token_vault_a, false),
-        AccountMeta::new(
-            if a_to_b {
-                pda_user_destination
-            } else {
-                pda_user_source
-            },
-            false,
-        ),
-        AccountMeta::new(pool_state.token_vault_b, false),
-        //Tick arrays
-        AccountMeta::new(from_str(tick_arrays.tick_array_0.as_str()).unwrap(), false),
-        AccountMeta::new(from_str(tick_arrays.tick_array_1.as_str()).unwrap(), false),
-        AccountMeta::new(from_str(tick_arrays.tick_array_2.as_str()).unwrap(), false),
-        //Oracle
-        AccountMeta::new_readonly(from_str(tick_arrays.oracle.as_str()).unwrap(), false),
-    ];
-
-    //Data Instruction
-    let other_amount_threshold: u64 = minimum_amount_out;
-    //SqrtPrice with 1% slippage
-    let sqrt_price_limit_1percent = pool_state.sqrt_price / 100;
-    let sqrt_price_limit = pool_state.sqrt_price - sqrt_price_limit_1percent;
-
-    // println!("sqrt_price_limit {:?}", pool_state.sqrt_price);
-    // println!("Computed sqrt_price_limit {:?}", sqrt_price_limit);
-
-    let mut sighash = [0u8; 8];
-    sighash.copy_from_slice(&hash::hash("global:swap".as_bytes()).to_bytes()[..8]);
-    let mut data = [sighash].concat();
-    data.extend_from_slice(&amount_in.to_le_bytes());
-    data.extend_from_slice(&other_amount_threshold.to_le_bytes());
-    data.extend_from_slice(&sqrt_price_limit.to_le_bytes());
-    data.extend_from_slice(&[1]); //True
-    data.extend_from_slice(&[1]); //True
+    let (owner_account_a, owner_account_b) = if a_to_b {
+        (pda_user_source, pda_user_destination)
+    } else {
+        (pda_user_destination, pda_user_source)
+    };
+
+    let (accounts, data) = if mint_a.is_token_2022() || mint_b.is_token_2022() {
+        let accounts = vec![
+            AccountMeta::new_readonly(mint_a.token_program, false),
+            AccountMeta::new_readonly(mint_b.token_program, false),
+            AccountMeta::new_readonly(from_str(MEMO_PROGRAM_ID).unwrap(), false),
+            AccountMeta::new_readonly(payer.pubkey(), true),
+            AccountMeta::new(whirpools, false),
+            AccountMeta::new_readonly(pool_state.token_mint_a, false),
+            AccountMeta::new_readonly(pool_state.token_mint_b, false),
+            AccountMeta::new(owner_account_a, false),
+            AccountMeta::new(pool_state.token_vault_a, false),
+            AccountMeta::new(owner_account_b, false),
+            AccountMeta::new(pool_state.token_vault_b, false),
+            AccountMeta::new(from_str(tick_arrays.tick_array_0.as_str()).unwrap(), false),
+            AccountMeta::new(from_str(tick_arrays.tick_array_1.as_str()).unwrap(), false),
+            AccountMeta::new(from_str(tick_arrays.tick_array_2.as_str()).unwrap(), false),
+            AccountMeta::new(from_str(tick_arrays.oracle.as_str()).unwrap(), false),
+        ];
+        (accounts, swap_v2_data(amount_in, minimum_amount_out, a_to_b))
+    } else {
+        let accounts = vec![
+            // TokenProgram
+            AccountMeta::new_readonly(spl_token::id(), false),
+            //Token Authority / User ?
+            AccountMeta::new(payer.pubkey(), true),
+            AccountMeta::new(whirpools, false),
+            AccountMeta::new(owner_account_a, false),
+            AccountMeta::new(pool_state.token_vault_a, false),
+            AccountMeta::new(owner_account_b, false),
+            AccountMeta::new(pool_state.token_vault_b, false),
+            //Tick arrays
+            AccountMeta::new(from_str(tick_arrays.tick_array_0.as_str()).unwrap(), false),
+            AccountMeta::new(from_str(tick_arrays.tick_array_1.as_str()).unwrap(), false),
+            AccountMeta::new(from_str(tick_arrays.tick_array_2.as_str()).unwrap(), false),
+            //Oracle
+            AccountMeta::new_readonly(from_str(tick_arrays.oracle.as_str()).unwrap(), false),
+        ];
+
+        //Data Instruction
+        let other_amount_threshold: u64 = minimum_amount_out;
+        //SqrtPrice with 1% slippage
+        let sqrt_price_limit_1percent = pool_state.sqrt_price / 100;
+        let sqrt_price_limit = pool_state.sqrt_price - sqrt_price_limit_1percent;
+
+        // println!("sqrt_price_limit {:?}", pool_state.sqrt_price);
+        // println!("Computed sqrt_price_limit {:?}", sqrt_price_limit);
+
+        let mut sighash = [0u8; 8];
+        sighash.copy_from_slice(&hash::hash("global:swap".as_bytes()).to_bytes()[..8]);
+        let mut data = [sighash].concat();
+        data.extend_from_slice(&amount_in.to_le_bytes());
+        data.extend_from_slice(&other_amount_threshold.to_le_bytes());
+        data.extend_from_slice(&sqrt_price_limit.to_le_bytes());
+        data.extend_from_slice(&[1]); //True
+        data.extend_from_slice(&[1]); //True
+        (accounts, data)
+    };
 
     let instruction = Instruction {
         program_id: amm_program,
@@ -193,6 +224,20 @@ pub struct SwapInstructionBaseIn {
     pub a_to_b: bool,
 }
 
+/// `swap_v2` data for an exact-input swap with no remaining accounts
+fn swap_v2_data(amount_in: u64, other_amount_threshold: u64, a_to_b: bool) -> Vec<u8> {
+    let sqrt_price_limit = if a_to_b { MIN_SQRT_PRICE_X64 } else { MAX_SQRT_PRICE_X64 };
+
+    let mut data = hash::hash(b"global:swap_v2").to_bytes()[..8].to_vec();
+    data.extend_from_slice(&amount_in.to_le_bytes());
+    data.extend_from_slice(&other_amount_threshold.to_le_bytes());
+    data.extend_from_slice(&sqrt_price_limit.to_le_bytes());
+    data.push(1); // amount_specified_is_input
+    data.push(a_to_b as u8);
+    data.push(0); // remaining_accounts_info: None
+    data
+}
+
 #[derive(BorshSerialize, BorshDeserialize, Debug, Serialize, Deserialize)]
 pub struct TickArraysRes {
     pub tick_array_0: String,
@@ -310,3 +355,19 @@ fn to_x64(num: u128) -> u128 {
     let result = num * 2_u128.pow(64);
     return result;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_swap_v2_data() {
+        let data = swap_v2_data(1_000, 990, false);
+        assert_eq!(data.len(), 8 + 8 + 8 + 16 + 3);
+        assert_eq!(&data[..8], &hash::hash(b"global:swap_v2").to_bytes()[..8]);
+        assert_eq!(&data[16..24], &990u64.to_le_bytes());
+        assert_eq!(&data[24..40], &MAX_SQRT_PRICE_X64.to_le_bytes());
+        assert_eq!(&data[40..], &[1, 0, 0]);
+        assert_eq!(&swap_v2_data(1_000, 990, true)[24..40], &MIN_SQRT_PRICE_X64.to_le_bytes());
+    }
+}
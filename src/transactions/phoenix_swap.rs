@@ -0,0 +1,216 @@
+//! Phoenix Swap Implementation
+//!
+//! Builds the Phoenix `Swap` instruction carrying an immediate-or-cancel order packet.
+//! Selling base sizes the order in base lots, buying base sizes it in quote lots, and
+//! `min_amount_out` becomes the minimum fill, so the program rejects the swap instead
+//! of filling below it.
+
+use anchor_client::solana_sdk::pubkey::Pubkey;
+use anchor_spl::associated_token::get_associated_token_address;
+use log::trace;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::instruction::{AccountMeta, Instruction};
+use std::str::FromStr;
+
+use crate::markets::phoenix::{decode_market_header, PHOENIX_PROGRAM_ID};
+use crate::markets::types::DexLabel;
+use crate::transactions::create_transaction::{InstructionDetails, MarketInfos};
+
+/// `PhoenixInstruction::Swap`
+const SWAP_INSTRUCTION_TAG: u8 = 0;
+
+/// `OrderPacket::ImmediateOrCancel`
+const ORDER_PACKET_IMMEDIATE_OR_CANCEL: u8 = 2;
+
+/// `Side` of the order
+const SIDE_BID: u8 = 0;
+const SIDE_ASK: u8 = 1;
+
+/// `SelfTradeBehavior::CancelProvide`
+const SELF_TRADE_CANCEL_PROVIDE: u8 = 1;
+
+/// Seed of the PDA the program logs market events through
+const LOG_AUTHORITY_SEED: &[u8] = b"log";
+
+/// Phoenix-specific error types
+#[derive(Debug, thiserror::Error)]
+pub enum PhoenixError {
+    #[error("Invalid program ID: {0}")]
+    InvalidProgramId(String),
+    #[error("Failed to fetch market account: {0}")]
+    MarketAccountFetch(String),
+    #[error("Failed to deserialize market header: {0}")]
+    MarketDeserialization(String),
+    #[error("Invalid swap parameters: {0}")]
+    InvalidParameters(String),
+}
+
+/// Phoenix Swap Parameters
+#[derive(Debug, Clone)]
+pub struct SwapParametersPhoenix {
+    /// Market account address
+    pub market: Pubkey,
+    /// Input token mint
+    pub input_token_mint: Pubkey,
+    /// Output token mint
+    pub output_token_mint: Pubkey,
+    /// Amount of input tokens to swap
+    pub amount_in: u64,
+    /// Minimum amount of output tokens expected
+    pub min_amount_out: u64,
+    /// Wallet signing the swap and owning the token accounts
+    pub wallet_pubkey: Pubkey,
+}
+
+impl SwapParametersPhoenix {
+    /// Validate swap parameters for safety
+    pub fn validate(&self) -> Result<(), PhoenixError> {
+        if self.amount_in == 0 {
+            return Err(PhoenixError::InvalidParameters("Amount in cannot be zero".to_string()));
+        }
+        if self.input_token_mint == self.output_token_mint {
+            return Err(PhoenixError::InvalidParameters("Input and output mints must differ".to_string()));
+        }
+        Ok(())
+    }
+}
+
+/// `Swap` instruction data for an IOC order of `size_lots` input lots that must fill at
+/// least `min_fill_lots` output lots
+fn swap_data(sell_base: bool, size_lots: u64, min_fill_lots: u64) -> Vec<u8> {
+    let (side, num_base_lots, num_quote_lots, min_base_lots, min_quote_lots) = if sell_base {
+        (SIDE_ASK, size_lots, 0, 0, min_fill_lots)
+    } else {
+        (SIDE_BID, 0, size_lots, min_fill_lots, 0)
+    };
+
+    let mut data = Vec::with_capacity(64);
+    data.push(SWAP_INSTRUCTION_TAG);
+    data.push(ORDER_PACKET_IMMEDIATE_OR_CANCEL);
+    data.push(side);
+    // price_in_ticks: None, the minimum fill bounds the price instead
+    data.push(0);
+    data.extend_from_slice(&num_base_lots.to_le_bytes());
+    data.extend_from_slice(&num_quote_lots.to_le_bytes());
+    data.extend_from_slice(&min_base_lots.to_le_bytes());
+    data.extend_from_slice(&min_quote_lots.to_le_bytes());
+    data.push(SELF_TRADE_CANCEL_PROVIDE);
+    // match_limit: None
+    data.push(0);
+    // client_order_id
+    data.extend_from_slice(&0u128.to_le_bytes());
+    // use_only_deposited_funds
+    data.push(0);
+    // last_valid_slot, last_valid_unix_timestamp_in_seconds: None
+    data.push(0);
+    data.push(0);
+    data
+}
+
+/// Constructs a Phoenix immediate-or-cancel swap
+pub async fn construct_phoenix_instructions(
+    params: SwapParametersPhoenix,
+) -> Result<Vec<InstructionDetails>, PhoenixError> {
+    params.validate()?;
+
+    let program_id =
+        Pubkey::from_str(PHOENIX_PROGRAM_ID).map_err(|e| PhoenixError::InvalidProgramId(e.to_string()))?;
+
+    let env = crate::common::constants::Env::new();
+    let rpc_client = RpcClient::new(env.rpc_url);
+
+    let market_account = rpc_client
+        .get_account(&params.market)
+        .await
+        .map_err(|e| PhoenixError::MarketAccountFetch(format!("{}: {}", params.market, e)))?;
+    let header = decode_market_header(&market_account.data)
+        .map_err(|e| PhoenixError::MarketDeserialization(e.to_string()))?;
+    if header.base_lot_size == 0 || header.quote_lot_size == 0 {
+        return Err(PhoenixError::MarketDeserialization("zero lot size".to_string()));
+    }
+
+    let sell_base = if params.input_token_mint == header.base_mint && params.output_token_mint == header.quote_mint {
+        true
+    } else if params.input_token_mint == header.quote_mint && params.output_token_mint == header.base_mint {
+        false
+    } else {
+        return Err(PhoenixError::InvalidParameters(format!(
+            "{} -> {} is not traded by market {}",
+            params.input_token_mint, params.output_token_mint, params.market
+        )));
+    };
+
+    let (in_lot_size, out_lot_size) = if sell_base {
+        (header.base_lot_size, header.quote_lot_size)
+    } else {
+        (header.quote_lot_size, header.base_lot_size)
+    };
+    let size_lots = params.amount_in / in_lot_size;
+    if size_lots == 0 {
+        return Err(PhoenixError::InvalidParameters(format!(
+            "{} is below one lot ({})",
+            params.amount_in, in_lot_size
+        )));
+    }
+    let min_fill_lots = params.min_amount_out.div_ceil(out_lot_size);
+
+    let (log_authority, _) = Pubkey::find_program_address(&[LOG_AUTHORITY_SEED], &program_id);
+    let base_account = get_associated_token_address(&params.wallet_pubkey, &header.base_mint);
+    let quote_account = get_associated_token_address(&params.wallet_pubkey, &header.quote_mint);
+
+    let accounts = vec![
+        AccountMeta::new_readonly(program_id, false),
+        AccountMeta::new_readonly(log_authority, false),
+        AccountMeta::new(params.market, false),
+        AccountMeta::new_readonly(params.wallet_pubkey, true),
+        AccountMeta::new(base_account, false),
+        AccountMeta::new(quote_account, false),
+        AccountMeta::new(header.base_vault, false),
+        AccountMeta::new(header.quote_vault, false),
+        AccountMeta::new_readonly(anchor_spl::token::ID, false),
+    ];
+
+    let instruction = Instruction {
+        program_id,
+        accounts,
+        data: swap_data(sell_base, size_lots, min_fill_lots),
+    };
+
+    trace!(
+        "Constructed Phoenix IOC on {}: {} {} -> min {} {}",
+        params.market,
+        params.amount_in,
+        params.input_token_mint,
+        params.min_amount_out,
+        params.output_token_mint
+    );
+
+    Ok(vec![InstructionDetails {
+        instruction,
+        details: format!("Phoenix swap: {} -> {}", params.input_token_mint, params.output_token_mint),
+        market: Some(MarketInfos {
+            dex_label: DexLabel::Phoenix,
+            address: params.market,
+        }),
+    }])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_swap_data_layout() {
+        let sell = swap_data(true, 12, 345);
+        // tag, packet, side, None, 4 x u64, self-trade, None, u128, bool, None, None
+        assert_eq!(sell.len(), 3 + 1 + 32 + 1 + 1 + 16 + 1 + 2);
+        assert_eq!(&sell[..4], &[SWAP_INSTRUCTION_TAG, ORDER_PACKET_IMMEDIATE_OR_CANCEL, SIDE_ASK, 0]);
+        assert_eq!(&sell[4..12], &12u64.to_le_bytes());
+        assert_eq!(&sell[28..36], &345u64.to_le_bytes());
+
+        let buy = swap_data(false, 12, 345);
+        assert_eq!(buy[2], SIDE_BID);
+        assert_eq!(&buy[12..20], &12u64.to_le_bytes());
+        assert_eq!(&buy[20..28], &345u64.to_le_bytes());
+    }
+}
@@ -12,13 +12,14 @@ use solana_client::nonblocking::rpc_client::RpcClient;
 use solana_sdk::{
     instruction::{AccountMeta, Instruction},
 };
-use spl_associated_token_account::get_associated_token_address;
 use anchor_spl::token::spl_token;
+use solana_program::hash;
 use log::{error, trace};
 use anyhow::Result;
 use std::str::FromStr;
 
 use crate::transactions::create_transaction::{InstructionDetails, MarketInfos};
+use crate::markets::token_2022::{fetch_mint_infos, MEMO_PROGRAM_ID};
 use crate::markets::types::DexLabel;
 
 /// Official Raydium CLMM Program ID
@@ -27,8 +28,15 @@ pub const RAYDIUM_CLMM_PROGRAM_ID: &str = "CAMMCzo5YL8w4VFF8KVHrK22GGUsp5VTaW7gr
 /// CLMM Swap instruction discriminator (based on official program)
 pub const CLMM_SWAP_INSTRUCTION: u8 = 9;
 
+/// Anchor discriminator of `swap_v2`, required when either mint is Token-2022
+fn swap_v2_discriminator() -> [u8; 8] {
+    let mut sighash = [0u8; 8];
+    sighash.copy_from_slice(&hash::hash(b"global:swap_v2").to_bytes()[..8]);
+    sighash
+}
+
 /// Tick array size constant from official implementation
-pub const TICK_ARRAY_SIZE: i32 = 88;
+pub const TICK_ARRAY_SIZE: i32 = 60;
 
 /// Maximum tick array start index
 pub const MAX_TICK_ARRAY_START_INDEX: i32 = 306;
@@ -106,7 +114,7 @@ impl SwapParametersRaydiumClmm {
 }
 
 /// Raydium CLMM Pool State (based on official program structure)
-#[derive(Debug, BorshDeserialize)]
+#[derive(Debug, Clone, BorshDeserialize)]
 pub struct RaydiumClmmPoolState {
     /// Discriminator (8 bytes)
     pub discriminator: [u8; 8],
@@ -161,6 +169,13 @@ pub struct RaydiumClmmPoolState {
 }
 
 impl RaydiumClmmPoolState {
+    /// Decode the leading fields of a pool account; the account is larger than
+    /// this struct, so the remaining bytes are ignored
+    pub fn from_account_data(data: &[u8]) -> Result<Self, ClmmError> {
+        Self::deserialize(&mut &data[..])
+            .map_err(|e| ClmmError::PoolStateDeserialization(e.to_string()))
+    }
+
     /// Validate pool state for safety and correctness
     pub fn validate(&self) -> Result<(), ClmmError> {
         // Check if pool is active (status should be 1 for active)
@@ -189,7 +204,7 @@ impl RaydiumClmmPoolState {
 
 /// Calculate tick array start index based on tick and tick spacing
 /// This follows the official Raydium CLMM tick array calculation logic
-fn get_tick_array_start_index(tick: i32, tick_spacing: u16) -> Result<i32, ClmmError> {
+pub fn get_tick_array_start_index(tick: i32, tick_spacing: u16) -> Result<i32, ClmmError> {
     if tick_spacing == 0 {
         return Err(ClmmError::TickArrayCalculation("Tick spacing cannot be zero".to_string()));
     }
@@ -213,12 +228,13 @@ fn get_tick_array_start_index(tick: i32, tick_spacing: u16) -> Result<i32, ClmmE
 
 /// Derive tick array PDA address
 /// Based on official Raydium CLMM program PDA derivation
-fn derive_tick_array_address(
+pub fn derive_tick_array_address(
     pool_id: &Pubkey,
     start_index: i32,
     program_id: &Pubkey,
 ) -> Result<Pubkey, ClmmError> {
-    let start_index_bytes = start_index.to_le_bytes();
+    // The program seeds tick arrays with the big-endian start index
+    let start_index_bytes = start_index.to_be_bytes();
     let seeds = &[
         b"tick_array",
         pool_id.as_ref(),
@@ -291,8 +307,7 @@ pub async fn construct_raydium_clmm_instructions(
         .map_err(|e| ClmmError::PoolAccountFetch(format!("{}: {}", params.pool, e)))?;
     
     // Deserialize pool state
-    let pool_state = RaydiumClmmPoolState::try_from_slice(&pool_account.data)
-        .map_err(|e| ClmmError::PoolStateDeserialization(e.to_string()))?;
+    let pool_state = RaydiumClmmPoolState::from_account_data(&pool_account.data)?;
     
     // Validate pool state
     pool_state.validate()?;
@@ -307,16 +322,58 @@ pub async fn construct_raydium_clmm_instructions(
         (pool_state.token_vault_1, pool_state.token_vault_0)
     };
     
-    // Get REAL user token accounts using actual wallet pubkey
-    let input_token_account = get_associated_token_address(
-        &params.wallet_pubkey, // REAL wallet pubkey - no more placeholders!
-        &params.input_token_mint,
-    );
-    let output_token_account = get_associated_token_address(
-        &params.wallet_pubkey, // REAL wallet pubkey - no more placeholders!
-        &params.output_token_mint,
-    );
     
+    // Token-2022 mints need their own ATAs and the swap_v2 instruction
+    let mints = fetch_mint_infos(&rpc_client, &[pool_state.token_mint_0, pool_state.token_mint_1])
+        .await
+        .map_err(|e| ClmmError::RpcClient(e.to_string()))?;
+    let (input_mint, output_mint) = if params.a_to_b {
+        (mints[&pool_state.token_mint_0], mints[&pool_state.token_mint_1])
+    } else {
+        (mints[&pool_state.token_mint_1], mints[&pool_state.token_mint_0])
+    };
+
+    // Get REAL user token accounts using actual wallet pubkey
+    let input_token_account = input_mint.associated_token_address(&params.wallet_pubkey);
+    let output_token_account = output_mint.associated_token_address(&params.wallet_pubkey);
+
+    if input_mint.is_token_2022() || output_mint.is_token_2022() {
+        let memo_program =
+            Pubkey::from_str(MEMO_PROGRAM_ID).map_err(|e| ClmmError::InvalidProgramId(e.to_string()))?;
+        let mut accounts = vec![
+            AccountMeta::new_readonly(params.wallet_pubkey, true),
+            AccountMeta::new_readonly(pool_state.amm_config, false),
+            AccountMeta::new(params.pool, false),
+            AccountMeta::new(input_token_account, false),
+            AccountMeta::new(output_token_account, false),
+            AccountMeta::new(input_vault, false),
+            AccountMeta::new(output_vault, false),
+            AccountMeta::new(pool_state.observation_id, false),
+            AccountMeta::new_readonly(spl_token::ID, false),
+            AccountMeta::new_readonly(anchor_spl::token_2022::ID, false),
+            AccountMeta::new_readonly(memo_program, false),
+            AccountMeta::new_readonly(input_mint.mint, false),
+            AccountMeta::new_readonly(output_mint.mint, false),
+        ];
+        // Tick arrays are passed as remaining accounts
+        accounts.extend(tick_arrays.iter().map(|tick_array| AccountMeta::new(*tick_array, false)));
+
+        trace!("✅ Constructed Raydium CLMM swap_v2 instruction for Token-2022 pool {}", params.pool);
+        return Ok(vec![InstructionDetails {
+            instruction: Instruction {
+                program_id,
+                accounts,
+                data: swap_v2_data(params.amount_in, params.min_amount_out, params.sqrt_price_limit),
+            },
+            details: format!("Raydium CLMM swap_v2: {} -> {}", params.input_token_mint, params.output_token_mint),
+            market: Some(MarketInfos {
+                dex_label: DexLabel::RaydiumClmm,
+                address: params.pool,
+            }),
+        }]);
+    }
+
+
     // Construct the swap instruction data
     // Format: [discriminator(1), amount_in(8), min_amount_out(8), sqrt_price_limit(16), a_to_b(1)]
     let mut instruction_data = Vec::with_capacity(34); // Pre-allocate for performance
@@ -367,6 +424,20 @@ pub async fn construct_raydium_clmm_instructions(
     }])
 }
 
+/// `swap_v2` data for an exact-input swap. `u128::MAX` means no price limit, which
+/// `swap_v2` spells as zero.
+fn swap_v2_data(amount_in: u64, min_amount_out: u64, sqrt_price_limit: u128) -> Vec<u8> {
+    let sqrt_price_limit_x64 = if sqrt_price_limit == u128::MAX { 0 } else { sqrt_price_limit };
+
+    let mut data = Vec::with_capacity(41);
+    data.extend_from_slice(&swap_v2_discriminator());
+    data.extend_from_slice(&amount_in.to_le_bytes());
+    data.extend_from_slice(&min_amount_out.to_le_bytes());
+    data.extend_from_slice(&sqrt_price_limit_x64.to_le_bytes());
+    data.push(1); // is_base_input
+    data
+}
+
 /// Wrapper function that maintains backward compatibility with the old interface
 /// Returns empty vector on error (for compatibility with existing code)
 pub async fn construct_raydium_clmm_instructions_compat(
@@ -437,6 +508,17 @@ mod tests {
         assert!(invalid_params.validate().is_err());
     }
 
+    #[test]
+    fn test_swap_v2_data() {
+        let data = swap_v2_data(1_000_000, 900_000, u128::MAX);
+        assert_eq!(data.len(), 41);
+        assert_eq!(&data[..8], &hash::hash(b"global:swap_v2").to_bytes()[..8]);
+        assert_eq!(&data[16..24], &900_000u64.to_le_bytes());
+        assert_eq!(&data[24..40], &0u128.to_le_bytes());
+        assert_eq!(data[40], 1);
+        assert_eq!(&swap_v2_data(1, 1, 42)[24..40], &42u128.to_le_bytes());
+    }
+
     #[test]
     fn test_clmm_account_index_enum() {
         // Verify enum values are correct
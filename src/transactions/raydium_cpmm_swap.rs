@@ -0,0 +1,219 @@
+//! Raydium CPMM Swap Implementation
+//!
+//! Builds the `swap_base_input` instruction of the Raydium CPMM program
+//! (CPMMoo8L3F4NbTegBCKVNunggL7H1ZpdTHKxQB5qKP1C). Each side of the pool names its own
+//! token program, so user token accounts are derived against the mint's program and
+//! Token-2022 mints work without special casing.
+
+use anchor_client::solana_sdk::pubkey::Pubkey;
+use log::trace;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_program::hash;
+use solana_sdk::instruction::{AccountMeta, Instruction};
+use spl_associated_token_account::get_associated_token_address_with_program_id;
+use std::str::FromStr;
+
+use crate::markets::raydium_cpmm::{decode_pool_state, RAYDIUM_CPMM_PROGRAM_ID};
+use crate::markets::types::DexLabel;
+use crate::transactions::create_transaction::{InstructionDetails, MarketInfos};
+
+/// Seed of the PDA that owns the vaults and the LP mint
+const AUTH_SEED: &[u8] = b"vault_and_lp_mint_auth_seed";
+
+/// CPMM-specific error types
+#[derive(Debug, thiserror::Error)]
+pub enum CpmmError {
+    #[error("Invalid program ID: {0}")]
+    InvalidProgramId(String),
+    #[error("Failed to fetch pool account: {0}")]
+    PoolAccountFetch(String),
+    #[error("Failed to deserialize pool state: {0}")]
+    PoolStateDeserialization(String),
+    #[error("Invalid swap parameters: {0}")]
+    InvalidParameters(String),
+}
+
+/// Account positions for the CPMM `swap_base_input` instruction
+#[derive(Debug, Clone, Copy)]
+#[repr(usize)]
+pub enum CpmmAccountIndex {
+    Payer = 0,
+    Authority = 1,
+    AmmConfig = 2,
+    PoolState = 3,
+    InputTokenAccount = 4,
+    OutputTokenAccount = 5,
+    InputVault = 6,
+    OutputVault = 7,
+    InputTokenProgram = 8,
+    OutputTokenProgram = 9,
+    InputTokenMint = 10,
+    OutputTokenMint = 11,
+    ObservationState = 12,
+}
+
+/// Raydium CPMM Swap Parameters
+#[derive(Debug, Clone)]
+pub struct SwapParametersRaydiumCpmm {
+    /// CPMM pool address
+    pub pool: Pubkey,
+    /// Input token mint
+    pub input_token_mint: Pubkey,
+    /// Output token mint
+    pub output_token_mint: Pubkey,
+    /// Amount of input tokens to swap
+    pub amount_in: u64,
+    /// Minimum amount of output tokens expected
+    pub min_amount_out: u64,
+    /// Wallet signing the swap and owning the token accounts
+    pub wallet_pubkey: Pubkey,
+}
+
+impl SwapParametersRaydiumCpmm {
+    /// Validate swap parameters for safety
+    pub fn validate(&self) -> Result<(), CpmmError> {
+        if self.amount_in == 0 {
+            return Err(CpmmError::InvalidParameters("Amount in cannot be zero".to_string()));
+        }
+        if self.input_token_mint == self.output_token_mint {
+            return Err(CpmmError::InvalidParameters("Input and output mints must differ".to_string()));
+        }
+        Ok(())
+    }
+}
+
+/// Anchor discriminator of `swap_base_input`
+fn swap_base_input_discriminator() -> [u8; 8] {
+    let mut sighash = [0u8; 8];
+    sighash.copy_from_slice(&hash::hash(b"global:swap_base_input").to_bytes()[..8]);
+    sighash
+}
+
+/// Constructs a Raydium CPMM exact-input swap instruction
+pub async fn construct_raydium_cpmm_instructions(
+    params: SwapParametersRaydiumCpmm,
+) -> Result<Vec<InstructionDetails>, CpmmError> {
+    params.validate()?;
+
+    let program_id = Pubkey::from_str(RAYDIUM_CPMM_PROGRAM_ID)
+        .map_err(|e| CpmmError::InvalidProgramId(e.to_string()))?;
+
+    let env = crate::common::constants::Env::new();
+    let rpc_client = RpcClient::new(env.rpc_url);
+
+    let pool_account = rpc_client
+        .get_account(&params.pool)
+        .await
+        .map_err(|e| CpmmError::PoolAccountFetch(format!("{}: {}", params.pool, e)))?;
+    let pool_state = decode_pool_state(&pool_account.data)
+        .map_err(|e| CpmmError::PoolStateDeserialization(e.to_string()))?;
+
+    let zero_for_one = if params.input_token_mint == pool_state.token_0_mint
+        && params.output_token_mint == pool_state.token_1_mint
+    {
+        true
+    } else if params.input_token_mint == pool_state.token_1_mint
+        && params.output_token_mint == pool_state.token_0_mint
+    {
+        false
+    } else {
+        return Err(CpmmError::InvalidParameters(format!(
+            "{} -> {} is not traded by pool {}",
+            params.input_token_mint, params.output_token_mint, params.pool
+        )));
+    };
+
+    let (input_vault, output_vault, input_program, output_program) = if zero_for_one {
+        (
+            pool_state.token_0_vault,
+            pool_state.token_1_vault,
+            pool_state.token_0_program,
+            pool_state.token_1_program,
+        )
+    } else {
+        (
+            pool_state.token_1_vault,
+            pool_state.token_0_vault,
+            pool_state.token_1_program,
+            pool_state.token_0_program,
+        )
+    };
+
+    let (authority, _) = Pubkey::find_program_address(&[AUTH_SEED], &program_id);
+    let input_token_account =
+        get_associated_token_address_with_program_id(&params.wallet_pubkey, &params.input_token_mint, &input_program);
+    let output_token_account =
+        get_associated_token_address_with_program_id(&params.wallet_pubkey, &params.output_token_mint, &output_program);
+
+    let mut instruction_data = Vec::with_capacity(24);
+    instruction_data.extend_from_slice(&swap_base_input_discriminator());
+    instruction_data.extend_from_slice(&params.amount_in.to_le_bytes());
+    instruction_data.extend_from_slice(&params.min_amount_out.to_le_bytes());
+
+    let mut accounts = Vec::with_capacity(13);
+    accounts.resize(13, AccountMeta::new_readonly(Pubkey::default(), false));
+
+    accounts[CpmmAccountIndex::Payer as usize] = AccountMeta::new_readonly(params.wallet_pubkey, true);
+    accounts[CpmmAccountIndex::Authority as usize] = AccountMeta::new_readonly(authority, false);
+    accounts[CpmmAccountIndex::AmmConfig as usize] = AccountMeta::new_readonly(pool_state.amm_config, false);
+    accounts[CpmmAccountIndex::PoolState as usize] = AccountMeta::new(params.pool, false);
+    accounts[CpmmAccountIndex::InputTokenAccount as usize] = AccountMeta::new(input_token_account, false);
+    accounts[CpmmAccountIndex::OutputTokenAccount as usize] = AccountMeta::new(output_token_account, false);
+    accounts[CpmmAccountIndex::InputVault as usize] = AccountMeta::new(input_vault, false);
+    accounts[CpmmAccountIndex::OutputVault as usize] = AccountMeta::new(output_vault, false);
+    accounts[CpmmAccountIndex::InputTokenProgram as usize] = AccountMeta::new_readonly(input_program, false);
+    accounts[CpmmAccountIndex::OutputTokenProgram as usize] = AccountMeta::new_readonly(output_program, false);
+    accounts[CpmmAccountIndex::InputTokenMint as usize] = AccountMeta::new_readonly(params.input_token_mint, false);
+    accounts[CpmmAccountIndex::OutputTokenMint as usize] = AccountMeta::new_readonly(params.output_token_mint, false);
+    accounts[CpmmAccountIndex::ObservationState as usize] = AccountMeta::new(pool_state.observation_key, false);
+
+    let instruction = Instruction {
+        program_id,
+        accounts,
+        data: instruction_data,
+    };
+
+    trace!(
+        "Constructed Raydium CPMM swap on {}: {} {} -> min {} {}",
+        params.pool,
+        params.amount_in,
+        params.input_token_mint,
+        params.min_amount_out,
+        params.output_token_mint
+    );
+
+    Ok(vec![InstructionDetails {
+        instruction,
+        details: format!("Raydium CPMM swap: {} -> {}", params.input_token_mint, params.output_token_mint),
+        market: Some(MarketInfos {
+            dex_label: DexLabel::RaydiumCpmm,
+            address: params.pool,
+        }),
+    }])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_swap_base_input_discriminator() {
+        assert_eq!(swap_base_input_discriminator(), [143, 190, 90, 218, 196, 30, 51, 222]);
+    }
+
+    #[test]
+    fn test_parameter_validation() {
+        let mint = Pubkey::new_unique();
+        let params = SwapParametersRaydiumCpmm {
+            pool: Pubkey::new_unique(),
+            input_token_mint: mint,
+            output_token_mint: Pubkey::new_unique(),
+            amount_in: 1_000,
+            min_amount_out: 0,
+            wallet_pubkey: Pubkey::new_unique(),
+        };
+        assert!(params.validate().is_ok());
+        assert!(SwapParametersRaydiumCpmm { amount_in: 0, ..params.clone() }.validate().is_err());
+        assert!(SwapParametersRaydiumCpmm { output_token_mint: mint, ..params }.validate().is_err());
+    }
+}